@@ -1,4 +1,11 @@
+use std::env;
+
 pub fn main() {
     println!("cargo:rerun-if-changed=src/build.rs");
+    // ecap-common tells us, through its `links` metadata, where its cdylib
+    // is built. It may still be building as this runs, but is built by
+    // the time anything using us is linked.
+    let libdir = env::var("DEP_ECAP_COMMON_LIBDIR").unwrap();
+    println!("cargo:rustc-link-search=native={}", libdir);
     println!("cargo:rustc-link-lib=dylib=ecap_common");
 }
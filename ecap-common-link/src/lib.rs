@@ -1,21 +1,25 @@
-#![feature(unwind_attributes)]
-
 extern crate ecap;
 extern crate erased_ecap;
 
+use std::any::Any;
+use std::fmt::{self, Write};
+use std::panic;
+
 use erased_ecap::adapter::ErasedService;
 use erased_ecap::host::Host;
 use erased_ecap::ErasedTranslator;
 use erased_ecap::ErasedTranslatorS;
 
 use ecap::adapter::Service;
+use ecap::common::log::{FrequencyLevel, ImportanceLevel, LogVerbosity, MessageSizeLevel};
 
 #[allow(improper_ctypes)]
 extern "Rust" {
-    #[unwind(allowed)]
     fn register_service(service: ErasedService);
-    #[unwind(allowed)]
     fn register_translator(translator: ErasedTranslatorS);
+    fn take_registered_services() -> Vec<ErasedService>;
+    fn register_host(host: &'static dyn Host);
+    fn registered_host() -> Option<&'static dyn Host>;
 }
 
 pub fn register_erased_service<T: Service<dyn Host>>(service: T)
@@ -34,3 +38,108 @@ pub fn register_erased_translator<T: 'static + ErasedTranslator>(translator: T)
         register_translator(translator);
     }
 }
+
+/// Services registered since the last call, if no translator has been
+/// registered; once one is, services are handed to it instead.
+pub fn take_erased_services() -> Vec<ErasedService> {
+    unsafe { take_registered_services() }
+}
+
+/// Make `host` available to adapters through `erased_host`, like
+/// libecap's `RegisterHost`.
+///
+/// Only one host can be registered per process.
+pub fn register_erased_host(host: &'static dyn Host) {
+    unsafe { register_host(host) }
+}
+
+/// The host registered with `register_erased_host`, if any; the
+/// equivalent of libecap's `MyHost`.
+pub fn erased_host() -> Option<&'static dyn Host> {
+    unsafe { registered_host() }
+}
+
+/// Register services when the adapter library is loaded.
+///
+/// Each argument is an expression creating a service, evaluated once at
+/// load time. If creating or registering a service panics, the panic is
+/// logged to the host and the library loads without that service,
+/// rather than the host aborting.
+///
+/// ```rust,ignore
+/// #[macro_use]
+/// extern crate ecap_common_link;
+///
+/// ecap_adapter!(MinimalService, ModifyService { config: None });
+/// ```
+///
+/// Use it once per adapter library.
+#[macro_export]
+macro_rules! ecap_adapter {
+    ($($service:expr),+ $(,)*) => {
+        #[doc(hidden)]
+        pub extern "C" fn __ecap_adapter_on_load() {
+            $(
+                $crate::load_service(stringify!($service), || $service);
+            )+
+        }
+
+        #[doc(hidden)]
+        #[used]
+        #[cfg_attr(not(target_os = "macos"), link_section = ".init_array")]
+        #[cfg_attr(target_os = "macos", link_section = "__DATA,__mod_init_func")]
+        pub static __ECAP_ADAPTER_ON_LOAD: extern "C" fn() = __ecap_adapter_on_load;
+    };
+}
+
+/// Create and register a service for `ecap_adapter!`, logging rather
+/// than unwinding if that panics.
+#[doc(hidden)]
+pub fn load_service<T, F>(name: &str, make: F)
+where
+    F: FnOnce() -> T,
+    T: Service<dyn Host>,
+    <T as Service<dyn Host>>::Transaction: 'static,
+{
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        register_erased_service(make());
+    }));
+    if let Err(payload) = result {
+        // Logging must not unwind into the loader either.
+        let _ = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            log_critical(format_args!(
+                "failed to load eCAP service {}: {}",
+                name,
+                panic_message(&*payload)
+            ))
+        }));
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Log to the registered host, or to stderr if there is none yet.
+fn log_critical(message: fmt::Arguments) {
+    let verbosity = LogVerbosity {
+        importance: ImportanceLevel::Critical,
+        frequency: FrequencyLevel::Application,
+        size: MessageSizeLevel::Normal,
+    };
+    match erased_host() {
+        Some(host) => {
+            if let Some(mut stream) = host.open_debug(verbosity) {
+                let _ = stream.write_fmt(message);
+                host.close_debug(stream);
+            }
+        }
+        None => eprintln!("{}", message),
+    }
+}
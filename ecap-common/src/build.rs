@@ -0,0 +1,29 @@
+use std::env;
+use std::path::Path;
+
+pub fn main() {
+    println!("cargo:rerun-if-changed=src/build.rs");
+    // Cargo does not tell build scripts where a crate's own cdylib goes.
+    // It is placed in the deps directory of the target directory, next
+    // to the `build` directory holding our `build/<pkg>/out`; tell the
+    // crates linking to it where that is.
+    //
+    // This relies on Cargo's layout of the target directory, which is
+    // not a stable interface, so stop here if it is not what we expect
+    // rather than failing to link later on.
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let out_dir = Path::new(&out_dir);
+    let build = out_dir.parent().and_then(Path::parent);
+    let deps = build.and_then(Path::parent).map(|dir| dir.join("deps"));
+    match (build, deps) {
+        (Some(build), Some(ref deps)) if build.ends_with("build") && deps.is_dir() => {
+            println!("cargo:libdir={}", deps.display());
+        }
+        _ => panic!(
+            "ecap-common cannot find where its library is built: expected OUT_DIR \
+             ({}) to be in Cargo's <target>/<profile>/build/<pkg>/out, next to \
+             <target>/<profile>/deps",
+            out_dir.display()
+        ),
+    }
+}
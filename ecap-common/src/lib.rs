@@ -1,27 +1,25 @@
-#![feature(unwind_attributes)]
-#![feature(alloc_system)]
-
-extern crate alloc_system;
-
-#[global_allocator]
-static ALLOC: alloc_system::System = alloc_system::System;
-
 #[macro_use]
 extern crate lazy_static;
 extern crate ecap;
 extern crate erased_ecap;
 
 use erased_ecap::adapter::ErasedService;
+use erased_ecap::host::Host;
 use erased_ecap::ErasedTranslatorS;
 use std::sync::Mutex;
 
+pub struct RegisteredHost(&'static dyn Host);
+
+// Hosts are only used from the thread they run adapters on.
+unsafe impl Send for RegisteredHost {}
+
 lazy_static! {
     pub static ref REGISTERED_ADAPTERS: Mutex<Vec<ErasedService>> = Mutex::new(Vec::new());
     pub static ref REGISTERED_TRANSLATORS: Mutex<Option<ErasedTranslatorS>> = Mutex::new(None);
+    pub static ref REGISTERED_HOST: Mutex<Option<RegisteredHost>> = Mutex::new(None);
 }
 
 #[no_mangle]
-#[unwind(allowed)]
 pub fn register_service(service: ErasedService) {
     let translator_slot = REGISTERED_TRANSLATORS.lock().unwrap();
     if let Some(translator) = &*translator_slot {
@@ -33,7 +31,6 @@ pub fn register_service(service: ErasedService) {
 }
 
 #[no_mangle]
-#[unwind(allowed)]
 pub fn register_translator(translator: ErasedTranslatorS) {
     let mut adapters = REGISTERED_ADAPTERS.lock().unwrap();
     for adapter in adapters.drain(..) {
@@ -43,3 +40,23 @@ pub fn register_translator(translator: ErasedTranslatorS) {
     assert!(translator_slot.is_none());
     *translator_slot = Some(translator);
 }
+
+/// Removes and returns all services registered while no translator was
+/// present.
+#[no_mangle]
+pub fn take_registered_services() -> Vec<ErasedService> {
+    let mut adapters = REGISTERED_ADAPTERS.lock().unwrap();
+    adapters.drain(..).collect()
+}
+
+#[no_mangle]
+pub fn register_host(host: &'static dyn Host) {
+    let mut host_slot = REGISTERED_HOST.lock().unwrap();
+    assert!(host_slot.is_none());
+    *host_slot = Some(RegisteredHost(host));
+}
+
+#[no_mangle]
+pub fn registered_host() -> Option<&'static dyn Host> {
+    REGISTERED_HOST.lock().unwrap().as_ref().map(|host| host.0)
+}
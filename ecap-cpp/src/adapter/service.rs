@@ -8,7 +8,11 @@ use std::{fmt::Write, mem};
 use common::log::Ostream;
 use common::options::CppOptions;
 use ffi_unwind;
+use host::CppHost;
 
+use ecap::common::log::{FrequencyLevel, ImportanceLevel, LogVerbosity, MessageSizeLevel};
+use ecap::common::Error;
+use ecap::host::Host as EcapHost;
 use erased_ecap::adapter::Service as ErasedService;
 use erased_ecap::host::Host;
 
@@ -31,7 +35,6 @@ pub unsafe fn to_service_mut<'a>(
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_start(service: ServicePtr) -> bool {
     ffi_unwind(
         &mut (),
@@ -40,7 +43,6 @@ pub unsafe extern "C" fn rust_service_start(service: ServicePtr) -> bool {
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_stop(service: ServicePtr) -> bool {
     ffi_unwind(
         &mut (),
@@ -49,7 +51,6 @@ pub unsafe extern "C" fn rust_service_stop(service: ServicePtr) -> bool {
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_retire(service: ServicePtr) -> bool {
     ffi_unwind(
         &mut (),
@@ -58,7 +59,6 @@ pub unsafe extern "C" fn rust_service_retire(service: ServicePtr) -> bool {
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_is_async(service: ServicePtr, out: *mut bool) -> bool {
     ffi_unwind(
         out,
@@ -67,7 +67,6 @@ pub unsafe extern "C" fn rust_service_is_async(service: ServicePtr, out: *mut bo
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_resume(service: ServicePtr) -> bool {
     ffi_unwind(
         &mut (),
@@ -76,7 +75,6 @@ pub unsafe extern "C" fn rust_service_resume(service: ServicePtr) -> bool {
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_suspend(service: ServicePtr, duration: *mut timeval) -> bool {
     ffi_unwind(
         &mut (),
@@ -93,7 +91,6 @@ pub unsafe extern "C" fn rust_service_suspend(service: ServicePtr, duration: *mu
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_describe(
     service: ServicePtr,
     stream: *mut ffi::Ostream,
@@ -109,7 +106,6 @@ pub unsafe extern "C" fn rust_service_describe(
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_uri(service: ServicePtr, out: *mut ffi::CVec) -> bool {
     ffi_unwind(
         out,
@@ -121,7 +117,6 @@ pub unsafe extern "C" fn rust_service_uri(service: ServicePtr, out: *mut ffi::CV
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_tag(service: ServicePtr, out: *mut ffi::CVec) -> bool {
     ffi_unwind(
         out,
@@ -132,8 +127,22 @@ pub unsafe extern "C" fn rust_service_tag(service: ServicePtr, out: *mut ffi::CV
     )
 }
 
+/// Logs a rejected configuration through the host rather than throwing
+/// into it, which would abort most hosts.
+fn report_config_error(service: &dyn ErasedService<dyn Host>, error: Error) {
+    let host = CppHost::new();
+    let verbosity = LogVerbosity {
+        importance: ImportanceLevel::Critical,
+        frequency: FrequencyLevel::Application,
+        size: MessageSizeLevel::Normal,
+    };
+    if let Some(mut stream) = EcapHost::open_debug(host, verbosity) {
+        let _ = write!(stream, "{}: {}", service.uri(), error);
+        EcapHost::close_debug(host, stream);
+    }
+}
+
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_configure(
     mut service: ServicePtr,
     options: *const ffi::Options,
@@ -142,13 +151,15 @@ pub unsafe extern "C" fn rust_service_configure(
         &mut (),
         panic::AssertUnwindSafe(|| {
             assert!(!options.is_null());
-            to_service_mut(&mut service).configure(CppOptions::from_ptr(options))
+            let service = to_service_mut(&mut service);
+            if let Err(e) = service.configure(CppOptions::from_ptr(options)) {
+                report_config_error(service, e);
+            }
         }),
     )
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_reconfigure(
     mut service: ServicePtr,
     options: *const ffi::Options,
@@ -157,13 +168,15 @@ pub unsafe extern "C" fn rust_service_reconfigure(
         &mut (),
         panic::AssertUnwindSafe(|| {
             assert!(!options.is_null());
-            to_service_mut(&mut service).reconfigure(CppOptions::from_ptr(options))
+            let service = to_service_mut(&mut service);
+            if let Err(e) = service.reconfigure(CppOptions::from_ptr(options)) {
+                report_config_error(service, e);
+            }
         }),
     )
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_wants_url(
     service: ServicePtr,
     url: *const c_char,
@@ -179,7 +192,6 @@ pub unsafe extern "C" fn rust_service_wants_url(
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_service_free(service: ServicePtr) -> bool {
     ffi_unwind(
         &mut (),
@@ -1,12 +1,12 @@
 use ecap::common::area::{Area, Details, DetailsConstructor, DetailsStack};
 use ffi;
 use libc::c_char;
-use std::mem;
-use std::ptr::{self, NonNull};
-use std::rc::Rc;
+use std::mem::{self, MaybeUninit};
+use std::panic;
+use std::ptr;
 use std::slice;
 
-use call_ffi_maybe_panic;
+use {call_ffi_maybe_panic, ffi_unwind};
 
 pub struct CppArea(ffi::Area);
 
@@ -24,9 +24,35 @@ impl CppArea {
         }
     }
 
+    /// Convert an area into a C++ area without copying its buffer.
+    ///
+    /// Areas which came from C++ are handed back as they were; other
+    /// areas are wrapped in C++ details holding a reference to them.
     pub fn from_area(area: Area) -> CppArea {
-        // FIXME: avoid this copy
-        CppArea::from_bytes(area.as_bytes())
+        let bytes = area.as_bytes();
+        if let Some(details) = area.details().downcast_ref::<CppDetails>() {
+            // The area may be a slice of the C++ area.
+            let raw = ffi::Area {
+                size: bytes.len(),
+                buf: bytes.as_ptr() as *mut c_char,
+                details: details.details,
+                __align: [],
+            };
+            unsafe {
+                ffi::rust_shim_area_ref(&raw);
+            }
+            return CppArea::from_raw(raw);
+        }
+        let details = raw_details(area.details());
+        let raw = call_ffi_maybe_panic(|out| unsafe {
+            ffi::rust_shim_area_from_rust(
+                bytes.as_ptr() as *const c_char,
+                bytes.len(),
+                details,
+                out,
+            )
+        });
+        CppArea::from_raw(raw)
     }
 
     pub fn into_raw(self) -> ffi::Area {
@@ -44,17 +70,39 @@ impl CppArea {
     pub fn as_ptr_mut(&mut self) -> *mut ffi::Area {
         &mut self.0
     }
+
+    /// The Rust area wrapped by this area, if it was created by
+    /// `from_area`.
+    fn rust_area(&self) -> Option<Area> {
+        let details = unsafe {
+            let mut raw = MaybeUninit::<ffi::RustDetails>::uninit();
+            if !ffi::rust_shim_area_rust_details(self.as_ptr(), raw.as_mut_ptr()) {
+                return None;
+            }
+            ptr::read(raw.as_ptr() as *const DetailsStack)
+        };
+        // Take our own reference; the C++ area keeps its one.
+        details.increment();
+        let area = Area::new(details);
+        // The C++ area may refer to only part of the Rust buffer.
+        let offset = self.0.buf as usize - area.as_bytes().as_ptr() as usize;
+        Some(area.slice(offset..offset + self.0.size))
+    }
 }
 
 impl Into<Area> for CppArea {
+    /// Areas which came from Rust are unwrapped.
     fn into(self) -> Area {
-        Area::new(self)
+        match self.rust_area() {
+            Some(area) => area,
+            None => Area::new(self),
+        }
     }
 }
 
 impl DetailsConstructor for CppArea {
     fn details(self) -> DetailsStack {
-        Rc::new(self).details()
+        DetailsStack::from(CppDetails::from_raw(self.into_raw()))
     }
 }
 
@@ -71,3 +119,81 @@ impl Drop for CppArea {
         }
     }
 }
+
+/// Details holding the C++ `shared_ptr` of a C++ area, so that C++ areas
+/// can be used from Rust without copying.
+///
+/// Each copy is one reference on the `shared_ptr`.
+#[derive(Copy, Clone)]
+struct CppDetails {
+    size: usize,
+    buf: *const c_char,
+    details: [u8; 16],
+    __align: [u64; 0],
+}
+
+impl CppDetails {
+    fn from_raw(raw: ffi::Area) -> CppDetails {
+        CppDetails {
+            size: raw.size,
+            buf: raw.buf,
+            details: raw.details,
+            __align: [],
+        }
+    }
+
+    fn to_raw(&self) -> ffi::Area {
+        ffi::Area {
+            size: self.size,
+            buf: self.buf as *mut c_char,
+            details: self.details,
+            __align: [],
+        }
+    }
+}
+
+impl Details for CppDetails {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.buf as *const u8, self.size) }
+    }
+
+    fn increment(&self) {
+        unsafe {
+            ffi::rust_shim_area_ref(&self.to_raw());
+        }
+    }
+
+    fn decrement(&self) {
+        unsafe {
+            ffi::rust_area_free(&mut self.to_raw());
+        }
+    }
+}
+
+fn raw_details(details: &DetailsStack) -> *const ffi::RustDetails {
+    assert!(mem::size_of::<DetailsStack>() <= mem::size_of::<ffi::RustDetails>());
+    assert!(mem::align_of::<DetailsStack>() <= mem::align_of::<ffi::RustDetails>());
+    details as *const DetailsStack as *const ffi::RustDetails
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_area_details_increment(details: *const ffi::RustDetails) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            assert!(!details.is_null());
+            (*(details as *const DetailsStack)).increment()
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_area_details_decrement(details: *const ffi::RustDetails) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            assert!(!details.is_null());
+            (*(details as *const DetailsStack)).decrement()
+        }),
+    )
+}
@@ -1,12 +1,16 @@
 use ffi;
-use libc::{c_char, c_void};
+use libc::{c_char, c_int, c_void};
 use std::ops;
 
-use call_ffi_maybe_panic;
+use {call_ffi_maybe_panic, call_ffi_result};
 use common::body::CppBody;
 use common::{options, CppArea, CppName, CppVersion};
-use ecap::common::header::{FirstLine, Header};
-use ecap::common::{Area, Body, Message as ConcreteMessage, Name, NamedValueVisitor, Version};
+use ecap::common::header::{
+    FirstLine, FirstLineKind, FirstLineKindMut, Header, RequestLine, StatusLine,
+};
+use ecap::common::{
+    Area, Body, Error, ErrorKind, Message as ConcreteMessage, Name, NamedValueVisitor, Version,
+};
 use host::CppHost;
 
 use erased_ecap::common::header::FirstLine as ErasedFirstLine;
@@ -58,7 +62,8 @@ impl Header for CppHeader {
         }
     }
 
-    fn visit_each<V: NamedValueVisitor>(&self, mut visitor: &mut V) {
+    fn visit_each<V: NamedValueVisitor>(&self, visitor: &mut V) {
+        let mut visitor: &mut dyn NamedValueVisitor = visitor;
         let visitor = &mut visitor;
         unsafe {
             call_ffi_maybe_panic(|_| unsafe {
@@ -79,13 +84,11 @@ impl Header for CppHeader {
         }
     }
 
-    fn parse(&mut self, buf: &Area) -> Result<(), ()> {
+    fn parse(&mut self, buf: &Area) -> Result<(), Error> {
         let cpp_area = CppArea::from_area(buf.clone());
-        // XXX: This throws exceptions
-        unsafe {
-            ffi::rust_shim_header_parse(self.as_ptr_mut(), cpp_area.as_ptr());
-        }
-        Ok(())
+        call_ffi_result(|_: *mut ()| unsafe {
+            ffi::rust_shim_header_parse(self.as_ptr_mut(), cpp_area.as_ptr())
+        }).map_err(|e| Error::new(ErrorKind::Parse, e.message().to_owned()))
     }
 }
 
@@ -108,11 +111,9 @@ impl FirstLine for CppFirstLine {
 
     fn protocol(&self) -> Name {
         unsafe {
-            let raw = call_ffi_maybe_panic(|raw| unsafe {
+            CppName::from_owned(call_ffi_maybe_panic(|raw| unsafe {
                 ffi::rust_shim_first_line_protocol(self.as_ptr(), raw)
-            });
-            // XXX: copy!
-            CppName::from_raw(&raw).to_owned()
+            }))
         }
     }
     fn set_protocol(&mut self, protocol: Name) {
@@ -121,6 +122,103 @@ impl FirstLine for CppFirstLine {
             ffi::rust_shim_first_line_set_protocol(self.as_ptr_mut(), raw.as_ptr());
         }
     }
+
+    fn kind(&self) -> Option<FirstLineKind> {
+        match self.cpp_kind() {
+            ffi::FIRST_LINE_REQUEST => Some(FirstLineKind::Request(self)),
+            ffi::FIRST_LINE_STATUS => Some(FirstLineKind::Status(self)),
+            _ => None,
+        }
+    }
+    fn kind_mut(&mut self) -> Option<FirstLineKindMut> {
+        match self.cpp_kind() {
+            ffi::FIRST_LINE_REQUEST => Some(FirstLineKindMut::Request(self)),
+            ffi::FIRST_LINE_STATUS => Some(FirstLineKindMut::Status(self)),
+            _ => None,
+        }
+    }
+}
+
+impl CppFirstLine {
+    fn cpp_kind(&self) -> c_int {
+        unsafe {
+            call_ffi_maybe_panic(|raw| unsafe { ffi::rust_shim_first_line_kind(self.as_ptr(), raw) })
+        }
+    }
+}
+
+/// Only valid if the C++ first line is a `libecap::RequestLine`; use
+/// `FirstLine::kind` to check.
+impl RequestLine for CppFirstLine {
+    fn uri(&self) -> Area {
+        unsafe {
+            CppArea::from_raw(call_ffi_maybe_panic(|raw| unsafe {
+                ffi::rust_shim_request_line_uri(self.as_ptr(), raw)
+            })).into()
+        }
+    }
+    fn set_uri(&mut self, uri: Area) {
+        let uri = CppArea::from_area(uri);
+        unsafe {
+            call_ffi_maybe_panic(|_| {
+                ffi::rust_shim_request_line_set_uri(self.as_ptr_mut(), uri.as_ptr())
+            })
+        }
+    }
+
+    fn method(&self) -> Name {
+        unsafe {
+            CppName::from_owned(call_ffi_maybe_panic(|raw| unsafe {
+                ffi::rust_shim_request_line_method(self.as_ptr(), raw)
+            }))
+        }
+    }
+    fn set_method(&mut self, method: Name) {
+        let raw = CppName::from_name(&method);
+        unsafe {
+            call_ffi_maybe_panic(|_| {
+                ffi::rust_shim_request_line_set_method(self.as_ptr_mut(), raw.as_ptr())
+            })
+        }
+    }
+}
+
+/// Only valid if the C++ first line is a `libecap::StatusLine`; use
+/// `FirstLine::kind` to check.
+impl StatusLine for CppFirstLine {
+    fn status_code(&self) -> u16 {
+        let code = unsafe {
+            call_ffi_maybe_panic(|raw| unsafe {
+                ffi::rust_shim_status_line_status_code(self.as_ptr(), raw)
+            })
+        };
+        code as u16
+    }
+    fn set_status_code(&mut self, code: u16) {
+        unsafe {
+            call_ffi_maybe_panic(|_| {
+                ffi::rust_shim_status_line_set_status_code(self.as_ptr_mut(), c_int::from(code))
+            })
+        }
+    }
+
+    // libecap's reason phrase is an area rather than a name.
+    fn reason_phrase(&self) -> Name {
+        let area: Area = unsafe {
+            CppArea::from_raw(call_ffi_maybe_panic(|raw| unsafe {
+                ffi::rust_shim_status_line_reason_phrase(self.as_ptr(), raw)
+            })).into()
+        };
+        Name::new_known(area.as_bytes().to_vec())
+    }
+    fn set_reason_phrase(&mut self, phrase: Name) {
+        let phrase = CppArea::from_bytes(phrase.image().unwrap_or(b""));
+        unsafe {
+            call_ffi_maybe_panic(|_| {
+                ffi::rust_shim_status_line_set_reason_phrase(self.as_ptr_mut(), phrase.as_ptr())
+            })
+        }
+    }
 }
 
 impl ConcreteMessage<CppHost> for CppMessage {
@@ -161,10 +259,8 @@ impl ConcreteMessage<CppHost> for CppMessage {
             }))
         }
     }
-    fn add_body(&mut self) {
-        unsafe {
-            call_ffi_maybe_panic(|_| unsafe { ffi::rust_shim_message_add_body(self.as_ptr_mut()) })
-        }
+    fn add_body(&mut self) -> Result<(), Error> {
+        call_ffi_result(|_: *mut ()| unsafe { ffi::rust_shim_message_add_body(self.as_ptr_mut()) })
     }
     fn body_mut(&mut self) -> Option<&mut CppBody> {
         unsafe {
@@ -190,10 +286,10 @@ impl ConcreteMessage<CppHost> for CppMessage {
             }
         }
     }
-    fn add_trailer(&mut self) {
-        unsafe {
-            ffi::rust_shim_message_add_trailer(self.as_ptr_mut());
-        }
+    fn add_trailer(&mut self) -> Result<(), Error> {
+        call_ffi_result(|_: *mut ()| unsafe {
+            ffi::rust_shim_message_add_trailer(self.as_ptr_mut())
+        })
     }
     fn trailer_mut(&mut self) -> &mut CppHeader {
         unsafe {
@@ -229,7 +325,7 @@ impl ConcreteMessage<dyn ErasedHost> for CppMessage {
     fn header(&self) -> &(dyn ErasedHeader + 'static) {
         <Self as ConcreteMessage<CppHost>>::header(self)
     }
-    fn add_body(&mut self) {
+    fn add_body(&mut self) -> Result<(), Error> {
         <Self as ConcreteMessage<CppHost>>::add_body(self)
     }
     fn body_mut(&mut self) -> Option<&mut (dyn Body + 'static)> {
@@ -244,7 +340,7 @@ impl ConcreteMessage<dyn ErasedHost> for CppMessage {
             None => None,
         }
     }
-    fn add_trailer(&mut self) {
+    fn add_trailer(&mut self) -> Result<(), Error> {
         <Self as ConcreteMessage<CppHost>>::add_trailer(self)
     }
     fn trailer_mut(&mut self) -> &mut (dyn ErasedHeader + 'static) {
@@ -309,7 +405,7 @@ impl ConcreteMessage<CppHost> for SharedPtrMessage {
     fn header(&self) -> &CppHeader {
         <CppMessage as ConcreteMessage<CppHost>>::header(self)
     }
-    fn add_body(&mut self) {
+    fn add_body(&mut self) -> Result<(), Error> {
         <CppMessage as ConcreteMessage<CppHost>>::add_body(self)
     }
     fn body_mut(&mut self) -> Option<&mut CppBody> {
@@ -318,7 +414,7 @@ impl ConcreteMessage<CppHost> for SharedPtrMessage {
     fn body(&self) -> Option<&CppBody> {
         <CppMessage as ConcreteMessage<CppHost>>::body(self)
     }
-    fn add_trailer(&mut self) {
+    fn add_trailer(&mut self) -> Result<(), Error> {
         <CppMessage as ConcreteMessage<CppHost>>::add_trailer(self)
     }
     fn trailer_mut(&mut self) -> &mut CppHeader {
@@ -353,7 +449,7 @@ impl ConcreteMessage<dyn ErasedHost> for SharedPtrMessage {
     fn header(&self) -> &(dyn ErasedHeader + 'static) {
         <CppMessage as ConcreteMessage<dyn ErasedHost>>::header(<Self as ops::Deref>::deref(self))
     }
-    fn add_body(&mut self) {
+    fn add_body(&mut self) -> Result<(), Error> {
         <CppMessage as ConcreteMessage<dyn ErasedHost>>::add_body(
             <Self as ops::DerefMut>::deref_mut(self),
         )
@@ -366,7 +462,7 @@ impl ConcreteMessage<dyn ErasedHost> for SharedPtrMessage {
     fn body(&self) -> Option<&(dyn Body + 'static)> {
         <CppMessage as ConcreteMessage<dyn ErasedHost>>::body(<Self as ops::Deref>::deref(self))
     }
-    fn add_trailer(&mut self) {
+    fn add_trailer(&mut self) -> Result<(), Error> {
         <CppMessage as ConcreteMessage<dyn ErasedHost>>::add_trailer(
             <Self as ops::DerefMut>::deref_mut(self),
         )
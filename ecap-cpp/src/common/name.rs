@@ -1,9 +1,61 @@
-use ecap::common::name::{Id, Name};
+use ecap::common::name::{Id, Name, FIRST_PREDEFINED_ID, PREDEFINED_COUNT};
 use ffi;
 use libc::{c_char, c_int};
+use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::{mem, ptr, slice, str};
 
+/// The ids libecap assigned to the predefined names, which need not
+/// match ours.
+struct PredefinedIds {
+    cpp: Vec<c_int>,
+    rust: HashMap<c_int, u32>,
+}
+
+lazy_static! {
+    static ref PREDEFINED_IDS: PredefinedIds = {
+        let mut cpp = vec![0; PREDEFINED_COUNT];
+        let count = unsafe { ffi::rust_shim_predefined_name_ids(cpp.as_mut_ptr(), cpp.len()) };
+        assert_eq!(count, PREDEFINED_COUNT, "predefined names differ from libecap's");
+        let rust = cpp.iter()
+            .enumerate()
+            .map(|(i, &id)| (id, FIRST_PREDEFINED_ID + i as u32))
+            .collect();
+        PredefinedIds { cpp, rust }
+    };
+}
+
+fn to_cpp_id(id: Id) -> c_int {
+    match id {
+        Id::Unknown => 0,
+        Id::Unidentified => 1,
+        // Only the predefined names have ids in common with libecap;
+        // other names are compared by image.
+        Id::Id(id) => id.checked_sub(FIRST_PREDEFINED_ID)
+            .and_then(|index| PREDEFINED_IDS.cpp.get(index as usize))
+            .map_or(1, |&id| id),
+    }
+}
+
+fn from_cpp_id(id: c_int) -> Id {
+    match id {
+        0 => Id::Unknown,
+        1 => Id::Unidentified,
+        other => PREDEFINED_IDS
+            .rust
+            .get(&other)
+            .map_or(Id::Unidentified, |&id| Id::Id(id)),
+    }
+}
+
+fn from_cpp_host_id(host_id: c_int) -> Option<u32> {
+    if host_id == c_int::min_value() {
+        None
+    } else {
+        Some(host_id as u32)
+    }
+}
+
 pub struct CppName<'a: 'b, 'b> {
     cpp: ffi::Name,
     name: PhantomData<&'b Name<'a>>,
@@ -21,11 +73,7 @@ impl<'a, 'b> CppName<'a, 'b> {
                 },
                 host_id: name.host_id()
                     .map_or(c_int::min_value(), |hid| hid as c_int),
-                id: match name.id() {
-                    Id::Unknown => 0,
-                    Id::Unidentified => 1,
-                    Id::Id(id) => id as i32,
-                },
+                id: to_cpp_id(name.id()),
                 phantom: PhantomData,
             };
 
@@ -39,20 +87,19 @@ impl<'a, 'b> CppName<'a, 'b> {
     pub fn from_raw(name: &'a ffi::Name) -> Name<'a> {
         unsafe {
             let image = slice::from_raw_parts(name.image.buf as *const u8, name.image.size);
-            let id = match name.id {
-                0 => Id::Unknown,
-                1 => Id::Unidentified,
-                other => Id::Id(other as u32),
-            };
-            let host_id = if name.host_id == c_int::min_value() {
-                None
-            } else {
-                Some(name.host_id as u32)
-            };
-            Name::from_raw(image, id, host_id)
+            Name::from_raw(image, from_cpp_id(name.id), from_cpp_host_id(name.host_id))
         }
     }
 
+    /// Takes a name returned by value from C++.
+    pub fn from_owned(name: ffi::OwnedName) -> Name<'static> {
+        Name::from_raw(
+            name.image.to_rust(),
+            from_cpp_id(name.id),
+            from_cpp_host_id(name.host_id),
+        )
+    }
+
     pub fn as_ptr(&self) -> *const ffi::Name {
         &self.cpp
     }
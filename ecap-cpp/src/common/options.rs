@@ -20,6 +20,8 @@ impl Options for CppOptions {
     }
 
     fn visit_each<V: NamedValueVisitor>(&self, mut visitor: V) {
+        // visitor_callback expects a pointer to a trait object.
+        let mut visitor: &mut dyn NamedValueVisitor = &mut visitor;
         let visitor_ptr = &mut visitor;
         call_ffi_maybe_panic(|_: *mut ()| unsafe {
             ffi::options_visit(
@@ -38,6 +40,6 @@ pub extern "C" fn visitor_callback(name: ffi::Name, area: ffi::Area, cb: *mut c_
         let visitor = &mut **(cb as *mut *mut dyn NamedValueVisitor);
 
         let name = CppName::from_raw(&name);
-        visitor.visit(&name, &Area::new(CppArea::from_raw(area)));
+        visitor.visit(&name, &CppArea::from_raw(area).into());
     }
 }
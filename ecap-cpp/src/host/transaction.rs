@@ -4,8 +4,8 @@ use std::mem::ManuallyDrop;
 use std::panic;
 use std::{mem, ptr, slice};
 
-use ecap::common::{Area, Delay};
-use {call_ffi_maybe_panic, ffi_unwind};
+use ecap::common::{Area, Delay, Error, ErrorKind};
+use {call_ffi_maybe_panic, call_ffi_result, ffi_unwind};
 
 use common::message::{CppMessage, SharedPtrMessage};
 use common::CppArea;
@@ -50,17 +50,23 @@ impl ErasedTransaction<dyn ErasedHost> for CppTransaction {
     fn use_virgin(&mut self) {
         <CppTransaction as ConcreteTransaction<CppHost>>::use_virgin(self)
     }
-    fn use_adapted(&mut self, msg: Box<dyn ErasedMessage>) {
+    fn use_adapted(&mut self, msg: Box<dyn ErasedMessage>) -> Result<(), Error> {
         match msg.downcast::<Box<dyn ErasedMessage>>() {
             Ok(msg) => match msg.downcast::<SharedPtrMessage>() {
                 Ok(msg) => {
                     <Self as ConcreteTransaction<CppHost>>::use_adapted::<SharedPtrMessage>(
                         self, *msg,
-                    );
+                    )
                 }
-                Err(_) => panic!("use_adapted should be called with result of clone"),
+                Err(_) => Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "use_adapted should be called with result of clone",
+                )),
             },
-            Err(_) => panic!("use_adapted should be called with result of clone, boxed once"),
+            Err(_) => Err(Error::new(
+                ErrorKind::Unsupported,
+                "use_adapted should be called with result of clone, boxed once",
+            )),
         }
     }
     fn block_virgin(&mut self) {
@@ -149,17 +155,20 @@ impl ConcreteTransaction<CppHost> for CppTransaction {
             ffi::rust_shim_host_xaction_use_virgin(self.as_ptr_mut())
         });
     }
-    fn use_adapted<M: 'static + ConcreteMessage<CppHost>>(&mut self, msg: M) {
+    fn use_adapted<M: 'static + ConcreteMessage<CppHost>>(&mut self, msg: M) -> Result<(), Error> {
         let v: &::std::any::Any = &msg;
         if let Some(shared_ptr_ref) = v.downcast_ref::<SharedPtrMessage>() {
-            call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            call_ffi_result(|_: *mut ()| unsafe {
                 ffi::rust_shim_host_xaction_use_adapted(
                     self.as_ptr_mut(),
                     <SharedPtrMessage>::as_ptr(shared_ptr_ref),
                 )
             })
         } else {
-            panic!("CppTransaction only works with Box<SharedPtrMessage>");
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "CppTransaction only works with Box<SharedPtrMessage>",
+            ))
         }
     }
     fn block_virgin(&mut self) {
@@ -233,7 +242,7 @@ impl ConcreteTransaction<CppHost> for CppTransaction {
         let raw = call_ffi_maybe_panic(|area| unsafe {
             ffi::rust_shim_host_xaction_vb_content(self.as_ptr_mut(), offset, size, area)
         });
-        Area::new(CppArea::from_raw(raw))
+        CppArea::from_raw(raw).into()
     }
 
     fn virgin_body_content_shift(&mut self, size: usize) {
@@ -279,7 +288,6 @@ unsafe fn to_transaction_mut<'a>(
 macro_rules! transaction_mut_method {
     ($c:ident, $method:ident) => {
         #[no_mangle]
-        #[unwind(aborts)]
         pub unsafe extern "C" fn $c(
             mut data: TransactionPtr,
             host: *mut ffi::HostTransaction,
@@ -310,7 +318,6 @@ transaction_mut_method!(
 );
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_xaction_ab_content(
     mut data: TransactionPtr,
     host: *mut ffi::HostTransaction,
@@ -330,7 +337,6 @@ pub unsafe extern "C" fn rust_xaction_ab_content(
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_xaction_ab_content_shift(
     mut data: TransactionPtr,
     host: *mut ffi::HostTransaction,
@@ -346,7 +352,6 @@ pub unsafe extern "C" fn rust_xaction_ab_content_shift(
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_xaction_vb_content_done(
     mut data: TransactionPtr,
     host: *mut ffi::HostTransaction,
@@ -362,7 +367,6 @@ pub unsafe extern "C" fn rust_xaction_vb_content_done(
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_xaction_create(
     mut service: ServicePtr,
     host: *mut ffi::HostTransaction,
@@ -383,7 +387,6 @@ pub unsafe extern "C" fn rust_xaction_create(
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_xaction_free(transaction: TransactionPtr) -> bool {
     ffi_unwind(
         &mut (),
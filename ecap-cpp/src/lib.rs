@@ -1,4 +1,3 @@
-#![feature(unwind_attributes, used)]
 #![allow(unused)]
 extern crate crossbeam;
 extern crate ecap;
@@ -47,21 +46,25 @@ macro_rules! foreign_ref {
 pub mod adapter;
 pub mod common;
 pub mod host;
+pub mod rust_host;
 
 use ecap::adapter::Service;
 use ecap::host::Host;
 use libc::{c_int, c_void};
 use std::any::Any;
+use std::ffi::CString;
 use std::ptr;
 
+use ecap::common::{Error, ErrorKind, Version};
 use ecap::Translator;
 use erased_ecap::adapter::Service as ErasedService;
 use erased_ecap::host::Host as ErasedHost;
+use host::CppHost;
 
 struct CppTranslator;
 
 impl Translator for CppTranslator {
-    fn register_service<H, T>(&self, service: T)
+    fn register_service<H, T>(&self, ecap_version: Version, service: T)
     where
         H: Host + ?Sized,
         T: Service<H> + 'static,
@@ -74,10 +77,12 @@ impl Translator for CppTranslator {
             let service_any: &Any = &service;
             assert!(service_any.is::<Box<dyn ErasedService<dyn ErasedHost>>>());
         }
+        // The host, not us, decides whether it supports the version.
+        let version = CString::new(ecap_version.to_string()).unwrap();
         let thin_ptr = Box::into_raw(Box::new(service));
         unsafe {
             assert!(call_ffi_maybe_panic(|raw| unsafe {
-                ffi::rust_shim_register_service(thin_ptr as *mut *mut c_void, raw)
+                ffi::rust_shim_register_service(thin_ptr as *mut *mut c_void, version.as_ptr(), raw)
             }));
         }
     }
@@ -109,9 +114,9 @@ fn panic_hook(info: &PanicInfo) {
         s.clone()
     } else if let Some(s) = info.payload().downcast_ref::<&'static str>() {
         String::from(*s)
-    } else if let Some(s) = info.payload().downcast_ref::<CppError>() {
+    } else if let Some(e) = info.payload().downcast_ref::<CppError>() {
         is_exception = true;
-        String::from("C++ exception")
+        e.0.clone()
     } else {
         String::from("unknown payload")
     };
@@ -147,11 +152,22 @@ impl PanicPayload {
     }
 }
 
-// This is intended to signal in a panic that the error occurred in C++...
-struct CppError;
+// This is intended to signal in a panic that the error occurred in C++,
+// with the message of the C++ exception.
+struct CppError(String);
+
+fn cpp_exception_message() -> String {
+    unsafe {
+        let mut raw = MaybeUninit::<ffi::CVec>::uninit();
+        if ffi::rust_shim_exception_message(raw.as_mut_ptr()) {
+            String::from_utf8_lossy(&raw.assume_init().to_rust()).into_owned()
+        } else {
+            String::from("unknown C++ exception")
+        }
+    }
+}
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_panic_pop(panic: *mut ffi::Panic) -> bool {
     // This code should be panic-free as they will not be properly handled by it.
     let next = match PANICS.try_pop() {
@@ -165,7 +181,6 @@ pub unsafe extern "C" fn rust_panic_pop(panic: *mut ffi::Panic) -> bool {
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub extern "C" fn rust_panic_free(panic: ffi::Panic) {
     // We are just dropping vectors here which should be panic-free; this is std
     // code, not user code.
@@ -187,19 +202,37 @@ where
     }
 }
 
-use std::mem::{self, ManuallyDrop};
+use std::mem::MaybeUninit;
 
 pub fn call_ffi_maybe_panic<F, R>(f: F) -> R
 where
     F: FnOnce(*mut R) -> bool,
 {
     unsafe {
-        let mut raw: ManuallyDrop<R> = ManuallyDrop::new(mem::uninitialized());
-        let res = f(&mut *raw);
+        let mut raw = MaybeUninit::<R>::uninit();
+        let res = f(raw.as_mut_ptr());
         if res {
-            ManuallyDrop::into_inner(raw)
+            raw.assume_init()
+        } else {
+            panic!(::CppError(cpp_exception_message()));
+        }
+    }
+}
+
+/// Like `call_ffi_maybe_panic`, but a C++ exception is returned as an
+/// `ErrorKind::Host` error instead of unwinding.
+pub fn call_ffi_result<F, R>(f: F) -> Result<R, Error>
+where
+    F: FnOnce(*mut R) -> bool,
+{
+    unsafe {
+        let mut raw = MaybeUninit::<R>::uninit();
+        if f(raw.as_mut_ptr()) {
+            Ok(raw.assume_init())
         } else {
-            panic!(::CppError);
+            let message = cpp_exception_message();
+            ffi::rust_shim_exception_discard();
+            Err(Error::new(ErrorKind::Host, message))
         }
     }
 }
@@ -207,8 +240,19 @@ where
 pub extern "C" fn on_load() {
     panic::set_hook(Box::new(panic_hook));
     ecap_common_link::register_erased_translator(CppTranslator);
+
+    // C++ hosts register with libecap before loading adapters; make the
+    // host available to Rust adapters as well, unless a Rust host
+    // already did.
+    if ecap_common_link::erased_host().is_none() {
+        if let Ok(host) = call_ffi_result(|out| unsafe { ffi::rust_host(out) }) {
+            let host: &'static CppHost = unsafe { CppHost::from_ptr(host) };
+            ecap_common_link::register_erased_host(host);
+        }
+    }
 }
 
-#[link_section = ".ctors"]
 #[used]
+#[cfg_attr(not(target_os = "macos"), link_section = ".init_array")]
+#[cfg_attr(target_os = "macos", link_section = "__DATA,__mod_init_func")]
 pub static __ON_LOAD_PTR: extern "C" fn() = on_load;
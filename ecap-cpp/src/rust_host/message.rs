@@ -0,0 +1,452 @@
+use ffi;
+use libc::{c_char, c_int};
+use std::{panic, ptr};
+
+use ecap::common::header::{
+    FirstLine, FirstLineKind, FirstLineKindMut, RequestLine, StatusLine,
+};
+use ecap::common::{Area, Body, Name, NamedValueVisitor};
+use erased_ecap::common::header::Header as ErasedHeader;
+use erased_ecap::common::Message as ErasedMessage;
+use erased_ecap::common::Options as ErasedOptions;
+
+use common::{CppArea, CppName, CppVersion};
+use {call_ffi_maybe_panic, ffi_unwind};
+
+use super::{from_dyn, throw_error, to_dyn};
+
+unsafe fn to_message<'a>(msg: ffi::DynPtr) -> &'a mut dyn ErasedMessage {
+    &mut *from_dyn::<dyn ErasedMessage>(msg)
+}
+
+unsafe fn to_header<'a>(header: ffi::DynPtr) -> &'a mut dyn ErasedHeader {
+    &mut *from_dyn::<dyn ErasedHeader>(header)
+}
+
+unsafe fn to_first_line<'a>(line: ffi::DynPtr) -> &'a mut dyn FirstLine {
+    &mut *from_dyn::<dyn FirstLine>(line)
+}
+
+unsafe fn to_request_line<'a>(line: ffi::DynPtr) -> &'a mut dyn RequestLine {
+    match to_first_line(line).kind_mut() {
+        Some(FirstLineKindMut::Request(line)) => line,
+        _ => panic!("first line is not a request line"),
+    }
+}
+
+unsafe fn to_status_line<'a>(line: ffi::DynPtr) -> &'a mut dyn StatusLine {
+    match to_first_line(line).kind_mut() {
+        Some(FirstLineKindMut::Status(line)) => line,
+        _ => panic!("first line is not a status line"),
+    }
+}
+
+/// Forwards each visited value to a C++ `NamedValueVisitor`.
+struct CppVisitor(*mut ffi::NamedValueVisitor);
+
+impl NamedValueVisitor for CppVisitor {
+    fn visit(&mut self, name: &Name, value: &Area) {
+        let name = CppName::from_name(name);
+        let value = value.as_bytes();
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_visitor_visit(
+                self.0,
+                name.as_ptr(),
+                value.as_ptr() as *const c_char,
+                value.len(),
+            )
+        });
+    }
+}
+
+fn empty_area() -> Area {
+    Area::from_bytes(&[])
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_message_clone(msg: ffi::DynPtr, out: *mut ffi::DynPtr) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            let clone = to_message(msg).clone();
+            to_dyn(Box::into_raw(clone) as *const dyn ErasedMessage)
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_message_free(msg: ffi::DynPtr) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let _: Box<dyn ErasedMessage> = Box::from_raw(from_dyn::<dyn ErasedMessage>(msg));
+        }),
+    )
+}
+
+macro_rules! message_part {
+    ($c:ident, $c_mut:ident, $method:ident, $method_mut:ident, $part:ty) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn $c(msg: ffi::DynPtr, out: *mut ffi::DynPtr) -> bool {
+            ffi_unwind(
+                out,
+                panic::AssertUnwindSafe(|| to_dyn(to_message(msg).$method() as *const $part)),
+            )
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $c_mut(msg: ffi::DynPtr, out: *mut ffi::DynPtr) -> bool {
+            ffi_unwind(
+                out,
+                panic::AssertUnwindSafe(|| {
+                    to_dyn(to_message(msg).$method_mut() as *const $part)
+                }),
+            )
+        }
+    };
+}
+
+message_part!(
+    rust_message_first_line,
+    rust_message_first_line_mut,
+    first_line,
+    first_line_mut,
+    dyn FirstLine
+);
+message_part!(
+    rust_message_header,
+    rust_message_header_mut,
+    header,
+    header_mut,
+    dyn ErasedHeader
+);
+message_part!(
+    rust_message_trailer,
+    rust_message_trailer_mut,
+    trailer,
+    trailer_mut,
+    dyn ErasedHeader
+);
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_message_body(msg: ffi::DynPtr, out: *mut ffi::DynPtr) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| match to_message(msg).body() {
+            Some(body) => to_dyn(body as *const dyn Body),
+            None => ffi::DynPtr::null(),
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_message_body_mut(msg: ffi::DynPtr, out: *mut ffi::DynPtr) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| match to_message(msg).body_mut() {
+            Some(body) => to_dyn(body as *const dyn Body),
+            None => ffi::DynPtr::null(),
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_message_add_body(msg: ffi::DynPtr) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| throw_error(to_message(msg).add_body())),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_message_add_trailer(msg: ffi::DynPtr) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| throw_error(to_message(msg).add_trailer())),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_header_has_any(
+    header: ffi::DynPtr,
+    name: *const ffi::Name,
+    out: *mut bool,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| to_header(header).contains_field(&CppName::from_raw(&*name))),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_header_value(
+    header: ffi::DynPtr,
+    name: *const ffi::Name,
+    out: *mut ffi::Area,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            let value = to_header(header).get(&CppName::from_raw(&*name));
+            CppArea::from_area(value.unwrap_or_else(empty_area)).into_raw()
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_header_add(
+    header: ffi::DynPtr,
+    name: *const ffi::Name,
+    value: ffi::Area,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let value: Area = CppArea::from_raw(value).into();
+            to_header(header).insert(CppName::from_raw(&*name), value);
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_header_remove_any(
+    header: ffi::DynPtr,
+    name: *const ffi::Name,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| to_header(header).remove_any(&CppName::from_raw(&*name))),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_header_visit_each(
+    header: ffi::DynPtr,
+    visitor: *mut ffi::NamedValueVisitor,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| to_header(header).visit_each(&mut CppVisitor(visitor))),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_header_image(header: ffi::DynPtr, out: *mut ffi::Area) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| CppArea::from_area(to_header(header).image()).into_raw()),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_header_parse(header: ffi::DynPtr, buf: ffi::Area) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let buf: Area = CppArea::from_raw(buf).into();
+            throw_error(to_header(header).parse(&buf));
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_first_line_version(
+    line: ffi::DynPtr,
+    out: *mut ffi::Version,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| CppVersion::to_raw(to_first_line(line).version())),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_first_line_set_version(
+    line: ffi::DynPtr,
+    version: *const ffi::Version,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let version = CppVersion::from_raw(ptr::read(version));
+            to_first_line(line).set_version(version);
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_first_line_protocol(
+    line: ffi::DynPtr,
+    out: *mut ffi::NameObject,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let protocol = to_first_line(line).protocol();
+            let protocol = CppName::from_name(&protocol);
+            call_ffi_maybe_panic(|_: *mut ()| ffi::rust_shim_name_assign(out, protocol.as_ptr()));
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_first_line_set_protocol(
+    line: ffi::DynPtr,
+    protocol: *const ffi::Name,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| to_first_line(line).set_protocol(CppName::from_raw(&*protocol))),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_first_line_kind(line: ffi::DynPtr, out: *mut c_int) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| match to_first_line(line).kind() {
+            Some(FirstLineKind::Request(_)) => ffi::FIRST_LINE_REQUEST,
+            Some(FirstLineKind::Status(_)) => ffi::FIRST_LINE_STATUS,
+            None => ffi::FIRST_LINE_OTHER,
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_request_line_uri(line: ffi::DynPtr, out: *mut ffi::Area) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| CppArea::from_area(to_request_line(line).uri()).into_raw()),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_request_line_set_uri(line: ffi::DynPtr, uri: ffi::Area) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let uri: Area = CppArea::from_raw(uri).into();
+            to_request_line(line).set_uri(uri);
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_request_line_method(
+    line: ffi::DynPtr,
+    out: *mut ffi::NameObject,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let method = to_request_line(line).method();
+            let method = CppName::from_name(&method);
+            call_ffi_maybe_panic(|_: *mut ()| ffi::rust_shim_name_assign(out, method.as_ptr()));
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_request_line_set_method(
+    line: ffi::DynPtr,
+    method: *const ffi::Name,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| to_request_line(line).set_method(CppName::from_raw(&*method))),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_status_line_status_code(line: ffi::DynPtr, out: *mut c_int) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| c_int::from(to_status_line(line).status_code())),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_status_line_set_status_code(line: ffi::DynPtr, code: c_int) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            assert!(code >= 0 && code <= c_int::from(u16::max_value()), "invalid status code");
+            to_status_line(line).set_status_code(code as u16);
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_status_line_reason_phrase(
+    line: ffi::DynPtr,
+    out: *mut ffi::Area,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            let phrase = to_status_line(line).reason_phrase();
+            CppArea::from_bytes(phrase.image().unwrap_or(b"")).into_raw()
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_status_line_set_reason_phrase(
+    line: ffi::DynPtr,
+    phrase: ffi::Area,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let phrase: Area = CppArea::from_raw(phrase).into();
+            to_status_line(line).set_reason_phrase(Name::new_known(phrase.as_bytes()));
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_body_size(body: ffi::DynPtr, out: *mut ffi::BodySize) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            let body = &*from_dyn::<dyn Body>(body);
+            match body.size() {
+                Some(size) => ffi::BodySize { known: true, size },
+                None => ffi::BodySize {
+                    known: false,
+                    size: 0,
+                },
+            }
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_options_option(
+    options: ffi::DynPtr,
+    name: *const ffi::Name,
+    out: *mut ffi::Area,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            let options = &*from_dyn::<dyn ErasedOptions>(options);
+            let value = options.option(&CppName::from_raw(&*name));
+            CppArea::from_area(value.unwrap_or_else(empty_area)).into_raw()
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_options_visit(
+    options: ffi::DynPtr,
+    visitor: *mut ffi::NamedValueVisitor,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let options = &*from_dyn::<dyn ErasedOptions>(options);
+            options.visit_each(&mut CppVisitor(visitor));
+        }),
+    )
+}
@@ -0,0 +1,186 @@
+//! Hosts written in Rust, driving adapters written in C++.
+//!
+//! A Rust host is installed into libecap with `register_host`. Any C++
+//! adapter library loaded afterwards registers its services with
+//! libecap, which passes them on to us. Services the host accepts, via
+//! `Host::note_versioned_service`, can then be retrieved with
+//! `take_services` and used like any other Rust service.
+//!
+//! libecap permits only a single host per process.
+
+use ffi;
+use libc::c_char;
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::fmt::Write;
+use std::{mem, panic, ptr, slice};
+
+use ecap::common::log::{FrequencyLevel, ImportanceLevel, LogVerbosity, MessageSizeLevel};
+use ecap::common::{Error, Version};
+use erased_ecap::common::log::DebugStream as ErasedDebugStream;
+use erased_ecap::common::Message as ErasedMessage;
+use erased_ecap::host::Host as ErasedHost;
+
+use {call_ffi_maybe_panic, ffi_unwind};
+
+mod message;
+mod service;
+mod transaction;
+
+pub use self::service::{CppAdapterTransaction, CppService};
+
+thread_local! {
+    static NOTED_SERVICES: RefCell<Vec<CppService>> = RefCell::new(Vec::new());
+}
+
+/// Install `host` as the libecap host.
+///
+/// This must be called before any C++ adapter library is loaded, and
+/// only once per process.
+pub fn register_host(host: Box<dyn ErasedHost>) {
+    let host: &'static dyn ErasedHost = Box::leak(host);
+    call_ffi_maybe_panic(|_: *mut ()| unsafe {
+        ffi::rust_shim_register_host(to_dyn(host as *const dyn ErasedHost))
+    });
+}
+
+/// Services registered by C++ adapters, and accepted by the host, since
+/// the last call.
+pub fn take_services() -> Vec<CppService> {
+    NOTED_SERVICES.with(|services| mem::replace(&mut *services.borrow_mut(), Vec::new()))
+}
+
+/// Pass a trait object pointer to C++.
+fn to_dyn<T: ?Sized>(ptr: *const T) -> ffi::DynPtr {
+    assert_eq!(mem::size_of::<*const T>(), mem::size_of::<ffi::DynPtr>());
+    unsafe { mem::transmute_copy(&ptr) }
+}
+
+/// Unwind with `result`'s error, if any, which C++ then sees as an
+/// exception carrying the error's message.
+fn throw_error(result: Result<(), Error>) {
+    if let Err(err) = result {
+        panic!("{}", err);
+    }
+}
+
+/// Recover a trait object pointer previously passed to C++ by `to_dyn`.
+unsafe fn from_dyn<T: ?Sized>(ptr: ffi::DynPtr) -> *mut T {
+    assert_eq!(mem::size_of::<*mut T>(), mem::size_of::<ffi::DynPtr>());
+    assert!(!ptr.is_null());
+    mem::transmute_copy(&ptr)
+}
+
+unsafe fn to_host<'a>(host: ffi::DynPtr) -> &'a dyn ErasedHost {
+    &*from_dyn::<dyn ErasedHost>(host)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_uri(host: ffi::DynPtr, out: *mut ffi::CVec) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| ffi::CVec::from(to_host(host).uri())),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_describe(host: ffi::DynPtr, out: *mut ffi::CVec) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| ffi::CVec::from(to_host(host).describe())),
+    )
+}
+
+/// Logs a refused service through the host; throwing would abort the
+/// adapter library's static constructors instead.
+fn report_refused_service(host: &dyn ErasedHost, error: Error) {
+    let verbosity = LogVerbosity {
+        importance: ImportanceLevel::Critical,
+        frequency: FrequencyLevel::Application,
+        size: MessageSizeLevel::Normal,
+    };
+    if let Some(mut stream) = host.open_debug(verbosity) {
+        let _ = write!(stream, "refusing C++ eCAP service: {}", error);
+        host.close_debug(stream);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_note_versioned_service(
+    host: ffi::DynPtr,
+    version: *const c_char,
+    service: *mut ffi::SharedPtr,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            assert!(!version.is_null());
+            assert!(!service.is_null());
+            let host = to_host(host);
+            let version = Version::parse(&CStr::from_ptr(version).to_string_lossy());
+            // Dropping a refused service only releases our reference;
+            // libecap keeps its own, so no adapter code runs.
+            let service = CppService::from_raw(ptr::read(service), version);
+            match host.note_versioned_service(version) {
+                Ok(()) => NOTED_SERVICES.with(|services| services.borrow_mut().push(service)),
+                Err(e) => report_refused_service(host, e),
+            }
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_open_debug(
+    host: ffi::DynPtr,
+    verbosity: ffi::LogVerbosity,
+    out: *mut ffi::DynPtr,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            match to_host(host).open_debug(LogVerbosity::from_mask(verbosity.0)) {
+                Some(stream) => to_dyn(Box::into_raw(stream) as *const dyn ErasedDebugStream),
+                None => ffi::DynPtr::null(),
+            }
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_close_debug(
+    host: ffi::DynPtr,
+    stream: ffi::DynPtr,
+    buf: *const c_char,
+    len: usize,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let mut stream: Box<dyn ErasedDebugStream> =
+                Box::from_raw(from_dyn::<dyn ErasedDebugStream>(stream));
+            let contents = slice::from_raw_parts(buf as *const u8, len);
+            let _ = stream.write_str(&String::from_utf8_lossy(contents));
+            to_host(host).close_debug(stream);
+        }),
+    )
+}
+
+fn message_to_dyn(message: Box<dyn ErasedMessage>) -> ffi::DynPtr {
+    to_dyn(Box::into_raw(message) as *const dyn ErasedMessage)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_new_request(host: ffi::DynPtr, out: *mut ffi::DynPtr) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| message_to_dyn(to_host(host).new_request())),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_new_response(host: ffi::DynPtr, out: *mut ffi::DynPtr) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| message_to_dyn(to_host(host).new_response())),
+    )
+}
@@ -0,0 +1,352 @@
+use ffi;
+use libc::{c_void, suseconds_t, time_t, timeval};
+use std::ffi::CStr;
+use std::ptr;
+use std::time::Duration;
+
+use ecap::adapter::{Registered, Registry, Service, Transaction, WakeHandle};
+use ecap::common::{Area, Error, ErrorKind, Name, NamedValueVisitor, Options, Version};
+use erased_ecap::common::Options as ErasedOptions;
+use erased_ecap::host::Host as ErasedHost;
+use erased_ecap::host::Transaction as ErasedTransaction;
+
+use {call_ffi_maybe_panic, call_ffi_result};
+use common::options::CppOptions;
+use common::CppArea;
+
+use super::to_dyn;
+
+type HostTransaction = dyn ErasedTransaction<dyn ErasedHost>;
+
+fn host_to_dyn(host: &mut HostTransaction) -> ffi::DynPtr {
+    to_dyn(host as *const HostTransaction)
+}
+
+fn to_string(v: ffi::CVec) -> String {
+    String::from_utf8_lossy(&v.to_rust()).into_owned()
+}
+
+/// C++ adapters reject their configuration by throwing.
+fn config_error(error: Error) -> Error {
+    Error::new(ErrorKind::Config, error.message().to_owned())
+}
+
+/// A service registered by a C++ adapter.
+///
+/// Its transactions are registered with the service from `start` until
+/// `stop`, so that the host transaction calls the adapter makes outside
+/// of a call from the host (e.g., from `Service::resume`) reach the host
+/// once it resumes them.
+pub struct CppService {
+    raw: ffi::SharedPtr,
+    version: Version,
+    registry: Registry,
+}
+
+impl CppService {
+    pub(super) fn from_raw(raw: ffi::SharedPtr, version: Version) -> CppService {
+        CppService {
+            raw,
+            version,
+            registry: Registry::new(),
+        }
+    }
+
+    fn as_ptr_mut(&self) -> *mut ffi::SharedPtr {
+        &self.raw as *const ffi::SharedPtr as *mut ffi::SharedPtr
+    }
+}
+
+impl Drop for CppService {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::rust_shim_adapter_service_free(&mut self.raw);
+        }
+    }
+}
+
+impl Service<dyn ErasedHost> for CppService {
+    type Transaction = Registered<CppAdapterTransaction>;
+
+    fn uri(&self) -> String {
+        to_string(call_ffi_maybe_panic(|out| unsafe {
+            ffi::rust_shim_adapter_service_uri(self.as_ptr_mut(), out)
+        }))
+    }
+
+    fn tag(&self) -> String {
+        to_string(call_ffi_maybe_panic(|out| unsafe {
+            ffi::rust_shim_adapter_service_tag(self.as_ptr_mut(), out)
+        }))
+    }
+
+    fn describe(&self) -> String {
+        to_string(call_ffi_maybe_panic(|out| unsafe {
+            ffi::rust_shim_adapter_service_describe(self.as_ptr_mut(), out)
+        }))
+    }
+
+    /// The version the adapter registered with.
+    fn libecap_version(&self) -> Version {
+        self.version
+    }
+
+    fn is_async(&self) -> bool {
+        call_ffi_maybe_panic(|out| unsafe {
+            ffi::rust_shim_adapter_service_is_async(self.as_ptr_mut(), out)
+        })
+    }
+
+    fn configure<T: Options>(&mut self, options: &T) -> Result<(), Error> {
+        let options: &dyn ErasedOptions = options;
+        call_ffi_result(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_service_configure(
+                self.as_ptr_mut(),
+                to_dyn(options as *const dyn ErasedOptions),
+            )
+        }).map_err(config_error)
+    }
+
+    fn reconfigure<T: Options>(&mut self, options: &T) -> Result<(), Error> {
+        let options: &dyn ErasedOptions = options;
+        call_ffi_result(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_service_reconfigure(
+                self.as_ptr_mut(),
+                to_dyn(options as *const dyn ErasedOptions),
+            )
+        }).map_err(config_error)
+    }
+
+    fn start(&self) {
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_service_start(self.as_ptr_mut())
+        });
+    }
+
+    fn suspend(&self, timeout: &mut Duration) {
+        let mut raw = timeval {
+            tv_sec: timeout.as_secs() as time_t,
+            tv_usec: timeout.subsec_micros() as suseconds_t,
+        };
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_service_suspend(self.as_ptr_mut(), &mut raw)
+        });
+        *timeout = Duration::from_secs(raw.tv_sec as u64) + Duration::from_micros(raw.tv_usec as u64);
+        // The adapter alone knows when it will next call its host
+        // transactions, so only pending wakeups shorten the timeout.
+        if self.registry.has_woken() {
+            *timeout = Duration::from_secs(0);
+        }
+    }
+
+    fn resume(&self) {
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_service_resume(self.as_ptr_mut())
+        });
+        self.registry.resume();
+    }
+
+    fn stop(&self) {
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_service_stop(self.as_ptr_mut())
+        });
+    }
+
+    fn retire(&self) {
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_service_retire(self.as_ptr_mut())
+        });
+    }
+
+    fn wants_url(&self, url: &CStr) -> bool {
+        call_ffi_maybe_panic(|out| unsafe {
+            ffi::rust_shim_adapter_service_wants_url(self.as_ptr_mut(), url.as_ptr(), out)
+        })
+    }
+
+    fn make_transaction(
+        &mut self,
+        host: &mut HostTransaction,
+    ) -> Registered<CppAdapterTransaction> {
+        let mut hostx = ptr::null_mut();
+        let xaction = call_ffi_maybe_panic(|out| unsafe {
+            ffi::rust_shim_adapter_service_make_xaction(
+                self.as_ptr_mut(),
+                host_to_dyn(host),
+                &mut hostx,
+                out,
+            )
+        });
+        self.registry.transaction(CppAdapterTransaction {
+            xaction,
+            hostx,
+            registry: self.registry.clone(),
+            waker: None,
+        })
+    }
+}
+
+/// A transaction created by a C++ adapter.
+///
+/// The host transaction passed to each method is made available to the
+/// adapter for the duration of that call. Calls the adapter makes into
+/// its host transaction at other times, between `start` and `stop`, are
+/// queued and delivered the next time the host calls into the
+/// transaction; the service asks the host to resume it from
+/// `Service::resume`. Calls returning a value, such as `virgin()` or
+/// `vbContent()`, cannot wait, and throw instead.
+pub struct CppAdapterTransaction {
+    xaction: ffi::SharedPtr,
+    hostx: *mut ffi::RustHostTransaction,
+    registry: Registry,
+    /// Boxed for C++, which holds on to it from `start` until `stop`.
+    waker: Option<Box<WakeHandle>>,
+}
+
+impl CppAdapterTransaction {
+    fn options(&self) -> &CppOptions {
+        unsafe {
+            let raw = call_ffi_maybe_panic(|out| unsafe {
+                ffi::rust_shim_adapter_xaction_options(
+                    &self.xaction as *const ffi::SharedPtr as *mut ffi::SharedPtr,
+                    out,
+                )
+            });
+            CppOptions::from_ptr(raw)
+        }
+    }
+}
+
+impl Drop for CppAdapterTransaction {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::rust_shim_adapter_xaction_free(&mut self.xaction, self.hostx);
+        }
+    }
+}
+
+impl Options for CppAdapterTransaction {
+    fn option(&self, name: &Name) -> Option<Area> {
+        Options::option(self.options(), name)
+    }
+
+    fn visit_each<V: NamedValueVisitor>(&self, visitor: V) {
+        Options::visit_each(self.options(), visitor)
+    }
+}
+
+macro_rules! adapter_transaction_method {
+    ($method:ident, $c:ident) => {
+        fn $method<'a>(&mut self, host: &'a mut HostTransaction)
+        where
+            HostTransaction: 'a,
+        {
+            call_ffi_maybe_panic(|_: *mut ()| unsafe {
+                ffi::$c(&mut self.xaction, self.hostx, host_to_dyn(host))
+            })
+        }
+    };
+}
+
+impl Transaction<dyn ErasedHost> for CppAdapterTransaction {
+    fn start<'a>(&mut self, host: &'a mut HostTransaction)
+    where
+        HostTransaction: 'a,
+    {
+        // The service registered the transaction as it started.
+        if let Some(handle) = self.registry.handle::<dyn ErasedHost>(host) {
+            let waker = Box::new(handle);
+            unsafe {
+                ffi::rust_shim_rust_host_xaction_set_waker(
+                    self.hostx,
+                    &*waker as *const WakeHandle as *const c_void,
+                );
+            }
+            self.waker = Some(waker);
+        }
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_xaction_start(&mut self.xaction, self.hostx, host_to_dyn(host))
+        })
+    }
+
+    fn stop<'a>(&mut self, host: &'a mut HostTransaction)
+    where
+        HostTransaction: 'a,
+    {
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_xaction_stop(&mut self.xaction, self.hostx, host_to_dyn(host))
+        });
+        unsafe {
+            ffi::rust_shim_rust_host_xaction_set_waker(self.hostx, ptr::null());
+        }
+        self.waker = None;
+    }
+
+    adapter_transaction_method!(resume, rust_shim_adapter_xaction_resume);
+    adapter_transaction_method!(adapted_body_discard, rust_shim_adapter_xaction_ab_discard);
+    adapter_transaction_method!(adapted_body_make, rust_shim_adapter_xaction_ab_make);
+    adapter_transaction_method!(
+        adapted_body_make_more,
+        rust_shim_adapter_xaction_ab_make_more
+    );
+    adapter_transaction_method!(
+        adapted_body_stop_making,
+        rust_shim_adapter_xaction_ab_stop_making
+    );
+    adapter_transaction_method!(adapted_body_pause, rust_shim_adapter_xaction_ab_pause);
+    adapter_transaction_method!(adapted_body_resume, rust_shim_adapter_xaction_ab_resume);
+    adapter_transaction_method!(
+        virgin_body_content_available,
+        rust_shim_adapter_xaction_vb_content_available
+    );
+
+    fn adapted_body_content<'a>(
+        &mut self,
+        host: &'a mut HostTransaction,
+        offset: usize,
+        size: usize,
+    ) -> Area
+    where
+        HostTransaction: 'a,
+    {
+        let raw = call_ffi_maybe_panic(|out| unsafe {
+            ffi::rust_shim_adapter_xaction_ab_content(
+                &mut self.xaction,
+                self.hostx,
+                host_to_dyn(host),
+                offset,
+                size,
+                out,
+            )
+        });
+        CppArea::from_raw(raw).into()
+    }
+
+    fn adapted_body_content_shift<'a>(&mut self, host: &'a mut HostTransaction, size: usize)
+    where
+        HostTransaction: 'a,
+    {
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_xaction_ab_content_shift(
+                &mut self.xaction,
+                self.hostx,
+                host_to_dyn(host),
+                size,
+            )
+        })
+    }
+
+    fn virgin_body_content_done<'a>(&mut self, host: &'a mut HostTransaction, at_end: bool)
+    where
+        HostTransaction: 'a,
+    {
+        call_ffi_maybe_panic(|_: *mut ()| unsafe {
+            ffi::rust_shim_adapter_xaction_vb_content_done(
+                &mut self.xaction,
+                self.hostx,
+                host_to_dyn(host),
+                at_end,
+            )
+        })
+    }
+}
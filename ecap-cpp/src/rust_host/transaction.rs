@@ -0,0 +1,177 @@
+use ffi;
+use libc::{c_char, c_void};
+use std::borrow::Cow;
+use std::{panic, slice};
+
+use ecap::adapter::WakeHandle;
+use ecap::common::{Area, Delay};
+use erased_ecap::common::Message as ErasedMessage;
+use erased_ecap::host::Host as ErasedHost;
+use erased_ecap::host::Transaction as ErasedTransaction;
+
+use common::CppArea;
+use ffi_unwind;
+
+use super::{from_dyn, throw_error, to_dyn};
+
+type HostTransaction = dyn ErasedTransaction<dyn ErasedHost>;
+
+unsafe fn to_transaction<'a>(xaction: ffi::DynPtr) -> &'a mut HostTransaction {
+    &mut *from_dyn::<HostTransaction>(xaction)
+}
+
+macro_rules! host_transaction_method {
+    ($c:ident, $method:ident) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn $c(xaction: ffi::DynPtr) -> bool {
+            ffi_unwind(
+                &mut (),
+                panic::AssertUnwindSafe(|| to_transaction(xaction).$method()),
+            )
+        }
+    };
+}
+
+host_transaction_method!(rust_host_xaction_use_virgin, use_virgin);
+host_transaction_method!(rust_host_xaction_block_virgin, block_virgin);
+host_transaction_method!(rust_host_xaction_adaptation_aborted, adaptation_aborted);
+host_transaction_method!(rust_host_xaction_resume, resume);
+host_transaction_method!(rust_host_xaction_vb_discard, virgin_body_discard);
+host_transaction_method!(rust_host_xaction_vb_make, virgin_body_make);
+host_transaction_method!(rust_host_xaction_vb_stop_making, virgin_body_stop_making);
+host_transaction_method!(rust_host_xaction_vb_make_more, virgin_body_make_more);
+host_transaction_method!(rust_host_xaction_vb_pause, virgin_body_pause);
+host_transaction_method!(rust_host_xaction_vb_resume, virgin_body_resume);
+host_transaction_method!(
+    rust_host_xaction_note_ab_content_available,
+    adapted_body_content_available
+);
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_xaction_virgin(
+    xaction: ffi::DynPtr,
+    out: *mut ffi::DynPtr,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            to_dyn(to_transaction(xaction).virgin() as *const dyn ErasedMessage)
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_xaction_cause(
+    xaction: ffi::DynPtr,
+    out: *mut ffi::DynPtr,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            to_dyn(to_transaction(xaction).cause() as *const dyn ErasedMessage)
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_xaction_adapted(
+    xaction: ffi::DynPtr,
+    out: *mut ffi::DynPtr,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            to_dyn(to_transaction(xaction).adapted() as *const dyn ErasedMessage)
+        }),
+    )
+}
+
+/// Takes ownership of `msg`, which must have been created by this host.
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_xaction_use_adapted(
+    xaction: ffi::DynPtr,
+    msg: ffi::DynPtr,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let msg: Box<dyn ErasedMessage> = Box::from_raw(from_dyn::<dyn ErasedMessage>(msg));
+            throw_error(to_transaction(xaction).use_adapted(msg));
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_xaction_adaptation_delayed(
+    xaction: ffi::DynPtr,
+    state: *const c_char,
+    state_len: usize,
+    progress: f64,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            let state = slice::from_raw_parts(state as *const u8, state_len);
+            let delay = Delay {
+                progress: if progress < 0.0 { None } else { Some(progress) },
+                description: if state.is_empty() {
+                    None
+                } else {
+                    Some(Cow::Owned(String::from_utf8_lossy(state).into_owned()))
+                },
+            };
+            to_transaction(xaction).adaptation_delayed(&delay);
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_xaction_vb_content(
+    xaction: ffi::DynPtr,
+    offset: usize,
+    size: usize,
+    out: *mut ffi::Area,
+) -> bool {
+    ffi_unwind(
+        out,
+        panic::AssertUnwindSafe(|| {
+            let area: Area = to_transaction(xaction).virgin_body_content(offset, size);
+            CppArea::from_area(area).into_raw()
+        }),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_xaction_vb_content_shift(
+    xaction: ffi::DynPtr,
+    size: usize,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| to_transaction(xaction).virgin_body_content_shift(size)),
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_xaction_note_ab_content_done(
+    xaction: ffi::DynPtr,
+    at_end: bool,
+) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| to_transaction(xaction).adapted_body_content_done(at_end)),
+    )
+}
+
+/// Called when the adapter uses its host transaction outside of a call
+/// from the host; the calls are delivered once the host resumes it.
+#[no_mangle]
+pub unsafe extern "C" fn rust_host_xaction_wake(waker: *const c_void) -> bool {
+    ffi_unwind(
+        &mut (),
+        panic::AssertUnwindSafe(|| {
+            assert!(!waker.is_null());
+            (*(waker as *const WakeHandle)).wake();
+        }),
+    )
+}
@@ -0,0 +1,101 @@
+// A minimal async libecap adapter for the rust_host tests.
+//
+// Transactions wait in start() until the service is resumed, which asks
+// the host to resume them from outside of any host call, as adapters
+// finishing work on other threads do. Once resumed, transactions
+// adapt the virgin message by adding an X-Async header.
+
+#include <libecap/common/area.h>
+#include <libecap/common/message.h>
+#include <libecap/common/header.h>
+#include <libecap/common/name.h>
+#include <libecap/common/registry.h>
+#include <libecap/adapter/service.h>
+#include <libecap/adapter/xaction.h>
+#include <libecap/host/xaction.h>
+#include <sys/time.h>
+#include <vector>
+
+namespace Adapter {
+
+class Xaction;
+
+class Service: public libecap::adapter::Service {
+    public:
+        virtual std::string uri() const { return "ecap://rust/test/cpp-async"; }
+        virtual std::string tag() const { return "0"; }
+        virtual void describe(std::ostream &os) const { os << "async C++ test adapter"; }
+
+        virtual void configure(const libecap::Options &) {}
+        virtual void reconfigure(const libecap::Options &) {}
+        virtual void start() {}
+        virtual void stop() {}
+        virtual void retire() {}
+
+        virtual bool wantsUrl(const char *) const { return true; }
+
+        virtual bool makesAsyncXactions() const { return true; }
+        virtual void suspend(timeval &timeout);
+        virtual void resume();
+
+        virtual MadeXactionPointer makeXaction(libecap::host::Xaction *hostx);
+
+        // Transactions which have started, and not been resumed since.
+        std::vector<libecap::host::Xaction *> waiting;
+};
+
+class Xaction: public libecap::adapter::Xaction {
+    public:
+        Xaction(Service &service, libecap::host::Xaction *hostx): service(service), hostx(hostx) {}
+
+        virtual const libecap::Area option(const libecap::Name &) const { return libecap::Area(); }
+        virtual void visitEachOption(libecap::NamedValueVisitor &) const {}
+
+        virtual void start() { service.waiting.push_back(hostx); }
+        virtual void stop() {}
+        virtual void resume();
+
+        virtual void abDiscard() {}
+        virtual void abMake() {}
+        virtual void abMakeMore() {}
+        virtual void abStopMaking() {}
+        virtual libecap::Area abContent(libecap::size_type, libecap::size_type) { return libecap::Area(); }
+        virtual void abContentShift(libecap::size_type) {}
+
+        virtual void noteVbContentDone(bool) {}
+        virtual void noteVbContentAvailable() {}
+
+    private:
+        Service &service;
+        libecap::host::Xaction *hostx;
+};
+
+} // namespace Adapter
+
+void Adapter::Service::suspend(timeval &timeout) {
+    if (!waiting.empty()) {
+        timeout.tv_sec = 0;
+        timeout.tv_usec = 0;
+    }
+}
+
+void Adapter::Service::resume() {
+    std::vector<libecap::host::Xaction *> ready;
+    ready.swap(waiting);
+    for (auto hostx: ready) {
+        hostx->resume();
+    }
+}
+
+Adapter::Service::MadeXactionPointer Adapter::Service::makeXaction(libecap::host::Xaction *hostx) {
+    return MadeXactionPointer(new Adapter::Xaction(*this, hostx));
+}
+
+void Adapter::Xaction::resume() {
+    auto adapted = hostx->virgin().clone();
+    adapted->header().add(libecap::Name("X-Async"), libecap::Area::FromTempString("yes"));
+    hostx->useAdapted(adapted);
+}
+
+static const bool Registered =
+    libecap::RegisterVersionedService(new Adapter::Service);
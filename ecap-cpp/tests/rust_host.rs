@@ -0,0 +1,79 @@
+//! Drives a C++ adapter through the in-memory host.
+//!
+//! The adapter, in `adapter_async.cc`, is built with the system C++
+//! compiler against the installed libecap.
+
+extern crate ecap;
+extern crate ecap_cpp;
+extern crate ecap_host;
+extern crate erased_ecap;
+extern crate libc;
+
+use std::env;
+use std::fs;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use ecap::adapter::Service;
+use ecap::common::header::Header;
+use ecap::common::{Message, Name};
+use ecap_cpp::rust_host;
+use ecap_host::{Decision, MemHost, MemMessage, MemTransaction};
+use erased_ecap::host::Host as ErasedHost;
+
+fn build_adapter() -> PathBuf {
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/adapter_async.cc");
+    let out = env::temp_dir().join(format!("ecap-cpp-adapter-async-{}.so", process::id()));
+    let status = Command::new(env::var("CXX").unwrap_or_else(|_| "c++".to_owned()))
+        .args(["-std=c++11", "-shared", "-fPIC", "-o"])
+        .arg(&out)
+        .arg(&source)
+        .arg("-lecap")
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to build {}", source.display());
+    out
+}
+
+/// Loads the adapter, whose services register with the host as the
+/// library's static constructors run.
+fn load_adapter(path: &Path) {
+    let path = CString::new(path.to_str().unwrap()).unwrap();
+    let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW) };
+    assert!(!handle.is_null(), "failed to load {:?}", path);
+}
+
+#[test]
+fn async_adapter_resumes_outside_host_calls() {
+    rust_host::register_host(Box::new(MemHost::new()));
+    let adapter = build_adapter();
+    load_adapter(&adapter);
+    let _ = fs::remove_file(&adapter);
+
+    let mut services = rust_host::take_services();
+    assert_eq!(services.len(), 1);
+    let mut service = services.pop().unwrap();
+    assert_eq!(
+        Service::<dyn ErasedHost>::uri(&service),
+        "ecap://rust/test/cpp-async"
+    );
+    assert!(Service::<dyn ErasedHost>::is_async(&service));
+
+    Service::<dyn ErasedHost>::start(&service);
+    let transaction = MemTransaction::new(MemMessage::request(
+        &b"GET"[..],
+        &b"http://example.com/"[..],
+    ));
+    // The adapter asks for the transaction to be resumed from
+    // `Service::resume`, outside of any call into the transaction.
+    let outcome = transaction.run::<dyn ErasedHost, _>(&mut service);
+    Service::<dyn ErasedHost>::stop(&service);
+
+    assert_eq!(outcome.decision(), Decision::Adapted);
+    assert!(outcome.is_complete());
+    let value = Message::<MemHost>::header(outcome.message().unwrap())
+        .get(&Name::new_known(&b"X-Async"[..]))
+        .unwrap();
+    assert_eq!(value.as_bytes(), b"yes");
+}
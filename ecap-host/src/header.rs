@@ -0,0 +1,357 @@
+use std::fmt;
+
+use ecap::common::header::{
+    FirstLine, FirstLineKind, FirstLineKindMut, Header, RequestLine, StatusLine,
+};
+use ecap::common::{Area, Error, ErrorKind, Name, NamedValueVisitor, Version};
+
+/// Header fields, stored in insertion order.
+///
+/// Field names are compared by id if both names are identified, and
+/// otherwise case-insensitively by image, as HTTP requires.
+#[derive(Debug, Clone, Default)]
+pub struct MemHeader {
+    fields: Vec<(Name<'static>, Area)>,
+}
+
+fn same_name(a: &Name, b: &Name) -> bool {
+    if a.identified() && b.identified() {
+        return a.id() == b.id();
+    }
+    match (a.image(), b.image()) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        _ => false,
+    }
+}
+
+impl MemHeader {
+    pub fn new() -> MemHeader {
+        MemHeader { fields: Vec::new() }
+    }
+
+    /// Number of fields, counting repeated names separately.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Iterate over the fields in insertion order.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a Name<'static>, &'a Area)> + 'a {
+        self.fields.iter().map(|&(ref name, ref value)| (name, value))
+    }
+
+    /// Write the fields in HTTP/1.1 wire format, one per line.
+    ///
+    /// The blank line terminating the header is not included.
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        for &(ref name, ref value) in &self.fields {
+            out.extend_from_slice(name.image().unwrap_or(b""));
+            out.extend_from_slice(b": ");
+            out.extend_from_slice(value.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+}
+
+impl Header for MemHeader {
+    fn contains_field(&self, field: &Name) -> bool {
+        self.fields.iter().any(|&(ref name, _)| same_name(name, field))
+    }
+
+    fn get(&self, field: &Name) -> Option<Area> {
+        let mut values = self.fields
+            .iter()
+            .filter(|&&(ref name, _)| same_name(name, field))
+            .map(|&(_, ref value)| value);
+        let first = values.next()?;
+        let mut joined = match values.next() {
+            // Avoid copying in the common single-value case.
+            None => return Some(first.clone()),
+            Some(second) => {
+                let mut joined = first.as_bytes().to_vec();
+                joined.extend_from_slice(b", ");
+                joined.extend_from_slice(second.as_bytes());
+                joined
+            }
+        };
+        for value in values {
+            joined.extend_from_slice(b", ");
+            joined.extend_from_slice(value.as_bytes());
+        }
+        Some(Area::from_bytes(&joined))
+    }
+
+    fn insert(&mut self, field: Name, value: Area) {
+        self.fields.push((field.to_owned(), value));
+    }
+
+    fn remove_any(&mut self, field: &Name) {
+        self.fields.retain(|&(ref name, _)| !same_name(name, field));
+    }
+
+    fn visit_each<V: NamedValueVisitor>(&self, visitor: &mut V) {
+        for &(ref name, ref value) in &self.fields {
+            visitor.visit(name, value);
+        }
+    }
+
+    fn image(&self) -> Area {
+        let mut out = Vec::new();
+        self.write_to(&mut out);
+        Area::from_bytes(&out)
+    }
+
+    /// Replaces all fields with those parsed from `buf`.
+    ///
+    /// Parsing stops at the first empty line; obsolete line folding is
+    /// not supported.
+    fn parse(&mut self, buf: &Area) -> Result<(), Error> {
+        let mut fields = Vec::new();
+        for line in buf.as_bytes().split(|b| *b == b'\n') {
+            let line = if line.last() == Some(&b'\r') {
+                &line[..line.len() - 1]
+            } else {
+                line
+            };
+            if line.is_empty() {
+                break;
+            }
+            let colon = line.iter()
+                .position(|b| *b == b':')
+                .ok_or_else(|| Error::new(ErrorKind::Parse, "header field without a colon"))?;
+            let name = &line[..colon];
+            if name.is_empty() || name.iter().any(|b| b.is_ascii_whitespace()) {
+                return Err(Error::new(
+                    ErrorKind::InvalidName,
+                    "empty header field name or name with whitespace",
+                ));
+            }
+            let value = trim(&line[colon + 1..]);
+            fields.push((Name::new_known(name.to_vec()), Area::from_bytes(value)));
+        }
+        self.fields = fields;
+        Ok(())
+    }
+}
+
+pub(crate) fn trim(mut value: &[u8]) -> &[u8] {
+    while let Some((first, rest)) = value.split_first() {
+        if *first != b' ' && *first != b'\t' {
+            break;
+        }
+        value = rest;
+    }
+    while let Some((last, rest)) = value.split_last() {
+        if *last != b' ' && *last != b'\t' {
+            break;
+        }
+        value = rest;
+    }
+    value
+}
+
+/// Writes a version as `major.minor`, e.g. `1.1`.
+pub(crate) struct DisplayVersion(pub Version);
+
+impl fmt::Display for DisplayVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}",
+            self.0.major.unwrap_or(1),
+            self.0.minor.unwrap_or(0)
+        )
+    }
+}
+
+/// The request line of a request, e.g. `GET / HTTP/1.1`.
+#[derive(Debug, Clone)]
+pub struct MemRequestLine {
+    pub version: Version,
+    pub protocol: Name<'static>,
+    pub method: Name<'static>,
+    pub uri: Area,
+}
+
+/// The status line of a response, e.g. `HTTP/1.1 200 OK`.
+#[derive(Debug, Clone)]
+pub struct MemStatusLine {
+    pub version: Version,
+    pub protocol: Name<'static>,
+    pub status_code: u16,
+    pub reason_phrase: Name<'static>,
+}
+
+/// The first line of a message: requests and responses differ only
+/// in the first line.
+#[derive(Debug, Clone)]
+pub enum MemFirstLine {
+    Request(MemRequestLine),
+    Status(MemStatusLine),
+}
+
+impl MemFirstLine {
+    pub fn is_request(&self) -> bool {
+        match *self {
+            MemFirstLine::Request(_) => true,
+            MemFirstLine::Status(_) => false,
+        }
+    }
+
+    /// Write the line in HTTP/1.1 wire format, including the CRLF.
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        match *self {
+            MemFirstLine::Request(ref line) => {
+                out.extend_from_slice(line.method.image().unwrap_or(b""));
+                out.push(b' ');
+                out.extend_from_slice(line.uri.as_bytes());
+                out.push(b' ');
+                out.extend_from_slice(line.protocol.image().unwrap_or(b"HTTP"));
+                out.extend_from_slice(format!("/{}", DisplayVersion(line.version)).as_bytes());
+            }
+            MemFirstLine::Status(ref line) => {
+                out.extend_from_slice(line.protocol.image().unwrap_or(b"HTTP"));
+                out.extend_from_slice(
+                    format!("/{} {} ", DisplayVersion(line.version), line.status_code).as_bytes(),
+                );
+                out.extend_from_slice(line.reason_phrase.image().unwrap_or(b""));
+            }
+        }
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+impl FirstLine for MemFirstLine {
+    fn version(&self) -> Version {
+        match *self {
+            MemFirstLine::Request(ref line) => line.version,
+            MemFirstLine::Status(ref line) => line.version,
+        }
+    }
+
+    fn set_version(&mut self, version: Version) {
+        match *self {
+            MemFirstLine::Request(ref mut line) => line.version = version,
+            MemFirstLine::Status(ref mut line) => line.version = version,
+        }
+    }
+
+    fn protocol(&self) -> Name {
+        match *self {
+            MemFirstLine::Request(ref line) => line.protocol.clone(),
+            MemFirstLine::Status(ref line) => line.protocol.clone(),
+        }
+    }
+
+    fn set_protocol(&mut self, protocol: Name) {
+        match *self {
+            MemFirstLine::Request(ref mut line) => line.protocol = protocol.to_owned(),
+            MemFirstLine::Status(ref mut line) => line.protocol = protocol.to_owned(),
+        }
+    }
+
+    fn kind(&self) -> Option<FirstLineKind> {
+        Some(match *self {
+            MemFirstLine::Request(ref line) => FirstLineKind::Request(line),
+            MemFirstLine::Status(ref line) => FirstLineKind::Status(line),
+        })
+    }
+
+    fn kind_mut(&mut self) -> Option<FirstLineKindMut> {
+        Some(match *self {
+            MemFirstLine::Request(ref mut line) => FirstLineKindMut::Request(line),
+            MemFirstLine::Status(ref mut line) => FirstLineKindMut::Status(line),
+        })
+    }
+}
+
+impl FirstLine for MemRequestLine {
+    fn version(&self) -> Version {
+        self.version
+    }
+
+    fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    fn protocol(&self) -> Name {
+        self.protocol.clone()
+    }
+
+    fn set_protocol(&mut self, protocol: Name) {
+        self.protocol = protocol.to_owned();
+    }
+
+    fn kind(&self) -> Option<FirstLineKind> {
+        Some(FirstLineKind::Request(self))
+    }
+
+    fn kind_mut(&mut self) -> Option<FirstLineKindMut> {
+        Some(FirstLineKindMut::Request(self))
+    }
+}
+
+impl RequestLine for MemRequestLine {
+    fn uri(&self) -> Area {
+        self.uri.clone()
+    }
+
+    fn set_uri(&mut self, uri: Area) {
+        self.uri = uri;
+    }
+
+    fn method(&self) -> Name {
+        self.method.clone()
+    }
+
+    fn set_method(&mut self, method: Name) {
+        self.method = method.to_owned();
+    }
+}
+
+impl FirstLine for MemStatusLine {
+    fn version(&self) -> Version {
+        self.version
+    }
+
+    fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    fn protocol(&self) -> Name {
+        self.protocol.clone()
+    }
+
+    fn set_protocol(&mut self, protocol: Name) {
+        self.protocol = protocol.to_owned();
+    }
+
+    fn kind(&self) -> Option<FirstLineKind> {
+        Some(FirstLineKind::Status(self))
+    }
+
+    fn kind_mut(&mut self) -> Option<FirstLineKindMut> {
+        Some(FirstLineKindMut::Status(self))
+    }
+}
+
+impl StatusLine for MemStatusLine {
+    fn status_code(&self) -> u16 {
+        self.status_code
+    }
+
+    fn set_status_code(&mut self, code: u16) {
+        self.status_code = code;
+    }
+
+    fn reason_phrase(&self) -> Name {
+        self.reason_phrase.clone()
+    }
+
+    fn set_reason_phrase(&mut self, reason_phrase: Name) {
+        self.reason_phrase = reason_phrase.to_owned();
+    }
+}
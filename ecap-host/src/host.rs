@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use ecap::common::log::{self, ImportanceLevel, LogVerbosity};
+use ecap::host::Host;
+
+use header::{MemFirstLine, MemHeader};
+use message::{MemBody, MemMessage};
+use transaction::MemTransaction;
+
+/// An in-memory host.
+///
+/// Debug output accepted by the host is collected and can be examined
+/// via [`MemHost::debug_log`].
+pub struct MemHost {
+    uri: String,
+    importance: ImportanceLevel,
+    log: RefCell<Vec<String>>,
+}
+
+impl MemHost {
+    /// Create a host which accepts debug output of `Normal` importance
+    /// or higher.
+    pub fn new() -> MemHost {
+        MemHost {
+            uri: String::from("ecap://rust/host/memory"),
+            importance: ImportanceLevel::Normal,
+            log: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Change the least important debug output this host accepts.
+    pub fn set_importance(&mut self, importance: ImportanceLevel) {
+        self.importance = importance;
+    }
+
+    /// All debug messages closed so far, in order.
+    pub fn debug_log(&self) -> Vec<String> {
+        self.log.borrow().clone()
+    }
+}
+
+impl Default for MemHost {
+    fn default() -> MemHost {
+        MemHost::new()
+    }
+}
+
+/// A debug stream which buffers a single message.
+#[derive(Debug)]
+pub struct MemDebugStream {
+    buffer: String,
+}
+
+impl fmt::Write for MemDebugStream {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.buffer.push_str(s);
+        Ok(())
+    }
+}
+
+impl log::DebugStream for MemDebugStream {}
+
+impl Host for MemHost {
+    type DebugStream = MemDebugStream;
+    type Message = MemMessage;
+    type MessageRef = MemMessage;
+    type Transaction = MemTransaction;
+    type TransactionRef = MemTransaction;
+    type Body = MemBody;
+    type Header = MemHeader;
+    type FirstLine = MemFirstLine;
+    type Trailer = MemHeader;
+
+    fn uri(&self) -> String {
+        self.uri.clone()
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "in-memory host from {} v{}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+
+    fn open_debug(&self, verbosity: LogVerbosity) -> Option<MemDebugStream> {
+        if verbosity.importance >= self.importance {
+            Some(MemDebugStream {
+                buffer: String::new(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn close_debug(&self, stream: MemDebugStream) {
+        self.log.borrow_mut().push(stream.buffer);
+    }
+
+    fn new_request(&self) -> MemMessage {
+        MemMessage::request(&b"GET"[..], b"/")
+    }
+
+    fn new_response(&self) -> MemMessage {
+        MemMessage::response(200, &b"OK"[..])
+    }
+}
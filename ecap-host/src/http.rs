@@ -0,0 +1,223 @@
+//! Reading and writing whole HTTP/1.1 messages.
+
+use std::error::Error;
+use std::fmt;
+use std::str;
+
+use ecap::common::header::Header;
+use ecap::common::name::{HEADER_CONTENT_LENGTH, HEADER_TRANSFER_ENCODING};
+use ecap::common::{Area, Message, Name, Version};
+
+use header::{trim, MemFirstLine, MemRequestLine, MemStatusLine};
+use host::MemHost;
+use message::MemMessage;
+
+/// Failure to parse an HTTP message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    reason: &'static str,
+}
+
+impl ParseError {
+    fn new(reason: &'static str) -> ParseError {
+        ParseError { reason }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid HTTP message: {}", self.reason)
+    }
+}
+
+impl Error for ParseError {
+    fn description(&self) -> &str {
+        self.reason
+    }
+}
+
+/// Parse a request or response, along with its body.
+///
+/// Chunked bodies are decoded; otherwise `Content-Length` is honored if
+/// present, and the rest of `input` is taken as the body if not. The
+/// header is left as-is.
+///
+/// The body is `None`, and the message is given no body, if the message
+/// has neither framing header nor content after the header.
+pub fn parse(input: &[u8]) -> Result<(MemMessage, Option<Vec<u8>>), ParseError> {
+    let (head, rest) = split_head(input).ok_or_else(|| ParseError::new("missing end of header"))?;
+    let mut message = parse_head(head)?;
+
+    let (body, has_body) = {
+        let header = Message::<MemHost>::header(&message);
+        let chunked = header
+            .get(&HEADER_TRANSFER_ENCODING)
+            .map_or(false, |value| has_token(value.as_bytes(), b"chunked"));
+        let length = header.get(&HEADER_CONTENT_LENGTH);
+        if chunked {
+            (decode_chunked(rest)?, true)
+        } else if let Some(length) = length {
+            let length = str::from_utf8(length.as_bytes())
+                .ok()
+                .and_then(|length| length.trim().parse::<usize>().ok())
+                .ok_or_else(|| ParseError::new("invalid Content-Length"))?;
+            if rest.len() < length {
+                return Err(ParseError::new("body shorter than Content-Length"));
+            }
+            (rest[..length].to_vec(), true)
+        } else {
+            (rest.to_vec(), !rest.is_empty())
+        }
+    };
+    if !has_body {
+        return Ok((message, None));
+    }
+    message.set_body_size(Some(body.len() as u64));
+    Ok((message, Some(body)))
+}
+
+/// Parse the first line and header of a message, without a body.
+///
+/// The blank line ending the header may be omitted.
+pub fn parse_head(head: &[u8]) -> Result<MemMessage, ParseError> {
+    let line_end = head.iter()
+        .position(|b| *b == b'\n')
+        .unwrap_or(head.len());
+    let first_line = parse_first_line(strip_cr(&head[..line_end]))?;
+
+    let mut message = MemMessage::new(first_line);
+    if line_end < head.len() {
+        Message::<MemHost>::header_mut(&mut message)
+            .parse(&Area::from_bytes(&head[line_end + 1..]))
+            .map_err(|_| ParseError::new("malformed header field"))?;
+    }
+    Ok(message)
+}
+
+/// Write `message` followed by `body`.
+///
+/// If the header asks for chunked encoding, the body is written as a
+/// single chunk; it is written unmodified otherwise.
+pub fn write(message: &MemMessage, body: &[u8], out: &mut Vec<u8>) {
+    message.write_head(out);
+    let chunked = Message::<MemHost>::header(message)
+        .get(&HEADER_TRANSFER_ENCODING)
+        .map_or(false, |value| has_token(value.as_bytes(), b"chunked"));
+    if chunked {
+        if !body.is_empty() {
+            out.extend_from_slice(format!("{:x}\r\n", body.len()).as_bytes());
+            out.extend_from_slice(body);
+            out.extend_from_slice(b"\r\n");
+        }
+        out.extend_from_slice(b"0\r\n\r\n");
+    } else {
+        out.extend_from_slice(body);
+    }
+}
+
+/// Split off the header, including the blank line that ends it.
+fn split_head(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut start = 0;
+    while let Some(end) = input[start..].iter().position(|b| *b == b'\n') {
+        let line = strip_cr(&input[start..start + end]);
+        if line.is_empty() {
+            let head = if start == 0 { &input[..0] } else { &input[..start - 1] };
+            return Some((head, &input[start + end + 1..]));
+        }
+        start += end + 1;
+    }
+    None
+}
+
+fn strip_cr(line: &[u8]) -> &[u8] {
+    if line.last() == Some(&b'\r') {
+        &line[..line.len() - 1]
+    } else {
+        line
+    }
+}
+
+fn has_token(value: &[u8], token: &[u8]) -> bool {
+    value
+        .split(|b| *b == b',')
+        .any(|part| trim(part).eq_ignore_ascii_case(token))
+}
+
+fn parse_version(version: &[u8]) -> Result<(Name<'static>, Version), ParseError> {
+    let invalid = || ParseError::new("invalid protocol version");
+    let slash = version.iter().position(|b| *b == b'/').ok_or_else(invalid)?;
+    let (protocol, number) = (&version[..slash], &version[slash + 1..]);
+    let number = str::from_utf8(number).map_err(|_| invalid())?;
+    let mut parts = number.splitn(2, '.');
+    let major = parts.next().and_then(|major| major.parse().ok()).ok_or_else(invalid)?;
+    let minor = parts.next().and_then(|minor| minor.parse().ok()).ok_or_else(invalid)?;
+    Ok((
+        Name::new_known(protocol.to_vec()),
+        Version {
+            major: Some(major),
+            minor: Some(minor),
+            micro: None,
+        },
+    ))
+}
+
+fn parse_first_line(line: &[u8]) -> Result<MemFirstLine, ParseError> {
+    let mut parts = line.splitn(3, |b| *b == b' ');
+    let first = parts.next().unwrap_or(b"");
+    let second = parts.next().ok_or_else(|| ParseError::new("incomplete first line"))?;
+    let third = parts.next().unwrap_or(b"");
+
+    if first.starts_with(b"HTTP/") {
+        let (protocol, version) = parse_version(first)?;
+        let status_code = str::from_utf8(second)
+            .ok()
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| ParseError::new("invalid status code"))?;
+        Ok(MemFirstLine::Status(MemStatusLine {
+            version,
+            protocol,
+            status_code,
+            reason_phrase: Name::new_known(third.to_vec()),
+        }))
+    } else {
+        let (protocol, version) = parse_version(third)?;
+        Ok(MemFirstLine::Request(MemRequestLine {
+            version,
+            protocol,
+            method: Name::new_known(first.to_vec()),
+            uri: Area::from_bytes(second),
+        }))
+    }
+}
+
+fn decode_chunked(mut input: &[u8]) -> Result<Vec<u8>, ParseError> {
+    let truncated = || ParseError::new("truncated chunked body");
+    let mut body = Vec::new();
+    loop {
+        let line_end = input.iter().position(|b| *b == b'\n').ok_or_else(truncated)?;
+        let line = strip_cr(&input[..line_end]);
+        // Chunk extensions are ignored.
+        let size = line.split(|b| *b == b';').next().unwrap_or(b"");
+        let size = str::from_utf8(size)
+            .ok()
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| ParseError::new("invalid chunk size"))?;
+        input = &input[line_end + 1..];
+        if size == 0 {
+            // Trailer fields are not preserved.
+            return Ok(body);
+        }
+        if input.len() < size {
+            return Err(truncated());
+        }
+        body.extend_from_slice(&input[..size]);
+        input = &input[size..];
+        input = if input.starts_with(b"\r\n") {
+            &input[2..]
+        } else if input.starts_with(b"\n") {
+            &input[1..]
+        } else {
+            return Err(ParseError::new("missing end of chunk"));
+        };
+    }
+}
@@ -0,0 +1,31 @@
+//! An in-memory implementation of `ecap::host::Host`.
+//!
+//! This host does not talk to a proxy: messages and bodies are kept in
+//! memory, which makes it possible to drive any adapter service
+//! through the full transaction lifecycle without a C++ host such as
+//! Squid. It is intended primarily for testing adapters.
+//!
+//! ```rust,ignore
+//! let request = MemMessage::request(&b"POST"[..], &b"http://example.com/"[..]);
+//!
+//! let mut transaction = MemTransaction::new(request);
+//! transaction.set_virgin_body(b"hello".to_vec());
+//! let outcome = transaction.run::<MemHost, _>(&mut service);
+//! assert_eq!(outcome.body(), b"hello");
+//! ```
+
+extern crate ecap;
+extern crate erased_ecap;
+
+mod header;
+mod host;
+pub mod http;
+mod message;
+mod options;
+mod transaction;
+
+pub use header::{MemFirstLine, MemHeader, MemRequestLine, MemStatusLine};
+pub use host::{MemDebugStream, MemHost};
+pub use message::{MemBody, MemMessage};
+pub use options::MemOptions;
+pub use transaction::{AsTransactionRef, Decision, MemTransaction, Outcome};
@@ -0,0 +1,195 @@
+use ecap;
+use ecap::common::name::PROTOCOL_HTTP;
+use ecap::common::{Body, Error, Message, Name, Version};
+
+use erased_ecap::common::header::FirstLine as ErasedFirstLine;
+use erased_ecap::common::header::Header as ErasedHeader;
+use erased_ecap::host::Host as ErasedHost;
+
+use header::{MemFirstLine, MemHeader, MemRequestLine, MemStatusLine};
+use host::MemHost;
+
+/// The body of a `MemMessage`.
+///
+/// Only the size is tracked here; content is exchanged through the
+/// transaction, as in libecap.
+#[derive(Debug, Clone, Default)]
+pub struct MemBody {
+    size: Option<u64>,
+}
+
+impl MemBody {
+    pub fn new(size: Option<u64>) -> MemBody {
+        MemBody { size }
+    }
+}
+
+impl Body for MemBody {
+    fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    fn set_size(&mut self, size: Option<u64>) -> Result<(), Error> {
+        self.size = size;
+        Ok(())
+    }
+}
+
+/// A request or response held entirely in memory.
+#[derive(Debug, Clone)]
+pub struct MemMessage {
+    first_line: MemFirstLine,
+    header: MemHeader,
+    body: Option<MemBody>,
+    trailer: MemHeader,
+}
+
+fn http_1_1() -> Version {
+    Version {
+        major: Some(1),
+        minor: Some(1),
+        micro: None,
+    }
+}
+
+impl MemMessage {
+    pub fn new(first_line: MemFirstLine) -> MemMessage {
+        MemMessage {
+            first_line,
+            header: MemHeader::new(),
+            body: None,
+            trailer: MemHeader::new(),
+        }
+    }
+
+    /// Create an HTTP/1.1 request without a body.
+    pub fn request<M, U>(method: M, uri: U) -> MemMessage
+    where
+        M: Into<Vec<u8>>,
+        U: AsRef<[u8]>,
+    {
+        MemMessage::new(MemFirstLine::Request(MemRequestLine {
+            version: http_1_1(),
+            protocol: PROTOCOL_HTTP,
+            method: Name::new_known(method.into()),
+            uri: ecap::common::Area::from_bytes(uri.as_ref()),
+        }))
+    }
+
+    /// Create an HTTP/1.1 response without a body.
+    pub fn response<R: Into<Vec<u8>>>(status_code: u16, reason_phrase: R) -> MemMessage {
+        MemMessage::new(MemFirstLine::Status(MemStatusLine {
+            version: http_1_1(),
+            protocol: PROTOCOL_HTTP,
+            status_code,
+            reason_phrase: Name::new_known(reason_phrase.into()),
+        }))
+    }
+
+    /// Add a body of the given size, replacing any existing body.
+    pub fn set_body_size(&mut self, size: Option<u64>) {
+        self.body = Some(MemBody::new(size));
+    }
+
+    /// Write the first line and header in HTTP/1.1 wire format,
+    /// including the blank line which terminates the header.
+    pub fn write_head(&self, out: &mut Vec<u8>) {
+        self.first_line.write_to(out);
+        self.header.write_to(out);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+impl Message<MemHost> for MemMessage {
+    type MessageClone = MemMessage;
+
+    fn clone(&self) -> MemMessage {
+        Clone::clone(self)
+    }
+
+    fn first_line_mut(&mut self) -> &mut MemFirstLine {
+        &mut self.first_line
+    }
+    fn first_line(&self) -> &MemFirstLine {
+        &self.first_line
+    }
+
+    fn header_mut(&mut self) -> &mut MemHeader {
+        &mut self.header
+    }
+    fn header(&self) -> &MemHeader {
+        &self.header
+    }
+
+    fn add_body(&mut self) -> Result<(), Error> {
+        if self.body.is_none() {
+            self.body = Some(MemBody::new(None));
+        }
+        Ok(())
+    }
+    fn body_mut(&mut self) -> Option<&mut MemBody> {
+        self.body.as_mut()
+    }
+    fn body(&self) -> Option<&MemBody> {
+        self.body.as_ref()
+    }
+
+    // The trailer always exists in memory.
+    fn add_trailer(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn trailer_mut(&mut self) -> &mut MemHeader {
+        &mut self.trailer
+    }
+    fn trailer(&self) -> &MemHeader {
+        &self.trailer
+    }
+}
+
+impl Message<dyn ErasedHost> for MemMessage {
+    type MessageClone = MemMessage;
+
+    fn clone(&self) -> MemMessage {
+        Clone::clone(self)
+    }
+
+    fn first_line_mut(&mut self) -> &mut (dyn ErasedFirstLine + 'static) {
+        &mut self.first_line
+    }
+    fn first_line(&self) -> &(dyn ErasedFirstLine + 'static) {
+        &self.first_line
+    }
+
+    fn header_mut(&mut self) -> &mut (dyn ErasedHeader + 'static) {
+        &mut self.header
+    }
+    fn header(&self) -> &(dyn ErasedHeader + 'static) {
+        &self.header
+    }
+
+    fn add_body(&mut self) -> Result<(), Error> {
+        <Self as Message<MemHost>>::add_body(self)
+    }
+    fn body_mut(&mut self) -> Option<&mut (dyn Body + 'static)> {
+        match self.body {
+            Some(ref mut body) => Some(body),
+            None => None,
+        }
+    }
+    fn body(&self) -> Option<&(dyn Body + 'static)> {
+        match self.body {
+            Some(ref body) => Some(body),
+            None => None,
+        }
+    }
+
+    fn add_trailer(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn trailer_mut(&mut self) -> &mut (dyn ErasedHeader + 'static) {
+        &mut self.trailer
+    }
+    fn trailer(&self) -> &(dyn ErasedHeader + 'static) {
+        &self.trailer
+    }
+}
@@ -0,0 +1,52 @@
+use ecap::common::{Area, Name, NamedValueVisitor, Options};
+
+/// Options held in memory, e.g. service configuration.
+///
+/// Names are compared by image; lookups return the first matching
+/// value.
+#[derive(Debug, Clone, Default)]
+pub struct MemOptions {
+    values: Vec<(Name<'static>, Area)>,
+}
+
+impl MemOptions {
+    pub fn new() -> MemOptions {
+        MemOptions { values: Vec::new() }
+    }
+
+    pub fn insert<N, V>(&mut self, name: N, value: V)
+    where
+        N: Into<Vec<u8>>,
+        V: AsRef<[u8]>,
+    {
+        self.values.push((
+            Name::new_known(name.into()),
+            Area::from_bytes(value.as_ref()),
+        ));
+    }
+
+    /// Parse a `name=value` pair, as commonly used on command lines.
+    ///
+    /// Returns `None` if there is no `=`.
+    pub fn insert_pair(&mut self, pair: &str) -> Option<()> {
+        let eq = pair.find('=')?;
+        self.insert(&pair[..eq], &pair[eq + 1..]);
+        Some(())
+    }
+}
+
+impl Options for MemOptions {
+    fn option(&self, name: &Name) -> Option<Area> {
+        let image = name.image()?;
+        self.values
+            .iter()
+            .find(|&&(ref candidate, _)| candidate.image() == Some(image))
+            .map(|&(_, ref value)| value.clone())
+    }
+
+    fn visit_each<V: NamedValueVisitor>(&self, mut visitor: V) {
+        for &(ref name, ref value) in &self.values {
+            visitor.visit(name, value);
+        }
+    }
+}
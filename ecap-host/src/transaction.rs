@@ -0,0 +1,508 @@
+use std::any::Any;
+use std::cmp;
+use std::thread;
+use std::time::Duration;
+
+use ecap::adapter::{Service, Transaction as AdapterTransaction};
+use ecap::common::{Area, Delay, Error, ErrorKind, Message as ConcreteMessage};
+use ecap::host::{Host, Transaction as ConcreteTransaction};
+
+use erased_ecap::common::Message as ErasedMessage;
+use erased_ecap::host::Host as ErasedHost;
+use erased_ecap::host::Transaction as ErasedTransaction;
+
+use host::MemHost;
+use message::MemMessage;
+
+/// How long an async service may ask us to wait between resumptions.
+const MAX_SUSPEND: Duration = Duration::from_millis(100);
+
+/// How many times an async service is resumed without making progress
+/// before the transaction is considered stalled.
+const MAX_IDLE_RESUMES: usize = 50;
+
+/// Which message, if any, the adapter decided the host should use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// The adapter has not decided yet.
+    Pending,
+    /// `use_virgin` was called.
+    Virgin,
+    /// `use_adapted` was called.
+    Adapted,
+    /// `block_virgin` was called.
+    Blocked,
+    /// `adaptation_aborted` was called.
+    Aborted,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BodyState {
+    Undecided,
+    Making,
+    Discarded,
+    Stopped,
+    Done,
+}
+
+/// Hosts whose transaction references can be backed by a `MemTransaction`.
+///
+/// This permits driving both services written against `MemHost`
+/// directly and erased services, such as those registered by adapter
+/// libraries.
+pub trait AsTransactionRef: Host {
+    fn transaction_ref(transaction: &mut MemTransaction) -> &mut Self::TransactionRef;
+}
+
+impl AsTransactionRef for MemHost {
+    fn transaction_ref(transaction: &mut MemTransaction) -> &mut MemTransaction {
+        transaction
+    }
+}
+
+impl AsTransactionRef for dyn ErasedHost {
+    fn transaction_ref(
+        transaction: &mut MemTransaction,
+    ) -> &mut (dyn ErasedTransaction<dyn ErasedHost> + 'static) {
+        transaction
+    }
+}
+
+/// The host side of a single transaction.
+///
+/// The virgin body is held in memory in its entirety, but is revealed
+/// to the adapter in chunks (see [`MemTransaction::set_chunk_size`]),
+/// so that adapters see realistic chunk boundaries.
+pub struct MemTransaction {
+    virgin: MemMessage,
+    cause: Option<MemMessage>,
+    adapted: Option<MemMessage>,
+    decision: Decision,
+    resume_requested: bool,
+    delays: Vec<String>,
+
+    vb_content: Vec<u8>,
+    vb_state: BodyState,
+    vb_paused: bool,
+    vb_revealed: usize,
+    vb_shifted: usize,
+    vb_chunk_size: usize,
+
+    ab_state: BodyState,
+    ab_content: Vec<u8>,
+    ab_available: bool,
+    ab_at_end: Option<bool>,
+}
+
+impl MemTransaction {
+    pub fn new(virgin: MemMessage) -> MemTransaction {
+        MemTransaction {
+            virgin,
+            cause: None,
+            adapted: None,
+            decision: Decision::Pending,
+            resume_requested: false,
+            delays: Vec::new(),
+            vb_content: Vec::new(),
+            vb_state: BodyState::Undecided,
+            vb_paused: false,
+            vb_revealed: 0,
+            vb_shifted: 0,
+            vb_chunk_size: usize::max_value(),
+            ab_state: BodyState::Undecided,
+            ab_content: Vec::new(),
+            ab_available: false,
+            ab_at_end: None,
+        }
+    }
+
+    /// Set the message which caused the virgin message, i.e. the
+    /// request when adapting a response.
+    pub fn set_cause(&mut self, cause: MemMessage) {
+        self.cause = Some(cause);
+    }
+
+    /// Set the virgin body content, adding a body of that size to the
+    /// virgin message.
+    pub fn set_virgin_body(&mut self, content: Vec<u8>) {
+        self.virgin.set_body_size(Some(content.len() as u64));
+        self.vb_content = content;
+    }
+
+    /// Reveal at most `size` bytes of virgin body per
+    /// `virgin_body_content_available` notification.
+    pub fn set_chunk_size(&mut self, size: usize) {
+        assert!(size > 0, "chunk size must be positive");
+        self.vb_chunk_size = size;
+    }
+
+    pub fn decision(&self) -> Decision {
+        self.decision
+    }
+
+    /// Create an adapter transaction and drive it to completion.
+    ///
+    /// Async services are suspended and resumed as needed; the
+    /// transaction is abandoned if it makes no progress.
+    pub fn run<H, S>(mut self, service: &mut S) -> Outcome
+    where
+        H: AsTransactionRef + ?Sized,
+        S: Service<H> + ?Sized,
+    {
+        let mut xaction = service.make_transaction(H::transaction_ref(&mut self));
+        xaction.start(H::transaction_ref(&mut self));
+        self.drive::<H, S>(service, &mut xaction);
+        xaction.stop(H::transaction_ref(&mut self));
+        self.into_outcome()
+    }
+
+    fn drive<H, S>(&mut self, service: &S, xaction: &mut S::Transaction)
+    where
+        H: AsTransactionRef + ?Sized,
+        S: Service<H> + ?Sized,
+    {
+        let mut asked_for_more = false;
+        let mut idle_resumes = 0;
+        while !self.is_finished() {
+            if self.resume_requested {
+                self.resume_requested = false;
+                idle_resumes = 0;
+                xaction.resume(H::transaction_ref(self));
+                continue;
+            }
+
+            if self.decision == Decision::Adapted && self.ab_state == BodyState::Undecided {
+                let has_body = self.adapted
+                    .as_ref()
+                    .map_or(false, |m| ConcreteMessage::<MemHost>::body(m).is_some());
+                if has_body {
+                    self.ab_state = BodyState::Making;
+                    xaction.adapted_body_make(H::transaction_ref(self));
+                } else {
+                    self.ab_state = BodyState::Done;
+                }
+                continue;
+            }
+
+            if self.ab_state == BodyState::Making && (self.ab_available || self.ab_at_end.is_some())
+            {
+                self.ab_available = false;
+                asked_for_more = false;
+                self.pull_adapted_body::<H, S>(xaction);
+                if self.ab_at_end.is_some() {
+                    self.ab_state = BodyState::Done;
+                }
+                continue;
+            }
+
+            if self.vb_state == BodyState::Making && !self.vb_paused {
+                if self.vb_revealed < self.vb_content.len() {
+                    self.vb_revealed = cmp::min(
+                        self.vb_content.len(),
+                        self.vb_revealed.saturating_add(self.vb_chunk_size),
+                    );
+                    xaction.virgin_body_content_available(H::transaction_ref(self));
+                    continue;
+                } else {
+                    self.vb_state = BodyState::Done;
+                    xaction.virgin_body_content_done(H::transaction_ref(self), true);
+                    continue;
+                }
+            }
+
+            if self.ab_state == BodyState::Making && !asked_for_more {
+                asked_for_more = true;
+                xaction.adapted_body_make_more(H::transaction_ref(self));
+                continue;
+            }
+
+            if service.is_async() && idle_resumes < MAX_IDLE_RESUMES {
+                idle_resumes += 1;
+                let mut timeout = MAX_SUSPEND;
+                service.suspend(&mut timeout);
+                thread::sleep(cmp::min(timeout, MAX_SUSPEND));
+                service.resume();
+                continue;
+            }
+
+            // No further progress is possible.
+            break;
+        }
+    }
+
+    fn pull_adapted_body<H, S>(&mut self, xaction: &mut S::Transaction)
+    where
+        H: AsTransactionRef + ?Sized,
+        S: Service<H> + ?Sized,
+    {
+        loop {
+            let area = xaction.adapted_body_content(
+                H::transaction_ref(self),
+                0,
+                usize::max_value(),
+            );
+            let len = area.as_bytes().len();
+            if len == 0 {
+                break;
+            }
+            self.ab_content.extend_from_slice(area.as_bytes());
+            xaction.adapted_body_content_shift(H::transaction_ref(self), len);
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        let virgin_body_idle = self.vb_state != BodyState::Making;
+        match self.decision {
+            Decision::Pending => false,
+            Decision::Aborted => true,
+            Decision::Virgin | Decision::Blocked => virgin_body_idle,
+            Decision::Adapted => virgin_body_idle && self.ab_state == BodyState::Done,
+        }
+    }
+
+    fn into_outcome(self) -> Outcome {
+        let complete = self.is_finished();
+        let (message, body, at_end) = match self.decision {
+            Decision::Virgin => (Some(self.virgin), self.vb_content, Some(true)),
+            Decision::Adapted => (self.adapted, self.ab_content, self.ab_at_end),
+            Decision::Pending | Decision::Blocked | Decision::Aborted => (None, Vec::new(), None),
+        };
+        Outcome {
+            decision: self.decision,
+            complete,
+            message,
+            body,
+            at_end,
+            delays: self.delays,
+        }
+    }
+}
+
+/// The result of running a transaction to completion.
+#[derive(Debug)]
+pub struct Outcome {
+    decision: Decision,
+    complete: bool,
+    message: Option<MemMessage>,
+    body: Vec<u8>,
+    at_end: Option<bool>,
+    delays: Vec<String>,
+}
+
+impl Outcome {
+    pub fn decision(&self) -> Decision {
+        self.decision
+    }
+
+    /// False if the transaction stalled before the adapter finished.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The message the host should forward: the virgin message if
+    /// `use_virgin` was called, and the adapted message if
+    /// `use_adapted` was called.
+    pub fn message(&self) -> Option<&MemMessage> {
+        self.message.as_ref()
+    }
+
+    /// The body content to forward along with `message`.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The `at_end` value passed to `adapted_body_content_done`, if it
+    /// was called.
+    pub fn at_end(&self) -> Option<bool> {
+        self.at_end
+    }
+
+    /// Descriptions passed to `adaptation_delayed`, in order.
+    pub fn delays(&self) -> &[String] {
+        &self.delays
+    }
+}
+
+fn not_mem_message() -> Error {
+    Error::new(ErrorKind::Unsupported, "MemTransaction only works with MemMessage")
+}
+
+impl ConcreteTransaction<MemHost> for MemTransaction {
+    fn virgin(&mut self) -> &mut MemMessage {
+        &mut self.virgin
+    }
+
+    /// The cause set with `set_cause`; transactions without one, such
+    /// as those on the request side, report an empty request.
+    fn cause(&mut self) -> &MemMessage {
+        self.cause
+            .get_or_insert_with(|| MemMessage::request(&b""[..], &b""[..]))
+    }
+
+    fn adapted(&mut self) -> &mut MemMessage {
+        self.adapted
+            .as_mut()
+            .expect("adapted called before use_adapted")
+    }
+
+    fn use_virgin(&mut self) {
+        assert_eq!(self.decision, Decision::Pending);
+        self.decision = Decision::Virgin;
+    }
+
+    fn use_adapted<M>(&mut self, msg: M) -> Result<(), Error>
+    where
+        M: ConcreteMessage<MemHost> + 'static,
+    {
+        assert_eq!(self.decision, Decision::Pending);
+        let mut msg = Some(msg);
+        let msg = match (&mut msg as &mut dyn Any).downcast_mut::<Option<MemMessage>>() {
+            Some(msg) => msg.take().unwrap(),
+            None => return Err(not_mem_message()),
+        };
+        self.adapted = Some(msg);
+        self.decision = Decision::Adapted;
+        Ok(())
+    }
+
+    fn block_virgin(&mut self) {
+        assert_eq!(self.decision, Decision::Pending);
+        self.decision = Decision::Blocked;
+    }
+
+    fn adaptation_delayed(&mut self, delay: &Delay) {
+        let description = delay.description.as_ref().map_or("", |d| d.as_ref());
+        self.delays.push(description.to_owned());
+    }
+
+    fn adaptation_aborted(&mut self) {
+        self.decision = Decision::Aborted;
+    }
+
+    fn resume(&mut self) {
+        self.resume_requested = true;
+    }
+
+    fn virgin_body_discard(&mut self) {
+        assert_eq!(self.vb_state, BodyState::Undecided);
+        self.vb_state = BodyState::Discarded;
+    }
+
+    fn virgin_body_make(&mut self) {
+        assert_eq!(self.vb_state, BodyState::Undecided);
+        assert!(
+            ConcreteMessage::<MemHost>::body(&self.virgin).is_some(),
+            "virgin message has no body"
+        );
+        self.vb_state = BodyState::Making;
+    }
+
+    fn virgin_body_make_more(&mut self) {
+        // All content is revealed as fast as the adapter consumes it.
+    }
+
+    fn virgin_body_stop_making(&mut self) {
+        if self.vb_state == BodyState::Making {
+            self.vb_state = BodyState::Stopped;
+        }
+    }
+
+    fn virgin_body_pause(&mut self) {
+        self.vb_paused = true;
+    }
+
+    fn virgin_body_resume(&mut self) {
+        self.vb_paused = false;
+    }
+
+    fn virgin_body_content(&mut self, offset: usize, size: usize) -> Area {
+        let start = cmp::min(self.vb_shifted.saturating_add(offset), self.vb_revealed);
+        let end = cmp::min(start.saturating_add(size), self.vb_revealed);
+        Area::from_bytes(&self.vb_content[start..end])
+    }
+
+    fn virgin_body_content_shift(&mut self, size: usize) {
+        assert!(
+            self.vb_shifted + size <= self.vb_revealed,
+            "shifted past the available virgin body content"
+        );
+        self.vb_shifted += size;
+    }
+
+    fn adapted_body_content_done(&mut self, at_end: bool) {
+        self.ab_at_end = Some(at_end);
+    }
+
+    fn adapted_body_content_available(&mut self) {
+        self.ab_available = true;
+    }
+}
+
+impl ErasedTransaction<dyn ErasedHost> for MemTransaction {
+    fn virgin(&mut self) -> &mut dyn ErasedMessage {
+        &mut self.virgin
+    }
+    fn cause(&mut self) -> &dyn ErasedMessage {
+        <Self as ConcreteTransaction<MemHost>>::cause(self)
+    }
+    fn adapted(&mut self) -> &mut dyn ErasedMessage {
+        <Self as ConcreteTransaction<MemHost>>::adapted(self)
+    }
+    fn use_virgin(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::use_virgin(self)
+    }
+    fn use_adapted(&mut self, msg: Box<dyn ErasedMessage>) -> Result<(), Error> {
+        // Messages arrive boxed twice when they pass through the
+        // erased `ecap::host::Transaction` impl.
+        let msg = match msg.downcast::<Box<dyn ErasedMessage>>() {
+            Ok(msg) => *msg,
+            Err(msg) => msg,
+        };
+        match msg.downcast::<MemMessage>() {
+            Ok(msg) => <Self as ConcreteTransaction<MemHost>>::use_adapted(self, *msg),
+            Err(_) => Err(not_mem_message()),
+        }
+    }
+    fn block_virgin(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::block_virgin(self)
+    }
+    fn adaptation_delayed(&mut self, delay: &Delay) {
+        <Self as ConcreteTransaction<MemHost>>::adaptation_delayed(self, delay)
+    }
+    fn adaptation_aborted(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::adaptation_aborted(self)
+    }
+    fn resume(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::resume(self)
+    }
+    fn virgin_body_discard(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::virgin_body_discard(self)
+    }
+    fn virgin_body_make(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::virgin_body_make(self)
+    }
+    fn virgin_body_make_more(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::virgin_body_make_more(self)
+    }
+    fn virgin_body_stop_making(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::virgin_body_stop_making(self)
+    }
+    fn virgin_body_pause(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::virgin_body_pause(self)
+    }
+    fn virgin_body_resume(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::virgin_body_resume(self)
+    }
+    fn virgin_body_content(&mut self, offset: usize, size: usize) -> Area {
+        <Self as ConcreteTransaction<MemHost>>::virgin_body_content(self, offset, size)
+    }
+    fn virgin_body_content_shift(&mut self, size: usize) {
+        <Self as ConcreteTransaction<MemHost>>::virgin_body_content_shift(self, size)
+    }
+    fn adapted_body_content_done(&mut self, at_end: bool) {
+        <Self as ConcreteTransaction<MemHost>>::adapted_body_content_done(self, at_end)
+    }
+    fn adapted_body_content_available(&mut self) {
+        <Self as ConcreteTransaction<MemHost>>::adapted_body_content_available(self)
+    }
+}
@@ -0,0 +1,395 @@
+//! The state machine of `AdaptedBody`, driven call by call.
+
+extern crate ecap;
+extern crate ecap_host;
+extern crate erased_ecap;
+
+use std::cmp;
+
+use ecap::adapter::{AdaptedBody, BodyState, BodyTransformer};
+use ecap::common::{Area, AreaChain, Delay, Error};
+use ecap_host::MemMessage;
+use erased_ecap::common::Message as ErasedMessage;
+use erased_ecap::host::Host as ErasedHost;
+use erased_ecap::host::Transaction as ErasedTransaction;
+
+type HostTransaction = dyn ErasedTransaction<dyn ErasedHost>;
+
+/// Upper-cases the body, failing on chunks containing `!`.
+#[derive(Default)]
+struct Upper {
+    failed: bool,
+}
+
+impl BodyTransformer for Upper {
+    fn transform(&mut self, chunk: Area, out: &mut AreaChain) {
+        if chunk.as_bytes().contains(&b'!') {
+            self.failed = true;
+        } else {
+            out.push(Area::from_bytes(&chunk.as_bytes().to_ascii_uppercase()));
+        }
+    }
+
+    fn finish(&mut self, out: &mut AreaChain) {
+        out.push(Area::from_static(b"."));
+    }
+
+    fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+/// A host transaction recording the calls made to it.
+///
+/// Virgin body content is made available with `add_content`.
+struct Recorder {
+    virgin: MemMessage,
+    content: Vec<u8>,
+    shifted: usize,
+    calls: Vec<String>,
+}
+
+impl Recorder {
+    fn new(body: bool) -> Recorder {
+        let mut virgin = MemMessage::response(200, "OK");
+        if body {
+            virgin.set_body_size(None);
+        }
+        Recorder {
+            virgin,
+            content: Vec::new(),
+            shifted: 0,
+            calls: Vec::new(),
+        }
+    }
+
+    fn add_content(&mut self, content: &[u8]) {
+        self.content.extend_from_slice(content);
+    }
+
+    /// The calls made since the last call to `take_calls`.
+    fn take_calls(&mut self) -> Vec<String> {
+        self.calls.drain(..).collect()
+    }
+
+    fn call(&mut self, call: &str) {
+        self.calls.push(call.to_owned());
+    }
+}
+
+impl ErasedTransaction<dyn ErasedHost> for Recorder {
+    fn virgin(&mut self) -> &mut dyn ErasedMessage {
+        &mut self.virgin
+    }
+    fn cause(&mut self) -> &dyn ErasedMessage {
+        unimplemented!()
+    }
+    fn adapted(&mut self) -> &mut dyn ErasedMessage {
+        unimplemented!()
+    }
+    fn use_virgin(&mut self) {
+        self.call("use_virgin");
+    }
+    fn use_adapted(&mut self, _msg: Box<dyn ErasedMessage>) -> Result<(), Error> {
+        self.call("use_adapted");
+        Ok(())
+    }
+    fn block_virgin(&mut self) {
+        self.call("block_virgin");
+    }
+    fn adaptation_delayed(&mut self, _delay: &Delay) {
+        self.call("adaptation_delayed");
+    }
+    fn adaptation_aborted(&mut self) {
+        self.call("adaptation_aborted");
+    }
+    fn resume(&mut self) {
+        self.call("resume");
+    }
+    fn virgin_body_discard(&mut self) {
+        self.call("virgin_body_discard");
+    }
+    fn virgin_body_make(&mut self) {
+        self.call("virgin_body_make");
+    }
+    fn virgin_body_make_more(&mut self) {
+        self.call("virgin_body_make_more");
+    }
+    fn virgin_body_stop_making(&mut self) {
+        self.call("virgin_body_stop_making");
+    }
+    fn virgin_body_pause(&mut self) {
+        self.call("virgin_body_pause");
+    }
+    fn virgin_body_resume(&mut self) {
+        self.call("virgin_body_resume");
+    }
+    fn virgin_body_content(&mut self, offset: usize, size: usize) -> Area {
+        let start = cmp::min(self.shifted + offset, self.content.len());
+        let end = cmp::min(start.saturating_add(size), self.content.len());
+        Area::from_bytes(&self.content[start..end])
+    }
+    fn virgin_body_content_shift(&mut self, size: usize) {
+        assert!(self.shifted + size <= self.content.len());
+        self.shifted += size;
+    }
+    fn adapted_body_content_done(&mut self, at_end: bool) {
+        self.call(&format!("adapted_body_content_done({})", at_end));
+    }
+    fn adapted_body_content_available(&mut self) {
+        self.call("adapted_body_content_available");
+    }
+}
+
+/// A body whose virgin body is on, with the calls of `start` taken.
+fn started(recorder: &mut Recorder) -> AdaptedBody<Upper> {
+    let mut body = AdaptedBody::new(Upper::default());
+    body.start::<dyn ErasedHost>(host(recorder));
+    assert_eq!(recorder.take_calls(), ["virgin_body_make"]);
+    body
+}
+
+fn host(recorder: &mut Recorder) -> &mut HostTransaction {
+    recorder
+}
+
+/// All unshifted adapted content, read as a host would, since each
+/// call returns content up to the end of a single chunk at most.
+fn content(body: &mut AdaptedBody<Upper>) -> Vec<u8> {
+    let mut content = Vec::new();
+    loop {
+        let area = body.adapted_body_content(content.len(), usize::MAX);
+        if area.as_bytes().is_empty() {
+            return content;
+        }
+        content.extend_from_slice(area.as_bytes());
+    }
+}
+
+#[test]
+fn start_with_body() {
+    let mut recorder = Recorder::new(true);
+    let body = started(&mut recorder);
+    assert_eq!(body.receiving(), BodyState::On);
+    assert_eq!(body.sending(), BodyState::Undecided);
+}
+
+#[test]
+fn start_without_body() {
+    let mut recorder = Recorder::new(false);
+    let mut body = AdaptedBody::new(Upper::default());
+    body.start::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(body.receiving(), BodyState::Never);
+    assert!(recorder.take_calls().is_empty());
+
+    // The adapted body ends as soon as it is made.
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(body.sending(), BodyState::Complete);
+    assert_eq!(recorder.take_calls(), ["adapted_body_content_done(true)"]);
+    assert!(content(&mut body).is_empty());
+}
+
+#[test]
+fn make_then_content() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(body.sending(), BodyState::On);
+    // There is nothing to announce yet.
+    assert!(recorder.take_calls().is_empty());
+    assert!(content(&mut body).is_empty());
+
+    recorder.add_content(b"hello");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(recorder.take_calls(), ["adapted_body_content_available"]);
+    // The virgin content was consumed.
+    assert_eq!(recorder.shifted, 5);
+    assert_eq!(content(&mut body), b"HELLO");
+    assert_eq!(body.adapted_body_content(1, 2).as_bytes(), b"EL");
+}
+
+#[test]
+fn content_before_make_is_buffered() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    recorder.add_content(b"hello");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+    assert!(recorder.take_calls().is_empty());
+    assert_eq!(body.buffered().to_area().as_bytes(), b"HELLO");
+
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(recorder.take_calls(), ["adapted_body_content_available"]);
+    assert_eq!(content(&mut body), b"HELLO");
+}
+
+#[test]
+fn content_shift() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    recorder.add_content(b"hello");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+    recorder.add_content(b" world");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+
+    body.adapted_body_content_shift(3);
+    assert_eq!(body.adapted_body_content(0, usize::MAX).as_bytes(), b"LO");
+    assert_eq!(content(&mut body), b"LO WORLD");
+    body.adapted_body_content_shift(8);
+    assert!(content(&mut body).is_empty());
+    assert!(body.buffered().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "shifted 6 bytes out of a chain of length 5")]
+fn content_shift_past_available_content() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    recorder.add_content(b"hello");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+    body.adapted_body_content_shift(6);
+}
+
+#[test]
+fn content_done() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    recorder.add_content(b"hello");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+    recorder.take_calls();
+
+    body.virgin_body_content_done::<dyn ErasedHost>(host(&mut recorder), true);
+    assert_eq!(body.receiving(), BodyState::Complete);
+    assert_eq!(body.sending(), BodyState::Complete);
+    assert_eq!(
+        recorder.take_calls(),
+        [
+            "adapted_body_content_available",
+            "adapted_body_content_done(true)",
+        ]
+    );
+    // What the transformer added at the end can still be read.
+    assert_eq!(content(&mut body), b"HELLO.");
+
+    // Nothing more is passed on.
+    body.virgin_body_content_done::<dyn ErasedHost>(host(&mut recorder), false);
+    body.adapted_body_make_more::<dyn ErasedHost>(host(&mut recorder));
+    assert!(recorder.take_calls().is_empty());
+}
+
+#[test]
+fn content_done_early() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    body.virgin_body_content_done::<dyn ErasedHost>(host(&mut recorder), false);
+    assert_eq!(
+        recorder.take_calls(),
+        [
+            "adapted_body_content_available",
+            "adapted_body_content_done(false)",
+        ]
+    );
+}
+
+#[test]
+fn make_more() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    body.adapted_body_make_more::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(recorder.take_calls(), ["virgin_body_make_more"]);
+
+    // Without a virgin body, there is nothing to ask for.
+    let mut recorder = Recorder::new(false);
+    let mut body = AdaptedBody::new(Upper::default());
+    body.start::<dyn ErasedHost>(host(&mut recorder));
+    body.adapted_body_make_more::<dyn ErasedHost>(host(&mut recorder));
+    assert!(recorder.take_calls().is_empty());
+}
+
+#[test]
+fn stop_making() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    body.adapted_body_stop_making::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(body.sending(), BodyState::Complete);
+    assert_eq!(body.receiving(), BodyState::Complete);
+    assert_eq!(recorder.take_calls(), ["virgin_body_stop_making"]);
+
+    // Later virgin content is left alone.
+    recorder.add_content(b"hello");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+    body.virgin_body_content_done::<dyn ErasedHost>(host(&mut recorder), true);
+    assert_eq!(recorder.shifted, 0);
+    assert!(recorder.take_calls().is_empty());
+    assert!(content(&mut body).is_empty());
+}
+
+#[test]
+fn discard() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_discard::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(body.sending(), BodyState::Never);
+    assert_eq!(body.receiving(), BodyState::Complete);
+    assert_eq!(recorder.take_calls(), ["virgin_body_stop_making"]);
+}
+
+#[test]
+#[should_panic]
+fn discard_after_make() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    body.adapted_body_discard::<dyn ErasedHost>(host(&mut recorder));
+}
+
+#[test]
+fn pause_and_resume() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    body.adapted_body_pause::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(recorder.take_calls(), ["virgin_body_pause"]);
+
+    // The host is not told about content while paused.
+    recorder.add_content(b"hello");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+    assert!(recorder.take_calls().is_empty());
+
+    body.adapted_body_resume::<dyn ErasedHost>(host(&mut recorder));
+    assert_eq!(
+        recorder.take_calls(),
+        ["virgin_body_resume", "adapted_body_content_available"]
+    );
+    assert_eq!(content(&mut body), b"HELLO");
+}
+
+#[test]
+fn failed_transformer() {
+    let mut recorder = Recorder::new(true);
+    let mut body = started(&mut recorder);
+    body.adapted_body_make::<dyn ErasedHost>(host(&mut recorder));
+    recorder.add_content(b"hello");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+    recorder.add_content(b"!");
+    body.virgin_body_content_available::<dyn ErasedHost>(host(&mut recorder));
+
+    // The virgin body is stopped, and the adapted body ends early.
+    assert_eq!(body.receiving(), BodyState::Complete);
+    assert_eq!(body.sending(), BodyState::Complete);
+    assert_eq!(
+        recorder.take_calls(),
+        [
+            "adapted_body_content_available",
+            "virgin_body_stop_making",
+            "adapted_body_content_available",
+            "adapted_body_content_done(false)",
+        ]
+    );
+    assert_eq!(content(&mut body), b"HELLO");
+}
@@ -0,0 +1,208 @@
+//! Logging through the debug streams of the host, with `log` and with
+//! `tracing`.
+
+extern crate ecap;
+extern crate ecap_host;
+extern crate erased_ecap;
+extern crate log;
+#[macro_use]
+extern crate tracing;
+extern crate tracing_subscriber;
+
+use std::ffi::CStr;
+use std::thread;
+
+use ecap::adapter::{Service, Transaction};
+use ecap::common::log::HostLayer;
+use ecap::common::{Area, Error, Name, NamedValueVisitor, Options};
+use ecap::host::{self, Transaction as HostTransaction};
+use ecap_host::{Decision, MemHost, MemMessage, MemTransaction};
+use erased_ecap::adapter::Service as ErasedService;
+use erased_ecap::host::Host as ErasedHost;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+/// A host which lives for the rest of the test, as loggers need.
+fn leaked_host() -> &'static MemHost {
+    Box::leak(Box::new(MemHost::new()))
+}
+
+/// Uses every virgin message, logging as it starts.
+struct LoggingService;
+
+impl<H: host::Host + ?Sized> Service<H> for LoggingService {
+    type Transaction = LoggingTransaction;
+
+    fn uri(&self) -> String {
+        "ecap://rust/test/debug-log".to_owned()
+    }
+    fn tag(&self) -> String {
+        "0".to_owned()
+    }
+    fn describe(&self) -> String {
+        "test service".to_owned()
+    }
+    fn configure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn reconfigure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn start(&self) {}
+    fn stop(&self) {}
+    fn retire(&self) {}
+    fn wants_url(&self, _url: &CStr) -> bool {
+        true
+    }
+    fn make_transaction(&mut self, _host: &mut H::TransactionRef) -> LoggingTransaction {
+        LoggingTransaction
+    }
+}
+
+struct LoggingTransaction;
+
+impl<H: host::Host + ?Sized> Transaction<H> for LoggingTransaction {
+    fn start<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        info!("using the virgin message");
+        debug!("not seen by the host");
+        hostx.use_virgin();
+    }
+    fn stop<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn resume<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_discard<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_make<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_make_more<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_stop_making<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_pause<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_resume<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_content<'a>(
+        &mut self,
+        _hostx: &'a mut H::TransactionRef,
+        _offset: usize,
+        _size: usize,
+    ) -> Area
+    where
+        H::TransactionRef: 'a,
+    {
+        Area::from_static(b"")
+    }
+    fn adapted_body_content_shift<'a>(&mut self, _hostx: &'a mut H::TransactionRef, _size: usize)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn virgin_body_content_done<'a>(&mut self, _hostx: &'a mut H::TransactionRef, _at_end: bool)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn virgin_body_content_available<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+}
+
+impl Options for LoggingTransaction {
+    fn option(&self, _name: &Name) -> Option<Area> {
+        None
+    }
+    fn visit_each<V: NamedValueVisitor>(&self, _visitor: V) {}
+}
+
+/// Runs a response to a request for `http://example.com/` through
+/// `LoggingService`, as an erased service.
+fn run_response() {
+    let mut service: Box<dyn ErasedService<dyn ErasedHost>> = Box::new(LoggingService);
+    let mut transaction = MemTransaction::new(MemMessage::response(200, "OK"));
+    transaction.set_cause(MemMessage::request(
+        &b"GET"[..],
+        &b"http://example.com/"[..],
+    ));
+    let outcome = transaction.run::<dyn ErasedHost, _>(&mut *service);
+    assert_eq!(outcome.decision(), Decision::Virgin);
+}
+
+// The logger of the `log` crate can only be set once per process, so
+// this is the only test using it.
+#[test]
+fn log_records() {
+    let host = leaked_host();
+    ecap::common::log::init(host).unwrap();
+
+    log::error!("failed: {}", 42);
+    log::warn!(target: "custom", "warned");
+    log::debug!("not seen by the host");
+    log::trace!("not seen either");
+    assert_eq!(
+        host.debug_log(),
+        ["debug_log: failed: 42", "custom: warned"]
+    );
+
+    // Records from other threads are dropped.
+    thread::spawn(|| log::error!("elsewhere")).join().unwrap();
+    assert_eq!(host.debug_log().len(), 2);
+}
+
+#[test]
+fn tracing_events_name_their_spans() {
+    let host = leaked_host();
+    let subscriber = Registry::default().with(HostLayer::new(host));
+    tracing::subscriber::with_default(subscriber, || {
+        info!(count = 3, "outside of any transaction");
+        let span = info_span!("outer", name = "value");
+        let _entered = span.enter();
+        run_response();
+    });
+
+    let log = host.debug_log();
+    assert_eq!(log.len(), 2, "{:?}", log);
+    assert_eq!(log[0], "debug_log: outside of any transaction count=3");
+
+    // The transaction span identifies the service, the transaction, and
+    // the URL of the request which caused the response.
+    let prefix = "outer{name=value}: xaction{service=ecap://rust/test/debug-log id=";
+    assert!(log[1].starts_with(prefix), "{}", log[1]);
+    let rest = &log[1][prefix.len()..];
+    let id_end = rest.find(' ').unwrap();
+    assert!(rest[..id_end].parse::<u64>().is_ok(), "{}", log[1]);
+    assert_eq!(
+        &rest[id_end..],
+        " direction=response url=http://example.com/}: debug_log: using the virgin message"
+    );
+}
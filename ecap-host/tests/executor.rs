@@ -0,0 +1,238 @@
+extern crate ecap;
+extern crate ecap_host;
+
+use std::cell::{Cell, RefCell};
+use std::ffi::CStr;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+
+use ecap::adapter::{Executor, Registered, Service, Spawned, Transaction, WakeHandle};
+use ecap::common::header::Header;
+use ecap::common::{Area, Error, Message, Name, NamedValueVisitor, Options};
+use ecap::host::{self, Transaction as HostTransaction};
+use ecap_host::{Decision, MemHost, MemMessage, MemTransaction};
+
+/// A future which completes once another thread is done with it.
+struct Lookup {
+    done: Arc<AtomicBool>,
+    started: bool,
+}
+
+impl Future for Lookup {
+    type Output = &'static str;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<&'static str> {
+        if self.done.load(Ordering::SeqCst) {
+            return Poll::Ready("found");
+        }
+        if !self.started {
+            self.started = true;
+            let done = self.done.clone();
+            let waker = cx.waker().clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(5));
+                done.store(true, Ordering::SeqCst);
+                waker.wake();
+            });
+        }
+        Poll::Pending
+    }
+}
+
+/// Adds the result of a lookup to each message.
+struct LookupService {
+    executor: Executor,
+    resumed: Rc<Cell<usize>>,
+    handle: Rc<RefCell<Option<WakeHandle>>>,
+}
+
+impl<H> Service<H> for LookupService
+where
+    H: host::Host + ?Sized,
+    H::TransactionRef: 'static,
+{
+    type Transaction = Registered<LookupTransaction>;
+
+    fn uri(&self) -> String {
+        "ecap://rust/test/lookup".to_owned()
+    }
+    fn tag(&self) -> String {
+        "0".to_owned()
+    }
+    fn describe(&self) -> String {
+        "test service".to_owned()
+    }
+    fn configure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn reconfigure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn start(&self) {}
+    fn stop(&self) {}
+    fn retire(&self) {}
+    fn wants_url(&self, _url: &CStr) -> bool {
+        true
+    }
+    fn is_async(&self) -> bool {
+        true
+    }
+    fn suspend(&self, timeout: &mut Duration) {
+        self.executor.suspend(timeout);
+    }
+    fn resume(&self) {
+        self.executor.resume();
+    }
+    fn make_transaction(&mut self, _host: &mut H::TransactionRef) -> Self::Transaction {
+        self.executor.transaction(LookupTransaction {
+            executor: self.executor.clone(),
+            lookup: None,
+            resumed: self.resumed.clone(),
+            handle: self.handle.clone(),
+        })
+    }
+}
+
+struct LookupTransaction {
+    executor: Executor,
+    lookup: Option<Spawned<&'static str>>,
+    resumed: Rc<Cell<usize>>,
+    handle: Rc<RefCell<Option<WakeHandle>>>,
+}
+
+impl<H> Transaction<H> for LookupTransaction
+where
+    H: host::Host + ?Sized,
+    H::TransactionRef: 'static,
+{
+    fn start<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        let lookup = Lookup {
+            done: Arc::new(AtomicBool::new(false)),
+            started: false,
+        };
+        *self.handle.borrow_mut() = self.executor.registry().handle::<H>(hostx);
+        self.lookup = Some(self.executor.spawn::<H, _>(hostx, lookup));
+    }
+    fn stop<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn resume<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.resumed.set(self.resumed.get() + 1);
+        let found = match self.lookup.as_mut().and_then(|lookup| lookup.take()) {
+            Some(found) => found,
+            None => return,
+        };
+        let mut adapted = hostx.virgin().clone();
+        adapted.header_mut().insert(
+            Name::new_known(&b"X-Lookup"[..]),
+            Area::from_static(found.as_bytes()),
+        );
+        hostx.use_adapted(adapted).unwrap();
+    }
+    fn adapted_body_discard<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_make<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_make_more<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_stop_making<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_pause<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_resume<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_content<'a>(
+        &mut self,
+        _hostx: &'a mut H::TransactionRef,
+        _offset: usize,
+        _size: usize,
+    ) -> Area
+    where
+        H::TransactionRef: 'a,
+    {
+        Area::from_static(b"")
+    }
+    fn adapted_body_content_shift<'a>(&mut self, _hostx: &'a mut H::TransactionRef, _size: usize)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn virgin_body_content_done<'a>(&mut self, _hostx: &'a mut H::TransactionRef, _at_end: bool)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn virgin_body_content_available<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+}
+
+impl Options for LookupTransaction {
+    fn option(&self, _name: &Name) -> Option<Area> {
+        None
+    }
+    fn visit_each<V: NamedValueVisitor>(&self, _visitor: V) {}
+}
+
+#[test]
+fn future_completion_resumes_transaction() {
+    let resumed = Rc::new(Cell::new(0));
+    let handle = Rc::new(RefCell::new(None));
+    let mut service = LookupService {
+        executor: Executor::new(),
+        resumed: resumed.clone(),
+        handle: handle.clone(),
+    };
+    let transaction = MemTransaction::new(MemMessage::request(&b"GET"[..], &b"/"[..]));
+    let outcome = transaction.run::<MemHost, _>(&mut service);
+
+    // The host resumed the transaction once, after the lookup.
+    assert_eq!(resumed.get(), 1);
+    assert_eq!(outcome.decision(), Decision::Adapted);
+    assert!(outcome.is_complete());
+    let lookup = Message::<MemHost>::header(outcome.message().unwrap())
+        .get(&Name::new_known(&b"X-Lookup"[..]))
+        .unwrap();
+    assert_eq!(lookup.as_bytes(), b"found");
+    assert!(service.executor.is_idle());
+
+    // Stopping the transaction unregistered it.
+    let handle = handle.borrow_mut().take().unwrap();
+    assert!(!handle.is_live());
+    assert!(!handle.wake());
+    assert!(!service.executor.registry().has_woken());
+}
@@ -0,0 +1,172 @@
+extern crate ecap;
+extern crate ecap_host;
+
+use ecap::common::header::Header;
+use ecap::common::name::{HEADER_CONTENT_LENGTH, HEADER_TRANSFER_ENCODING};
+use ecap::common::{Area, Body, Message, Name};
+use ecap_host::http;
+use ecap_host::{MemFirstLine, MemHost, MemMessage};
+
+fn field(message: &MemMessage, name: &Name) -> Option<Vec<u8>> {
+    Message::<MemHost>::header(message)
+        .get(name)
+        .map(|value| value.as_bytes().to_vec())
+}
+
+fn body_size(message: &MemMessage) -> Option<Option<u64>> {
+    Message::<MemHost>::body(message).map(|body| body.size())
+}
+
+#[test]
+fn request_without_body() {
+    let (message, body) =
+        http::parse(b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+    match *Message::<MemHost>::first_line(&message) {
+        MemFirstLine::Request(ref line) => {
+            assert_eq!(line.method.image(), Some(&b"GET"[..]));
+            assert_eq!(line.uri.as_bytes(), b"/index.html");
+            assert_eq!(line.version.major, Some(1));
+            assert_eq!(line.version.minor, Some(1));
+        }
+        MemFirstLine::Status(_) => panic!("not a request"),
+    }
+    assert_eq!(
+        field(&message, &Name::new_known(&b"Host"[..])),
+        Some(b"example.com".to_vec())
+    );
+    assert_eq!(body, None);
+    assert_eq!(body_size(&message), None);
+}
+
+#[test]
+fn response_with_content_length() {
+    let (message, body) =
+        http::parse(b"HTTP/1.0 404 Not Found\r\nContent-Length: 5\r\n\r\nhello, world").unwrap();
+    match *Message::<MemHost>::first_line(&message) {
+        MemFirstLine::Status(ref line) => {
+            assert_eq!(line.status_code, 404);
+            assert_eq!(line.reason_phrase.image(), Some(&b"Not Found"[..]));
+            assert_eq!(line.version.minor, Some(0));
+        }
+        MemFirstLine::Request(_) => panic!("not a response"),
+    }
+    // Anything beyond Content-Length is not part of the message.
+    assert_eq!(body, Some(b"hello".to_vec()));
+    assert_eq!(body_size(&message), Some(Some(5)));
+}
+
+#[test]
+fn response_with_empty_body() {
+    let (message, body) = http::parse(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+    assert_eq!(body, Some(Vec::new()));
+    assert_eq!(body_size(&message), Some(Some(0)));
+}
+
+#[test]
+fn response_until_end_of_input() {
+    let (message, body) = http::parse(b"HTTP/1.1 200 OK\n\nhello\n").unwrap();
+    assert_eq!(body, Some(b"hello\n".to_vec()));
+    assert_eq!(body_size(&message), Some(Some(6)));
+}
+
+#[test]
+fn chunked_body() {
+    let (message, body) = http::parse(
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, Chunked\r\n\r\n\
+          5;name=value\r\nhello\r\n\
+          7\nworld!\n\n\
+          0\r\nTrailer: x\r\n\r\n",
+    )
+    .unwrap();
+    assert_eq!(body, Some(b"helloworld!\n".to_vec()));
+    assert_eq!(body_size(&message), Some(Some(12)));
+    // The header is left as-is.
+    assert_eq!(
+        field(&message, &HEADER_TRANSFER_ENCODING),
+        Some(b"gzip, Chunked".to_vec())
+    );
+}
+
+#[test]
+fn head_without_blank_line() {
+    let message = http::parse_head(b"POST / HTTP/1.1\r\nContent-Length: 3").unwrap();
+    assert_eq!(field(&message, &HEADER_CONTENT_LENGTH), Some(b"3".to_vec()));
+    assert_eq!(body_size(&message), None);
+
+    let message = http::parse_head(b"HTTP/1.1 204 No Content").unwrap();
+    assert!(Message::<MemHost>::header(&message).is_empty());
+}
+
+#[test]
+fn malformed_messages() {
+    let inputs: &[&[u8]] = &[
+        b"",
+        b"GET / HTTP/1.1\r\nHost: example.com\r\n",
+        b"GET\r\n\r\n",
+        b"GET / HTTP\r\n\r\n",
+        b"GET / HTTP/x.1\r\n\r\n",
+        b"HTTP/1.1 OK\r\n\r\n",
+        b"GET / HTTP/1.1\r\nno colon\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\nContent-Length: x\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nhello",
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n",
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel",
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nx\r\nhello\r\n0\r\n\r\n",
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello!\r\n0\r\n\r\n",
+    ];
+    for input in inputs {
+        assert!(
+            http::parse(input).is_err(),
+            "{:?}",
+            String::from_utf8_lossy(input)
+        );
+    }
+}
+
+#[test]
+fn write_with_content_length() {
+    let mut message = MemMessage::response(200, "OK");
+    Message::<MemHost>::header_mut(&mut message)
+        .insert(HEADER_CONTENT_LENGTH, Area::from_bytes(b"5"));
+    let mut out = Vec::new();
+    http::write(&message, b"hello", &mut out);
+    assert_eq!(
+        out,
+        &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"[..]
+    );
+}
+
+#[test]
+fn write_chunked() {
+    let mut message = MemMessage::request(&b"POST"[..], &b"/"[..]);
+    Message::<MemHost>::header_mut(&mut message)
+        .insert(HEADER_TRANSFER_ENCODING, Area::from_bytes(b"chunked"));
+
+    let mut out = Vec::new();
+    http::write(&message, b"hello, world", &mut out);
+    assert_eq!(
+        out,
+        &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nc\r\nhello, world\r\n0\r\n\r\n"[..]
+    );
+    let (_, body) = http::parse(&out).unwrap();
+    assert_eq!(body, Some(b"hello, world".to_vec()));
+
+    // An empty body is only the last chunk.
+    let mut out = Vec::new();
+    http::write(&message, b"", &mut out);
+    assert!(out.ends_with(b"\r\n\r\n0\r\n\r\n"));
+    let (_, body) = http::parse(&out).unwrap();
+    assert_eq!(body, Some(Vec::new()));
+}
+
+#[test]
+fn round_trip() {
+    let input: &[u8] = b"HTTP/1.1 200 OK\r\n\
+                         Content-Type: text/plain\r\n\
+                         Content-Length: 12\r\n\r\n\
+                         hello, world";
+    let (message, body) = http::parse(input).unwrap();
+    let mut out = Vec::new();
+    http::write(&message, &body.unwrap(), &mut out);
+    assert_eq!(out, input);
+}
@@ -0,0 +1,300 @@
+extern crate ecap;
+extern crate ecap_host;
+extern crate erased_ecap;
+
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::rc::Rc;
+use std::time::Duration;
+
+use ecap::adapter::{AdaptedBody, BodyTransformer, Replace, Service, Transaction};
+use ecap::common::header::Header;
+use ecap::common::name::{HEADER_CONTENT_LENGTH, HEADER_TRANSFER_ENCODING};
+use ecap::common::{Area, Error, Message, Name, NamedValueVisitor, Options};
+use ecap::host::{self, Transaction as HostTransaction};
+use ecap_host::{Decision, MemHost, MemMessage, MemTransaction};
+use erased_ecap::adapter::Service as ErasedService;
+use erased_ecap::host::Host as ErasedHost;
+
+/// Adapts every message, running its body through a new transformer.
+struct BodyService<F> {
+    make: F,
+}
+
+impl<H, F, T> Service<H> for BodyService<F>
+where
+    H: host::Host + ?Sized,
+    F: FnMut() -> T,
+    T: BodyTransformer,
+{
+    type Transaction = BodyTransaction<T>;
+
+    fn uri(&self) -> String {
+        "ecap://rust/test/body".to_owned()
+    }
+    fn tag(&self) -> String {
+        "0".to_owned()
+    }
+    fn describe(&self) -> String {
+        "test service".to_owned()
+    }
+    fn configure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn reconfigure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn start(&self) {}
+    fn stop(&self) {}
+    fn retire(&self) {}
+    fn wants_url(&self, _url: &CStr) -> bool {
+        true
+    }
+    fn make_transaction(&mut self, _host: &mut H::TransactionRef) -> Self::Transaction {
+        BodyTransaction {
+            body: AdaptedBody::new((self.make)()),
+            decide: true,
+        }
+    }
+}
+
+struct BodyTransaction<T> {
+    body: AdaptedBody<T>,
+    decide: bool,
+}
+
+impl<H: host::Host + ?Sized, T: BodyTransformer> Transaction<H> for BodyTransaction<T> {
+    fn start<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        if !self.decide {
+            return;
+        }
+        self.body.start::<H>(hostx);
+        let size = self.body.adapted_size::<H>(hostx);
+        let adapted = hostx.virgin().clone();
+        hostx.use_adapted_sized(adapted, size).unwrap();
+    }
+    fn stop<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn resume<'a>(&mut self, _hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_discard<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.adapted_body_discard::<H>(hostx);
+    }
+    fn adapted_body_make<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.adapted_body_make::<H>(hostx);
+    }
+    fn adapted_body_make_more<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.adapted_body_make_more::<H>(hostx);
+    }
+    fn adapted_body_stop_making<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.adapted_body_stop_making::<H>(hostx);
+    }
+    fn adapted_body_pause<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.adapted_body_pause::<H>(hostx);
+    }
+    fn adapted_body_resume<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.adapted_body_resume::<H>(hostx);
+    }
+    fn adapted_body_content<'a>(
+        &mut self,
+        _hostx: &'a mut H::TransactionRef,
+        offset: usize,
+        size: usize,
+    ) -> Area
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.adapted_body_content(offset, size)
+    }
+    fn adapted_body_content_shift<'a>(&mut self, _hostx: &'a mut H::TransactionRef, size: usize)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.adapted_body_content_shift(size);
+    }
+    fn virgin_body_content_done<'a>(&mut self, hostx: &'a mut H::TransactionRef, at_end: bool)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.virgin_body_content_done::<H>(hostx, at_end);
+    }
+    fn virgin_body_content_available<'a>(&mut self, hostx: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.body.virgin_body_content_available::<H>(hostx);
+    }
+}
+
+impl<T> Options for BodyTransaction<T> {
+    fn option(&self, _name: &Name) -> Option<Area> {
+        None
+    }
+    fn visit_each<V: NamedValueVisitor>(&self, _visitor: V) {}
+}
+
+/// An async service whose transactions never decide, counting how
+/// often it is resumed.
+struct StalledService {
+    resumes: Rc<Cell<usize>>,
+}
+
+impl<H: host::Host + ?Sized> Service<H> for StalledService {
+    type Transaction = BodyTransaction<fn(Area) -> Area>;
+
+    fn uri(&self) -> String {
+        "ecap://rust/test/stalled".to_owned()
+    }
+    fn tag(&self) -> String {
+        "0".to_owned()
+    }
+    fn describe(&self) -> String {
+        "test service".to_owned()
+    }
+    fn configure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn reconfigure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn start(&self) {}
+    fn stop(&self) {}
+    fn retire(&self) {}
+    fn wants_url(&self, _url: &CStr) -> bool {
+        true
+    }
+    fn is_async(&self) -> bool {
+        true
+    }
+    fn suspend(&self, timeout: &mut Duration) {
+        *timeout = Duration::from_millis(1);
+    }
+    fn resume(&self) {
+        self.resumes.set(self.resumes.get() + 1);
+    }
+    fn make_transaction(&mut self, _host: &mut H::TransactionRef) -> Self::Transaction {
+        BodyTransaction {
+            body: AdaptedBody::new(pass as fn(Area) -> Area),
+            decide: false,
+        }
+    }
+}
+
+fn pass(chunk: Area) -> Area {
+    chunk
+}
+
+fn response(body: &[u8], chunk_size: usize) -> MemTransaction {
+    let mut transaction = MemTransaction::new(MemMessage::response(200, "OK"));
+    transaction.set_cause(MemMessage::request(
+        &b"GET"[..],
+        &b"http://example.com/"[..],
+    ));
+    transaction.set_virgin_body(body.to_vec());
+    transaction.set_chunk_size(chunk_size);
+    transaction
+}
+
+fn field(message: &MemMessage, name: &Name) -> Option<Vec<u8>> {
+    Message::<MemHost>::header(message)
+        .get(name)
+        .map(|value| value.as_bytes().to_vec())
+}
+
+#[test]
+fn passthru_round_trip() {
+    let mut service = BodyService {
+        make: || pass as fn(Area) -> Area,
+    };
+    let outcome = response(b"hello, world", 5).run::<MemHost, _>(&mut service);
+    assert_eq!(outcome.decision(), Decision::Adapted);
+    assert!(outcome.is_complete());
+    assert_eq!(outcome.at_end(), Some(true));
+    assert_eq!(outcome.body(), b"hello, world");
+}
+
+#[test]
+fn response_without_cause() {
+    // Erased services open a tracing span naming the URL of the cause.
+    let mut service: Box<dyn ErasedService<dyn ErasedHost>> = Box::new(BodyService {
+        make: || pass as fn(Area) -> Area,
+    });
+    let mut transaction = MemTransaction::new(MemMessage::response(200, "OK"));
+    transaction.set_virgin_body(b"hello".to_vec());
+    let outcome = transaction.run::<dyn ErasedHost, _>(&mut *service);
+    assert_eq!(outcome.decision(), Decision::Adapted);
+    assert!(outcome.is_complete());
+    assert_eq!(outcome.body(), b"hello");
+}
+
+#[test]
+fn modifying_across_chunks() {
+    let mut service = BodyService {
+        make: || Replace::new(vec![("world", "there!")]),
+    };
+    // The pattern straddles the chunk boundaries.
+    let outcome = response(b"hello, world; world", 3).run::<MemHost, _>(&mut service);
+    assert_eq!(outcome.decision(), Decision::Adapted);
+    assert!(outcome.is_complete());
+    assert_eq!(outcome.at_end(), Some(true));
+    assert_eq!(outcome.body(), &b"hello, there!; there!"[..]);
+    // The adapted size was not known up front, and framing the body is
+    // left to whoever sends the message.
+    let message = outcome.message().unwrap();
+    assert_eq!(field(message, &HEADER_CONTENT_LENGTH), None);
+    assert_eq!(field(message, &HEADER_TRANSFER_ENCODING), None);
+}
+
+#[test]
+fn modifying_same_length_keeps_content_length() {
+    let mut service = BodyService {
+        make: || Replace::new(vec![("world", "WORLD")]),
+    };
+    let outcome = response(b"hello, world", 4).run::<MemHost, _>(&mut service);
+    assert_eq!(outcome.body(), b"hello, WORLD");
+    let message = outcome.message().unwrap();
+    assert_eq!(field(message, &HEADER_CONTENT_LENGTH), Some(b"12".to_vec()));
+    assert_eq!(field(message, &HEADER_TRANSFER_ENCODING), None);
+}
+
+#[test]
+fn stalled_async_service_is_abandoned() {
+    let resumes = Rc::new(Cell::new(0));
+    let mut service = StalledService {
+        resumes: resumes.clone(),
+    };
+    let mut transaction = MemTransaction::new(MemMessage::request(&b"GET"[..], &b"/"[..]));
+    transaction.set_virgin_body(Vec::new());
+    let outcome = transaction.run::<MemHost, _>(&mut service);
+    assert_eq!(outcome.decision(), Decision::Pending);
+    assert!(!outcome.is_complete());
+    // The service was resumed until the host gave up on it.
+    assert_eq!(resumes.get(), 50);
+}
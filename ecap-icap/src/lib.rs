@@ -0,0 +1,24 @@
+//! An ICAP (RFC 3507) front-end for eCAP adapter services.
+//!
+//! Proxies which speak ICAP rather than eCAP send each message to a
+//! [`Server`], which runs it through an `ecap::adapter::Service` using
+//! the in-memory host from `ecap-host`, and returns the adapted message
+//! (or `204 No Content` if the adapter did not change it).
+//!
+//! ```rust,ignore
+//! let mut server = Server::<dyn ErasedHost, _>::new();
+//! server.add_service("respmod", Method::Respmod, service);
+//! server.serve(TcpListener::bind("127.0.0.1:1344")?)?;
+//! ```
+//!
+//! The [`protocol`] module contains the ICAP message framing, for use by
+//! ICAP clients as well.
+
+extern crate ecap;
+extern crate ecap_host;
+
+pub mod protocol;
+mod server;
+
+pub use protocol::{Error, Method};
+pub use server::Server;
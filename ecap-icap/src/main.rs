@@ -0,0 +1,154 @@
+//! Serves an adapter library over ICAP.
+//!
+//! ```text
+//! ecap-icap -m respmod -o victim=foo -o replacement=bar \
+//!     target/debug/libadapter_modifying.so
+//! ```
+
+extern crate ecap;
+extern crate ecap_icap;
+extern crate ecap_host;
+extern crate ecap_loader;
+extern crate erased_ecap;
+
+use std::env;
+use std::net::TcpListener;
+use std::process;
+
+use ecap::adapter::Service;
+use ecap_host::MemOptions;
+use ecap_icap::{Method, Server};
+use ecap_loader::Module;
+use erased_ecap::host::Host as ErasedHost;
+
+const USAGE: &str = "\
+usage: ecap-icap [OPTIONS] ADAPTER
+
+Serves a service of the adapter library ADAPTER over ICAP, at
+icap://ADDRESS/PATH.
+
+options:
+    -l, --listen ADDRESS  listen on ADDRESS (default: 127.0.0.1:1344)
+    -m, --method METHOD   reqmod or respmod (default: respmod)
+    -p, --path PATH       serve the service under PATH (default: METHOD)
+    --preview SIZE        ask clients for previews of SIZE bytes
+    -o NAME=VALUE         pass a configuration option to the service
+    -s, --service URI     use the service with this URI, not the first
+    -h, --help            show this message";
+
+struct Args {
+    listen: String,
+    method: Method,
+    path: Option<String>,
+    preview: Option<usize>,
+    options: MemOptions,
+    service: Option<String>,
+    adapter: String,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = env::args().skip(1);
+    let mut parsed = Args {
+        listen: String::from("127.0.0.1:1344"),
+        method: Method::Respmod,
+        path: None,
+        preview: None,
+        options: MemOptions::new(),
+        service: None,
+        adapter: String::new(),
+    };
+    let mut adapter = None;
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-l" | "--listen" => {
+                parsed.listen = args.next().ok_or("--listen requires an address")?;
+            }
+            "-m" | "--method" => {
+                parsed.method = match &args.next().ok_or("--method requires a method")?[..] {
+                    "reqmod" | "REQMOD" => Method::Reqmod,
+                    "respmod" | "RESPMOD" => Method::Respmod,
+                    method => return Err(format!("unknown method `{}`", method)),
+                };
+            }
+            "-p" | "--path" => {
+                parsed.path = Some(args.next().ok_or("--path requires a path")?);
+            }
+            "--preview" => {
+                let size = args.next().ok_or("--preview requires a size")?;
+                parsed.preview = Some(size.parse()
+                    .map_err(|_| format!("invalid preview size `{}`", size))?);
+            }
+            "-o" => {
+                let pair = args.next().ok_or("-o requires NAME=VALUE")?;
+                parsed
+                    .options
+                    .insert_pair(&pair)
+                    .ok_or_else(|| format!("option `{}` is not NAME=VALUE", pair))?;
+            }
+            "-s" | "--service" => {
+                parsed.service = Some(args.next().ok_or("--service requires a URI")?);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ if adapter.is_none() => adapter = Some(arg),
+            _ => return Err(String::from("too many arguments")),
+        }
+    }
+
+    parsed.adapter = adapter.ok_or("missing ADAPTER")?;
+    Ok(parsed)
+}
+
+fn run() -> Result<(), String> {
+    let args = parse_args()?;
+
+    let module = Module::open(&args.adapter).map_err(|e| e.to_string())?;
+    let path = module.path().display().to_string();
+    let mut services = module.into_services();
+    let index = match args.service {
+        None => 0,
+        Some(ref uri) => services
+            .iter()
+            .position(|service| service.uri() == *uri)
+            .ok_or_else(|| format!("{} has no service {}", path, uri))?,
+    };
+    let mut service = services.swap_remove(index);
+    drop(services);
+
+    service
+        .configure(&args.options)
+        .map_err(|e| format!("{}: {}", service.uri(), e))?;
+    service.start();
+
+    let listener = TcpListener::bind(&args.listen[..])
+        .map_err(|e| format!("failed to listen on {}: {}", args.listen, e))?;
+    let method = args.method;
+    let path = args.path.unwrap_or_else(|| method.as_str().to_lowercase());
+    eprintln!(
+        "serving {} at icap://{}/{}",
+        service.uri(),
+        args.listen,
+        path
+    );
+
+    let mut server = Server::<dyn ErasedHost, _>::new();
+    server.set_preview(args.preview);
+    server.add_service(&path, method, service);
+    let result = server.serve(listener);
+
+    for service in server.into_services() {
+        service.stop();
+    }
+    result.map_err(|e| format!("failed to accept connection: {}", e))
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("ecap-icap: {}", e);
+        process::exit(1);
+    }
+}
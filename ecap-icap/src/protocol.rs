@@ -0,0 +1,454 @@
+//! ICAP/1.0 message framing.
+//!
+//! HTTP messages are carried inside ICAP messages, as described by the
+//! `Encapsulated` header: the HTTP headers come first, followed by a
+//! body in chunked encoding.
+
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::str;
+
+use ecap::common::header::Header;
+use ecap::common::{Area, Message, Name};
+use ecap_host::{http, MemHeader, MemHost, MemMessage};
+
+/// Failure to read an ICAP message.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The ICAP framing is invalid.
+    Protocol(&'static str),
+    /// An encapsulated HTTP header is invalid.
+    Http(http::ParseError),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<http::ParseError> for Error {
+    fn from(e: http::ParseError) -> Error {
+        Error::Http(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Protocol(reason) => write!(f, "invalid ICAP message: {}", reason),
+            Error::Http(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "I/O error",
+            Error::Protocol(reason) => reason,
+            Error::Http(_) => "invalid encapsulated HTTP message",
+        }
+    }
+}
+
+/// An ICAP request method.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Method {
+    Options,
+    Reqmod,
+    Respmod,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Method::Options => "OPTIONS",
+            Method::Reqmod => "REQMOD",
+            Method::Respmod => "RESPMOD",
+        }
+    }
+
+    fn from_bytes(method: &[u8]) -> Option<Method> {
+        match method {
+            b"OPTIONS" => Some(Method::Options),
+            b"REQMOD" => Some(Method::Reqmod),
+            b"RESPMOD" => Some(Method::Respmod),
+            _ => None,
+        }
+    }
+}
+
+/// The first line and header of an ICAP request.
+#[derive(Debug, Clone)]
+pub struct RequestHead {
+    pub method: Method,
+    pub uri: String,
+    pub header: MemHeader,
+}
+
+impl RequestHead {
+    pub fn new(method: Method, uri: &str) -> RequestHead {
+        RequestHead {
+            method,
+            uri: uri.to_owned(),
+            header: MemHeader::new(),
+        }
+    }
+
+    /// Read a request head; `None` is returned if the stream ended
+    /// before the request began.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Option<RequestHead>, Error> {
+        let (first_line, header) = match read_head(reader)? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        let mut parts = first_line.split(|b| *b == b' ');
+        let method = parts.next().and_then(Method::from_bytes);
+        let uri = parts.next().and_then(|uri| str::from_utf8(uri).ok());
+        let version = parts.next();
+        match (method, uri, version) {
+            (Some(method), Some(uri), Some(b"ICAP/1.0")) => Ok(Some(RequestHead {
+                method,
+                uri: uri.to_owned(),
+                header,
+            })),
+            (None, _, _) => Err(Error::Protocol("unknown method")),
+            _ => Err(Error::Protocol("invalid request line")),
+        }
+    }
+
+    /// The path of the service URI, without the leading slash, e.g.
+    /// `respmod` for `icap://proxy.example:1344/respmod?mode=strict`.
+    pub fn service(&self) -> &str {
+        let uri = match self.uri.find("://") {
+            Some(scheme_end) => &self.uri[scheme_end + 3..],
+            None => &self.uri,
+        };
+        let path = uri.find('/').map_or("", |start| &uri[start + 1..]);
+        path.split('?').next().unwrap_or("")
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(format!("{} {} ICAP/1.0\r\n", self.method.as_str(), self.uri).as_bytes());
+        self.header.write_to(out);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// The first line and header of an ICAP response.
+#[derive(Debug, Clone)]
+pub struct ResponseHead {
+    pub status: u16,
+    pub reason: String,
+    pub header: MemHeader,
+}
+
+impl ResponseHead {
+    pub fn new(status: u16, reason: &str) -> ResponseHead {
+        ResponseHead {
+            status,
+            reason: reason.to_owned(),
+            header: MemHeader::new(),
+        }
+    }
+
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<ResponseHead, Error> {
+        let (first_line, header) =
+            read_head(reader)?.ok_or_else(|| Error::Protocol("connection closed"))?;
+        let mut parts = first_line.splitn(3, |b| *b == b' ');
+        if parts.next() != Some(b"ICAP/1.0") {
+            return Err(Error::Protocol("invalid status line"));
+        }
+        let status = parts
+            .next()
+            .and_then(|status| str::from_utf8(status).ok())
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| Error::Protocol("invalid status code"))?;
+        let reason = String::from_utf8_lossy(parts.next().unwrap_or(b"")).into_owned();
+        Ok(ResponseHead {
+            status,
+            reason,
+            header,
+        })
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(format!("ICAP/1.0 {} {}\r\n", self.status, self.reason).as_bytes());
+        self.header.write_to(out);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Read lines up to and including an empty line.
+fn read_head<R: BufRead>(reader: &mut R) -> Result<Option<(Vec<u8>, MemHeader)>, Error> {
+    let mut first_line = Vec::new();
+    // Tolerate empty lines before the first line.
+    while first_line.is_empty() {
+        if reader.read_until(b'\n', &mut first_line)? == 0 {
+            return Ok(None);
+        }
+        trim_line(&mut first_line);
+    }
+
+    let mut fields = Vec::new();
+    loop {
+        let start = fields.len();
+        if reader.read_until(b'\n', &mut fields)? == 0 {
+            return Err(Error::Protocol("connection closed within header"));
+        }
+        let mut line = fields[start..].to_vec();
+        trim_line(&mut line);
+        if line.is_empty() {
+            break;
+        }
+    }
+
+    let mut header = MemHeader::new();
+    header
+        .parse(&Area::from_bytes(&fields))
+        .map_err(|_| Error::Protocol("malformed header field"))?;
+    Ok(Some((first_line, header)))
+}
+
+fn trim_line(line: &mut Vec<u8>) {
+    while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+        line.pop();
+    }
+}
+
+/// The value of `name` in `header`, as a string.
+pub fn header_value(header: &MemHeader, name: &str) -> Option<String> {
+    header
+        .get(&Name::new_known(name.as_bytes()))
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+}
+
+pub fn set_header_value(header: &mut MemHeader, name: &str, value: &str) {
+    let name = Name::new_known(name.as_bytes().to_vec());
+    header.remove_any(&name);
+    header.insert(name, Area::from_bytes(value.as_bytes()));
+}
+
+/// A section of an encapsulated message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Section {
+    RequestHeader,
+    ResponseHeader,
+    RequestBody,
+    ResponseBody,
+    OptionsBody,
+    NullBody,
+}
+
+impl Section {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Section::RequestHeader => "req-hdr",
+            Section::ResponseHeader => "res-hdr",
+            Section::RequestBody => "req-body",
+            Section::ResponseBody => "res-body",
+            Section::OptionsBody => "opt-body",
+            Section::NullBody => "null-body",
+        }
+    }
+
+    fn from_str(section: &str) -> Option<Section> {
+        match section {
+            "req-hdr" => Some(Section::RequestHeader),
+            "res-hdr" => Some(Section::ResponseHeader),
+            "req-body" => Some(Section::RequestBody),
+            "res-body" => Some(Section::ResponseBody),
+            "opt-body" => Some(Section::OptionsBody),
+            "null-body" => Some(Section::NullBody),
+            _ => None,
+        }
+    }
+
+    fn is_body(&self) -> bool {
+        match *self {
+            Section::RequestHeader | Section::ResponseHeader | Section::NullBody => false,
+            Section::RequestBody | Section::ResponseBody | Section::OptionsBody => true,
+        }
+    }
+}
+
+/// HTTP messages encapsulated in an ICAP message.
+#[derive(Debug, Clone, Default)]
+pub struct Encapsulated {
+    pub request: Option<MemMessage>,
+    pub response: Option<MemMessage>,
+    /// Whether a (chunked) body follows the headers.
+    pub has_body: bool,
+}
+
+impl Encapsulated {
+    /// Read the encapsulated headers described by the `Encapsulated`
+    /// header of `header`. The body, if any, is left unread.
+    pub fn read<R: BufRead>(reader: &mut R, header: &MemHeader) -> Result<Encapsulated, Error> {
+        let value = header_value(header, "Encapsulated")
+            .ok_or_else(|| Error::Protocol("missing Encapsulated header"))?;
+        let mut sections = Vec::new();
+        for entry in value.split(',') {
+            let mut entry = entry.trim().splitn(2, '=');
+            let section = entry.next().and_then(Section::from_str);
+            let offset = entry.next().and_then(|offset| offset.trim().parse::<usize>().ok());
+            match (section, offset) {
+                (Some(section), Some(offset)) => sections.push((section, offset)),
+                _ => return Err(Error::Protocol("invalid Encapsulated header")),
+            }
+        }
+
+        let mut encapsulated = Encapsulated::default();
+        let mut position = 0;
+        for (i, &(section, offset)) in sections.iter().enumerate() {
+            if offset != position {
+                return Err(Error::Protocol("Encapsulated offsets out of order"));
+            }
+            if section.is_body() || section == Section::NullBody {
+                if i + 1 != sections.len() {
+                    return Err(Error::Protocol("body must be the last section"));
+                }
+                encapsulated.has_body = section.is_body();
+                break;
+            }
+            let end = sections
+                .get(i + 1)
+                .map(|&(_, end)| end)
+                .ok_or_else(|| Error::Protocol("missing body section"))?;
+            if end < offset {
+                return Err(Error::Protocol("Encapsulated offsets out of order"));
+            }
+            let mut head = Vec::new();
+            read_exactly(reader, end - offset, &mut head)?;
+            position = end;
+            if !head.ends_with(b"\n\r\n") && !head.ends_with(b"\n\n") {
+                return Err(Error::Protocol("Encapsulated offset within a header"));
+            }
+            let message = http::parse_head(&head)?;
+            match section {
+                Section::RequestHeader => encapsulated.request = Some(message),
+                _ => encapsulated.response = Some(message),
+            }
+        }
+        Ok(encapsulated)
+    }
+
+    /// Write the encapsulated headers, returning the value of the
+    /// `Encapsulated` header describing them.
+    ///
+    /// The body section is labelled after the last message present.
+    pub fn write_to(&self, out: &mut Vec<u8>) -> String {
+        let start = out.len();
+        let mut sections = Vec::new();
+        if let Some(ref request) = self.request {
+            sections.push(format!("{}={}", Section::RequestHeader.as_str(), out.len() - start));
+            request.write_head(out);
+        }
+        if let Some(ref response) = self.response {
+            sections.push(format!("{}={}", Section::ResponseHeader.as_str(), out.len() - start));
+            response.write_head(out);
+        }
+        let body = if !self.has_body {
+            Section::NullBody
+        } else if self.response.is_some() {
+            Section::ResponseBody
+        } else if self.request.is_some() {
+            Section::RequestBody
+        } else {
+            Section::OptionsBody
+        };
+        sections.push(format!("{}={}", body.as_str(), out.len() - start));
+        sections.join(", ")
+    }
+}
+
+/// Whether `message` has a body.
+pub fn has_body(message: &MemMessage) -> bool {
+    Message::<MemHost>::body(message).is_some()
+}
+
+/// Read chunks into `body` up to and including the last chunk.
+///
+/// Returns true if the last chunk carried the `ieof` extension, i.e.
+/// a preview contained the entire body.
+pub fn read_chunks<R: BufRead>(reader: &mut R, body: &mut Vec<u8>) -> Result<bool, Error> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Err(Error::Protocol("connection closed within body"));
+        }
+        trim_line(&mut line);
+        let mut parts = line.splitn(2, |b| *b == b';');
+        let size = parts
+            .next()
+            .and_then(|size| str::from_utf8(size).ok())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| Error::Protocol("invalid chunk size"))?;
+        let ieof = parts
+            .next()
+            .map_or(false, |ext| ext.split(|b| *b == b';').any(|ext| trim(ext) == b"ieof"));
+
+        if size == 0 {
+            // Skip any trailer fields, up to the terminating empty line.
+            loop {
+                line.clear();
+                if reader.read_until(b'\n', &mut line)? == 0 {
+                    return Err(Error::Protocol("connection closed within body"));
+                }
+                trim_line(&mut line);
+                if line.is_empty() {
+                    return Ok(ieof);
+                }
+            }
+        }
+
+        read_exactly(reader, size, body)?;
+        line.clear();
+        reader.read_until(b'\n', &mut line)?;
+        trim_line(&mut line);
+        if !line.is_empty() {
+            return Err(Error::Protocol("missing end of chunk"));
+        }
+    }
+}
+
+/// Append exactly `len` bytes from `reader` to `out`.
+///
+/// Unlike `read_exact`, the buffer grows only as data arrives, so a
+/// bogus length cannot exhaust memory.
+fn read_exactly<R: BufRead>(reader: &mut R, len: usize, out: &mut Vec<u8>) -> Result<(), Error> {
+    let read = reader.by_ref().take(len as u64).read_to_end(out)?;
+    if read < len {
+        return Err(Error::Protocol("connection closed within message"));
+    }
+    Ok(())
+}
+
+fn trim(value: &[u8]) -> &[u8] {
+    let start = value.iter().position(|b| *b != b' ').unwrap_or(value.len());
+    let end = value.iter().rposition(|b| *b != b' ').map_or(start, |end| end + 1);
+    &value[start..end]
+}
+
+/// Write `data` as a single chunk, which is omitted if `data` is empty.
+pub fn write_chunk(out: &mut Vec<u8>, data: &[u8]) {
+    if !data.is_empty() {
+        out.extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+        out.extend_from_slice(data);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Write the last chunk, marking the end of a body or preview.
+pub fn write_last_chunk(out: &mut Vec<u8>, ieof: bool) {
+    if ieof {
+        out.extend_from_slice(b"0; ieof\r\n\r\n");
+    } else {
+        out.extend_from_slice(b"0\r\n\r\n");
+    }
+}
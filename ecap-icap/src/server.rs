@@ -0,0 +1,294 @@
+use std::ffi::CString;
+use std::io::{self, BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::net::TcpListener;
+
+use ecap::adapter::Service;
+use ecap::common::header::Header;
+use ecap::common::name::HEADER_CONTENT_LENGTH;
+use ecap::common::{Area, Message};
+use ecap_host::{AsTransactionRef, Decision, MemFirstLine, MemHost, MemMessage, MemTransaction};
+
+use protocol::{self, header_value, set_header_value, Encapsulated, Error, Method, RequestHead,
+               ResponseHead};
+
+struct Route<S> {
+    path: String,
+    method: Method,
+    service: S,
+}
+
+/// An ICAP server dispatching to eCAP services.
+///
+/// Each service is reachable under its own path, e.g.
+/// `icap://127.0.0.1:1344/respmod`, and supports a single method in
+/// addition to `OPTIONS`. Services must be configured and started by
+/// the caller.
+///
+/// Connections are handled one at a time, as services need not be
+/// thread-safe.
+pub struct Server<H: ?Sized, S> {
+    routes: Vec<Route<S>>,
+    preview: Option<usize>,
+    _host: PhantomData<fn(&H)>,
+}
+
+impl<H, S> Server<H, S>
+where
+    H: AsTransactionRef + ?Sized,
+    S: Service<H>,
+{
+    pub fn new() -> Server<H, S> {
+        Server {
+            routes: Vec::new(),
+            preview: None,
+            _host: PhantomData,
+        }
+    }
+
+    /// Serve `service` under `path` (without the leading slash).
+    pub fn add_service(&mut self, path: &str, method: Method, service: S) {
+        assert!(method != Method::Options, "OPTIONS is supported by all services");
+        let path = if path.starts_with('/') { &path[1..] } else { path };
+        self.routes.push(Route {
+            path: path.to_owned(),
+            method,
+            service,
+        });
+    }
+
+    /// Ask clients to send a preview of `size` bytes before the rest of
+    /// each body.
+    ///
+    /// Requests for URLs a service does not want are answered with
+    /// `204 No Content` once the preview has been read, so clients need
+    /// not send the rest of their bodies. Services are run on whole
+    /// bodies only, so other requests are always continued.
+    pub fn set_preview(&mut self, size: Option<usize>) {
+        self.preview = size;
+    }
+
+    /// Consume the server, returning its services.
+    pub fn into_services(self) -> Vec<S> {
+        self.routes.into_iter().map(|route| route.service).collect()
+    }
+
+    /// Accept and handle connections until accepting fails.
+    pub fn serve(&mut self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let reader = BufReader::new(stream.try_clone()?);
+            if let Err(e) = self.handle(reader, stream) {
+                eprintln!("ICAP connection failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle requests on a single connection until it is closed.
+    pub fn handle<R: BufRead, W: Write>(&mut self, mut reader: R, mut writer: W) -> io::Result<()> {
+        loop {
+            let request = match RequestHead::read(&mut reader) {
+                Ok(Some(request)) => request,
+                Ok(None) => return Ok(()),
+                Err(Error::Io(e)) => return Err(e),
+                Err(_) => return send(&mut writer, &status_only(400, "Bad Request", None)),
+            };
+            let close = header_value(&request.header, "Connection")
+                .map_or(false, |value| value.eq_ignore_ascii_case("close"));
+
+            match self.respond(&request, &mut reader, &mut writer) {
+                Ok(response) => send(&mut writer, &response)?,
+                Err(Error::Io(e)) => return Err(e),
+                // The rest of the request cannot be skipped reliably.
+                Err(_) => return send(&mut writer, &status_only(400, "Bad Request", None)),
+            }
+            if close {
+                return Ok(());
+            }
+        }
+    }
+
+    fn respond<R: BufRead, W: Write>(
+        &mut self,
+        request: &RequestHead,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<Vec<u8>, Error> {
+        let preview = self.preview;
+        let route = match self.routes
+            .iter_mut()
+            .find(|route| route.path == request.service())
+        {
+            Some(route) => route,
+            None => {
+                discard(reader, request)?;
+                return Ok(status_only(404, "ICAP Service Not Found", None));
+            }
+        };
+
+        let tag = istag(&route.service.tag());
+        if request.method == Method::Options {
+            discard(reader, request)?;
+            let mut head = response_head(200, "OK", Some(&tag));
+            set_header_value(&mut head.header, "Methods", route.method.as_str());
+            set_header_value(&mut head.header, "Service", &route.service.describe());
+            set_header_value(&mut head.header, "Allow", "204");
+            if let Some(preview) = preview {
+                set_header_value(&mut head.header, "Preview", &preview.to_string());
+            }
+            set_header_value(&mut head.header, "Encapsulated", "null-body=0");
+            let mut response = Vec::new();
+            head.write_to(&mut response);
+            return Ok(response);
+        }
+        if request.method != route.method {
+            discard(reader, request)?;
+            return Ok(status_only(405, "Method Not Allowed For Service", Some(&tag)));
+        }
+
+        let encapsulated = Encapsulated::read(reader, &request.header)?;
+        let is_preview = header_value(&request.header, "Preview").is_some();
+        let allows_204 = header_value(&request.header, "Allow")
+            .map_or(false, |allow| allow.split(',').any(|code| code.trim() == "204"));
+        // A 204 is always allowed in response to a preview.
+        let mut allow_204 = allows_204 || is_preview;
+
+        let (virgin, cause) = match route.method {
+            Method::Reqmod => (encapsulated.request, None),
+            _ => (encapsulated.response, encapsulated.request),
+        };
+        let virgin = virgin.ok_or_else(|| Error::Protocol("missing encapsulated message"))?;
+        let wanted = request_uri(cause.as_ref().unwrap_or(&virgin))
+            .map_or(true, |uri| route.service.wants_url(&uri));
+
+        let mut body = Vec::new();
+        if encapsulated.has_body {
+            let ieof = protocol::read_chunks(reader, &mut body)?;
+            // Services run on whole bodies, so only continue if this one
+            // wants the message; otherwise a 204 spares the client from
+            // sending the rest.
+            if is_preview && !ieof && wanted {
+                writer.write_all(b"ICAP/1.0 100 Continue\r\n\r\n")?;
+                writer.flush()?;
+                protocol::read_chunks(reader, &mut body)?;
+                allow_204 = allows_204;
+            }
+        }
+
+        if !wanted {
+            return Ok(if allow_204 {
+                status_only(204, "No Content", Some(&tag))
+            } else {
+                adapted(&virgin, &body, &tag)
+            });
+        }
+
+        let mut transaction = MemTransaction::new(virgin);
+        if encapsulated.has_body {
+            transaction.set_virgin_body(body);
+        }
+        if let Some(cause) = cause {
+            transaction.set_cause(cause);
+        }
+        let outcome = transaction.run::<H, S>(&mut route.service);
+
+        Ok(match (outcome.decision(), outcome.message()) {
+            _ if !outcome.is_complete() => status_only(500, "Server Error", Some(&tag)),
+            (Decision::Virgin, _) if allow_204 => status_only(204, "No Content", Some(&tag)),
+            (Decision::Virgin, Some(message)) | (Decision::Adapted, Some(message)) => {
+                adapted(message, outcome.body(), &tag)
+            }
+            (Decision::Blocked, _) => blocked(&tag),
+            _ => status_only(500, "Server Error", Some(&tag)),
+        })
+    }
+}
+
+/// Read the encapsulated message of a request which will not be
+/// handled, so that the next request can be read.
+fn discard<R: BufRead>(reader: &mut R, request: &RequestHead) -> Result<(), Error> {
+    if header_value(&request.header, "Encapsulated").is_none() {
+        return Ok(());
+    }
+    let encapsulated = Encapsulated::read(reader, &request.header)?;
+    if encapsulated.has_body {
+        protocol::read_chunks(reader, &mut Vec::new())?;
+    }
+    Ok(())
+}
+
+fn send<W: Write>(writer: &mut W, response: &[u8]) -> io::Result<()> {
+    writer.write_all(response)?;
+    writer.flush()
+}
+
+fn request_uri(message: &MemMessage) -> Option<CString> {
+    match *Message::<MemHost>::first_line(message) {
+        MemFirstLine::Request(ref line) => CString::new(line.uri.as_bytes()).ok(),
+        MemFirstLine::Status(_) => None,
+    }
+}
+
+/// Quote a service tag for use as an `ISTag`, which is limited to 32
+/// characters.
+fn istag(tag: &str) -> String {
+    let tag: String = tag.chars()
+        .filter(|c| *c != '"' && !c.is_control())
+        .take(32)
+        .collect();
+    format!("\"{}\"", tag)
+}
+
+fn response_head(status: u16, reason: &str, tag: Option<&str>) -> ResponseHead {
+    let mut head = ResponseHead::new(status, reason);
+    set_header_value(
+        &mut head.header,
+        "Server",
+        concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
+    );
+    if let Some(tag) = tag {
+        set_header_value(&mut head.header, "ISTag", tag);
+    }
+    head
+}
+
+/// A response without an encapsulated message.
+fn status_only(status: u16, reason: &str, tag: Option<&str>) -> Vec<u8> {
+    let mut head = response_head(status, reason, tag);
+    set_header_value(&mut head.header, "Encapsulated", "null-body=0");
+    let mut response = Vec::new();
+    head.write_to(&mut response);
+    response
+}
+
+/// A `200 OK` response carrying `message` and `body`.
+fn adapted(message: &MemMessage, body: &[u8], tag: &str) -> Vec<u8> {
+    let is_request = Message::<MemHost>::first_line(message).is_request();
+    let encapsulated = Encapsulated {
+        request: if is_request { Some(Clone::clone(message)) } else { None },
+        response: if is_request { None } else { Some(Clone::clone(message)) },
+        has_body: protocol::has_body(message),
+    };
+    let mut sections = Vec::new();
+    let value = encapsulated.write_to(&mut sections);
+
+    let mut head = response_head(200, "OK", Some(tag));
+    set_header_value(&mut head.header, "Encapsulated", &value);
+    let mut response = Vec::new();
+    head.write_to(&mut response);
+    response.extend_from_slice(&sections);
+    if encapsulated.has_body {
+        protocol::write_chunk(&mut response, body);
+        protocol::write_last_chunk(&mut response, false);
+    }
+    response
+}
+
+/// A `200 OK` response replacing the message with an HTTP error.
+fn blocked(tag: &str) -> Vec<u8> {
+    let mut message = MemMessage::response(403, &b"Forbidden"[..]);
+    Message::<MemHost>::header_mut(&mut message)
+        .insert(HEADER_CONTENT_LENGTH, Area::from_static(b"0"));
+    adapted(&message, &[], tag)
+}
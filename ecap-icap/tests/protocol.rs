@@ -0,0 +1,232 @@
+extern crate ecap;
+extern crate ecap_host;
+extern crate ecap_icap;
+
+use std::io::Cursor;
+
+use ecap::common::Message;
+use ecap_host::{MemFirstLine, MemHost, MemMessage};
+use ecap_icap::protocol::{self, header_value, Encapsulated, RequestHead, ResponseHead};
+use ecap_icap::{Error, Method};
+
+fn request(input: &[u8]) -> (RequestHead, Cursor<&[u8]>) {
+    let mut reader = Cursor::new(input);
+    let head = RequestHead::read(&mut reader).unwrap().unwrap();
+    (head, reader)
+}
+
+fn is_protocol_error<T>(result: Result<T, Error>) -> bool {
+    match result {
+        Err(Error::Protocol(_)) | Err(Error::Http(_)) => true,
+        _ => false,
+    }
+}
+
+fn status_code(message: &MemMessage) -> u16 {
+    match *Message::<MemHost>::first_line(message) {
+        MemFirstLine::Status(ref line) => line.status_code,
+        MemFirstLine::Request(_) => panic!("not a response"),
+    }
+}
+
+#[test]
+fn request_head() {
+    let (head, _) = request(
+        b"\r\nRESPMOD icap://proxy.example:1344/respmod?mode=strict ICAP/1.0\r\n\
+          Host: proxy.example\r\n\
+          Encapsulated: null-body=0\r\n\r\n",
+    );
+    assert_eq!(head.method, Method::Respmod);
+    assert_eq!(head.service(), "respmod");
+    assert_eq!(
+        header_value(&head.header, "Host"),
+        Some("proxy.example".to_owned())
+    );
+}
+
+#[test]
+fn end_of_stream_before_request() {
+    let mut reader = Cursor::new(&b""[..]);
+    assert!(RequestHead::read(&mut reader).unwrap().is_none());
+}
+
+#[test]
+fn encapsulated_offsets() {
+    let req_hdr: &[u8] = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n";
+    let res_hdr: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n";
+    let mut input = format!(
+        "RESPMOD icap://proxy.example/respmod ICAP/1.0\r\n\
+         Encapsulated: req-hdr=0, res-hdr={}, res-body={}\r\n\r\n",
+        req_hdr.len(),
+        req_hdr.len() + res_hdr.len()
+    )
+    .into_bytes();
+    input.extend_from_slice(req_hdr);
+    input.extend_from_slice(res_hdr);
+    input.extend_from_slice(b"5\r\nhello\r\n0\r\n\r\n");
+
+    let (head, mut reader) = request(&input);
+    let encapsulated = Encapsulated::read(&mut reader, &head.header).unwrap();
+    assert!(encapsulated.has_body);
+    assert!(encapsulated.request.is_some());
+    assert_eq!(status_code(encapsulated.response.as_ref().unwrap()), 200);
+
+    let mut body = Vec::new();
+    assert!(!protocol::read_chunks(&mut reader, &mut body).unwrap());
+    assert_eq!(body, b"hello");
+}
+
+#[test]
+fn encapsulated_round_trip() {
+    let response = MemMessage::response(204, "No Content");
+    let encapsulated = Encapsulated {
+        request: None,
+        response: Some(response),
+        has_body: false,
+    };
+    let mut out = Vec::new();
+    let value = encapsulated.write_to(&mut out);
+    assert_eq!(value, format!("res-hdr=0, null-body={}", out.len()));
+
+    let mut head = RequestHead::new(Method::Respmod, "icap://proxy.example/respmod");
+    protocol::set_header_value(&mut head.header, "Encapsulated", &value);
+    let read = Encapsulated::read(&mut Cursor::new(&out[..]), &head.header).unwrap();
+    assert!(!read.has_body);
+    assert!(read.request.is_none());
+    assert_eq!(status_code(read.response.as_ref().unwrap()), 204);
+}
+
+#[test]
+fn chunked_body_with_extensions() {
+    let mut reader =
+        Cursor::new(&b"3;name=value\r\nhel\r\nA\r\nlo, world!\r\n0\r\nTrailer: x\r\n\r\nrest"[..]);
+    let mut body = Vec::new();
+    assert!(!protocol::read_chunks(&mut reader, &mut body).unwrap());
+    assert_eq!(body, b"hello, world!");
+    assert_eq!(reader.position(), reader.get_ref().len() as u64 - 4);
+}
+
+#[test]
+fn chunked_body_ieof() {
+    let mut reader = Cursor::new(&b"5\r\nhello\r\n0; ieof\r\n\r\n"[..]);
+    let mut body = Vec::new();
+    assert!(protocol::read_chunks(&mut reader, &mut body).unwrap());
+    assert_eq!(body, b"hello");
+}
+
+#[test]
+fn chunked_body_round_trip() {
+    let mut out = Vec::new();
+    protocol::write_chunk(&mut out, b"hello");
+    protocol::write_chunk(&mut out, b"");
+    protocol::write_last_chunk(&mut out, true);
+    let mut body = Vec::new();
+    assert!(protocol::read_chunks(&mut Cursor::new(&out[..]), &mut body).unwrap());
+    assert_eq!(body, b"hello");
+}
+
+#[test]
+fn preview_then_continuation() {
+    // A preview of 4 bytes which does not contain the whole body,
+    // followed, after `100 Continue`, by the rest of it.
+    let (head, mut reader) = request(
+        b"REQMOD icap://proxy.example/reqmod ICAP/1.0\r\n\
+          Preview: 4\r\n\
+          Encapsulated: req-hdr=0, req-body=38\r\n\r\n\
+          POST / HTTP/1.1\r\nHost: example.com\r\n\r\n\
+          4\r\nhell\r\n0\r\n\r\n\
+          1\r\no\r\n0\r\n\r\n",
+    );
+    assert_eq!(header_value(&head.header, "Preview"), Some("4".to_owned()));
+    let encapsulated = Encapsulated::read(&mut reader, &head.header).unwrap();
+    assert!(encapsulated.has_body);
+
+    let mut body = Vec::new();
+    assert!(!protocol::read_chunks(&mut reader, &mut body).unwrap());
+    assert_eq!(body, b"hell");
+    assert!(!protocol::read_chunks(&mut reader, &mut body).unwrap());
+    assert_eq!(body, b"hello");
+}
+
+#[test]
+fn response_head() {
+    let mut reader = Cursor::new(&b"ICAP/1.0 100 Continue\r\n\r\n"[..]);
+    let head = ResponseHead::read(&mut reader).unwrap();
+    assert_eq!(head.status, 100);
+    assert_eq!(head.reason, "Continue");
+    assert!(head.header.is_empty());
+}
+
+#[test]
+fn malformed_request_heads() {
+    let inputs: &[&[u8]] = &[
+        b"GET / ICAP/1.0\r\n\r\n",
+        b"REQMOD icap://proxy.example/reqmod HTTP/1.1\r\n\r\n",
+        b"REQMOD\r\n\r\n",
+        b"REQMOD icap://proxy.example/reqmod ICAP/1.0\r\nno colon\r\n\r\n",
+        b"REQMOD icap://proxy.example/reqmod ICAP/1.0\r\nHost: x\r\n",
+    ];
+    for input in inputs {
+        assert!(
+            is_protocol_error(RequestHead::read(&mut Cursor::new(*input))),
+            "{:?}",
+            String::from_utf8_lossy(input)
+        );
+    }
+}
+
+#[test]
+fn malformed_response_heads() {
+    let inputs: &[&[u8]] = &[b"", b"HTTP/1.1 200 OK\r\n\r\n", b"ICAP/1.0 OK\r\n\r\n"];
+    for input in inputs {
+        assert!(is_protocol_error(ResponseHead::read(&mut Cursor::new(
+            *input
+        ))));
+    }
+}
+
+#[test]
+fn malformed_encapsulated() {
+    let header: &[u8] = b"HTTP/1.1 200 OK\r\n\r\n";
+    let values = [
+        "res-hdr=0",
+        "res-hdr=x, null-body=19",
+        "res-hdr=0, body=19",
+        "null-body=0, res-hdr=0",
+        "res-hdr=4, null-body=19",
+        "res-hdr=0, null-body=10",
+        // An offset beyond the data available.
+        "res-hdr=0, null-body=18446744073709551615",
+    ];
+    for value in &values {
+        let mut head = RequestHead::new(Method::Respmod, "icap://proxy.example/respmod");
+        protocol::set_header_value(&mut head.header, "Encapsulated", value);
+        let result = Encapsulated::read(&mut Cursor::new(header), &head.header);
+        assert!(is_protocol_error(result), "{}", value);
+    }
+
+    let head = RequestHead::new(Method::Respmod, "icap://proxy.example/respmod");
+    let result = Encapsulated::read(&mut Cursor::new(header), &head.header);
+    assert!(is_protocol_error(result));
+}
+
+#[test]
+fn malformed_chunks() {
+    let inputs: &[&[u8]] = &[
+        b"",
+        b"x\r\n",
+        b"5\r\nhel",
+        b"5\r\nhello!\r\n0\r\n\r\n",
+        b"5\r\nhello\r\n0\r\n",
+        // A size which must not be allocated up front.
+        b"ffffffffffffffff\r\nhello\r\n0\r\n\r\n",
+    ];
+    for input in inputs {
+        let mut body = Vec::new();
+        assert!(
+            is_protocol_error(protocol::read_chunks(&mut Cursor::new(*input), &mut body)),
+            "{:?}",
+            String::from_utf8_lossy(input)
+        );
+    }
+}
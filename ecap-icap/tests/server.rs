@@ -0,0 +1,459 @@
+extern crate ecap;
+extern crate ecap_host;
+extern crate ecap_icap;
+
+use std::ffi::CStr;
+use std::io::Cursor;
+
+use ecap::adapter::{AdaptedBody, Replace, Service, Transaction};
+use ecap::common::header::Header;
+use ecap::common::{Area, Error, Message, Name, NamedValueVisitor, Options};
+use ecap::host::Transaction as HostTransaction;
+use ecap_host::{http, MemFirstLine, MemHost, MemMessage, MemTransaction};
+use ecap_icap::protocol::{
+    self, header_value, set_header_value, Encapsulated, RequestHead, ResponseHead,
+};
+use ecap_icap::{Method, Server};
+
+#[derive(Debug, Copy, Clone)]
+enum Action {
+    Virgin,
+    Adapt,
+    Block,
+}
+
+/// Decides on every message as told, replacing `hello` with `howdy`
+/// when adapting, and counting the transactions it makes.
+///
+/// URLs ending in `/skip` are not wanted.
+struct TestService {
+    action: Action,
+    transactions: usize,
+}
+
+impl Service<MemHost> for TestService {
+    type Transaction = TestTransaction;
+
+    fn uri(&self) -> String {
+        "ecap://rust/test/icap".to_owned()
+    }
+    fn tag(&self) -> String {
+        "1".to_owned()
+    }
+    fn describe(&self) -> String {
+        "test service".to_owned()
+    }
+    fn configure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn reconfigure<O: Options + ?Sized>(&mut self, _options: &O) -> Result<(), Error> {
+        Ok(())
+    }
+    fn start(&self) {}
+    fn stop(&self) {}
+    fn retire(&self) {}
+    fn wants_url(&self, url: &CStr) -> bool {
+        !url.to_bytes().ends_with(b"/skip")
+    }
+    fn make_transaction(&mut self, _host: &mut MemTransaction) -> TestTransaction {
+        self.transactions += 1;
+        TestTransaction {
+            action: self.action,
+            body: AdaptedBody::new(Replace::new(vec![("hello", "howdy")])),
+        }
+    }
+}
+
+struct TestTransaction {
+    action: Action,
+    body: AdaptedBody<Replace>,
+}
+
+impl Transaction<MemHost> for TestTransaction {
+    fn start<'a>(&mut self, hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+        match self.action {
+            Action::Virgin => hostx.use_virgin(),
+            Action::Block => hostx.block_virgin(),
+            Action::Adapt => {
+                self.body.start::<MemHost>(hostx);
+                let size = self.body.adapted_size::<MemHost>(hostx);
+                let mut adapted = Clone::clone(hostx.virgin());
+                Message::<MemHost>::header_mut(&mut adapted).insert(
+                    Name::new_known(&b"X-Adapted"[..]),
+                    Area::from_static(b"yes"),
+                );
+                hostx.use_adapted_sized(adapted, size).unwrap();
+            }
+        }
+    }
+    fn stop<'a>(&mut self, _hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+    }
+    fn resume<'a>(&mut self, _hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+    }
+    fn adapted_body_discard<'a>(&mut self, hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+        self.body.adapted_body_discard::<MemHost>(hostx);
+    }
+    fn adapted_body_make<'a>(&mut self, hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+        self.body.adapted_body_make::<MemHost>(hostx);
+    }
+    fn adapted_body_make_more<'a>(&mut self, hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+        self.body.adapted_body_make_more::<MemHost>(hostx);
+    }
+    fn adapted_body_stop_making<'a>(&mut self, hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+        self.body.adapted_body_stop_making::<MemHost>(hostx);
+    }
+    fn adapted_body_pause<'a>(&mut self, hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+        self.body.adapted_body_pause::<MemHost>(hostx);
+    }
+    fn adapted_body_resume<'a>(&mut self, hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+        self.body.adapted_body_resume::<MemHost>(hostx);
+    }
+    fn adapted_body_content<'a>(
+        &mut self,
+        _hostx: &'a mut MemTransaction,
+        offset: usize,
+        size: usize,
+    ) -> Area
+    where
+        MemTransaction: 'a,
+    {
+        self.body.adapted_body_content(offset, size)
+    }
+    fn adapted_body_content_shift<'a>(&mut self, _hostx: &'a mut MemTransaction, size: usize)
+    where
+        MemTransaction: 'a,
+    {
+        self.body.adapted_body_content_shift(size);
+    }
+    fn virgin_body_content_done<'a>(&mut self, hostx: &'a mut MemTransaction, at_end: bool)
+    where
+        MemTransaction: 'a,
+    {
+        self.body.virgin_body_content_done::<MemHost>(hostx, at_end);
+    }
+    fn virgin_body_content_available<'a>(&mut self, hostx: &'a mut MemTransaction)
+    where
+        MemTransaction: 'a,
+    {
+        self.body.virgin_body_content_available::<MemHost>(hostx);
+    }
+}
+
+impl Options for TestTransaction {
+    fn option(&self, _name: &Name) -> Option<Area> {
+        None
+    }
+    fn visit_each<V: NamedValueVisitor>(&self, _visitor: V) {}
+}
+
+/// A server with a `reqmod` service taking `action`.
+fn server(action: Action) -> Server<MemHost, TestService> {
+    let mut server = Server::new();
+    server.add_service(
+        "reqmod",
+        Method::Reqmod,
+        TestService {
+            action,
+            transactions: 0,
+        },
+    );
+    server
+}
+
+/// How many transactions the service of `server` made.
+fn transactions(server: Server<MemHost, TestService>) -> usize {
+    server.into_services()[0].transactions
+}
+
+/// An ICAP request encapsulating the HTTP request `http`.
+///
+/// With a `preview` size, the returned request ends with the preview,
+/// and the rest of the body is returned separately.
+fn request(
+    method: Method,
+    path: &str,
+    headers: &[(&str, &str)],
+    http: &[u8],
+    preview: Option<usize>,
+) -> (Vec<u8>, Vec<u8>) {
+    let (message, body) = http::parse(http).unwrap();
+    let encapsulated = Encapsulated {
+        has_body: body.is_some(),
+        request: Some(message),
+        response: None,
+    };
+    let mut sections = Vec::new();
+    let value = encapsulated.write_to(&mut sections);
+
+    let mut head = RequestHead::new(method, &format!("icap://icap.example/{}", path));
+    for &(name, value) in headers {
+        set_header_value(&mut head.header, name, value);
+    }
+    set_header_value(&mut head.header, "Encapsulated", &value);
+
+    let mut out = Vec::new();
+    let mut rest = Vec::new();
+    let body = body.unwrap_or_default();
+    match preview {
+        Some(size) if encapsulated.has_body => {
+            set_header_value(&mut head.header, "Preview", &size.to_string());
+            head.write_to(&mut out);
+            out.extend_from_slice(&sections);
+            let (preview, remainder) = body.split_at(size.min(body.len()));
+            protocol::write_chunk(&mut out, preview);
+            protocol::write_last_chunk(&mut out, remainder.is_empty());
+            if !remainder.is_empty() {
+                protocol::write_chunk(&mut rest, remainder);
+                protocol::write_last_chunk(&mut rest, false);
+            }
+        }
+        _ => {
+            head.write_to(&mut out);
+            out.extend_from_slice(&sections);
+            if encapsulated.has_body {
+                protocol::write_chunk(&mut out, &body);
+                protocol::write_last_chunk(&mut out, false);
+            }
+        }
+    }
+    (out, rest)
+}
+
+const POST: &[u8] = b"POST http://example.com/form HTTP/1.1\r\n\
+                      Content-Length: 12\r\n\r\n\
+                      hello, world";
+
+/// A response, and the encapsulated message and body if there is one.
+struct Response {
+    head: ResponseHead,
+    message: Option<MemMessage>,
+    body: Vec<u8>,
+}
+
+fn handle(server: &mut Server<MemHost, TestService>, input: &[u8]) -> Vec<Response> {
+    let mut output = Vec::new();
+    server.handle(Cursor::new(input), &mut output).unwrap();
+
+    let mut reader = Cursor::new(&output[..]);
+    let mut responses = Vec::new();
+    while (reader.position() as usize) < output.len() {
+        let head = ResponseHead::read(&mut reader).unwrap();
+        let mut message = None;
+        let mut body = Vec::new();
+        if head.status != 100 {
+            let encapsulated = Encapsulated::read(&mut reader, &head.header).unwrap();
+            if encapsulated.has_body {
+                protocol::read_chunks(&mut reader, &mut body).unwrap();
+            }
+            message = encapsulated.response.or(encapsulated.request);
+        }
+        responses.push(Response {
+            head,
+            message,
+            body,
+        });
+    }
+    responses
+}
+
+fn statuses(responses: &[Response]) -> Vec<u16> {
+    responses
+        .iter()
+        .map(|response| response.head.status)
+        .collect()
+}
+
+fn field(message: &MemMessage, name: &str) -> Option<Vec<u8>> {
+    Message::<MemHost>::header(message)
+        .get(&Name::new_known(name.as_bytes()))
+        .map(|value| value.as_bytes().to_vec())
+}
+
+#[test]
+fn options() {
+    let mut server = server(Action::Virgin);
+    server.set_preview(Some(4));
+    let mut head = RequestHead::new(Method::Options, "icap://icap.example/reqmod");
+    set_header_value(&mut head.header, "Encapsulated", "null-body=0");
+    let mut input = Vec::new();
+    head.write_to(&mut input);
+
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [200]);
+    let header = &responses[0].head.header;
+    assert_eq!(header_value(header, "Methods"), Some("REQMOD".to_owned()));
+    assert_eq!(header_value(header, "ISTag"), Some("\"1\"".to_owned()));
+    assert_eq!(header_value(header, "Allow"), Some("204".to_owned()));
+    assert_eq!(header_value(header, "Preview"), Some("4".to_owned()));
+}
+
+#[test]
+fn unknown_service() {
+    let mut server = server(Action::Adapt);
+    let (input, _) = request(Method::Reqmod, "respmod", &[], POST, None);
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [404]);
+    assert_eq!(transactions(server), 0);
+}
+
+#[test]
+fn wrong_method() {
+    let mut server = server(Action::Adapt);
+    let (input, _) = request(Method::Respmod, "reqmod", &[], POST, None);
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [405]);
+    assert_eq!(transactions(server), 0);
+}
+
+#[test]
+fn adapted() {
+    let mut server = server(Action::Adapt);
+    let (input, _) = request(
+        Method::Reqmod,
+        "reqmod?x=y",
+        &[("Allow", "204")],
+        POST,
+        None,
+    );
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [200]);
+    let message = responses[0].message.as_ref().unwrap();
+    assert!(Message::<MemHost>::first_line(message).is_request());
+    assert_eq!(field(message, "X-Adapted"), Some(b"yes".to_vec()));
+    assert_eq!(responses[0].body, b"howdy, world");
+    assert_eq!(transactions(server), 1);
+}
+
+#[test]
+fn virgin_with_allow_204() {
+    let mut server = server(Action::Virgin);
+    let (input, _) = request(Method::Reqmod, "reqmod", &[("Allow", "204")], POST, None);
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [204]);
+    assert!(responses[0].message.is_none());
+    assert_eq!(transactions(server), 1);
+}
+
+#[test]
+fn virgin_without_allow_204() {
+    // The virgin message is sent back instead.
+    let mut server = server(Action::Virgin);
+    let (input, _) = request(Method::Reqmod, "reqmod", &[], POST, None);
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [200]);
+    let message = responses[0].message.as_ref().unwrap();
+    assert_eq!(field(message, "X-Adapted"), None);
+    assert_eq!(responses[0].body, b"hello, world");
+}
+
+#[test]
+fn blocked() {
+    let mut server = server(Action::Block);
+    let (input, _) = request(Method::Reqmod, "reqmod", &[("Allow", "204")], POST, None);
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [200]);
+    let message = responses[0].message.as_ref().unwrap();
+    match *Message::<MemHost>::first_line(message) {
+        MemFirstLine::Status(ref line) => assert_eq!(line.status_code, 403),
+        MemFirstLine::Request(_) => panic!("not a response"),
+    }
+    assert!(responses[0].body.is_empty());
+}
+
+#[test]
+fn preview_continued() {
+    let mut server = server(Action::Adapt);
+    let (mut input, rest) = request(Method::Reqmod, "reqmod", &[], POST, Some(4));
+    input.extend_from_slice(&rest);
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [100, 200]);
+    assert_eq!(responses[1].body, b"howdy, world");
+    assert_eq!(transactions(server), 1);
+}
+
+#[test]
+fn virgin_after_preview_continued() {
+    // Once the client sent the whole body, a 204 needs `Allow: 204`.
+    let mut server = server(Action::Virgin);
+    let (mut input, rest) = request(Method::Reqmod, "reqmod", &[], POST, Some(4));
+    input.extend_from_slice(&rest);
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [100, 200]);
+    assert_eq!(responses[1].body, b"hello, world");
+}
+
+#[test]
+fn preview_with_whole_body() {
+    let mut server = server(Action::Virgin);
+    let (input, rest) = request(Method::Reqmod, "reqmod", &[], POST, Some(20));
+    assert!(rest.is_empty());
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [204]);
+    assert_eq!(transactions(server), 1);
+}
+
+#[test]
+fn unwanted_url_after_preview() {
+    // The rest of the body is never sent, and the connection is ready
+    // for the next request.
+    let mut server = server(Action::Adapt);
+    let skip = b"POST http://example.com/skip HTTP/1.1\r\n\
+                 Content-Length: 12\r\n\r\n\
+                 hello, world";
+    let (mut input, _) = request(Method::Reqmod, "reqmod", &[], skip, Some(4));
+    let (next, _) = request(Method::Reqmod, "reqmod", &[], POST, None);
+    input.extend_from_slice(&next);
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [204, 200]);
+    assert_eq!(responses[1].body, b"howdy, world");
+    assert_eq!(transactions(server), 1);
+}
+
+#[test]
+fn unwanted_url_without_allow_204() {
+    let mut server = server(Action::Adapt);
+    let skip = b"GET http://example.com/skip HTTP/1.1\r\n\r\n";
+    let (input, _) = request(Method::Reqmod, "reqmod", &[], skip, None);
+    let responses = handle(&mut server, &input);
+    assert_eq!(statuses(&responses), [200]);
+    let message = responses[0].message.as_ref().unwrap();
+    assert_eq!(field(message, "X-Adapted"), None);
+    assert_eq!(transactions(server), 0);
+}
+
+#[test]
+fn malformed_request() {
+    let mut server = server(Action::Adapt);
+    let responses = handle(
+        &mut server,
+        b"REQMOD icap://icap.example/reqmod HTTP/1.1\r\n\r\n",
+    );
+    assert_eq!(statuses(&responses), [400]);
+}
@@ -0,0 +1,41 @@
+//! Loading of adapter libraries at runtime.
+//!
+//! Adapters built with `ecap-common-link` register their services from
+//! static constructors when their library is loaded. `Module::open`
+//! loads such a library and collects the services it registered, which
+//! can then be driven through any host using `erased_ecap::host::Host`.
+//!
+//! ```rust,ignore
+//! let module = Module::open("target/debug/libadapter_passthru.so")?;
+//! for service in module.services() {
+//!     println!("{}: {}", service.uri(), service.describe());
+//! }
+//! ```
+//!
+//! The library stays loaded for as long as any of its services, or any
+//! transactions they created, are alive.
+
+extern crate ecap;
+extern crate ecap_common_link;
+extern crate erased_ecap;
+#[macro_use]
+extern crate lazy_static;
+extern crate libc;
+
+mod library;
+mod module;
+mod service;
+
+pub use library::LoadError;
+pub use module::Module;
+pub use service::{LoadedService, LoadedTransaction};
+
+use erased_ecap::host::Host as ErasedHost;
+
+/// Make `host` the host of loaded adapters, which retrieve it with
+/// `ecap_common_link::erased_host`, e.g. to log through it.
+///
+/// Only one host can be registered per process.
+pub fn register_host(host: &'static dyn ErasedHost) {
+    ecap_common_link::register_erased_host(host);
+}
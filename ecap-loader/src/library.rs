@@ -0,0 +1,116 @@
+use libc::{self, c_void};
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Failure to load an adapter library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The path contains an interior NUL byte.
+    InvalidPath(PathBuf),
+
+    /// The dynamic loader rejected the library.
+    Open { path: PathBuf, message: String },
+
+    /// The library loaded, but registered no services.
+    ///
+    /// This is also the case when the library had already been loaded
+    /// by this process, as its static constructors do not run again.
+    NoServices(PathBuf),
+
+    /// The host refused every service the library registered, e.g.
+    /// because it was built for an incompatible version of libecap.
+    ///
+    /// `message` is the reason the last of them was refused.
+    Refused { path: PathBuf, message: String },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::InvalidPath(ref path) => {
+                write!(f, "invalid adapter path: {}", path.display())
+            }
+            LoadError::Open {
+                ref path,
+                ref message,
+            } => write!(f, "failed to load {}: {}", path.display(), message),
+            LoadError::NoServices(ref path) => {
+                write!(f, "{} registered no services", path.display())
+            }
+            LoadError::Refused {
+                ref path,
+                ref message,
+            } => write!(
+                f,
+                "{} registered only services the host refused: {}",
+                path.display(),
+                message
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn description(&self) -> &str {
+        match *self {
+            LoadError::InvalidPath(_) => "invalid adapter path",
+            LoadError::Open { .. } => "failed to load adapter",
+            LoadError::NoServices(_) => "adapter registered no services",
+            LoadError::Refused { .. } => "adapter registered only refused services",
+        }
+    }
+}
+
+/// A handle returned by `dlopen`, closed on drop.
+pub(crate) struct Library {
+    handle: *mut c_void,
+    path: PathBuf,
+}
+
+// dlopen handles may be used and closed from any thread.
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+impl Library {
+    pub(crate) fn open(path: &Path) -> Result<Library, LoadError> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| LoadError::InvalidPath(path.to_owned()))?;
+        let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(LoadError::Open {
+                path: path.to_owned(),
+                message: last_error(),
+            });
+        }
+        Ok(Library {
+            handle,
+            path: path.to_owned(),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe {
+            libc::dlclose(self.handle);
+        }
+    }
+}
+
+fn last_error() -> String {
+    unsafe {
+        let error = libc::dlerror();
+        if error.is_null() {
+            String::from("unknown error")
+        } else {
+            CStr::from_ptr(error).to_string_lossy().into_owned()
+        }
+    }
+}
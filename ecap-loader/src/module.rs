@@ -0,0 +1,130 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use ecap::common::log::{FrequencyLevel, ImportanceLevel, LogVerbosity, MessageSizeLevel};
+use ecap::common::Error;
+use ecap::host::check_libecap_version;
+use ecap_common_link::{erased_host, take_erased_services};
+use erased_ecap::host::Host as ErasedHost;
+
+use library::{LoadError, Library};
+use service::LoadedService;
+
+lazy_static! {
+    /// Services are collected from a process-wide list, so loads must
+    /// not interleave.
+    static ref LOADING: Mutex<()> = Mutex::new(());
+}
+
+/// An adapter library, along with the services it registered.
+pub struct Module {
+    services: Vec<LoadedService>,
+    library: Arc<Library>,
+}
+
+impl Module {
+    /// Loads the adapter library at `path`.
+    ///
+    /// Any services registered through `ecap-common-link` since the last
+    /// load are attributed to this library; they are only collected if no
+    /// translator (such as `ecap-cpp`) has been registered in this process.
+    ///
+    /// Each service's libecap version is checked with the registered
+    /// host's `note_versioned_service`, or against `LIBECAP_VERSION` if
+    /// there is no host. Refused services are logged and skipped, as
+    /// `ecap-cpp` does for C++ adapters; the library fails to load only
+    /// if all of its services are refused.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Module, LoadError> {
+        let path = path.as_ref();
+        let _guard = LOADING.lock().unwrap();
+
+        let library = Arc::new(Library::open(path)?);
+        let mut services = Vec::new();
+        let mut refused = None;
+        for service in take_erased_services() {
+            let noted = match erased_host() {
+                Some(host) => host.note_versioned_service(service.version()),
+                None => check_libecap_version(service.version()),
+            };
+            match noted {
+                Ok(()) => {
+                    let service = service.take::<dyn ErasedHost>();
+                    services.push(LoadedService::new(service, library.clone()));
+                }
+                // Refused services are never called into, not even to
+                // drop them, as they may not be compatible with us; the
+                // `ErasedService` leaks its allocation instead.
+                Err(e) => {
+                    report_refused_service(path, &e);
+                    refused = Some(e);
+                }
+            }
+        }
+        if services.is_empty() {
+            return Err(match refused {
+                Some(e) => LoadError::Refused {
+                    path: path.to_owned(),
+                    message: e.to_string(),
+                },
+                None => LoadError::NoServices(path.to_owned()),
+            });
+        }
+
+        Ok(Module { services, library })
+    }
+
+    /// The path this module was loaded from.
+    pub fn path(&self) -> &Path {
+        self.library.path()
+    }
+
+    pub fn services(&self) -> &[LoadedService] {
+        &self.services
+    }
+
+    pub fn services_mut(&mut self) -> &mut [LoadedService] {
+        &mut self.services
+    }
+
+    /// Take ownership of the services; the library stays loaded until
+    /// they (and their transactions) are dropped.
+    pub fn into_services(self) -> Vec<LoadedService> {
+        self.services
+    }
+
+    /// Retires all services and unloads the library.
+    ///
+    /// Returns `false` if transactions created by this module are still
+    /// alive, in which case the library is unloaded once the last of
+    /// them is dropped.
+    pub fn unload(self) -> bool {
+        let Module { services, library } = self;
+        drop(services);
+        Arc::try_unwrap(library).is_ok()
+    }
+}
+
+/// Log a refused service to the registered host, or to stderr if there
+/// is none.
+fn report_refused_service(path: &Path, error: &Error) {
+    let verbosity = LogVerbosity {
+        importance: ImportanceLevel::Critical,
+        frequency: FrequencyLevel::Application,
+        size: MessageSizeLevel::Normal,
+    };
+    match erased_host() {
+        Some(host) => {
+            if let Some(mut stream) = host.open_debug(verbosity) {
+                let _ = write!(
+                    stream,
+                    "refusing eCAP service from {}: {}",
+                    path.display(),
+                    error
+                );
+                host.close_debug(stream);
+            }
+        }
+        None => eprintln!("refusing eCAP service from {}: {}", path.display(), error),
+    }
+}
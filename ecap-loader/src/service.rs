@@ -0,0 +1,216 @@
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ecap::adapter::{Service, Transaction};
+use ecap::common::{Area, Error, Name, NamedValueVisitor, Options, Version};
+use erased_ecap::adapter::Service as ErasedService;
+use erased_ecap::adapter::Transaction as ErasedTransaction;
+use erased_ecap::host::Host as ErasedHost;
+use erased_ecap::host::Transaction as HostTransaction;
+
+use library::Library;
+
+type AdapterTransaction = Box<dyn ErasedTransaction>;
+
+/// A service registered by a loaded adapter library.
+///
+/// The service is retired when dropped, if that has not already
+/// happened.
+pub struct LoadedService {
+    // The service must be dropped before its library is closed.
+    service: Box<dyn ErasedService<dyn ErasedHost>>,
+    retired: Cell<bool>,
+    library: Arc<Library>,
+}
+
+impl LoadedService {
+    pub(crate) fn new(
+        service: Box<dyn ErasedService<dyn ErasedHost>>,
+        library: Arc<Library>,
+    ) -> LoadedService {
+        LoadedService {
+            service,
+            retired: Cell::new(false),
+            library,
+        }
+    }
+
+    /// The path of the library which registered this service.
+    pub fn path(&self) -> &Path {
+        self.library.path()
+    }
+
+    pub fn is_retired(&self) -> bool {
+        self.retired.get()
+    }
+}
+
+impl Drop for LoadedService {
+    fn drop(&mut self) {
+        if !self.retired.get() {
+            ErasedService::<dyn ErasedHost>::retire(&*self.service);
+        }
+    }
+}
+
+impl Service<dyn ErasedHost> for LoadedService {
+    type Transaction = LoadedTransaction;
+
+    fn uri(&self) -> String {
+        ErasedService::<dyn ErasedHost>::uri(&*self.service)
+    }
+
+    fn tag(&self) -> String {
+        ErasedService::<dyn ErasedHost>::tag(&*self.service)
+    }
+
+    fn describe(&self) -> String {
+        ErasedService::<dyn ErasedHost>::describe(&*self.service)
+    }
+
+    fn libecap_version(&self) -> Version {
+        ErasedService::<dyn ErasedHost>::libecap_version(&*self.service)
+    }
+
+    fn is_async(&self) -> bool {
+        ErasedService::<dyn ErasedHost>::is_async(&*self.service)
+    }
+
+    fn configure<T: Options>(&mut self, options: &T) -> Result<(), Error> {
+        ErasedService::<dyn ErasedHost>::configure(&mut *self.service, options)
+    }
+
+    fn reconfigure<T: Options>(&mut self, options: &T) -> Result<(), Error> {
+        ErasedService::<dyn ErasedHost>::reconfigure(&mut *self.service, options)
+    }
+
+    fn start(&self) {
+        ErasedService::<dyn ErasedHost>::start(&*self.service)
+    }
+
+    fn suspend(&self, timeout: &mut Duration) {
+        ErasedService::<dyn ErasedHost>::suspend(&*self.service, timeout)
+    }
+
+    fn resume(&self) {
+        ErasedService::<dyn ErasedHost>::resume(&*self.service)
+    }
+
+    fn stop(&self) {
+        ErasedService::<dyn ErasedHost>::stop(&*self.service)
+    }
+
+    fn retire(&self) {
+        self.retired.set(true);
+        ErasedService::<dyn ErasedHost>::retire(&*self.service)
+    }
+
+    fn wants_url(&self, url: &CStr) -> bool {
+        ErasedService::<dyn ErasedHost>::wants_url(&*self.service, url)
+    }
+
+    fn make_transaction(
+        &mut self,
+        host: &mut (dyn HostTransaction<dyn ErasedHost> + 'static),
+    ) -> LoadedTransaction {
+        LoadedTransaction {
+            transaction: ErasedService::<dyn ErasedHost>::make_transaction(
+                &mut *self.service,
+                host,
+            ),
+            _library: self.library.clone(),
+        }
+    }
+}
+
+/// A transaction created by a `LoadedService`.
+///
+/// This keeps the adapter library loaded until it is dropped.
+pub struct LoadedTransaction {
+    transaction: AdapterTransaction,
+    // Only held so that the library outlives the transaction.
+    _library: Arc<Library>,
+}
+
+impl Options for LoadedTransaction {
+    fn option(&self, name: &Name) -> Option<Area> {
+        Options::option(&self.transaction, name)
+    }
+
+    fn visit_each<V: NamedValueVisitor>(&self, visitor: V) {
+        Options::visit_each(&self.transaction, visitor)
+    }
+}
+
+macro_rules! delegate_transaction {
+    ($name:ident) => {
+        fn $name<'a>(&mut self, host: &'a mut (dyn HostTransaction<dyn ErasedHost> + 'static))
+        where
+            (dyn HostTransaction<dyn ErasedHost> + 'static): 'a,
+        {
+            <AdapterTransaction as Transaction<dyn ErasedHost>>::$name(&mut self.transaction, host)
+        }
+    };
+}
+
+impl Transaction<dyn ErasedHost> for LoadedTransaction {
+    delegate_transaction!(start);
+    delegate_transaction!(stop);
+    delegate_transaction!(resume);
+    delegate_transaction!(adapted_body_discard);
+    delegate_transaction!(adapted_body_make);
+    delegate_transaction!(adapted_body_make_more);
+    delegate_transaction!(adapted_body_stop_making);
+    delegate_transaction!(adapted_body_pause);
+    delegate_transaction!(adapted_body_resume);
+
+    fn adapted_body_content<'a>(
+        &mut self,
+        host: &'a mut (dyn HostTransaction<dyn ErasedHost> + 'static),
+        offset: usize,
+        size: usize,
+    ) -> Area
+    where
+        (dyn HostTransaction<dyn ErasedHost> + 'static): 'a,
+    {
+        <AdapterTransaction as Transaction<dyn ErasedHost>>::adapted_body_content(
+            &mut self.transaction,
+            host,
+            offset,
+            size,
+        )
+    }
+
+    fn adapted_body_content_shift<'a>(
+        &mut self,
+        host: &'a mut (dyn HostTransaction<dyn ErasedHost> + 'static),
+        size: usize,
+    ) where
+        (dyn HostTransaction<dyn ErasedHost> + 'static): 'a,
+    {
+        <AdapterTransaction as Transaction<dyn ErasedHost>>::adapted_body_content_shift(
+            &mut self.transaction,
+            host,
+            size,
+        )
+    }
+
+    fn virgin_body_content_done<'a>(
+        &mut self,
+        host: &'a mut (dyn HostTransaction<dyn ErasedHost> + 'static),
+        at_end: bool,
+    ) where
+        (dyn HostTransaction<dyn ErasedHost> + 'static): 'a,
+    {
+        <AdapterTransaction as Transaction<dyn ErasedHost>>::virgin_body_content_done(
+            &mut self.transaction,
+            host,
+            at_end,
+        )
+    }
+
+    delegate_transaction!(virgin_body_content_available);
+}
@@ -0,0 +1,223 @@
+//! Runs a single HTTP message through an adapter service, without a
+//! proxy.
+//!
+//! ```text
+//! ecap-run -o victim=foo -o replacement=bar \
+//!     target/debug/libadapter_modifying.so response.http
+//! ```
+
+extern crate ecap;
+extern crate ecap_host;
+extern crate ecap_loader;
+extern crate erased_ecap;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+use ecap::adapter::Service;
+use ecap::common::header::Header;
+use ecap::common::log::ImportanceLevel;
+use ecap::common::name::HEADER_CONTENT_LENGTH;
+use ecap::common::Message;
+use ecap_host::{http, Decision, MemHost, MemMessage, MemOptions, MemTransaction};
+use ecap_loader::{LoadedService, Module};
+use erased_ecap::host::Host as ErasedHost;
+
+const USAGE: &str = "\
+usage: ecap-run [OPTIONS] ADAPTER [MESSAGE]
+
+Runs the HTTP message in MESSAGE (or stdin) through a service of the
+adapter library ADAPTER, and writes the resulting message to stdout.
+
+options:
+    -o NAME=VALUE       pass a configuration option to the service
+    -s, --service URI   use the service with this URI, not the first
+    -c, --cause FILE    the request which caused MESSAGE, a response
+    -v, --verbose       describe the service and outcome on stderr,
+                        and include debugging output of the adapter
+    -h, --help          show this message
+
+Debug output of the adapter is written to stderr. The exit status is 2 if the adapter blocked or aborted the message.";
+
+/// Exit status when the adapter blocked or aborted the message.
+const EXIT_NO_MESSAGE: i32 = 2;
+
+struct Args {
+    options: MemOptions,
+    service: Option<String>,
+    cause: Option<String>,
+    verbose: bool,
+    adapter: String,
+    message: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = env::args().skip(1);
+    let mut options = MemOptions::new();
+    let mut service = None;
+    let mut cause = None;
+    let mut verbose = false;
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-v" | "--verbose" => verbose = true,
+            "-o" => {
+                let pair = args.next().ok_or("-o requires NAME=VALUE")?;
+                options
+                    .insert_pair(&pair)
+                    .ok_or_else(|| format!("option `{}` is not NAME=VALUE", pair))?;
+            }
+            "-s" | "--service" => {
+                service = Some(args.next().ok_or("--service requires a URI")?);
+            }
+            "-c" | "--cause" => {
+                cause = Some(args.next().ok_or("--cause requires a file")?);
+            }
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option `{}`", arg));
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let adapter = positional.next().ok_or("missing ADAPTER")?;
+    let message = positional.next();
+    if positional.next().is_some() {
+        return Err(String::from("too many arguments"));
+    }
+
+    Ok(Args {
+        options,
+        service,
+        cause,
+        verbose,
+        adapter,
+        message,
+    })
+}
+
+/// Reads `path`, or stdin if `path` is `None` or `-`.
+fn read_input(path: Option<&str>) -> Result<Vec<u8>, String> {
+    let mut input = Vec::new();
+    match path {
+        None | Some("-") => io::stdin()
+            .read_to_end(&mut input)
+            .map_err(|e| format!("failed to read stdin: {}", e))?,
+        Some(path) => File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut input))
+            .map_err(|e| format!("failed to read {}: {}", path, e))?,
+    };
+    Ok(input)
+}
+
+fn read_message(path: Option<&str>) -> Result<(MemMessage, Option<Vec<u8>>), String> {
+    let input = read_input(path)?;
+    http::parse(&input).map_err(|e| format!("{}: {}", path.unwrap_or("stdin"), e))
+}
+
+fn select_service(module: Module, uri: Option<&str>) -> Result<LoadedService, String> {
+    let path = module.path().display().to_string();
+    let mut services = module.into_services();
+    let index = match uri {
+        None => 0,
+        Some(uri) => services
+            .iter()
+            .position(|service| service.uri() == uri)
+            .ok_or_else(|| format!("{} has no service {}", path, uri))?,
+    };
+    Ok(services.swap_remove(index))
+}
+
+/// `message` with a `Content-Length` for `body`, unless it is already
+/// framed, e.g. when the adapter did not know the size of the body.
+fn framed(message: &MemMessage, body: &[u8]) -> MemMessage {
+    let mut message = Clone::clone(message);
+    if !Message::<MemHost>::header(&message).contains_field(&HEADER_CONTENT_LENGTH) {
+        Message::<MemHost>::set_body_length(&mut message, Some(body.len() as u64));
+    }
+    message
+}
+
+fn run() -> Result<i32, String> {
+    let args = parse_args()?;
+
+    let mut host = MemHost::new();
+    if args.verbose {
+        host.set_importance(ImportanceLevel::Debug);
+    }
+    let host: &'static MemHost = Box::leak(Box::new(host));
+    ecap_loader::register_host(host);
+
+    let module = Module::open(&args.adapter).map_err(|e| e.to_string())?;
+    let mut service = select_service(module, args.service.as_ref().map(|s| &s[..]))?;
+    if args.verbose {
+        eprintln!("service {}: {}", service.uri(), service.describe());
+    }
+
+    let (virgin, body) = read_message(args.message.as_ref().map(|s| &s[..]))?;
+    let mut transaction = MemTransaction::new(virgin);
+    if let Some(body) = body {
+        transaction.set_virgin_body(body);
+    }
+    if let Some(ref cause) = args.cause {
+        transaction.set_cause(read_message(Some(cause))?.0);
+    }
+
+    service
+        .configure(&args.options)
+        .map_err(|e| format!("{}: {}", service.uri(), e))?;
+    service.start();
+    let outcome = transaction.run::<dyn ErasedHost, _>(&mut service);
+    service.stop();
+    service.retire();
+
+    for line in host.debug_log() {
+        eprintln!("{}", line);
+    }
+
+    for delay in outcome.delays() {
+        eprintln!("delayed: {}", delay);
+    }
+    if args.verbose {
+        eprintln!("decision: {:?}", outcome.decision());
+    }
+    if !outcome.is_complete() {
+        eprintln!("warning: the adapter did not finish the transaction");
+    }
+
+    match outcome.message() {
+        Some(message) => {
+            let message = framed(message, outcome.body());
+            let mut out = Vec::new();
+            http::write(&message, outcome.body(), &mut out);
+            io::stdout()
+                .write_all(&out)
+                .map_err(|e| format!("failed to write stdout: {}", e))?;
+            Ok(0)
+        }
+        None => {
+            if outcome.decision() != Decision::Pending {
+                eprintln!("no message: the adapter decided {:?}", outcome.decision());
+            }
+            Ok(EXIT_NO_MESSAGE)
+        }
+    }
+}
+
+fn main() {
+    match run() {
+        Ok(status) => process::exit(status),
+        Err(e) => {
+            eprintln!("ecap-run: {}", e);
+            process::exit(1);
+        }
+    }
+}
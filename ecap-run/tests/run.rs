@@ -0,0 +1,97 @@
+//! Runs messages through the sample modifying adapter with `ecap-run`.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Output, Stdio};
+
+/// The adapter library, which is built into the directory of the test
+/// as one of its dependencies.
+fn adapter() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    exe.parent().unwrap().join(format!(
+        "{}adapter_modifying{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    ))
+}
+
+/// Runs `input` through the adapter, replacing `hello` with `howdy`.
+fn run(args: &[&str], input: &[u8]) -> Output {
+    run_replacing("howdy", args, input)
+}
+
+/// Runs `input` through the adapter, replacing `hello` with
+/// `replacement`.
+fn run_replacing(replacement: &str, args: &[&str], input: &[u8]) -> Output {
+    let replacement = format!("replacement={}", replacement);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ecap-run"))
+        .args(["-o", "victim=hello", "-o", &replacement])
+        .args(args)
+        .arg(adapter())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn request_without_body() {
+    let output = run(
+        &[],
+        b"GET http://example.com/hello HTTP/1.1\r\nHost: example.com\r\n\r\n",
+    );
+    // No body, and so no framing, is added.
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "GET http://example.com/hello HTTP/1.1\r\n\
+         Host: example.com\r\n\
+         X-Ecap: foo\r\n\r\n"
+    );
+}
+
+#[test]
+fn request_of_new_length() {
+    let output = run_replacing(
+        "hi",
+        &[],
+        b"POST http://example.com/ HTTP/1.1\r\nContent-Length: 12\r\n\r\nhello, world",
+    );
+    // The adapter cannot know the new length up front, so it is framed
+    // by ecap-run, which has the whole body.
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "POST http://example.com/ HTTP/1.1\r\n\
+         X-Ecap: foo\r\n\
+         Content-Length: 9\r\n\r\n\
+         hi, world"
+    );
+}
+
+#[test]
+fn response_with_cause() {
+    let cause = env::temp_dir().join(format!("ecap-run-cause-{}.http", process::id()));
+    fs::write(&cause, b"GET http://example.com/ HTTP/1.1\r\n\r\n").unwrap();
+    let output = run(
+        &["--cause", cause.to_str().unwrap()],
+        b"HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\nhello, world",
+    );
+    fs::remove_file(&cause).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "HTTP/1.1 200 OK\r\n\
+         X-Ecap: foo\r\n\
+         Content-Length: 12\r\n\r\n\
+         howdy, world"
+    );
+}
@@ -1,11 +1,9 @@
-#![feature(unwind_attributes, extern_types)]
-
 extern crate libc;
 
 use std::marker::PhantomData;
 use std::{mem, ptr};
 
-use libc::{c_char, c_int, c_void, size_t};
+use libc::{c_char, c_int, c_void, size_t, timeval};
 
 #[repr(C)]
 pub struct Panic {
@@ -34,17 +32,33 @@ pub struct Version {
     pub micro: c_int,
 }
 
-extern "C" {
-    pub type FirstLine;
-    pub type RequestLine;
-    pub type StatusLine;
-    pub type Message;
-    pub type Header;
-    pub type Body;
-    pub type HostTransaction;
-    pub type Options;
-    pub type Host;
-    pub type Ostream;
+/// Declares types only ever used behind pointers from C++.
+macro_rules! opaque_types {
+    ($($name:ident),* $(,)*) => {
+        $(
+            #[repr(C)]
+            pub struct $name {
+                _private: [u8; 0],
+                _marker: PhantomData<*mut ()>,
+            }
+        )*
+    };
+}
+
+opaque_types! {
+    FirstLine,
+    RequestLine,
+    StatusLine,
+    Message,
+    Header,
+    Body,
+    HostTransaction,
+    Options,
+    Host,
+    Ostream,
+    RustHostTransaction,
+    NamedValueVisitor,
+    NameObject,
 }
 
 #[repr(C)]
@@ -54,6 +68,32 @@ pub struct LogVerbosity(pub size_t);
 #[repr(align(8))]
 pub struct SharedPtrMessage([u8; 16], PhantomData<*mut ()>);
 
+/// A `shared_ptr` to a C++ adapter service or transaction.
+#[repr(C)]
+#[repr(align(8))]
+pub struct SharedPtr([u8; 16], PhantomData<*mut ()>);
+
+/// A Rust trait object pointer, passed through C++ untouched.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct DynPtr {
+    pub data: *mut c_void,
+    pub vtable: *mut c_void,
+}
+
+impl DynPtr {
+    pub fn null() -> DynPtr {
+        DynPtr {
+            data: ptr::null_mut(),
+            vtable: ptr::null_mut(),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.data.is_null()
+    }
+}
+
 #[repr(C)]
 pub struct BodySize {
     pub known: bool,
@@ -72,6 +112,19 @@ pub struct Name {
     pub phantom: PhantomData<*mut ()>,
 }
 
+/// A name returned by value from C++, whose image is owned by Rust.
+#[repr(C)]
+pub struct OwnedName {
+    pub image: CVec,
+    pub id: c_int,
+    pub host_id: c_int,
+}
+
+/// The kinds of first line, as returned by `rust_shim_first_line_kind`.
+pub const FIRST_LINE_OTHER: c_int = 0;
+pub const FIRST_LINE_REQUEST: c_int = 1;
+pub const FIRST_LINE_STATUS: c_int = 2;
+
 #[repr(C)]
 pub struct Area {
     pub size: size_t,
@@ -82,6 +135,12 @@ pub struct Area {
     pub __align: [u64; 0],
 }
 
+/// Storage for the Rust details of an area, held by C++ area details;
+/// we assert that this is large enough for an `ecap::common::area::DetailsStack`.
+#[repr(C)]
+#[repr(align(8))]
+pub struct RustDetails(pub [u8; 96], pub PhantomData<*mut ()>);
+
 #[repr(C)]
 pub struct PStr {
     pub size: size_t,
@@ -125,7 +184,6 @@ impl Drop for CVec {
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_new_string(buf: *const c_char, len: size_t) -> CVec {
     let mut data = Vec::with_capacity(len);
     ptr::copy_nonoverlapping(buf as *const u8, data.as_mut_ptr(), len);
@@ -139,19 +197,29 @@ pub unsafe extern "C" fn rust_new_string(buf: *const c_char, len: size_t) -> CVe
 }
 
 #[no_mangle]
-#[unwind(aborts)]
 pub unsafe extern "C" fn rust_free_string(s: CVec) {
     mem::drop(s);
 }
 
 pub type VisitorCallback = extern "C" fn(Name, Area, *mut c_void);
 
-#[unwind(aborts)]
 extern "C" {
     pub fn rust_shim_first_line_version(line: *const FirstLine, out: *mut Version) -> bool;
     pub fn rust_shim_first_line_set_version(line: *mut FirstLine, version: *const Version) -> bool;
-    pub fn rust_shim_first_line_protocol(line: *const FirstLine, out: *mut Name) -> bool;
+    pub fn rust_shim_first_line_protocol(line: *const FirstLine, out: *mut OwnedName) -> bool;
     pub fn rust_shim_first_line_set_protocol(line: *mut FirstLine, protocol: *const Name) -> bool;
+    pub fn rust_shim_first_line_kind(line: *const FirstLine, out: *mut c_int) -> bool;
+
+    pub fn rust_shim_request_line_uri(line: *const FirstLine, out: *mut Area) -> bool;
+    pub fn rust_shim_request_line_set_uri(line: *mut FirstLine, uri: *const Area) -> bool;
+    pub fn rust_shim_request_line_method(line: *const FirstLine, out: *mut OwnedName) -> bool;
+    pub fn rust_shim_request_line_set_method(line: *mut FirstLine, method: *const Name) -> bool;
+
+    pub fn rust_shim_status_line_status_code(line: *const FirstLine, out: *mut c_int) -> bool;
+    pub fn rust_shim_status_line_set_status_code(line: *mut FirstLine, code: c_int) -> bool;
+    pub fn rust_shim_status_line_reason_phrase(line: *const FirstLine, out: *mut Area) -> bool;
+    pub fn rust_shim_status_line_set_reason_phrase(line: *mut FirstLine, phrase: *const Area)
+        -> bool;
 
     pub fn rust_shim_message_first_line(msg: *const Message, out: *mut *const FirstLine) -> bool;
     pub fn rust_shim_message_first_line_mut(msg: *mut Message, out: *mut *mut FirstLine) -> bool;
@@ -238,6 +306,14 @@ extern "C" {
 
     pub fn rust_area_new_slice(buf: *const c_char, len: size_t, out: *mut Area) -> bool;
     pub fn rust_area_free(area: *mut Area);
+    pub fn rust_shim_area_ref(area: *const Area);
+    pub fn rust_shim_area_from_rust(
+        buf: *const c_char,
+        len: size_t,
+        details: *const RustDetails,
+        out: *mut Area,
+    ) -> bool;
+    pub fn rust_shim_area_rust_details(area: *const Area, out: *mut RustDetails) -> bool;
 
     pub fn options_option(options: *const Options, name: *const Name, out: *mut Area) -> bool;
     pub fn options_visit(options: *const Options, cb: VisitorCallback, extra: *mut c_void) -> bool;
@@ -255,5 +331,128 @@ extern "C" {
     pub fn rust_shim_host_new_response(host: *const Host, out: *mut SharedPtrMessage) -> bool;
     pub fn rust_shim_ostream_write(stream: *mut Ostream, buf: *const c_char, len: size_t) -> bool;
 
-    pub fn rust_shim_register_service(service: *mut *mut c_void, out: *mut bool) -> bool;
+    pub fn rust_shim_register_service(
+        service: *mut *mut c_void,
+        version: *const c_char,
+        out: *mut bool,
+    ) -> bool;
+
+    pub fn rust_shim_register_host(host: DynPtr) -> bool;
+    pub fn rust_shim_exception_message(out: *mut CVec) -> bool;
+    pub fn rust_shim_exception_discard();
+
+    pub fn rust_shim_name_assign(out: *mut NameObject, name: *const Name) -> bool;
+    pub fn rust_shim_predefined_name_ids(ids: *mut c_int, len: size_t) -> size_t;
+    pub fn rust_shim_visitor_visit(
+        visitor: *mut NamedValueVisitor,
+        name: *const Name,
+        buf: *const c_char,
+        len: size_t,
+    ) -> bool;
+
+    pub fn rust_shim_adapter_service_free(service: *mut SharedPtr);
+    pub fn rust_shim_adapter_service_uri(service: *mut SharedPtr, out: *mut CVec) -> bool;
+    pub fn rust_shim_adapter_service_tag(service: *mut SharedPtr, out: *mut CVec) -> bool;
+    pub fn rust_shim_adapter_service_describe(service: *mut SharedPtr, out: *mut CVec) -> bool;
+    pub fn rust_shim_adapter_service_is_async(service: *mut SharedPtr, out: *mut bool) -> bool;
+    pub fn rust_shim_adapter_service_configure(service: *mut SharedPtr, options: DynPtr) -> bool;
+    pub fn rust_shim_adapter_service_reconfigure(service: *mut SharedPtr, options: DynPtr)
+        -> bool;
+    pub fn rust_shim_adapter_service_start(service: *mut SharedPtr) -> bool;
+    pub fn rust_shim_adapter_service_stop(service: *mut SharedPtr) -> bool;
+    pub fn rust_shim_adapter_service_retire(service: *mut SharedPtr) -> bool;
+    pub fn rust_shim_adapter_service_resume(service: *mut SharedPtr) -> bool;
+    pub fn rust_shim_adapter_service_suspend(service: *mut SharedPtr, timeout: *mut timeval)
+        -> bool;
+    pub fn rust_shim_adapter_service_wants_url(
+        service: *mut SharedPtr,
+        url: *const c_char,
+        out: *mut bool,
+    ) -> bool;
+    pub fn rust_shim_adapter_service_make_xaction(
+        service: *mut SharedPtr,
+        host: DynPtr,
+        hostx: *mut *mut RustHostTransaction,
+        out: *mut SharedPtr,
+    ) -> bool;
+
+    pub fn rust_shim_adapter_xaction_free(xaction: *mut SharedPtr, hostx: *mut RustHostTransaction);
+    pub fn rust_shim_rust_host_xaction_set_waker(
+        hostx: *mut RustHostTransaction,
+        waker: *const c_void,
+    );
+    pub fn rust_shim_adapter_xaction_options(
+        xaction: *mut SharedPtr,
+        out: *mut *const Options,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_start(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_stop(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_resume(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_ab_discard(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_ab_make(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_ab_make_more(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_ab_stop_making(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_ab_pause(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_ab_resume(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_vb_content_available(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_vb_content_done(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+        at_end: bool,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_ab_content(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+        offset: size_t,
+        size: size_t,
+        out: *mut Area,
+    ) -> bool;
+    pub fn rust_shim_adapter_xaction_ab_content_shift(
+        xaction: *mut SharedPtr,
+        hostx: *mut RustHostTransaction,
+        host: DynPtr,
+        size: size_t,
+    ) -> bool;
 }
@@ -1,3 +1,5 @@
+// The version ecap::common::LIBECAP_VERSION names, which libecap's
+// headers expect to be defined.
 #define LIBECAP_VERSION "1.0.1"
 
 #include <iostream>
@@ -10,6 +12,7 @@
 #include <libecap/host/xaction.h>
 #include <libecap/host/host.h>
 #include <libecap/common/name.h>
+#include <libecap/common/names.h>
 #include <libecap/common/named_values.h>
 #include <libecap/common/message.h>
 #include <libecap/common/header.h>
@@ -17,10 +20,12 @@
 #include <libecap/common/body.h>
 #include <libecap/common/body_size.h>
 #include <libecap/common/memory.h>
+#include <libecap/common/area.h>
 #include <libecap/common/delay.h>
 #include <sys/time.h>
 #include <climits>
 #include <exception>
+#include <functional>
 #include <vector>
 #include <libecap/common/errors.h>
 
@@ -61,6 +66,14 @@ struct rust_area {
     rust_details__ details;
 };
 
+// A Rust DetailsStack, copied bytewise; Rust asserts that it fits.
+#define RUST_DETAILS_SIZE 96
+
+struct rust_details_stack {
+    char value[RUST_DETAILS_SIZE];
+    uint64_t __align[0];
+};
+
 struct panic_location {
     rust_string file;
     int line;
@@ -98,6 +111,21 @@ struct rust_name {
     int host_id;
 };
 
+// A name whose image is owned by Rust, for names returned by value.
+struct rust_owned_name {
+    rust_string image;
+    int id;
+    int host_id;
+};
+
+// Which of libecap::RequestLine and libecap::StatusLine a first line is;
+// these must match the FIRST_LINE_* constants in ecap-sys.
+enum first_line_kind: int {
+    FIRST_LINE_OTHER = 0,
+    FIRST_LINE_REQUEST = 1,
+    FIRST_LINE_STATUS = 2,
+};
+
 // The returned rust_name contains pointers into the passed name and must not outlive name.
 rust_name to_rust_name(const libecap::Name &name) {
     const cpp_name &namef = reinterpret_cast<const cpp_name &>(name);
@@ -120,6 +148,48 @@ libecap::Name from_rust_name(const rust_name *name) {
     return *reinterpret_cast<libecap::Name*>(&cpp);
 }
 
+// The names predefined by libecap; this must list the same names, in
+// the same order, as ecap::common::name.
+static const libecap::Name *const predefined_names[] = {
+    &libecap::protocolHttp,
+    &libecap::protocolHttps,
+    &libecap::protocolFtp,
+    &libecap::protocolGopher,
+    &libecap::protocolWais,
+    &libecap::protocolUrn,
+    &libecap::protocolWhois,
+    &libecap::methodGet,
+    &libecap::methodPut,
+    &libecap::methodPost,
+    &libecap::methodHead,
+    &libecap::methodConnect,
+    &libecap::methodOptions,
+    &libecap::methodDelete,
+    &libecap::methodTrace,
+    &libecap::headerContentLength,
+    &libecap::headerTransferEncoding,
+    &libecap::headerReferer,
+    &libecap::headerVia,
+    &libecap::headerXClientIp,
+    &libecap::headerXServerIp,
+    &libecap::metaClientIp,
+    &libecap::metaServerIp,
+    &libecap::metaUserName,
+    &libecap::metaAuthenticatedUser,
+    &libecap::metaAuthenticatedGroups,
+    &libecap::metaSubscriberId,
+};
+
+// Writes the ids libecap assigned to the predefined names into ids, which
+// has room for len ids, and returns the number of predefined names.
+extern "C" size_t rust_shim_predefined_name_ids(int *ids, size_t len) noexcept {
+    const size_t count = sizeof(predefined_names) / sizeof(predefined_names[0]);
+    for (size_t i = 0; i < count && i < len; i++) {
+        ids[i] = reinterpret_cast<const cpp_name *>(predefined_names[i])->id_;
+    }
+    return count;
+}
+
 extern "C" void rust_area_free(rust_area *area) noexcept {
     libecap::Area::Details& details =
         reinterpret_cast<libecap::Area::Details&>(area->details);
@@ -145,6 +215,14 @@ rust_area to_rust_area(libecap::Area area) {
     return foo;
 }
 
+// Takes another reference on the details of area, which Rust copies
+// bytewise; each copy is released with rust_area_free.
+extern "C" void rust_shim_area_ref(const rust_area *area) noexcept {
+    rust_details__ copy;
+    new (&copy.details) libecap::Area::Details(
+        reinterpret_cast<const libecap::Area::Details&>(area->details));
+}
+
 #define SHARED_PTR_MESSAGE_SIZE 16
 
 struct rust_shared_ptr_message {
@@ -215,6 +293,9 @@ extern "C" {
 
     bool rust_panic_pop(rust_panic *) noexcept;
     void rust_panic_free(rust_panic ) noexcept;
+
+    bool rust_area_details_increment(const rust_details_stack *) noexcept;
+    bool rust_area_details_decrement(const rust_details_stack *) noexcept;
 }
 
 template<typename F>
@@ -267,6 +348,39 @@ rust_string to_rust_string(const std::string &s) {
     return rust_new_string(s.data(), s.length());
 }
 
+// Writes the what() message of the most recent exception caught by a
+// shim into out, if there is one.
+extern "C" bool rust_shim_exception_message(rust_string *out) noexcept {
+    if (CURRENT_EXCEPTIONS.empty()) {
+        return false;
+    }
+    try {
+        std::rethrow_exception(CURRENT_EXCEPTIONS.back());
+    } catch (std::exception const &e) {
+        *out = to_rust_string(e.what());
+    } catch (...) {
+        *out = to_rust_string("unknown C++ exception");
+    }
+    return true;
+}
+
+// Forgets the most recent exception caught by a shim, once Rust has
+// handled it as an error instead of unwinding back into C++.
+extern "C" void rust_shim_exception_discard() noexcept {
+    if (!CURRENT_EXCEPTIONS.empty()) {
+        CURRENT_EXCEPTIONS.pop_back();
+    }
+}
+
+rust_owned_name to_rust_owned_name(const libecap::Name &name) {
+    const cpp_name &namef = reinterpret_cast<const cpp_name &>(name);
+    return rust_owned_name {
+        image: to_rust_string(namef.image_),
+        id: namef.id_,
+        host_id: namef.hostId_,
+    };
+}
+
 // TODO: This will copy the std::string returned by uri
 extern "C" bool rust_shim_host_uri(const libecap::host::Host *host, rust_string *out) noexcept {
     return call_cpp_catch_exception([&] () {
@@ -324,6 +438,48 @@ extern "C" bool rust_area_new_slice(char *buf, size_t len, rust_area *out) noexc
     });
 }
 
+// Area details which hold a reference on the details of a Rust area, so
+// that Rust buffers can be passed to C++ without copying.
+class RustAreaDetails: public libecap::AreaDetails {
+    public:
+        RustAreaDetails(const rust_details_stack &aDetails): details(aDetails) {
+            call_rust_maybe_throw([&] () {
+                return ::rust_area_details_increment(&details);
+            });
+        }
+
+        virtual ~RustAreaDetails() {
+            call_rust_maybe_throw([&] () {
+                return ::rust_area_details_decrement(&details);
+            });
+        }
+
+        const rust_details_stack details;
+};
+
+// The returned area takes its own reference on details; the caller keeps
+// the reference it had.
+extern "C" bool rust_shim_area_from_rust(const char *buf, size_t len,
+        const rust_details_stack *details, rust_area *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        libecap::Area::Details ptr(new RustAreaDetails(*details));
+        *out = to_rust_area(libecap::Area(buf, len, ptr));
+    });
+}
+
+// If the details of area were created by rust_shim_area_from_rust, copies
+// the Rust details into out, without taking a reference.
+extern "C" bool rust_shim_area_rust_details(const rust_area *area, rust_details_stack *out) noexcept {
+    const libecap::Area::Details &details =
+        reinterpret_cast<const libecap::Area::Details&>(area->details);
+    auto rust = dynamic_cast<const RustAreaDetails *>(details.get());
+    if (rust == nullptr) {
+        return false;
+    }
+    *out = rust->details;
+    return true;
+}
+
 namespace Adapter { // not required, but adds clarity
 
 class Service: public libecap::adapter::Service {
@@ -774,9 +930,9 @@ extern "C" bool rust_shim_first_line_set_version(libecap::FirstLine *first_line,
     });
 }
 
-extern "C" bool rust_shim_first_line_protocol(const libecap::FirstLine *first_line, rust_name *out) noexcept {
+extern "C" bool rust_shim_first_line_protocol(const libecap::FirstLine *first_line, rust_owned_name *out) noexcept {
     return call_cpp_catch_exception([&] () {
-        *out = to_rust_name(first_line->protocol());
+        *out = to_rust_owned_name(first_line->protocol());
     });
 }
 
@@ -786,6 +942,71 @@ extern "C" bool rust_shim_first_line_set_protocol(libecap::FirstLine *first_line
     });
 }
 
+extern "C" bool rust_shim_first_line_kind(const libecap::FirstLine *first_line, int *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        if (dynamic_cast<const libecap::RequestLine *>(first_line)) {
+            *out = FIRST_LINE_REQUEST;
+        } else if (dynamic_cast<const libecap::StatusLine *>(first_line)) {
+            *out = FIRST_LINE_STATUS;
+        } else {
+            *out = FIRST_LINE_OTHER;
+        }
+    });
+}
+
+// The request and status line accessors take the first line, and throw
+// std::bad_cast if it is of the other kind.
+
+extern "C" bool rust_shim_request_line_uri(const libecap::FirstLine *first_line, rust_area *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = to_rust_area(dynamic_cast<const libecap::RequestLine &>(*first_line).uri());
+    });
+}
+
+extern "C" bool rust_shim_request_line_set_uri(libecap::FirstLine *first_line, const rust_area *uri) noexcept {
+    return call_cpp_catch_exception([&] () {
+        auto area = libecap::Area(uri->buf, uri->size);
+        dynamic_cast<libecap::RequestLine &>(*first_line).uri(area);
+    });
+}
+
+extern "C" bool rust_shim_request_line_method(const libecap::FirstLine *first_line, rust_owned_name *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = to_rust_owned_name(dynamic_cast<const libecap::RequestLine &>(*first_line).method());
+    });
+}
+
+extern "C" bool rust_shim_request_line_set_method(libecap::FirstLine *first_line, const rust_name *method) noexcept {
+    return call_cpp_catch_exception([&] () {
+        dynamic_cast<libecap::RequestLine &>(*first_line).method(from_rust_name(method));
+    });
+}
+
+extern "C" bool rust_shim_status_line_status_code(const libecap::FirstLine *first_line, int *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = dynamic_cast<const libecap::StatusLine &>(*first_line).statusCode();
+    });
+}
+
+extern "C" bool rust_shim_status_line_set_status_code(libecap::FirstLine *first_line, int code) noexcept {
+    return call_cpp_catch_exception([&] () {
+        dynamic_cast<libecap::StatusLine &>(*first_line).statusCode(code);
+    });
+}
+
+extern "C" bool rust_shim_status_line_reason_phrase(const libecap::FirstLine *first_line, rust_area *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = to_rust_area(dynamic_cast<const libecap::StatusLine &>(*first_line).reasonPhrase());
+    });
+}
+
+extern "C" bool rust_shim_status_line_set_reason_phrase(libecap::FirstLine *first_line, const rust_area *phrase) noexcept {
+    return call_cpp_catch_exception([&] () {
+        auto area = libecap::Area(phrase->buf, phrase->size);
+        dynamic_cast<libecap::StatusLine &>(*first_line).reasonPhrase(area);
+    });
+}
+
 extern "C" bool rust_shim_header_has_any(const libecap::Header *header, const rust_name* name, bool *out) noexcept {
     return call_cpp_catch_exception([&] () {
         *out = header->hasAny(from_rust_name(name));
@@ -833,8 +1054,1022 @@ extern "C" bool rust_shim_header_visit_each(
     });
 }
 
-extern "C" bool rust_shim_register_service(const void **service, bool *out) noexcept {
+// The version is the one the Rust service was built for, which the host
+// checks against its own libecap.
+extern "C" bool rust_shim_register_service(
+        const void **service, const char *version, bool *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = libecap::RegisterVersionedService(new Adapter::Service(service), version);
+    });
+}
+
+// Rust hosts driving C++ adapters.
+//
+// The classes below implement the libecap host interfaces on top of Rust
+// trait objects, which are passed across the boundary as raw fat pointers.
+
+struct rust_dyn {
+    void *data;
+    void *vtable;
+};
+
+static const rust_dyn RUST_DYN_NULL = rust_dyn { data: nullptr, vtable: nullptr };
+
+struct rust_shared_ptr {
+    char value[SHARED_PTR_MESSAGE_SIZE];
+    uint64_t __align[0];
+};
+
+static_assert(sizeof(libecap::shared_ptr<libecap::adapter::Service>) <= sizeof(rust_shared_ptr));
+static_assert(sizeof(libecap::shared_ptr<libecap::adapter::Xaction>) <= sizeof(rust_shared_ptr));
+static_assert(alignof(libecap::shared_ptr<libecap::adapter::Xaction>) == alignof(rust_shared_ptr));
+
+template<typename T>
+rust_shared_ptr to_rust_shared_ptr(libecap::shared_ptr<T> ptr) {
+    rust_shared_ptr foo;
+    auto p = new (&foo.value) libecap::shared_ptr<T>;
+    *p = ptr;
+    return foo;
+}
+
+template<typename T>
+libecap::shared_ptr<T> &from_rust_shared_ptr(rust_shared_ptr *ptr) {
+    return *reinterpret_cast<libecap::shared_ptr<T>*>(&ptr->value);
+}
+
+extern "C" {
+    bool rust_host_uri(rust_dyn, rust_string *) noexcept;
+    bool rust_host_describe(rust_dyn, rust_string *) noexcept;
+    bool rust_host_note_versioned_service(rust_dyn, const char *, rust_shared_ptr *) noexcept;
+    bool rust_host_open_debug(rust_dyn, RustLogVerbosity, rust_dyn *) noexcept;
+    bool rust_host_close_debug(rust_dyn, rust_dyn, const char *, size_t) noexcept;
+    bool rust_host_new_request(rust_dyn, rust_dyn *) noexcept;
+    bool rust_host_new_response(rust_dyn, rust_dyn *) noexcept;
+
+    bool rust_host_xaction_virgin(rust_dyn, rust_dyn *) noexcept;
+    bool rust_host_xaction_cause(rust_dyn, rust_dyn *) noexcept;
+    bool rust_host_xaction_adapted(rust_dyn, rust_dyn *) noexcept;
+    bool rust_host_xaction_use_virgin(rust_dyn) noexcept;
+    bool rust_host_xaction_use_adapted(rust_dyn, rust_dyn) noexcept;
+    bool rust_host_xaction_block_virgin(rust_dyn) noexcept;
+    bool rust_host_xaction_adaptation_delayed(rust_dyn, const char *, size_t, double) noexcept;
+    bool rust_host_xaction_adaptation_aborted(rust_dyn) noexcept;
+    bool rust_host_xaction_resume(rust_dyn) noexcept;
+    bool rust_host_xaction_vb_discard(rust_dyn) noexcept;
+    bool rust_host_xaction_vb_make(rust_dyn) noexcept;
+    bool rust_host_xaction_vb_stop_making(rust_dyn) noexcept;
+    bool rust_host_xaction_vb_make_more(rust_dyn) noexcept;
+    bool rust_host_xaction_vb_pause(rust_dyn) noexcept;
+    bool rust_host_xaction_vb_resume(rust_dyn) noexcept;
+    bool rust_host_xaction_vb_content(rust_dyn, size_t, size_t, rust_area *) noexcept;
+    bool rust_host_xaction_vb_content_shift(rust_dyn, size_t) noexcept;
+    bool rust_host_xaction_note_ab_content_done(rust_dyn, bool) noexcept;
+    bool rust_host_xaction_note_ab_content_available(rust_dyn) noexcept;
+    bool rust_host_xaction_wake(const void *) noexcept;
+
+    bool rust_message_clone(rust_dyn, rust_dyn *) noexcept;
+    bool rust_message_free(rust_dyn) noexcept;
+    bool rust_message_first_line(rust_dyn, rust_dyn *) noexcept;
+    bool rust_message_first_line_mut(rust_dyn, rust_dyn *) noexcept;
+    bool rust_message_header(rust_dyn, rust_dyn *) noexcept;
+    bool rust_message_header_mut(rust_dyn, rust_dyn *) noexcept;
+    bool rust_message_add_body(rust_dyn) noexcept;
+    bool rust_message_body(rust_dyn, rust_dyn *) noexcept;
+    bool rust_message_body_mut(rust_dyn, rust_dyn *) noexcept;
+    bool rust_message_add_trailer(rust_dyn) noexcept;
+    bool rust_message_trailer(rust_dyn, rust_dyn *) noexcept;
+    bool rust_message_trailer_mut(rust_dyn, rust_dyn *) noexcept;
+
+    bool rust_header_has_any(rust_dyn, const rust_name *, bool *) noexcept;
+    bool rust_header_value(rust_dyn, const rust_name *, rust_area *) noexcept;
+    bool rust_header_add(rust_dyn, const rust_name *, rust_area) noexcept;
+    bool rust_header_remove_any(rust_dyn, const rust_name *) noexcept;
+    bool rust_header_visit_each(rust_dyn, libecap::NamedValueVisitor *) noexcept;
+    bool rust_header_image(rust_dyn, rust_area *) noexcept;
+    bool rust_header_parse(rust_dyn, rust_area) noexcept;
+
+    bool rust_first_line_version(rust_dyn, rust_version *) noexcept;
+    bool rust_first_line_set_version(rust_dyn, const rust_version *) noexcept;
+    bool rust_first_line_protocol(rust_dyn, libecap::Name *) noexcept;
+    bool rust_first_line_set_protocol(rust_dyn, const rust_name *) noexcept;
+    bool rust_first_line_kind(rust_dyn, int *) noexcept;
+
+    bool rust_request_line_uri(rust_dyn, rust_area *) noexcept;
+    bool rust_request_line_set_uri(rust_dyn, rust_area) noexcept;
+    bool rust_request_line_method(rust_dyn, libecap::Name *) noexcept;
+    bool rust_request_line_set_method(rust_dyn, const rust_name *) noexcept;
+
+    bool rust_status_line_status_code(rust_dyn, int *) noexcept;
+    bool rust_status_line_set_status_code(rust_dyn, int) noexcept;
+    bool rust_status_line_reason_phrase(rust_dyn, rust_area *) noexcept;
+    bool rust_status_line_set_reason_phrase(rust_dyn, rust_area) noexcept;
+
+    bool rust_body_size(rust_dyn, body_size *) noexcept;
+
+    bool rust_options_option(rust_dyn, const rust_name *, rust_area *) noexcept;
+    bool rust_options_visit(rust_dyn, libecap::NamedValueVisitor *) noexcept;
+}
+
+extern "C" bool rust_shim_name_assign(libecap::Name *out, const rust_name *name) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = from_rust_name(name);
+    });
+}
+
+extern "C" bool rust_shim_visitor_visit(
+        libecap::NamedValueVisitor *visitor, const rust_name *name, const char *buf, size_t len) noexcept {
+    return call_cpp_catch_exception([&] () {
+        visitor->visit(from_rust_name(name), libecap::Area::FromTempBuffer(buf, len));
+    });
+}
+
+namespace RustHost {
+
+class Header: public libecap::Header {
+    public:
+        virtual bool hasAny(const libecap::Name &name) const;
+        virtual Value value(const libecap::Name &name) const;
+        virtual void add(const libecap::Name &name, const Value &value);
+        virtual void removeAny(const libecap::Name &name);
+        virtual void visitEach(libecap::NamedValueVisitor &visitor) const;
+        virtual libecap::Area image() const;
+        virtual void parse(const libecap::Area &buf);
+
+        rust_dyn rust_header = RUST_DYN_NULL;
+};
+
+// The FirstLine methods, shared by the request and status lines so that
+// adapters can dynamic_cast the first line as they do with other hosts.
+template <class Base>
+class FirstLineOf: public Base {
+    public:
+        virtual libecap::Version version() const;
+        virtual void version(const libecap::Version &aVersion);
+        virtual libecap::Name protocol() const;
+        virtual void protocol(const libecap::Name &aProtocol);
+
+        rust_dyn rust_first_line = RUST_DYN_NULL;
+};
+
+typedef FirstLineOf<libecap::FirstLine> FirstLine;
+
+class RequestLine: public FirstLineOf<libecap::RequestLine> {
+    public:
+        virtual void uri(const libecap::Area &aUri);
+        virtual libecap::Area uri() const;
+        virtual void method(const libecap::Name &aMethod);
+        virtual libecap::Name method() const;
+};
+
+class StatusLine: public FirstLineOf<libecap::StatusLine> {
+    public:
+        virtual void statusCode(int code);
+        virtual int statusCode() const;
+        virtual void reasonPhrase(const libecap::Area &phrase);
+        virtual libecap::Area reasonPhrase() const;
+};
+
+class Body: public libecap::Body {
+    public:
+        virtual libecap::BodySize bodySize() const;
+
+        rust_dyn rust_body = RUST_DYN_NULL;
+};
+
+class Message: public libecap::Message {
+    public:
+        Message(rust_dyn msg, bool owned): rust_message(msg), owned(owned) {}
+        virtual ~Message();
+
+        virtual libecap::shared_ptr<libecap::Message> clone() const;
+
+        virtual libecap::FirstLine &firstLine();
+        virtual const libecap::FirstLine &firstLine() const;
+
+        virtual libecap::Header &header();
+        virtual const libecap::Header &header() const;
+
+        virtual void addBody();
+        virtual libecap::Body *body();
+        virtual const libecap::Body *body() const;
+
+        virtual void addTrailer();
+        virtual libecap::Header *trailer();
+        virtual const libecap::Header *trailer() const;
+
+        // Hand the Rust message over to the caller; this message must
+        // not be used afterwards.
+        rust_dyn release();
+
+        // Point this borrowed message at another Rust message.
+        void rebind(rust_dyn msg);
+
+        rust_dyn message() const;
+
+    private:
+        // Picks the first line matching the kind of the Rust one.
+        libecap::FirstLine &bindFirstLine(rust_dyn line) const;
+
+        rust_dyn rust_message;
+        bool owned;
+
+        mutable FirstLine first_line_;
+        mutable RequestLine request_line_;
+        mutable StatusLine status_line_;
+        mutable Header header_;
+        mutable Header trailer_;
+        mutable Body body_;
+};
+
+class Xaction: public libecap::host::Xaction {
+    public:
+        Xaction(): virgin_(RUST_DYN_NULL, false), adapted_(RUST_DYN_NULL, false), cause_(RUST_DYN_NULL, false) {}
+
+        virtual const libecap::Area option(const libecap::Name &name) const;
+        virtual void visitEachOption(libecap::NamedValueVisitor &visitor) const;
+
+        virtual libecap::Message &virgin();
+        virtual const libecap::Message &cause();
+        virtual libecap::Message &adapted();
+
+        virtual void useVirgin();
+        virtual void useAdapted(const libecap::shared_ptr<libecap::Message> &msg);
+        virtual void blockVirgin();
+
+        virtual void adaptationDelayed(const libecap::Delay &);
+        virtual void adaptationAborted();
+
+        virtual void resume();
+
+        virtual void vbDiscard();
+        virtual void vbMake();
+        virtual void vbStopMaking();
+        virtual void vbMakeMore();
+        virtual void vbPause();
+        virtual void vbResume();
+
+        virtual libecap::Area vbContent(libecap::size_type offset, libecap::size_type size);
+        virtual void vbContentShift(libecap::size_type size);
+
+        virtual void noteAbContentDone(bool atEnd);
+        virtual void noteAbContentAvailable();
+
+        // Deliver the calls made while unbound to the Rust host
+        // transaction, which must now be bound.
+        void flush();
+
+        // Hand over a resume the adapter asked for, if any.
+        bool takeResume();
+
+        // The Rust host transaction; only set while the adapter
+        // transaction is being called into by the Rust host.
+        rust_dyn current = RUST_DYN_NULL;
+
+        // The Rust wake handle of the transaction, set from start until
+        // stop. Calls made outside of a call from the Rust host wake the
+        // transaction, and are delivered once the host resumes it.
+        const void *waker = nullptr;
+
+    private:
+        rust_dyn bound() const;
+
+        // Calls the Rust host transaction, or queues the call until it
+        // is bound again.
+        void callOrDefer(std::function<bool(rust_dyn)> f);
+
+        // Ask the Rust host to resume the transaction.
+        void wake();
+
+        std::vector<std::function<bool(rust_dyn)>> deferred_;
+        bool resume_ = false;
+
+        Message virgin_;
+        Message adapted_;
+        Message cause_;
+};
+
+// Binds the Rust host transaction for the duration of a call into the adapter.
+class Binding {
+    public:
+        Binding(Xaction *x, rust_dyn host): xaction(x), previous(x->current) {
+            xaction->current = host;
+            try {
+                xaction->flush();
+            } catch (...) {
+                xaction->current = previous;
+                throw;
+            }
+        }
+        ~Binding() {
+            xaction->current = previous;
+        }
+
+    private:
+        Xaction *xaction;
+        rust_dyn previous;
+};
+
+class DebugStream: public std::ostringstream {
+    public:
+        explicit DebugStream(rust_dyn stream): rust_stream(stream) {}
+
+        rust_dyn rust_stream;
+};
+
+class Host: public libecap::host::Host {
+    public:
+        explicit Host(rust_dyn host): rust_host(host) {}
+
+        virtual std::string uri() const;
+        virtual void describe(std::ostream &os) const;
+
+        virtual void noteVersionedService(const char *libecapVersion,
+            const libecap::weak_ptr<libecap::adapter::Service> &s);
+
+        virtual std::ostream *openDebug(libecap::LogVerbosity lv);
+        virtual void closeDebug(std::ostream *debug);
+
+        virtual libecap::shared_ptr<libecap::Message> newRequest() const;
+        virtual libecap::shared_ptr<libecap::Message> newResponse() const;
+
+    private:
+        // Owned by Rust and never freed, as libecap hosts live forever.
+        rust_dyn rust_host;
+};
+
+class Options: public libecap::Options {
+    public:
+        explicit Options(rust_dyn options): rust_options(options) {}
+
+        virtual const libecap::Area option(const libecap::Name &name) const;
+        virtual void visitEachOption(libecap::NamedValueVisitor &visitor) const;
+
+    private:
+        rust_dyn rust_options;
+};
+
+} // namespace RustHost
+
+bool RustHost::Header::hasAny(const libecap::Name &name) const {
+    bool out;
+    auto rname = to_rust_name(name);
+    call_rust_maybe_throw([&] () {
+        return ::rust_header_has_any(rust_header, &rname, &out);
+    });
+    return out;
+}
+
+RustHost::Header::Value RustHost::Header::value(const libecap::Name &name) const {
+    rust_area out;
+    auto rname = to_rust_name(name);
+    call_rust_maybe_throw([&] () {
+        return ::rust_header_value(rust_header, &rname, &out);
+    });
+    return from_rust_area(out);
+}
+
+void RustHost::Header::add(const libecap::Name &name, const Value &value) {
+    auto rname = to_rust_name(name);
+    call_rust_maybe_throw([&] () {
+        return ::rust_header_add(rust_header, &rname, to_rust_area(value));
+    });
+}
+
+void RustHost::Header::removeAny(const libecap::Name &name) {
+    auto rname = to_rust_name(name);
+    call_rust_maybe_throw([&] () {
+        return ::rust_header_remove_any(rust_header, &rname);
+    });
+}
+
+void RustHost::Header::visitEach(libecap::NamedValueVisitor &visitor) const {
+    call_rust_maybe_throw([&] () {
+        return ::rust_header_visit_each(rust_header, &visitor);
+    });
+}
+
+libecap::Area RustHost::Header::image() const {
+    rust_area out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_header_image(rust_header, &out);
+    });
+    return from_rust_area(out);
+}
+
+void RustHost::Header::parse(const libecap::Area &buf) {
+    call_rust_maybe_throw([&] () {
+        return ::rust_header_parse(rust_header, to_rust_area(buf));
+    });
+}
+
+template <class Base>
+libecap::Version RustHost::FirstLineOf<Base>::version() const {
+    rust_version out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_first_line_version(rust_first_line, &out);
+    });
+    return libecap::Version(out.majr, out.minr, out.micr);
+}
+
+template <class Base>
+void RustHost::FirstLineOf<Base>::version(const libecap::Version &aVersion) {
+    auto version = rust_version {
+        majr: aVersion.majr,
+        minr: aVersion.minr,
+        micr: aVersion.micr,
+    };
+    call_rust_maybe_throw([&] () {
+        return ::rust_first_line_set_version(rust_first_line, &version);
+    });
+}
+
+template <class Base>
+libecap::Name RustHost::FirstLineOf<Base>::protocol() const {
+    libecap::Name out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_first_line_protocol(rust_first_line, &out);
+    });
+    return out;
+}
+
+template <class Base>
+void RustHost::FirstLineOf<Base>::protocol(const libecap::Name &aProtocol) {
+    auto rname = to_rust_name(aProtocol);
+    call_rust_maybe_throw([&] () {
+        return ::rust_first_line_set_protocol(rust_first_line, &rname);
+    });
+}
+
+void RustHost::RequestLine::uri(const libecap::Area &aUri) {
+    call_rust_maybe_throw([&] () {
+        return ::rust_request_line_set_uri(rust_first_line, to_rust_area(aUri));
+    });
+}
+
+libecap::Area RustHost::RequestLine::uri() const {
+    rust_area out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_request_line_uri(rust_first_line, &out);
+    });
+    return from_rust_area(out);
+}
+
+void RustHost::RequestLine::method(const libecap::Name &aMethod) {
+    auto rname = to_rust_name(aMethod);
+    call_rust_maybe_throw([&] () {
+        return ::rust_request_line_set_method(rust_first_line, &rname);
+    });
+}
+
+libecap::Name RustHost::RequestLine::method() const {
+    libecap::Name out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_request_line_method(rust_first_line, &out);
+    });
+    return out;
+}
+
+void RustHost::StatusLine::statusCode(int code) {
+    call_rust_maybe_throw([&] () {
+        return ::rust_status_line_set_status_code(rust_first_line, code);
+    });
+}
+
+int RustHost::StatusLine::statusCode() const {
+    int out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_status_line_status_code(rust_first_line, &out);
+    });
+    return out;
+}
+
+void RustHost::StatusLine::reasonPhrase(const libecap::Area &phrase) {
+    call_rust_maybe_throw([&] () {
+        return ::rust_status_line_set_reason_phrase(rust_first_line, to_rust_area(phrase));
+    });
+}
+
+libecap::Area RustHost::StatusLine::reasonPhrase() const {
+    rust_area out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_status_line_reason_phrase(rust_first_line, &out);
+    });
+    return from_rust_area(out);
+}
+
+libecap::BodySize RustHost::Body::bodySize() const {
+    body_size out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_body_size(rust_body, &out);
+    });
+    if (out.known) {
+        return libecap::BodySize(out.size);
+    } else {
+        return libecap::BodySize();
+    }
+}
+
+RustHost::Message::~Message() {
+    if (owned) {
+        call_rust_maybe_throw([&] () {
+            return ::rust_message_free(rust_message);
+        });
+    }
+}
+
+rust_dyn RustHost::Message::message() const {
+    Must(rust_message.data != nullptr);
+    return rust_message;
+}
+
+rust_dyn RustHost::Message::release() {
+    Must(owned);
+    rust_dyn msg = message();
+    rust_message = RUST_DYN_NULL;
+    owned = false;
+    return msg;
+}
+
+void RustHost::Message::rebind(rust_dyn msg) {
+    Must(!owned);
+    rust_message = msg;
+}
+
+libecap::shared_ptr<libecap::Message> RustHost::Message::clone() const {
+    rust_dyn out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_clone(message(), &out);
+    });
+    return libecap::shared_ptr<libecap::Message>(new RustHost::Message(out, true));
+}
+
+libecap::FirstLine &RustHost::Message::bindFirstLine(rust_dyn line) const {
+    int kind;
+    call_rust_maybe_throw([&] () {
+        return ::rust_first_line_kind(line, &kind);
+    });
+    switch (kind) {
+        case FIRST_LINE_REQUEST:
+            request_line_.rust_first_line = line;
+            return request_line_;
+        case FIRST_LINE_STATUS:
+            status_line_.rust_first_line = line;
+            return status_line_;
+        default:
+            first_line_.rust_first_line = line;
+            return first_line_;
+    }
+}
+
+libecap::FirstLine &RustHost::Message::firstLine() {
+    rust_dyn line;
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_first_line_mut(message(), &line);
+    });
+    return bindFirstLine(line);
+}
+
+const libecap::FirstLine &RustHost::Message::firstLine() const {
+    rust_dyn line;
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_first_line(message(), &line);
+    });
+    return bindFirstLine(line);
+}
+
+libecap::Header &RustHost::Message::header() {
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_header_mut(message(), &header_.rust_header);
+    });
+    return header_;
+}
+
+const libecap::Header &RustHost::Message::header() const {
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_header(message(), &header_.rust_header);
+    });
+    return header_;
+}
+
+void RustHost::Message::addBody() {
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_add_body(message());
+    });
+}
+
+libecap::Body *RustHost::Message::body() {
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_body_mut(message(), &body_.rust_body);
+    });
+    return body_.rust_body.data == nullptr ? nullptr : &body_;
+}
+
+const libecap::Body *RustHost::Message::body() const {
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_body(message(), &body_.rust_body);
+    });
+    return body_.rust_body.data == nullptr ? nullptr : &body_;
+}
+
+void RustHost::Message::addTrailer() {
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_add_trailer(message());
+    });
+}
+
+libecap::Header *RustHost::Message::trailer() {
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_trailer_mut(message(), &trailer_.rust_header);
+    });
+    return &trailer_;
+}
+
+const libecap::Header *RustHost::Message::trailer() const {
+    call_rust_maybe_throw([&] () {
+        return ::rust_message_trailer(message(), &trailer_.rust_header);
+    });
+    return &trailer_;
+}
+
+rust_dyn RustHost::Xaction::bound() const {
+    if (current.data == nullptr) {
+        throw TextExceptionHere("host transaction used outside of a call from the Rust host");
+    }
+    return current;
+}
+
+void RustHost::Xaction::callOrDefer(std::function<bool(rust_dyn)> f) {
+    if (current.data != nullptr) {
+        call_rust_maybe_throw([&] () {
+            return f(current);
+        });
+        return;
+    }
+    wake();
+    deferred_.push_back(std::move(f));
+}
+
+void RustHost::Xaction::wake() {
+    if (!waker) {
+        throw TextExceptionHere("host transaction used before start or after stop");
+    }
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_xaction_wake(waker);
+    });
+}
+
+void RustHost::Xaction::flush() {
+    // Calls may queue more while they are delivered.
+    while (!deferred_.empty()) {
+        auto f = std::move(deferred_.front());
+        deferred_.erase(deferred_.begin());
+        call_rust_maybe_throw([&] () {
+            return f(bound());
+        });
+    }
+}
+
+bool RustHost::Xaction::takeResume() {
+    bool resume = resume_;
+    resume_ = false;
+    return resume;
+}
+
+const libecap::Area RustHost::Xaction::option(const libecap::Name &) const {
+    return libecap::Area(); // the Rust host provides no meta-information
+}
+
+void RustHost::Xaction::visitEachOption(libecap::NamedValueVisitor &) const {
+    // the Rust host provides no meta-information
+}
+
+#define HOST_XACTION_MESSAGE_SHIM(rname__, cpp_name__, member__, type__) \
+    type__ &RustHost::Xaction::cpp_name__() { \
+        rust_dyn out; \
+        call_rust_maybe_throw([&] () { \
+            return ::rname__(bound(), &out); \
+        }); \
+        member__.rebind(out); \
+        return member__; \
+    }
+
+HOST_XACTION_MESSAGE_SHIM(rust_host_xaction_virgin, virgin, virgin_, libecap::Message);
+HOST_XACTION_MESSAGE_SHIM(rust_host_xaction_cause, cause, cause_, const libecap::Message);
+HOST_XACTION_MESSAGE_SHIM(rust_host_xaction_adapted, adapted, adapted_, libecap::Message);
+
+#define HOST_XACTION_METHOD_SHIM(rname__, cpp_name__) \
+    void RustHost::Xaction::cpp_name__() { \
+        callOrDefer(::rname__); \
+    }
+
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_use_virgin, useVirgin);
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_block_virgin, blockVirgin);
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_adaptation_aborted, adaptationAborted);
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_vb_discard, vbDiscard);
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_vb_make, vbMake);
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_vb_stop_making, vbStopMaking);
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_vb_make_more, vbMakeMore);
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_vb_pause, vbPause);
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_vb_resume, vbResume);
+HOST_XACTION_METHOD_SHIM(rust_host_xaction_note_ab_content_available, noteAbContentAvailable);
+
+void RustHost::Xaction::resume() {
+    // The adapter transaction is resumed once the Rust host resumes us,
+    // whether it asked from within a call or not.
+    if (current.data == nullptr) {
+        wake();
+        resume_ = true;
+        return;
+    }
+    resume_ = true;
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_xaction_resume(current);
+    });
+}
+
+void RustHost::Xaction::useAdapted(const libecap::shared_ptr<libecap::Message> &msg) {
+    auto rust_msg = dynamic_cast<RustHost::Message*>(msg.get());
+    if (!rust_msg) {
+        throw TextExceptionHere("useAdapted requires a message created by the Rust host");
+    }
+    // The message is only released once delivered, so that it is freed
+    // along with any calls which never are.
+    callOrDefer([msg, rust_msg] (rust_dyn host) {
+        return ::rust_host_xaction_use_adapted(host, rust_msg->release());
+    });
+}
+
+void RustHost::Xaction::adaptationDelayed(const libecap::Delay &delay) {
+    std::string state = delay.state;
+    double progress = delay.progress;
+    callOrDefer([state, progress] (rust_dyn host) {
+        return ::rust_host_xaction_adaptation_delayed(host, state.data(), state.size(), progress);
+    });
+}
+
+libecap::Area RustHost::Xaction::vbContent(libecap::size_type offset, libecap::size_type size) {
+    rust_area out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_xaction_vb_content(bound(), offset, size, &out);
+    });
+    return from_rust_area(out);
+}
+
+void RustHost::Xaction::vbContentShift(libecap::size_type size) {
+    callOrDefer([size] (rust_dyn host) {
+        return ::rust_host_xaction_vb_content_shift(host, size);
+    });
+}
+
+void RustHost::Xaction::noteAbContentDone(bool atEnd) {
+    callOrDefer([atEnd] (rust_dyn host) {
+        return ::rust_host_xaction_note_ab_content_done(host, atEnd);
+    });
+}
+
+std::string RustHost::Host::uri() const {
+    rust_string s;
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_uri(rust_host, &s);
+    });
+    std::string ret = std::string(s.buf, s.size);
+    ::rust_free_string(s);
+    return ret;
+}
+
+void RustHost::Host::describe(std::ostream &os) const {
+    rust_string s;
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_describe(rust_host, &s);
+    });
+    os.write(s.buf, s.size);
+    ::rust_free_string(s);
+}
+
+void RustHost::Host::noteVersionedService(const char *libecapVersion,
+        const libecap::weak_ptr<libecap::adapter::Service> &s) {
+    auto service = s.lock();
+    Must(service);
+    rust_shared_ptr raw = to_rust_shared_ptr(service);
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_note_versioned_service(rust_host, libecapVersion, &raw);
+    });
+}
+
+std::ostream *RustHost::Host::openDebug(libecap::LogVerbosity lv) {
+    rust_dyn stream;
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_open_debug(rust_host, RustLogVerbosity { mask: lv.mask }, &stream);
+    });
+    if (stream.data == nullptr) {
+        return nullptr;
+    }
+    return new RustHost::DebugStream(stream);
+}
+
+void RustHost::Host::closeDebug(std::ostream *debug) {
+    if (!debug) {
+        return;
+    }
+    auto stream = dynamic_cast<RustHost::DebugStream*>(debug);
+    Must(stream);
+    std::string buffered = stream->str();
+    rust_dyn rust_stream = stream->rust_stream;
+    delete stream;
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_close_debug(rust_host, rust_stream, buffered.data(), buffered.size());
+    });
+}
+
+libecap::shared_ptr<libecap::Message> RustHost::Host::newRequest() const {
+    rust_dyn out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_new_request(rust_host, &out);
+    });
+    return libecap::shared_ptr<libecap::Message>(new RustHost::Message(out, true));
+}
+
+libecap::shared_ptr<libecap::Message> RustHost::Host::newResponse() const {
+    rust_dyn out;
+    call_rust_maybe_throw([&] () {
+        return ::rust_host_new_response(rust_host, &out);
+    });
+    return libecap::shared_ptr<libecap::Message>(new RustHost::Message(out, true));
+}
+
+const libecap::Area RustHost::Options::option(const libecap::Name &name) const {
+    rust_area out;
+    auto rname = to_rust_name(name);
+    call_rust_maybe_throw([&] () {
+        return ::rust_options_option(rust_options, &rname, &out);
+    });
+    return from_rust_area(out);
+}
+
+void RustHost::Options::visitEachOption(libecap::NamedValueVisitor &visitor) const {
+    call_rust_maybe_throw([&] () {
+        return ::rust_options_visit(rust_options, &visitor);
+    });
+}
+
+extern "C" bool rust_shim_register_host(rust_dyn host) noexcept {
+    return call_cpp_catch_exception([&] () {
+        libecap::RegisterHost(libecap::shared_ptr<libecap::host::Host>(new RustHost::Host(host)));
+    });
+}
+
+typedef libecap::shared_ptr<libecap::adapter::Service> AdapterServicePtr;
+typedef libecap::shared_ptr<libecap::adapter::Xaction> AdapterXactionPtr;
+
+extern "C" void rust_shim_adapter_service_free(rust_shared_ptr *service) noexcept {
+    from_rust_shared_ptr<libecap::adapter::Service>(service).reset();
+}
+
+extern "C" bool rust_shim_adapter_service_uri(rust_shared_ptr *service, rust_string *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = to_rust_string(from_rust_shared_ptr<libecap::adapter::Service>(service)->uri());
+    });
+}
+
+extern "C" bool rust_shim_adapter_service_tag(rust_shared_ptr *service, rust_string *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = to_rust_string(from_rust_shared_ptr<libecap::adapter::Service>(service)->tag());
+    });
+}
+
+extern "C" bool rust_shim_adapter_service_describe(rust_shared_ptr *service, rust_string *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        std::ostringstream desc;
+        from_rust_shared_ptr<libecap::adapter::Service>(service)->describe(desc);
+        *out = to_rust_string(desc.str());
+    });
+}
+
+extern "C" bool rust_shim_adapter_service_is_async(rust_shared_ptr *service, bool *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = from_rust_shared_ptr<libecap::adapter::Service>(service)->makesAsyncXactions();
+    });
+}
+
+extern "C" bool rust_shim_adapter_service_configure(rust_shared_ptr *service, rust_dyn options) noexcept {
+    return call_cpp_catch_exception([&] () {
+        from_rust_shared_ptr<libecap::adapter::Service>(service)->configure(RustHost::Options(options));
+    });
+}
+
+extern "C" bool rust_shim_adapter_service_reconfigure(rust_shared_ptr *service, rust_dyn options) noexcept {
+    return call_cpp_catch_exception([&] () {
+        from_rust_shared_ptr<libecap::adapter::Service>(service)->reconfigure(RustHost::Options(options));
+    });
+}
+
+extern "C" bool rust_shim_adapter_service_suspend(rust_shared_ptr *service, timeval *timeout) noexcept {
+    return call_cpp_catch_exception([&] () {
+        from_rust_shared_ptr<libecap::adapter::Service>(service)->suspend(*timeout);
+    });
+}
+
+extern "C" bool rust_shim_adapter_service_wants_url(rust_shared_ptr *service, const char *url, bool *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = from_rust_shared_ptr<libecap::adapter::Service>(service)->wantsUrl(url);
+    });
+}
+
+#define ADAPTER_SERVICE_METHOD_C_SHIM(cpp_name__) \
+    extern "C" bool rust_shim_adapter_service_ ## cpp_name__(rust_shared_ptr *service) noexcept { \
+        return call_cpp_catch_exception([&] () { \
+            from_rust_shared_ptr<libecap::adapter::Service>(service)->cpp_name__(); \
+        }); \
+    }
+
+ADAPTER_SERVICE_METHOD_C_SHIM(start);
+ADAPTER_SERVICE_METHOD_C_SHIM(stop);
+ADAPTER_SERVICE_METHOD_C_SHIM(retire);
+ADAPTER_SERVICE_METHOD_C_SHIM(resume);
+
+extern "C" bool rust_shim_adapter_service_make_xaction(
+        rust_shared_ptr *service, rust_dyn host, RustHost::Xaction **hostx, rust_shared_ptr *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        auto x = new RustHost::Xaction();
+        try {
+            RustHost::Binding binding(x, host);
+            *out = to_rust_shared_ptr(
+                from_rust_shared_ptr<libecap::adapter::Service>(service)->makeXaction(x));
+        } catch (...) {
+            delete x;
+            throw;
+        }
+        *hostx = x;
+    });
+}
+
+extern "C" void rust_shim_adapter_xaction_free(rust_shared_ptr *xaction, RustHost::Xaction *hostx) noexcept {
+    // The adapter transaction may refer to the host transaction until it is gone.
+    from_rust_shared_ptr<libecap::adapter::Xaction>(xaction).reset();
+    delete hostx;
+}
+
+extern "C" bool rust_shim_adapter_xaction_options(rust_shared_ptr *xaction, const libecap::Options **out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        *out = from_rust_shared_ptr<libecap::adapter::Xaction>(xaction).get();
+    });
+}
+
+#define ADAPTER_XACTION_METHOD_C_SHIM(cpp_name__, c_name__) \
+    extern "C" bool rust_shim_adapter_xaction_ ## c_name__( \
+            rust_shared_ptr *xaction, RustHost::Xaction *hostx, rust_dyn host) noexcept { \
+        return call_cpp_catch_exception([&] () { \
+            RustHost::Binding binding(hostx, host); \
+            from_rust_shared_ptr<libecap::adapter::Xaction>(xaction)->cpp_name__(); \
+        }); \
+    }
+
+ADAPTER_XACTION_METHOD_C_SHIM(start, start);
+ADAPTER_XACTION_METHOD_C_SHIM(stop, stop);
+ADAPTER_XACTION_METHOD_C_SHIM(abDiscard, ab_discard);
+ADAPTER_XACTION_METHOD_C_SHIM(abMake, ab_make);
+ADAPTER_XACTION_METHOD_C_SHIM(abMakeMore, ab_make_more);
+ADAPTER_XACTION_METHOD_C_SHIM(abStopMaking, ab_stop_making);
+ADAPTER_XACTION_METHOD_C_SHIM(abPause, ab_pause);
+ADAPTER_XACTION_METHOD_C_SHIM(abResume, ab_resume);
+ADAPTER_XACTION_METHOD_C_SHIM(noteVbContentAvailable, vb_content_available);
+
+// The Rust host also resumes transactions to deliver calls made outside
+// of its own, so the adapter is only resumed if it asked to be.
+extern "C" bool rust_shim_adapter_xaction_resume(
+        rust_shared_ptr *xaction, RustHost::Xaction *hostx, rust_dyn host) noexcept {
+    return call_cpp_catch_exception([&] () {
+        RustHost::Binding binding(hostx, host);
+        if (hostx->takeResume()) {
+            from_rust_shared_ptr<libecap::adapter::Xaction>(xaction)->resume();
+        }
+    });
+}
+
+extern "C" void rust_shim_rust_host_xaction_set_waker(RustHost::Xaction *hostx, const void *waker) noexcept {
+    hostx->waker = waker;
+}
+
+extern "C" bool rust_shim_adapter_xaction_ab_content(rust_shared_ptr *xaction,
+        RustHost::Xaction *hostx, rust_dyn host, size_t offset, size_t size, rust_area *out) noexcept {
+    return call_cpp_catch_exception([&] () {
+        RustHost::Binding binding(hostx, host);
+        *out = to_rust_area(from_rust_shared_ptr<libecap::adapter::Xaction>(xaction)->abContent(offset, size));
+    });
+}
+
+extern "C" bool rust_shim_adapter_xaction_ab_content_shift(rust_shared_ptr *xaction,
+        RustHost::Xaction *hostx, rust_dyn host, size_t size) noexcept {
+    return call_cpp_catch_exception([&] () {
+        RustHost::Binding binding(hostx, host);
+        from_rust_shared_ptr<libecap::adapter::Xaction>(xaction)->abContentShift(size);
+    });
+}
+
+extern "C" bool rust_shim_adapter_xaction_vb_content_done(rust_shared_ptr *xaction,
+        RustHost::Xaction *hostx, rust_dyn host, bool atEnd) noexcept {
     return call_cpp_catch_exception([&] () {
-        *out = libecap::RegisterVersionedService(new Adapter::Service(service));
+        RustHost::Binding binding(hostx, host);
+        from_rust_shared_ptr<libecap::adapter::Xaction>(xaction)->noteVbContentDone(atEnd);
     });
 }
@@ -0,0 +1,227 @@
+use adapter::BodyTransformer;
+use common::{Area, AreaChain, Body, Message};
+use host::{self, Transaction as HostTransaction};
+
+/// The state of the virgin or adapted body of an `AdaptedBody`.
+#[derive(Debug, PartialEq, Copy, Eq, Clone)]
+pub enum BodyState {
+    /// Neither side has decided whether the body will be transferred.
+    Undecided,
+    /// The body is being transferred.
+    On,
+    /// The body has been transferred, or the transfer was stopped.
+    Complete,
+    /// There is no body, or it will not be transferred.
+    Never,
+}
+
+/// The body half of an adapter transaction which adapts the virgin
+/// body chunk by chunk.
+///
+/// This tracks the state of the virgin and adapted bodies, and buffers
+/// adapted content until the host consumes it. Each chunk of virgin
+/// body is passed to the `BodyTransformer` as soon as the host makes it
+/// available.
+///
+/// The transaction calls `start` from its own `start`, before
+/// `use_adapted`, and forwards the body methods of
+/// `adapter::Transaction` to the methods of the same name:
+///
+/// ```rust,ignore
+/// fn start<'a>(&mut self, host: &'a mut H::TransactionRef) {
+///     self.body.start::<H>(host);
+///     let adapted = host.virgin().clone();
+///     if host.use_adapted(adapted).is_err() {
+///         host.adaptation_aborted();
+///     }
+/// }
+///
+/// fn adapted_body_make<'a>(&mut self, host: &'a mut H::TransactionRef) {
+///     self.body.adapted_body_make::<H>(host)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AdaptedBody<T> {
+    receiving: BodyState,
+    sending: BodyState,
+    paused: bool,
+    buffer: AreaChain,
+    /// How the virgin body ended, if the host has not been told yet.
+    done: Option<bool>,
+    transformer: T,
+}
+
+impl<T: BodyTransformer> AdaptedBody<T> {
+    pub fn new(transformer: T) -> AdaptedBody<T> {
+        AdaptedBody {
+            receiving: BodyState::Undecided,
+            sending: BodyState::Undecided,
+            paused: false,
+            buffer: AreaChain::new(),
+            done: None,
+            transformer,
+        }
+    }
+
+    pub fn transformer(&self) -> &T {
+        &self.transformer
+    }
+
+    pub fn transformer_mut(&mut self) -> &mut T {
+        &mut self.transformer
+    }
+
+    /// The state of the virgin body.
+    pub fn receiving(&self) -> BodyState {
+        self.receiving
+    }
+
+    /// The state of the adapted body.
+    pub fn sending(&self) -> BodyState {
+        self.sending
+    }
+
+    /// Adapted content which the host has not consumed yet.
+    pub fn buffered(&self) -> &AreaChain {
+        &self.buffer
+    }
+
+    /// The size of the adapted body, if the transformer knows it from
+    /// the size of the virgin body.
+    ///
+    /// Pass it to `host::Transaction::use_adapted_sized`.
+    pub fn adapted_size<H: host::Host + ?Sized>(
+        &self,
+        host: &mut H::TransactionRef,
+    ) -> Option<u64> {
+        let virgin = host.virgin().body().and_then(|body| body.size());
+        self.transformer.adapted_size(virgin)
+    }
+
+    /// Register interest in the virgin body, if there is one.
+    ///
+    /// Call this from `adapter::Transaction::start`.
+    pub fn start<H: host::Host + ?Sized>(&mut self, host: &mut H::TransactionRef) {
+        assert_eq!(self.receiving, BodyState::Undecided);
+        if host.virgin().body().is_some() {
+            self.receiving = BodyState::On;
+            host.virgin_body_make();
+        } else {
+            self.receiving = BodyState::Never;
+            self.done = Some(true);
+        }
+    }
+
+    fn stop_virgin_body<H: host::Host + ?Sized>(&mut self, host: &mut H::TransactionRef) {
+        if self.receiving == BodyState::On {
+            host.virgin_body_stop_making();
+            self.receiving = BodyState::Complete;
+        }
+    }
+
+    /// Notify the host of buffered content, and of the end of the body.
+    fn notify<H: host::Host + ?Sized>(&mut self, host: &mut H::TransactionRef) {
+        if self.sending != BodyState::On || self.paused {
+            return;
+        }
+        if !self.buffer.is_empty() {
+            host.adapted_body_content_available();
+        }
+        if let Some(at_end) = self.done.take() {
+            self.sending = BodyState::Complete;
+            host.adapted_body_content_done(at_end);
+        }
+    }
+
+    pub fn adapted_body_discard<H: host::Host + ?Sized>(&mut self, host: &mut H::TransactionRef) {
+        assert_eq!(self.sending, BodyState::Undecided);
+        self.sending = BodyState::Never;
+        self.stop_virgin_body::<H>(host);
+    }
+
+    pub fn adapted_body_make<H: host::Host + ?Sized>(&mut self, host: &mut H::TransactionRef) {
+        assert_eq!(self.sending, BodyState::Undecided);
+        self.sending = BodyState::On;
+        self.notify::<H>(host);
+    }
+
+    pub fn adapted_body_make_more<H: host::Host + ?Sized>(&mut self, host: &mut H::TransactionRef) {
+        if self.receiving == BodyState::On {
+            host.virgin_body_make_more();
+        }
+    }
+
+    pub fn adapted_body_stop_making<H: host::Host + ?Sized>(
+        &mut self,
+        host: &mut H::TransactionRef,
+    ) {
+        self.sending = BodyState::Complete;
+        self.stop_virgin_body::<H>(host);
+    }
+
+    /// Stop notifying the host, and pause the virgin body.
+    pub fn adapted_body_pause<H: host::Host + ?Sized>(&mut self, host: &mut H::TransactionRef) {
+        self.paused = true;
+        if self.receiving == BodyState::On {
+            host.virgin_body_pause();
+        }
+    }
+
+    pub fn adapted_body_resume<H: host::Host + ?Sized>(&mut self, host: &mut H::TransactionRef) {
+        self.paused = false;
+        if self.receiving == BodyState::On {
+            host.virgin_body_resume();
+        }
+        self.notify::<H>(host);
+    }
+
+    /// Adapted content, relative to the last shift.
+    pub fn adapted_body_content(&mut self, offset: usize, size: usize) -> Area {
+        assert!(self.sending == BodyState::On || self.sending == BodyState::Complete);
+        self.buffer.content(offset, size)
+    }
+
+    pub fn adapted_body_content_shift(&mut self, size: usize) {
+        self.buffer.shift(size);
+    }
+
+    /// Transform all available virgin content.
+    pub fn virgin_body_content_available<H: host::Host + ?Sized>(
+        &mut self,
+        host: &mut H::TransactionRef,
+    ) {
+        if self.receiving != BodyState::On {
+            return;
+        }
+        let chunk = host.virgin_body_content(0, usize::max_value());
+        let len = chunk.as_bytes().len();
+        host.virgin_body_content_shift(len);
+        if self.sending == BodyState::Never || self.sending == BodyState::Complete {
+            // Nobody will read the adapted body.
+            return;
+        }
+        self.transformer.transform(chunk, &mut self.buffer);
+        if self.transformer.failed() {
+            self.stop_virgin_body::<H>(host);
+            self.done = Some(false);
+        }
+        self.notify::<H>(host);
+    }
+
+    /// Pass the end of the virgin body on to the adapted body.
+    pub fn virgin_body_content_done<H: host::Host + ?Sized>(
+        &mut self,
+        host: &mut H::TransactionRef,
+        at_end: bool,
+    ) {
+        if self.receiving != BodyState::On {
+            return;
+        }
+        self.receiving = BodyState::Complete;
+        if self.sending != BodyState::Never && self.sending != BodyState::Complete {
+            self.transformer.finish(&mut self.buffer);
+        }
+        self.done = Some(at_end && !self.transformer.failed());
+        self.notify::<H>(host);
+    }
+}
@@ -0,0 +1,657 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+
+use brotli;
+use flate2::write::{DeflateDecoder, GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder};
+use flate2::Compression;
+
+use adapter::BodyTransformer;
+use common::header::Header;
+use common::name::HEADER_CONTENT_LENGTH;
+use common::{Area, AreaChain, Error, ErrorKind, Name};
+
+/// The size of the brotli buffers, and the settings of its encoder.
+const BROTLI_BUFFER: usize = 4096;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+
+/// The `Content-Encoding` header field name.
+///
+/// libecap does not predefine it, so it is matched by its image.
+pub fn content_encoding() -> Name<'static> {
+    Name::new_known(&b"Content-Encoding"[..])
+}
+
+/// A content coding of a message body, as named by `Content-Encoding`.
+#[derive(Debug, PartialEq, Copy, Eq, Clone)]
+pub enum ContentEncoding {
+    /// `gzip`, or its alias `x-gzip`.
+    Gzip,
+    /// `deflate`: zlib data, or raw deflate data as some servers send.
+    Deflate,
+    /// `br`
+    Brotli,
+}
+
+impl ContentEncoding {
+    /// The coding with the given name, ignoring case.
+    pub fn from_name(name: &[u8]) -> Option<ContentEncoding> {
+        let name = name.to_ascii_lowercase();
+        match &name[..] {
+            b"gzip" | b"x-gzip" => Some(ContentEncoding::Gzip),
+            b"deflate" => Some(ContentEncoding::Deflate),
+            b"br" => Some(ContentEncoding::Brotli),
+            _ => None,
+        }
+    }
+
+    /// The name of this coding in `Content-Encoding`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+            ContentEncoding::Brotli => "br",
+        }
+    }
+
+    /// The coding of the body described by `header`, or `None` if the
+    /// body is not encoded.
+    ///
+    /// Fails with `ErrorKind::Unsupported` for unknown codings and for
+    /// several codings applied one after another.
+    pub fn of<Hd: Header + ?Sized>(header: &Hd) -> Result<Option<ContentEncoding>, Error> {
+        let value = match header.get(&content_encoding()) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let mut codings = value
+            .as_bytes()
+            .split(|&b| b == b',')
+            .map(trim)
+            .filter(|name| !name.is_empty() && !name.eq_ignore_ascii_case(b"identity"));
+        let name = match codings.next() {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        if codings.next().is_some() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "multiple content codings",
+            ));
+        }
+        match ContentEncoding::from_name(name) {
+            Some(coding) => Ok(Some(coding)),
+            None => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "unsupported content coding {:?}",
+                    String::from_utf8_lossy(name)
+                ),
+            )),
+        }
+    }
+
+    fn decoder(&self) -> Box<dyn Coder> {
+        match *self {
+            ContentEncoding::Gzip => Box::new(GzDecoder::new(Vec::new())),
+            ContentEncoding::Deflate => Box::new(Deflate {
+                start: Vec::new(),
+                decoder: None,
+            }),
+            ContentEncoding::Brotli => {
+                Box::new(brotli::DecompressorWriter::new(Vec::new(), BROTLI_BUFFER))
+            }
+        }
+    }
+
+    fn encoder(&self) -> Box<dyn Coder> {
+        match *self {
+            ContentEncoding::Gzip => Box::new(GzEncoder::new(Vec::new(), Compression::default())),
+            ContentEncoding::Deflate => {
+                Box::new(ZlibEncoder::new(Vec::new(), Compression::default()))
+            }
+            ContentEncoding::Brotli => Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                BROTLI_BUFFER,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            )),
+        }
+    }
+}
+
+fn trim(mut bytes: &[u8]) -> &[u8] {
+    while let Some((&b, rest)) = bytes.split_first() {
+        if !b.is_ascii_whitespace() {
+            break;
+        }
+        bytes = rest;
+    }
+    while let Some((&b, rest)) = bytes.split_last() {
+        if !b.is_ascii_whitespace() {
+            break;
+        }
+        bytes = rest;
+    }
+    bytes
+}
+
+/// A streaming decoder or encoder.
+trait Coder {
+    /// Code `input`, returning the output produced so far.
+    fn code(&mut self, input: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// The input has ended: return the rest of the output.
+    fn end(self: Box<Self>) -> io::Result<Vec<u8>>;
+}
+
+macro_rules! flate2_coder {
+    ($($coder:ident),*) => {
+        $(
+            impl Coder for $coder<Vec<u8>> {
+                fn code(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+                    self.write_all(input)?;
+                    Ok(mem::replace(self.get_mut(), Vec::new()))
+                }
+
+                fn end(self: Box<Self>) -> io::Result<Vec<u8>> {
+                    (*self).finish()
+                }
+            }
+        )*
+    };
+}
+
+flate2_coder!(
+    GzDecoder,
+    ZlibDecoder,
+    DeflateDecoder,
+    GzEncoder,
+    ZlibEncoder
+);
+
+impl Coder for brotli::DecompressorWriter<Vec<u8>> {
+    fn code(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        self.write_all(input)?;
+        Ok(mem::replace(self.get_mut(), Vec::new()))
+    }
+
+    fn end(self: Box<Self>) -> io::Result<Vec<u8>> {
+        (*self)
+            .into_inner()
+            .map_err(|_| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated brotli stream"))
+    }
+}
+
+impl Coder for brotli::CompressorWriter<Vec<u8>> {
+    fn code(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        self.write_all(input)?;
+        Ok(mem::replace(self.get_mut(), Vec::new()))
+    }
+
+    fn end(self: Box<Self>) -> io::Result<Vec<u8>> {
+        Ok((*self).into_inner())
+    }
+}
+
+/// Decodes `deflate` bodies, which should be zlib data, but which some
+/// servers send as raw deflate data.
+///
+/// The first two bytes tell them apart.
+struct Deflate {
+    start: Vec<u8>,
+    decoder: Option<Box<dyn Coder>>,
+}
+
+impl Deflate {
+    /// Pick the decoder by the bytes seen so far, and decode them.
+    fn begin(&mut self) -> io::Result<Vec<u8>> {
+        let start = mem::replace(&mut self.start, Vec::new());
+        let zlib = match (start.get(0), start.get(1)) {
+            (Some(&cmf), Some(&flg)) => {
+                cmf & 0x0f == 8 && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0
+            }
+            _ => true,
+        };
+        let mut decoder: Box<dyn Coder> = if zlib {
+            Box::new(ZlibDecoder::new(Vec::new()))
+        } else {
+            Box::new(DeflateDecoder::new(Vec::new()))
+        };
+        let output = decoder.code(&start);
+        self.decoder = Some(decoder);
+        output
+    }
+}
+
+impl Coder for Deflate {
+    fn code(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        if let Some(ref mut decoder) = self.decoder {
+            return decoder.code(input);
+        }
+        self.start.extend_from_slice(input);
+        if self.start.len() < 2 {
+            return Ok(Vec::new());
+        }
+        self.begin()
+    }
+
+    fn end(mut self: Box<Self>) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        if self.decoder.is_none() {
+            if self.start.is_empty() {
+                return Ok(output);
+            }
+            output = self.begin()?;
+        }
+        output.extend(self.decoder.take().unwrap().end()?);
+        Ok(output)
+    }
+}
+
+fn to_area(bytes: Vec<u8>) -> Area {
+    Area::new(Rc::new(bytes))
+}
+
+/// A transformer which decodes the virgin body for another transformer,
+/// and encodes its output again.
+///
+/// Until `prepare` is called with the header of the adapted message,
+/// the body is passed through as is. `prepare` finds the coding of the
+/// body in the `Content-Encoding` of the header, which is still that of
+/// the virgin message, and updates the header to describe the adapted
+/// body:
+///
+/// ```rust,ignore
+/// let mut adapted = host.virgin().clone();
+/// if self.body.transformer_mut().prepare(adapted.header_mut()).is_err() {
+///     // An unknown coding: leave the message alone.
+///     host.use_virgin();
+///     return;
+/// }
+/// ```
+///
+/// A body in an unsupported coding is passed on untouched if used
+/// anyway: the inner transformer never sees it.
+///
+/// If the body cannot be decoded, the transformer fails and the rest of
+/// the body is dropped.
+pub struct Decoded<T> {
+    inner: T,
+    reencode: bool,
+    encoding: Option<ContentEncoding>,
+    /// Whether the coding is unsupported, so that the body bypasses
+    /// `inner`.
+    bypass: bool,
+    decoder: Option<Box<dyn Coder>>,
+    encoder: Option<Box<dyn Coder>>,
+    /// Output of `inner` which has not been encoded yet.
+    decoded: AreaChain,
+    error: Option<io::Error>,
+}
+
+impl<T: BodyTransformer> Decoded<T> {
+    /// Decode the body for `inner`.
+    ///
+    /// If `reencode` is false, the adapted body is sent without a
+    /// content coding instead.
+    pub fn new(inner: T, reencode: bool) -> Decoded<T> {
+        Decoded {
+            inner,
+            reencode,
+            encoding: None,
+            bypass: false,
+            decoder: None,
+            encoder: None,
+            decoded: AreaChain::new(),
+            error: None,
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// The coding of the virgin body, once known.
+    pub fn encoding(&self) -> Option<ContentEncoding> {
+        self.encoding
+    }
+
+    /// Why the body could not be decoded or encoded, if it could not.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Set up decoding according to the `Content-Encoding` of `header`,
+    /// and update `header` to match the adapted body.
+    ///
+    /// For an encoded body, the `Content-Length` is removed, as the
+    /// length of the adapted body is not known in advance, and unless
+    /// re-encoding, so is the `Content-Encoding`.
+    ///
+    /// Fails with `ErrorKind::Unsupported` if the coding is not
+    /// supported, leaving `header` alone; the body is then passed on
+    /// untouched, bypassing the inner transformer.
+    pub fn prepare<Hd: Header + ?Sized>(
+        &mut self,
+        header: &mut Hd,
+    ) -> Result<Option<ContentEncoding>, Error> {
+        let encoding = match ContentEncoding::of(header) {
+            Ok(Some(encoding)) => encoding,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                self.bypass = true;
+                return Err(e);
+            }
+        };
+        self.encoding = Some(encoding);
+        self.decoder = Some(encoding.decoder());
+        if self.reencode {
+            self.encoder = Some(encoding.encoder());
+        } else {
+            header.remove_any(&content_encoding());
+        }
+        header.remove_any(&HEADER_CONTENT_LENGTH);
+        Ok(Some(encoding))
+    }
+
+    fn fail(&mut self, error: io::Error) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+        self.decoder = None;
+        self.encoder = None;
+        self.decoded = AreaChain::new();
+    }
+
+    /// Move the output of the inner transformer to `out`, encoding it.
+    fn encode(&mut self, out: &mut AreaChain) {
+        let decoded = mem::replace(&mut self.decoded, AreaChain::new());
+        let encoder = match self.encoder {
+            Some(ref mut encoder) => encoder,
+            None => return out.extend(decoded.areas().cloned()),
+        };
+        let mut result = Ok(());
+        for area in decoded.areas() {
+            match encoder.code(area.as_bytes()) {
+                Ok(encoded) => out.push(to_area(encoded)),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+}
+
+impl<T: BodyTransformer> BodyTransformer for Decoded<T> {
+    fn transform(&mut self, chunk: Area, out: &mut AreaChain) {
+        if self.bypass {
+            return out.push(chunk);
+        }
+        if self.error.is_some() {
+            return;
+        }
+        let chunk = match self.decoder {
+            Some(ref mut decoder) => decoder.code(chunk.as_bytes()).map(to_area),
+            None => Ok(chunk),
+        };
+        match chunk {
+            Ok(chunk) => self.inner.transform(chunk, &mut self.decoded),
+            Err(e) => return self.fail(e),
+        }
+        self.encode(out);
+    }
+
+    fn finish(&mut self, out: &mut AreaChain) {
+        if self.bypass || self.error.is_some() {
+            return;
+        }
+        if let Some(decoder) = self.decoder.take() {
+            match decoder.end() {
+                Ok(rest) => self.inner.transform(to_area(rest), &mut self.decoded),
+                Err(e) => return self.fail(e),
+            }
+        }
+        self.inner.finish(&mut self.decoded);
+        self.encode(out);
+        if let Some(encoder) = self.encoder.take() {
+            match encoder.end() {
+                Ok(rest) => out.push(to_area(rest)),
+                Err(e) => self.fail(e),
+            }
+        }
+    }
+
+    fn failed(&self) -> bool {
+        self.error.is_some() || (!self.bypass && self.inner.failed())
+    }
+
+    /// Unknown for encoded bodies, unless passed on untouched.
+    fn adapted_size(&self, virgin: Option<u64>) -> Option<u64> {
+        if self.bypass {
+            return virgin;
+        }
+        match self.encoding {
+            Some(_) => None,
+            None => self.inner.adapted_size(virgin),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Decoded<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Decoded")
+            .field("inner", &self.inner)
+            .field("reencode", &self.reencode)
+            .field("encoding", &self.encoding)
+            .field("bypass", &self.bypass)
+            .field("decoded", &self.decoded)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::write::DeflateEncoder;
+
+    use super::*;
+    use adapter::Replace;
+    use common::NamedValueVisitor;
+
+    /// A header which is just a list of fields.
+    #[derive(Default)]
+    struct Fields(Vec<(Name<'static>, Area)>);
+
+    impl Header for Fields {
+        fn contains_field(&self, field: &Name) -> bool {
+            self.0.iter().any(|entry| &entry.0 == field)
+        }
+        fn get(&self, field: &Name) -> Option<Area> {
+            self.0
+                .iter()
+                .find(|entry| &entry.0 == field)
+                .map(|entry| entry.1.clone())
+        }
+        fn insert(&mut self, field: Name, value: Area) {
+            self.0.push((field.to_owned(), value));
+        }
+        fn remove_any(&mut self, field: &Name) {
+            self.0.retain(|entry| &entry.0 != field);
+        }
+        fn visit_each<V: NamedValueVisitor>(&self, visitor: &mut V) {
+            for entry in &self.0 {
+                visitor.visit(&entry.0, &entry.1);
+            }
+        }
+        fn image(&self) -> Area {
+            unimplemented!()
+        }
+        fn parse(&mut self, _buf: &Area) -> Result<(), Error> {
+            unimplemented!()
+        }
+    }
+
+    const BODY: &[u8] = b"hello, world! hello, world! hello, world!";
+    const ADAPTED: &[u8] = b"hello, there! hello, there! hello, there!";
+
+    fn encoded_header(coding: &str, length: usize) -> Fields {
+        let mut header = Fields::default();
+        header.insert(content_encoding(), Area::from_bytes(coding.as_bytes()));
+        header.insert(
+            HEADER_CONTENT_LENGTH,
+            Area::from_bytes(length.to_string().as_bytes()),
+        );
+        header
+    }
+
+    fn encode<W: Write>(mut encoder: W, finish: fn(W) -> io::Result<Vec<u8>>) -> Vec<u8> {
+        encoder.write_all(BODY).unwrap();
+        finish(encoder).unwrap()
+    }
+
+    fn decoded(reencode: bool) -> Decoded<Replace> {
+        Decoded::new(Replace::new(vec![("world", "there")]), reencode)
+    }
+
+    /// Run `body` through `decoded` in small chunks.
+    fn run(decoded: &mut Decoded<Replace>, body: &[u8]) -> Vec<u8> {
+        let mut out = AreaChain::new();
+        for chunk in body.chunks(7) {
+            decoded.transform(Area::from_bytes(chunk), &mut out);
+        }
+        decoded.finish(&mut out);
+        assert!(!decoded.failed(), "{:?}", decoded.error());
+        out.to_area().as_bytes().to_vec()
+    }
+
+    fn decode(encoding: ContentEncoding, body: &[u8]) -> Vec<u8> {
+        let mut decoder = encoding.decoder();
+        let mut decoded = decoder.code(body).unwrap();
+        decoded.extend(decoder.end().unwrap());
+        decoded
+    }
+
+    fn round_trip(coding: &str, encoded: Vec<u8>, encoding: ContentEncoding) {
+        // Re-encoded with the same coding.
+        let mut header = encoded_header(coding, encoded.len());
+        let mut transformer = decoded(true);
+        assert_eq!(transformer.prepare(&mut header).unwrap(), Some(encoding));
+        assert!(!header.contains_field(&HEADER_CONTENT_LENGTH));
+        assert_eq!(
+            header.get(&content_encoding()).unwrap().as_bytes(),
+            coding.as_bytes()
+        );
+        assert_eq!(transformer.adapted_size(Some(encoded.len() as u64)), None);
+        let adapted = run(&mut transformer, &encoded);
+        assert_eq!(decode(encoding, &adapted), ADAPTED);
+        assert_eq!(transformer.inner().replaced(), 3);
+
+        // Sent without a coding.
+        let mut header = encoded_header(coding, encoded.len());
+        let mut transformer = decoded(false);
+        transformer.prepare(&mut header).unwrap();
+        assert!(!header.contains_field(&content_encoding()));
+        assert_eq!(run(&mut transformer, &encoded), ADAPTED);
+    }
+
+    #[test]
+    fn gzip() {
+        let encoded = encode(GzEncoder::new(Vec::new(), Compression::default()), |e| {
+            e.finish()
+        });
+        round_trip("gzip", encoded.clone(), ContentEncoding::Gzip);
+        round_trip("X-GZip", encoded, ContentEncoding::Gzip);
+    }
+
+    #[test]
+    fn deflate_zlib() {
+        let encoded = encode(ZlibEncoder::new(Vec::new(), Compression::default()), |e| {
+            e.finish()
+        });
+        round_trip("deflate", encoded, ContentEncoding::Deflate);
+    }
+
+    #[test]
+    fn deflate_raw() {
+        let encoded = encode(
+            DeflateEncoder::new(Vec::new(), Compression::default()),
+            |e| e.finish(),
+        );
+        round_trip("deflate", encoded, ContentEncoding::Deflate);
+    }
+
+    #[test]
+    fn deflate_sniffed_across_chunks() {
+        // The first chunk is too short to tell zlib from raw deflate.
+        let encoded = encode(
+            DeflateEncoder::new(Vec::new(), Compression::default()),
+            |e| e.finish(),
+        );
+        let mut header = encoded_header("deflate", encoded.len());
+        let mut transformer = decoded(false);
+        transformer.prepare(&mut header).unwrap();
+        let mut out = AreaChain::new();
+        transformer.transform(Area::from_bytes(&encoded[..1]), &mut out);
+        transformer.transform(Area::from_bytes(&encoded[1..]), &mut out);
+        transformer.finish(&mut out);
+        assert_eq!(out.to_area().as_bytes(), ADAPTED);
+    }
+
+    #[test]
+    fn brotli() {
+        let encoded = encode(
+            brotli::CompressorWriter::new(Vec::new(), BROTLI_BUFFER, BROTLI_QUALITY, BROTLI_WINDOW),
+            |e| Ok(e.into_inner()),
+        );
+        round_trip("br", encoded, ContentEncoding::Brotli);
+    }
+
+    #[test]
+    fn identity() {
+        let mut header = encoded_header("identity", BODY.len());
+        let mut transformer = decoded(true);
+        assert_eq!(transformer.prepare(&mut header).unwrap(), None);
+        assert!(header.contains_field(&HEADER_CONTENT_LENGTH));
+        assert_eq!(run(&mut transformer, BODY), ADAPTED);
+    }
+
+    #[test]
+    fn unsupported_coding_bypasses_inner() {
+        for coding in &["compress", "gzip, br"] {
+            let mut header = encoded_header(coding, BODY.len());
+            let mut transformer = decoded(true);
+            let error = transformer.prepare(&mut header).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Unsupported);
+            assert_eq!(
+                header.get(&content_encoding()).unwrap().as_bytes(),
+                coding.as_bytes()
+            );
+            assert!(header.contains_field(&HEADER_CONTENT_LENGTH));
+            assert_eq!(
+                transformer.adapted_size(Some(BODY.len() as u64)),
+                Some(BODY.len() as u64)
+            );
+            assert_eq!(run(&mut transformer, BODY), BODY);
+            assert_eq!(transformer.inner().replaced(), 0);
+        }
+    }
+
+    #[test]
+    fn corrupt_body() {
+        let mut header = encoded_header("gzip", BODY.len());
+        let mut transformer = decoded(true);
+        transformer.prepare(&mut header).unwrap();
+        let mut out = AreaChain::new();
+        transformer.transform(Area::from_bytes(BODY), &mut out);
+        transformer.finish(&mut out);
+        assert!(transformer.failed());
+        assert!(transformer.error().is_some());
+    }
+}
@@ -0,0 +1,315 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+use adapter::{Registered, Registry, WakeHandle};
+use host;
+
+/// Runs the futures of an async service's transactions on the host
+/// thread.
+///
+/// The service wraps its transactions with `Executor::transaction`,
+/// and forwards its async methods to the executor. Transactions `spawn`
+/// futures, e.g. for a remote lookup, and keep the returned `Spawned`
+/// handle; the executor polls woken futures in `Service::resume` and
+/// asks the host to resume each transaction whose future completed.
+/// The transaction then picks up the output in its
+/// `adapter::Transaction::resume`:
+///
+/// ```rust,ignore
+/// fn is_async(&self) -> bool {
+///     true
+/// }
+///
+/// fn suspend(&self, timeout: &mut Duration) {
+///     self.executor.suspend(timeout)
+/// }
+///
+/// fn resume(&self) {
+///     self.executor.resume()
+/// }
+///
+/// fn make_transaction(&mut self, _host: &mut H::TransactionRef) -> Registered<LookupTransaction> {
+///     self.executor.transaction(LookupTransaction { executor: self.executor.clone(), lookup: None })
+/// }
+/// ```
+///
+/// and in the transaction:
+///
+/// ```rust,ignore
+/// fn start<'a>(&mut self, host: &'a mut H::TransactionRef) {
+///     self.lookup = Some(self.executor.spawn::<H, _>(host, lookup()));
+/// }
+/// ```
+///
+/// Futures need not be `Send`, but they may be woken from any thread;
+/// as the host only waits in `suspend` for so long, pending futures
+/// are noticed within the poll interval. Once a transaction stops, its
+/// completed futures no longer resume it.
+///
+/// Transactions are resumed through the executor's `Registry`, which
+/// the service may also hand to transactions which wake themselves.
+#[derive(Clone, Default)]
+pub struct Executor {
+    shared: Rc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    registry: Registry,
+    tasks: RefCell<HashMap<usize, Task>>,
+    next_id: Cell<usize>,
+    woken: Arc<WakeQueue>,
+}
+
+/// Ids of the tasks to poll, filled from any thread.
+#[derive(Default)]
+struct WakeQueue {
+    ids: Mutex<Vec<usize>>,
+}
+
+impl WakeQueue {
+    fn lock(&self) -> MutexGuard<Vec<usize>> {
+        // A panicking waker cannot leave the list inconsistent.
+        self.ids.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, id: usize) {
+        let mut ids = self.lock();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+}
+
+struct TaskWaker {
+    id: usize,
+    queue: Arc<WakeQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.queue.push(self.id);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.queue.push(self.id);
+    }
+}
+
+struct Task {
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    waker: Waker,
+    /// The transaction which spawned the task.
+    handle: WakeHandle,
+    /// Set once the `Spawned` handle is gone.
+    cancelled: Rc<Cell<bool>>,
+}
+
+/// Stores the output of a future for its `Spawned` handle.
+struct Store<F: Future> {
+    future: Pin<Box<F>>,
+    slot: Rc<RefCell<Option<F::Output>>>,
+}
+
+impl<F: Future> Future for Store<F> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        match self.future.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                *self.slot.borrow_mut() = Some(output);
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Executor {
+    pub fn new() -> Executor {
+        Executor::default()
+    }
+
+    /// The registry through which transactions are resumed.
+    pub fn registry(&self) -> &Registry {
+        &self.shared.registry
+    }
+
+    /// How long the host may wait while futures are pending: the
+    /// longest a wakeup from another thread goes unnoticed.
+    pub fn set_poll_interval(&self, interval: Duration) {
+        self.shared.registry.set_poll_interval(interval);
+    }
+
+    /// Wrap `transaction`, so that its futures can resume it from
+    /// `start` until `stop`.
+    pub fn transaction<T>(&self, transaction: T) -> Registered<T> {
+        self.shared.registry.transaction(transaction)
+    }
+
+    /// Run `future` for the transaction of `host`.
+    ///
+    /// The future is first polled from the next `Service::resume`;
+    /// once it completes, the host is asked to resume the transaction.
+    ///
+    /// # Panics
+    ///
+    /// If the transaction of `host` is not registered: it must be made
+    /// with `Executor::transaction`, and be running.
+    pub fn spawn<H, F>(&self, host: &mut H::TransactionRef, future: F) -> Spawned<F::Output>
+    where
+        H: host::Host + ?Sized,
+        F: Future + 'static,
+    {
+        let handle = self
+            .shared
+            .registry
+            .handle::<H>(host)
+            .expect("spawned a future for a transaction which is not registered");
+        let id = self.shared.next_id.get();
+        self.shared.next_id.set(id.wrapping_add(1));
+
+        let slot = Rc::new(RefCell::new(None));
+        let cancelled = Rc::new(Cell::new(false));
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            queue: self.shared.woken.clone(),
+        }));
+        let task = Task {
+            future: Box::pin(Store {
+                future: Box::pin(future),
+                slot: slot.clone(),
+            }),
+            waker,
+            handle,
+            cancelled: cancelled.clone(),
+        };
+        self.shared.tasks.borrow_mut().insert(id, task);
+        self.shared.woken.push(id);
+        Spawned {
+            slot,
+            cancelled,
+            shared: Rc::downgrade(&self.shared),
+            id,
+        }
+    }
+
+    /// Whether there are no futures left to run.
+    pub fn is_idle(&self) -> bool {
+        self.shared.tasks.borrow().is_empty()
+    }
+
+    /// Shorten the time the host waits before calling `resume`: to
+    /// nothing if futures have been woken, and to the poll interval if
+    /// any are pending.
+    ///
+    /// Call this from `Service::suspend`.
+    pub fn suspend(&self, timeout: &mut Duration) {
+        if !self.shared.woken.lock().is_empty() {
+            *timeout = Duration::from_secs(0);
+        }
+        // Transactions with pending futures are registered while they run.
+        self.shared.registry.suspend(timeout);
+    }
+
+    /// Poll the woken futures, and resume the host transactions whose
+    /// futures completed, along with any woken through the registry.
+    ///
+    /// Call this from `Service::resume`.
+    pub fn resume(&self) {
+        let woken = ::std::mem::replace(&mut *self.shared.woken.lock(), Vec::new());
+        for id in woken {
+            // The task is taken out while it runs, so that it may spawn
+            // or cancel others.
+            let mut task = match self.shared.tasks.borrow_mut().remove(&id) {
+                Some(task) => task,
+                None => continue,
+            };
+            if task.cancelled.get() {
+                continue;
+            }
+            let poll = {
+                let mut cx = Context::from_waker(&task.waker);
+                task.future.as_mut().poll(&mut cx)
+            };
+            match poll {
+                Poll::Ready(()) => {
+                    if !task.cancelled.get() {
+                        task.handle.wake();
+                    }
+                }
+                Poll::Pending => {
+                    if !task.cancelled.get() {
+                        self.shared.tasks.borrow_mut().insert(id, task);
+                    }
+                }
+            }
+        }
+        self.shared.registry.resume();
+    }
+}
+
+impl fmt::Debug for Executor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Executor")
+            .field("tasks", &self.shared.tasks.borrow().len())
+            .field("woken", &self.shared.woken.lock().len())
+            .finish()
+    }
+}
+
+/// The output of a future run by an `Executor`, once it is ready.
+///
+/// Dropping the handle cancels the future.
+pub struct Spawned<T> {
+    slot: Rc<RefCell<Option<T>>>,
+    cancelled: Rc<Cell<bool>>,
+    shared: ::std::rc::Weak<Shared>,
+    id: usize,
+}
+
+impl<T> Spawned<T> {
+    /// Whether the future has completed and its output not been taken.
+    pub fn is_ready(&self) -> bool {
+        self.slot.borrow().is_some()
+    }
+
+    /// The output of the future, if it has completed.
+    ///
+    /// Call this from `adapter::Transaction::resume`.
+    pub fn take(&mut self) -> Option<T> {
+        self.slot.borrow_mut().take()
+    }
+
+    /// Stop running the future.
+    pub fn cancel(self) {}
+}
+
+impl<T> Drop for Spawned<T> {
+    fn drop(&mut self) {
+        self.cancelled.set(true);
+        if let Some(shared) = self.shared.upgrade() {
+            // The task is not in the map while it runs; the flag stops
+            // it being run or resuming the host again.
+            if let Ok(mut tasks) = shared.tasks.try_borrow_mut() {
+                tasks.remove(&self.id);
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Spawned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Spawned")
+            .field("output", &self.slot.borrow())
+            .field("cancelled", &self.cancelled.get())
+            .finish()
+    }
+}
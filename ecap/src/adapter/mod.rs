@@ -1,5 +1,25 @@
+mod body;
+pub use self::body::{AdaptedBody, BodyState};
+
+#[cfg(feature = "content-encoding")]
+mod encoding;
+#[cfg(feature = "content-encoding")]
+pub use self::encoding::{content_encoding, ContentEncoding, Decoded};
+
+mod executor;
+pub use self::executor::{Executor, Spawned};
+
+mod replace;
+pub use self::replace::Replace;
+
 mod service;
 pub use self::service::Service;
 
 mod transaction;
 pub use self::transaction::Transaction;
+
+mod transform;
+pub use self::transform::BodyTransformer;
+
+mod wake;
+pub use self::wake::{Registered, Registry, WakeHandle};
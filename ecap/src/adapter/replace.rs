@@ -0,0 +1,310 @@
+use std::cmp;
+
+use adapter::BodyTransformer;
+use common::{Area, AreaChain};
+
+const ROOT: usize = 0;
+
+/// Streaming find and replace of several patterns at once.
+///
+/// Patterns are matched with an Aho-Corasick automaton, so matches
+/// spanning chunk boundaries are found, while only the bytes which may
+/// start a match (fewer than the longest pattern) are held back between
+/// chunks. Content between matches is passed on without copying.
+///
+/// When matches overlap, the one which ends first wins, and of the
+/// patterns ending there, the longest. Replacements are not searched
+/// again.
+///
+/// ```rust,ignore
+/// let replace = Replace::new(vec![("cat", "dog"), ("mouse", "cheese")]);
+/// let body = AdaptedBody::new(replace);
+/// ```
+#[derive(Debug)]
+pub struct Replace {
+    /// The transitions of the automaton, by state and byte.
+    next: Vec<[usize; 256]>,
+    /// The length of the string leading to each state.
+    depth: Vec<usize>,
+    /// The longest pattern which ends at each state, if any.
+    matched: Vec<Option<usize>>,
+    patterns: Vec<usize>,
+    replacements: Vec<Area>,
+
+    state: usize,
+    /// Input which may be the start of a match.
+    pending: Vec<u8>,
+    replaced: u64,
+}
+
+impl Replace {
+    /// Create a transformer replacing each pattern with its replacement.
+    ///
+    /// If a pattern is given twice, the first replacement is used.
+    ///
+    /// # Panics
+    ///
+    /// If a pattern is empty.
+    pub fn new<I, P, R>(pairs: I) -> Replace
+    where
+        I: IntoIterator<Item = (P, R)>,
+        P: AsRef<[u8]>,
+        R: AsRef<[u8]>,
+    {
+        let mut replace = Replace {
+            next: vec![[ROOT; 256]],
+            depth: vec![0],
+            matched: vec![None],
+            patterns: Vec::new(),
+            replacements: Vec::new(),
+            state: ROOT,
+            pending: Vec::new(),
+            replaced: 0,
+        };
+
+        // Build the trie of patterns; missing transitions point at
+        // the root for now.
+        for (pattern, replacement) in pairs {
+            let pattern = pattern.as_ref();
+            assert!(!pattern.is_empty(), "cannot replace an empty pattern");
+            let mut state = ROOT;
+            for &byte in pattern {
+                if replace.next[state][byte as usize] == ROOT {
+                    replace.next.push([ROOT; 256]);
+                    replace.depth.push(replace.depth[state] + 1);
+                    replace.matched.push(None);
+                    let new = replace.next.len() - 1;
+                    replace.next[state][byte as usize] = new;
+                }
+                state = replace.next[state][byte as usize];
+            }
+            if replace.matched[state].is_none() {
+                replace.matched[state] = Some(replace.patterns.len());
+            }
+            replace.patterns.push(pattern.len());
+            replace
+                .replacements
+                .push(Area::from_bytes(replacement.as_ref()));
+        }
+
+        // Turn the trie into a DFA in breadth-first order, following
+        // the failure link of each state for its missing transitions.
+        let mut fail = vec![ROOT; replace.next.len()];
+        let mut queue = ::std::collections::VecDeque::new();
+        for byte in 0..256 {
+            let child = replace.next[ROOT][byte];
+            if child != ROOT {
+                queue.push_back(child);
+            }
+        }
+        while let Some(state) = queue.pop_front() {
+            if replace.matched[state].is_none() {
+                replace.matched[state] = replace.matched[fail[state]];
+            }
+            for byte in 0..256 {
+                let child = replace.next[state][byte];
+                let fallback = replace.next[fail[state]][byte];
+                if child != ROOT && replace.depth[child] == replace.depth[state] + 1 {
+                    fail[child] = fallback;
+                    queue.push_back(child);
+                } else {
+                    replace.next[state][byte] = fallback;
+                }
+            }
+        }
+        replace
+    }
+
+    /// The number of replacements made so far.
+    pub fn replaced(&self) -> u64 {
+        self.replaced
+    }
+
+    /// Append the first `len` bytes of the unemitted input, which is
+    /// `pending` followed by `chunk[*start..]`, to `out`.
+    fn emit(&mut self, chunk: &Area, start: &mut usize, len: usize, out: &mut AreaChain) {
+        let from_pending = cmp::min(len, self.pending.len());
+        if from_pending > 0 {
+            out.push(Area::from_bytes(&self.pending[..from_pending]));
+            self.pending.drain(..from_pending);
+        }
+        let from_chunk = len - from_pending;
+        if from_chunk > 0 {
+            out.push(chunk.slice(*start..*start + from_chunk));
+            *start += from_chunk;
+        }
+    }
+}
+
+impl BodyTransformer for Replace {
+    fn transform(&mut self, chunk: Area, out: &mut AreaChain) {
+        let mut start = 0;
+        for (i, &byte) in chunk.as_bytes().iter().enumerate() {
+            self.state = self.next[self.state][byte as usize];
+            if let Some(pattern) = self.matched[self.state] {
+                // The match is the end of the unemitted input.
+                let unemitted = self.pending.len() + (i + 1 - start);
+                let len = self.patterns[pattern];
+                self.emit(&chunk, &mut start, unemitted - len, out);
+                self.pending.clear();
+                start = i + 1;
+                out.push(self.replacements[pattern].clone());
+                self.replaced += 1;
+                self.state = ROOT;
+            }
+        }
+        // Hold back what may be the start of a match.
+        let len = chunk.as_bytes().len();
+        let unemitted = self.pending.len() + (len - start);
+        let held = self.depth[self.state];
+        self.emit(&chunk, &mut start, unemitted - held, out);
+        self.pending.extend_from_slice(&chunk.as_bytes()[start..]);
+    }
+
+    fn finish(&mut self, out: &mut AreaChain) {
+        if !self.pending.is_empty() {
+            out.push(Area::from_bytes(&self.pending));
+            self.pending.clear();
+        }
+        self.state = ROOT;
+    }
+
+    /// Unchanged if each replacement is as long as its pattern.
+    fn adapted_size(&self, virgin: Option<u64>) -> Option<u64> {
+        let same_length = self
+            .patterns
+            .iter()
+            .zip(&self.replacements)
+            .all(|(&len, replacement)| replacement.as_bytes().len() == len);
+        if same_length {
+            virgin
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(replace: &mut Replace, chunk: &[u8]) -> Vec<u8> {
+        let mut out = AreaChain::new();
+        replace.transform(Area::from_bytes(chunk), &mut out);
+        out.to_area().as_bytes().to_vec()
+    }
+
+    fn finish(replace: &mut Replace) -> Vec<u8> {
+        let mut out = AreaChain::new();
+        replace.finish(&mut out);
+        out.to_area().as_bytes().to_vec()
+    }
+
+    fn run(replace: &mut Replace, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut adapted = Vec::new();
+        for chunk in chunks {
+            adapted.extend(transform(replace, chunk));
+        }
+        adapted.extend(finish(replace));
+        adapted
+    }
+
+    #[test]
+    fn single_chunk() {
+        let mut replace = Replace::new(vec![("cat", "dog"), ("mouse", "cheese")]);
+        let adapted = run(&mut replace, &[b"a cat and a mouse, and a cat"]);
+        assert_eq!(adapted, &b"a dog and a cheese, and a dog"[..]);
+        assert_eq!(replace.replaced(), 3);
+    }
+
+    #[test]
+    fn match_split_across_chunks() {
+        let mut replace = Replace::new(vec![("mouse", "cheese")]);
+        assert_eq!(transform(&mut replace, b"a mo"), b"a ");
+        assert_eq!(transform(&mut replace, b"u"), b"");
+        assert_eq!(transform(&mut replace, b"se!"), b"cheese!");
+        assert_eq!(finish(&mut replace), b"");
+        assert_eq!(replace.replaced(), 1);
+    }
+
+    #[test]
+    fn every_split() {
+        let body = b"the cat sat on the mat with the catalogue";
+        for split in 0..body.len() {
+            let mut replace = Replace::new(vec![("cat", "dog"), ("the mat", "a rug")]);
+            let adapted = run(&mut replace, &[&body[..split], &body[split..]]);
+            assert_eq!(
+                adapted,
+                &b"the dog sat on a rug with the dogalogue"[..],
+                "split at {}",
+                split
+            );
+        }
+    }
+
+    #[test]
+    fn failed_match_is_passed_on() {
+        // A partial match which fails must not lose the bytes which
+        // may start another match.
+        let mut replace = Replace::new(vec![("aab", "X")]);
+        assert_eq!(run(&mut replace, &[b"aa", b"aab"]), b"aaX");
+    }
+
+    #[test]
+    fn overlap_ending_first_wins() {
+        let mut replace = Replace::new(vec![("abcd", "1"), ("bc", "2")]);
+        assert_eq!(run(&mut replace, &[b"abcde"]), b"a2de");
+
+        let mut replace = Replace::new(vec![("bc", "2"), ("cde", "3")]);
+        assert_eq!(run(&mut replace, &[b"abcde"]), b"a2de");
+    }
+
+    #[test]
+    fn overlap_longest_wins() {
+        let mut replace = Replace::new(vec![("bc", "2"), ("abc", "1")]);
+        assert_eq!(run(&mut replace, &[b"xabcx"]), b"x1x");
+
+        let mut replace = Replace::new(vec![("abc", "1"), ("c", "3"), ("bc", "2")]);
+        assert_eq!(run(&mut replace, &[b"xab", b"cx"]), b"x1x");
+    }
+
+    #[test]
+    fn replacements_are_not_searched() {
+        let mut replace = Replace::new(vec![("a", "aa"), ("b", "a")]);
+        assert_eq!(run(&mut replace, &[b"ab"]), b"aaa");
+        assert_eq!(replace.replaced(), 2);
+    }
+
+    #[test]
+    fn duplicate_pattern() {
+        let mut replace = Replace::new(vec![("cat", "dog"), ("cat", "cow")]);
+        assert_eq!(run(&mut replace, &[b"cat"]), b"dog");
+    }
+
+    #[test]
+    fn partial_match_left_at_finish() {
+        let mut replace = Replace::new(vec![("world", "there")]);
+        assert_eq!(transform(&mut replace, b"hello, wor"), b"hello, ");
+        assert_eq!(finish(&mut replace), b"wor");
+        assert_eq!(replace.replaced(), 0);
+
+        // The partial match does not carry over to the next body.
+        assert_eq!(run(&mut replace, &[b"ld"]), b"ld");
+    }
+
+    #[test]
+    fn adapted_size() {
+        let replace = Replace::new(vec![("cat", "dog"), ("mouse", "house")]);
+        assert_eq!(replace.adapted_size(Some(10)), Some(10));
+        assert_eq!(replace.adapted_size(None), None);
+
+        let replace = Replace::new(vec![("cat", "dog"), ("mouse", "cheese")]);
+        assert_eq!(replace.adapted_size(Some(10)), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_pattern() {
+        Replace::new(vec![("", "x")]);
+    }
+}
@@ -1,7 +1,7 @@
 use std::ffi::CStr;
 use std::time::Duration;
 
-use {adapter, common::Options, host};
+use {adapter, common::Error, common::Options, common::Version, common::LIBECAP_VERSION, host};
 
 /// This trait is the equivalent of libecap::adapter::Service.
 pub trait Service<H: ?Sized + host::Host> {
@@ -18,22 +18,37 @@ pub trait Service<H: ?Sized + host::Host> {
     // FIXME: Migrate to fmt::Display impl?
     fn describe(&self) -> String;
 
+    /// The version of libecap this service was built for.
+    ///
+    /// Hosts check it through `Host::note_versioned_service` when the
+    /// service is registered, and refuse services they are not
+    /// compatible with.
+    fn libecap_version(&self) -> Version {
+        LIBECAP_VERSION
+    }
+
     /// Determines whether this adapter requires async transactions.
     ///
     /// If false (as by default) the Host will not call suspend and resume.
+    ///
+    /// Async services can run the futures of their transactions on an
+    /// `adapter::Executor`, forwarding `suspend` and `resume` to it.
     fn is_async(&self) -> bool {
         false
     }
 
     /// Called by the Host to initially configure the adapter service.
     ///
-    /// Should only be called once.
-    fn configure<T: Options>(&mut self, options: &T);
+    /// Should only be called once. A service which rejects its
+    /// configuration returns an error, which the host logs; see
+    /// `common::config` for deserializing options into a typed
+    /// configuration.
+    fn configure<T: Options>(&mut self, options: &T) -> Result<(), Error>;
 
     /// Called by the host when the configuration for an adapter
     /// changes. It may be called with the same configuration as passed
     /// previously.
-    fn reconfigure<T: Options>(&mut self, options: &T);
+    fn reconfigure<T: Options>(&mut self, options: &T) -> Result<(), Error>;
 
     /// Prepare for creation of transactions via `make_transaction` calls.
     fn start(&self);
@@ -98,10 +113,10 @@ where
     fn describe(&self) -> String {
         (&**self).describe()
     }
-    fn configure<T: Options>(&mut self, options: &T) {
+    fn configure<T: Options>(&mut self, options: &T) -> Result<(), Error> {
         (&mut **self).configure(options)
     }
-    fn reconfigure<T: Options>(&mut self, options: &T) {
+    fn reconfigure<T: Options>(&mut self, options: &T) -> Result<(), Error> {
         (&mut **self).reconfigure(options)
     }
     fn start(&self) {
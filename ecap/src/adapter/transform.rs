@@ -0,0 +1,45 @@
+use common::{Area, AreaChain};
+
+/// Incremental adaptation of a body, for `AdaptedBody`.
+///
+/// Chunks of virgin body are passed to `transform` as they arrive, and
+/// the output is appended to the adapted body. A transformer may hold
+/// back the end of a chunk, for example because it may be the start of
+/// a match continuing in the next chunk, as long as it emits it from a
+/// later call.
+///
+/// Any `FnMut(Area) -> Area` is a transformer which holds nothing back.
+pub trait BodyTransformer {
+    /// Transform a chunk of the virgin body, appending the output to
+    /// `out`.
+    fn transform(&mut self, chunk: Area, out: &mut AreaChain);
+
+    /// The virgin body has ended: append anything held back to `out`.
+    ///
+    /// This is also called if the virgin body was cut off.
+    fn finish(&mut self, out: &mut AreaChain);
+
+    /// Whether the transformer gave up, e.g. on a corrupt body.
+    ///
+    /// `AdaptedBody` then stops the virgin body and ends the adapted
+    /// body early.
+    fn failed(&self) -> bool {
+        false
+    }
+
+    /// The size of the adapted body, given that of the virgin body, if
+    /// it is known before the body is transformed.
+    ///
+    /// By default the size is unknown.
+    fn adapted_size(&self, _virgin: Option<u64>) -> Option<u64> {
+        None
+    }
+}
+
+impl<F: FnMut(Area) -> Area> BodyTransformer for F {
+    fn transform(&mut self, chunk: Area, out: &mut AreaChain) {
+        out.push(self(chunk));
+    }
+
+    fn finish(&mut self, _out: &mut AreaChain) {}
+}
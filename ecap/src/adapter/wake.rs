@@ -0,0 +1,392 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::{Rc, Weak};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use adapter::Transaction;
+use common::{Area, Name, NamedValueVisitor, Options};
+use host::{self, Transaction as HostTransaction};
+
+/// How long the host may wait while transactions are registered,
+/// unless told otherwise.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The live transactions of an async service, which worker threads
+/// can ask the host to resume.
+///
+/// The service wraps its transactions with `Registry::transaction`,
+/// which registers the host transaction from `adapter::Transaction::start`
+/// until `stop`. Transactions look up their `WakeHandle` with `handle`,
+/// and hand it to worker threads, which `wake` it once they have
+/// something for the transaction. The service forwards `suspend` and
+/// `resume` to the registry, which calls `host::Transaction::resume` on
+/// the host thread for each transaction woken since:
+///
+/// ```rust,ignore
+/// fn make_transaction(&mut self, _host: &mut H::TransactionRef) -> Registered<Lookup> {
+///     self.registry.transaction(Lookup { registry: self.registry.clone(), results })
+/// }
+/// ```
+///
+/// and in the transaction:
+///
+/// ```rust,ignore
+/// fn start<'a>(&mut self, host: &'a mut H::TransactionRef) {
+///     let handle = self.registry.handle::<H>(host).unwrap();
+///     let results = self.results.clone();
+///     self.pool.execute(move || {
+///         *results.lock().unwrap() = Some(lookup());
+///         handle.wake();
+///     });
+/// }
+/// ```
+///
+/// Handles outlive their transactions safely: once the transaction has
+/// stopped, waking does nothing.
+#[derive(Clone, Default)]
+pub struct Registry {
+    shared: Rc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    live: RefCell<HashMap<u64, Rc<Live>>>,
+    signals: Arc<Signals>,
+    next_id: Cell<u64>,
+    poll_interval: Cell<Option<Duration>>,
+}
+
+/// A registered host transaction.
+struct Live {
+    /// The address of the host transaction, to resume it once.
+    addr: usize,
+    resume: Box<dyn Fn()>,
+}
+
+/// The part of a registry shared with other threads.
+#[derive(Default)]
+struct Signals {
+    state: Mutex<SignalState>,
+}
+
+#[derive(Default)]
+struct SignalState {
+    live: HashSet<u64>,
+    woken: Vec<u64>,
+}
+
+impl Signals {
+    fn lock(&self) -> MutexGuard<SignalState> {
+        // A panicking waker cannot leave the state inconsistent.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// How long the host may wait while transactions are registered:
+    /// the longest a wakeup from another thread goes unnoticed.
+    pub fn set_poll_interval(&self, interval: Duration) {
+        self.shared.poll_interval.set(Some(interval));
+    }
+
+    /// Wrap `transaction`, so that its host transaction is registered
+    /// from `start` until `stop`.
+    pub fn transaction<T>(&self, transaction: T) -> Registered<T> {
+        Registered {
+            transaction,
+            registry: self.clone(),
+            registration: None,
+        }
+    }
+
+    /// The wake handle of the transaction of `host`, if it is
+    /// registered.
+    ///
+    /// Transactions made with `Registry::transaction` are registered
+    /// once they have started, and until they stop.
+    pub fn handle<H>(&self, host: &H::TransactionRef) -> Option<WakeHandle>
+    where
+        H: host::Host + ?Sized,
+    {
+        let addr = host as *const H::TransactionRef as *const u8 as usize;
+        self.shared
+            .live
+            .borrow()
+            .iter()
+            .find(|&(_, live)| live.addr == addr)
+            .map(|(&id, _)| WakeHandle {
+                id,
+                signals: self.shared.signals.clone(),
+            })
+    }
+
+    /// Register the transaction of `host`, until the registration is
+    /// dropped.
+    ///
+    /// # Safety
+    ///
+    /// `resume` resumes `host` through a pointer kept until the
+    /// registration is gone, so the host transaction must outlive the
+    /// registration.
+    unsafe fn register<H>(&self, host: &mut H::TransactionRef) -> Registration
+    where
+        H: host::Host + ?Sized,
+        H::TransactionRef: 'static,
+    {
+        let id = self.shared.next_id.get();
+        self.shared.next_id.set(id + 1);
+
+        let ptr = host as *mut H::TransactionRef;
+        let live = Live {
+            addr: ptr as *mut u8 as usize,
+            // The registration is removed before the host transaction
+            // goes away, so `ptr` is valid whenever this is called.
+            resume: Box::new(move || (*ptr).resume()),
+        };
+        self.shared.live.borrow_mut().insert(id, Rc::new(live));
+        self.shared.signals.lock().live.insert(id);
+        Registration {
+            id,
+            shared: Rc::downgrade(&self.shared),
+            signals: self.shared.signals.clone(),
+        }
+    }
+
+    /// Whether any transaction was woken since the last `resume`.
+    pub fn has_woken(&self) -> bool {
+        !self.shared.signals.lock().woken.is_empty()
+    }
+
+    /// Shorten the time the host waits before calling `resume`: to
+    /// nothing if any transaction was woken, and to the poll interval
+    /// if any are registered.
+    ///
+    /// Call this from `Service::suspend`.
+    pub fn suspend(&self, timeout: &mut Duration) {
+        let limit = if self.has_woken() {
+            Duration::from_secs(0)
+        } else if !self.shared.live.borrow().is_empty() {
+            self.shared
+                .poll_interval
+                .get()
+                .unwrap_or(DEFAULT_POLL_INTERVAL)
+        } else {
+            return;
+        };
+        if limit < *timeout {
+            *timeout = limit;
+        }
+    }
+
+    /// Resume the host transactions woken since the last call, each
+    /// once.
+    ///
+    /// Call this from `Service::resume`.
+    pub fn resume(&self) {
+        let woken = ::std::mem::replace(&mut self.shared.signals.lock().woken, Vec::new());
+        let mut resumed: Vec<Rc<Live>> = Vec::new();
+        for id in woken {
+            let live = match self.shared.live.borrow().get(&id) {
+                Some(live) => live.clone(),
+                None => continue,
+            };
+            if resumed.iter().any(|r| r.addr == live.addr) {
+                continue;
+            }
+            (live.resume)();
+            resumed.push(live);
+        }
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("live", &self.shared.live.borrow().len())
+            .field("woken", &self.shared.signals.lock().woken.len())
+            .finish()
+    }
+}
+
+/// A transaction's entry in a `Registry`, removed when dropped.
+struct Registration {
+    id: u64,
+    shared: Weak<Shared>,
+    signals: Arc<Signals>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        {
+            let mut signals = self.signals.lock();
+            signals.live.remove(&self.id);
+            signals.woken.retain(|&id| id != self.id);
+        }
+        if let Some(shared) = self.shared.upgrade() {
+            shared.live.borrow_mut().remove(&self.id);
+        }
+    }
+}
+
+/// An adapter transaction registered with a `Registry` while it runs.
+///
+/// The host transaction is registered when the host starts the
+/// transaction, and unregistered when it stops it, or drops it without
+/// starting or stopping it. Hosts do either before their host
+/// transaction goes away, so the registry only ever resumes live host
+/// transactions.
+pub struct Registered<T> {
+    transaction: T,
+    registry: Registry,
+    registration: Option<Registration>,
+}
+
+impl<T> Registered<T> {
+    pub fn get_ref(&self) -> &T {
+        &self.transaction
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.transaction
+    }
+
+    /// The wrapped transaction, now unregistered.
+    pub fn into_inner(self) -> T {
+        self.transaction
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Registered<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Registered")
+            .field("transaction", &self.transaction)
+            .field("registered", &self.registration.is_some())
+            .finish()
+    }
+}
+
+impl<T: Options> Options for Registered<T> {
+    fn option(&self, name: &Name) -> Option<Area> {
+        self.transaction.option(name)
+    }
+
+    fn visit_each<V: NamedValueVisitor>(&self, visitor: V) {
+        self.transaction.visit_each(visitor)
+    }
+}
+
+macro_rules! registered_method {
+    ($method:ident) => {
+        fn $method<'a>(&mut self, host: &'a mut H::TransactionRef)
+        where
+            H::TransactionRef: 'a,
+        {
+            self.transaction.$method(host)
+        }
+    };
+}
+
+impl<H, T> Transaction<H> for Registered<T>
+where
+    H: host::Host + ?Sized,
+    H::TransactionRef: 'static,
+    T: Transaction<H>,
+{
+    fn start<'a>(&mut self, host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        // The host stops or drops the transaction before the host
+        // transaction goes away, and either drops the registration.
+        self.registration = Some(unsafe { self.registry.register::<H>(host) });
+        self.transaction.start(host)
+    }
+
+    fn stop<'a>(&mut self, host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.transaction.stop(host);
+        self.registration = None;
+    }
+
+    registered_method!(resume);
+    registered_method!(adapted_body_discard);
+    registered_method!(adapted_body_make);
+    registered_method!(adapted_body_make_more);
+    registered_method!(adapted_body_stop_making);
+    registered_method!(adapted_body_pause);
+    registered_method!(adapted_body_resume);
+    registered_method!(virgin_body_content_available);
+
+    fn adapted_body_content<'a>(
+        &mut self,
+        host: &'a mut H::TransactionRef,
+        offset: usize,
+        size: usize,
+    ) -> Area
+    where
+        H::TransactionRef: 'a,
+    {
+        self.transaction.adapted_body_content(host, offset, size)
+    }
+
+    fn adapted_body_content_shift<'a>(&mut self, host: &'a mut H::TransactionRef, size: usize)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.transaction.adapted_body_content_shift(host, size)
+    }
+
+    fn virgin_body_content_done<'a>(&mut self, host: &'a mut H::TransactionRef, at_end: bool)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.transaction.virgin_body_content_done(host, at_end)
+    }
+}
+
+/// Asks the host to resume a registered transaction, from any thread.
+#[derive(Clone)]
+pub struct WakeHandle {
+    id: u64,
+    signals: Arc<Signals>,
+}
+
+impl WakeHandle {
+    /// Queue the transaction to be resumed by the next
+    /// `Registry::resume`.
+    ///
+    /// Returns false, doing nothing, if the transaction has been
+    /// unregistered.
+    pub fn wake(&self) -> bool {
+        let mut signals = self.signals.lock();
+        if !signals.live.contains(&self.id) {
+            return false;
+        }
+        if !signals.woken.contains(&self.id) {
+            signals.woken.push(self.id);
+        }
+        true
+    }
+
+    /// Whether the transaction is still registered.
+    pub fn is_live(&self) -> bool {
+        self.signals.lock().live.contains(&self.id)
+    }
+}
+
+impl fmt::Debug for WakeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WakeHandle")
+            .field("id", &self.id)
+            .field("live", &self.is_live())
+            .finish()
+    }
+}
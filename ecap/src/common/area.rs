@@ -1,17 +1,36 @@
+use std::any::TypeId;
+use std::collections::{vec_deque, VecDeque};
 use std::fmt;
+use std::iter::FromIterator;
 use std::marker::PhantomData;
 use std::mem;
+use std::ops::{Bound, RangeBounds};
 use std::ptr::{self, NonNull};
 use std::rc::Rc;
+use std::slice;
+use std::sync::Arc;
+
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+#[cfg(feature = "mmap")]
+use memmap::Mmap;
+#[cfg(feature = "mmap")]
+use std::fs::File;
+#[cfg(feature = "mmap")]
+use std::io;
 
 /// This is a continous and fixed-size buffer, that can be copied
 /// without copying the underlying buffer.
 ///
 /// It does not implement Send/Sync because it may contain a Details
 /// object that is not thread-safe, for example [`Rc`](`std::rc::Rc`).
+/// See `SendArea` for a thread-safe variant.
 ///
 /// It can be created from a variety of types, including `Rc<[u8]>`,
-/// `Arc<[u8]>`.
+/// `Arc<[u8]>` and `&'static [u8]`, or from a file with `from_mmap`.
+///
+/// An area may refer to only part of the buffer of its details; see
+/// `Area::slice`.
 ///
 /// Most of the time you do not want to work with Area itself.
 /// Instead, prefer to create it at the boundary between Host/Adapter
@@ -20,6 +39,8 @@ use std::rc::Rc;
 /// XXX: Debug impl
 pub struct Area {
     ptr: DetailsStack,
+    start: *const u8,
+    len: usize,
     // Do not implement Send/Sync.
     _data: PhantomData<*mut ()>,
 }
@@ -32,8 +53,15 @@ impl fmt::Debug for Area {
 
 impl Area {
     pub fn new<T: DetailsConstructor>(value: T) -> Area {
+        let ptr = value.details();
+        let (start, len) = {
+            let bytes = ptr.as_bytes();
+            (bytes.as_ptr(), bytes.len())
+        };
         Area {
-            ptr: value.details(),
+            ptr,
+            start,
+            len,
             _data: PhantomData,
         }
     }
@@ -44,8 +72,97 @@ impl Area {
         Area::new(r)
     }
 
+    /// Create an Area for a static buffer, such as a canned response
+    /// page, without copying or reference counting.
+    pub fn from_static(v: &'static [u8]) -> Area {
+        Area::new(v)
+    }
+
+    /// Create an Area by mapping a file read-only.
+    ///
+    /// The mapping is released when the last clone of the area is
+    /// dropped.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this process or
+    /// any other, while the area or any of its clones are alive: areas
+    /// are taken to never change, and accessing a truncated mapping
+    /// faults.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn from_mmap(file: &File) -> io::Result<Area> {
+        if file.metadata()?.len() == 0 {
+            // Empty mappings are not allowed.
+            return Ok(Area::from_static(&[]));
+        }
+        let map = Mmap::map(file)?;
+        Ok(Area::new(Arc::new(map)))
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
-        self.ptr.as_bytes()
+        unsafe { slice::from_raw_parts(self.start, self.len) }
+    }
+
+    /// Create an Area for part of this area, without copying.
+    ///
+    /// The returned area shares the details of this one.
+    ///
+    /// # Panics
+    ///
+    /// If the range is out of bounds, as with slice indexing.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Area {
+        let begin = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => self.len,
+        };
+        assert!(
+            begin <= end,
+            "slice index starts at {} but ends at {}",
+            begin,
+            end
+        );
+        assert!(
+            end <= self.len,
+            "index {} out of range for area of length {}",
+            end,
+            self.len
+        );
+        self.ptr.increment();
+        Area {
+            ptr: self.ptr,
+            start: unsafe { self.start.add(begin) },
+            len: end - begin,
+            _data: PhantomData,
+        }
+    }
+
+    /// The details of this area.
+    ///
+    /// Host/Adapter implementations can use this to recognize areas
+    /// which they created, for example to hand back the original
+    /// buffer instead of wrapping it again.
+    ///
+    /// Note that the details cover the whole buffer, which may be
+    /// larger than this area if it was sliced.
+    pub fn details(&self) -> &DetailsStack {
+        &self.ptr
+    }
+
+    /// Convert this area into its details, passing on its reference.
+    ///
+    /// The caller is responsible for decrementing the returned details,
+    /// for example by passing them to `Area::new`. As with `details`,
+    /// the bounds of a sliced area are lost.
+    pub fn into_details(self) -> DetailsStack {
+        let ptr = self.ptr;
+        mem::forget(self);
+        ptr
     }
 }
 
@@ -54,6 +171,8 @@ impl Clone for Area {
         self.ptr.increment();
         Area {
             ptr: self.ptr,
+            start: self.start,
+            len: self.len,
             _data: PhantomData,
         }
     }
@@ -65,6 +184,216 @@ impl Drop for Area {
     }
 }
 
+/// A sequence of areas, used as a single buffer without joining them.
+///
+/// This is useful for adapters which produce the adapted body in
+/// chunks: `content` and `shift` map directly onto
+/// `adapted_body_content` and `adapted_body_content_shift`.
+///
+/// ```rust,ignore
+/// fn adapted_body_content(&mut self, host: .., offset: usize, size: usize) -> Area {
+///     self.adapted.content(offset, size)
+/// }
+///
+/// fn adapted_body_content_shift(&mut self, host: .., size: usize) {
+///     self.adapted.shift(size)
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AreaChain {
+    areas: VecDeque<Area>,
+    len: usize,
+}
+
+impl AreaChain {
+    pub fn new() -> AreaChain {
+        AreaChain::default()
+    }
+
+    /// Append an area to the end of the chain.
+    pub fn push(&mut self, area: Area) {
+        if area.as_bytes().is_empty() {
+            return;
+        }
+        self.len += area.as_bytes().len();
+        self.areas.push_back(area);
+    }
+
+    /// The total length of the areas in the chain.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The areas in the chain, in order.
+    pub fn areas(&self) -> vec_deque::Iter<Area> {
+        self.areas.iter()
+    }
+
+    /// At most `size` bytes starting at `offset`, without copying.
+    ///
+    /// Like `adapted_body_content`, this may return less than requested:
+    /// the returned area ends at the end of the area containing
+    /// `offset`. It is empty if `offset` is at or past the end of the
+    /// chain.
+    pub fn content(&self, mut offset: usize, size: usize) -> Area {
+        for area in &self.areas {
+            let len = area.as_bytes().len();
+            if offset < len {
+                let end = if size < len - offset {
+                    offset + size
+                } else {
+                    len
+                };
+                return area.slice(offset..end);
+            }
+            offset -= len;
+        }
+        Area::from_bytes(&[])
+    }
+
+    /// Remove the first `size` bytes of the chain.
+    ///
+    /// # Panics
+    ///
+    /// If the chain is shorter than `size`.
+    pub fn shift(&mut self, mut size: usize) {
+        assert!(
+            size <= self.len,
+            "shifted {} bytes out of a chain of length {}",
+            size,
+            self.len
+        );
+        self.len -= size;
+        while size > 0 {
+            let len = self.areas[0].as_bytes().len();
+            if size < len {
+                let rest = self.areas[0].slice(size..);
+                self.areas[0] = rest;
+                return;
+            }
+            self.areas.pop_front();
+            size -= len;
+        }
+    }
+
+    /// Join the chain into one area.
+    ///
+    /// This only copies if the chain has more than one area.
+    pub fn to_area(&self) -> Area {
+        match self.areas.len() {
+            0 => Area::from_bytes(&[]),
+            1 => self.areas[0].clone(),
+            _ => {
+                let mut bytes = Vec::with_capacity(self.len);
+                for area in &self.areas {
+                    bytes.extend_from_slice(area.as_bytes());
+                }
+                Area::new(Rc::<[u8]>::from(bytes))
+            }
+        }
+    }
+}
+
+impl Extend<Area> for AreaChain {
+    fn extend<I: IntoIterator<Item = Area>>(&mut self, iter: I) {
+        for area in iter {
+            self.push(area);
+        }
+    }
+}
+
+impl FromIterator<Area> for AreaChain {
+    fn from_iter<I: IntoIterator<Item = Area>>(iter: I) -> AreaChain {
+        let mut chain = AreaChain::new();
+        chain.extend(iter);
+        chain
+    }
+}
+
+/// A thread-safe `Area`.
+///
+/// This can only be created from details which may be used from any
+/// thread, such as `Arc<[u8]>`, so it can be sent to other threads, for
+/// example to inspect a body on a worker thread without copying it.
+///
+/// It converts into an `Area` without copying the underlying buffer.
+pub struct SendArea {
+    ptr: DetailsStack,
+}
+
+// SendDetailsConstructor guarantees that the details are thread-safe.
+unsafe impl Send for SendArea {}
+unsafe impl Sync for SendArea {}
+
+impl fmt::Debug for SendArea {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+impl SendArea {
+    pub fn new<T: SendDetailsConstructor>(value: T) -> SendArea {
+        SendArea {
+            ptr: value.details(),
+        }
+    }
+
+    /// Create a SendArea by copying a byte slice.
+    pub fn from_bytes(v: &[u8]) -> SendArea {
+        let r: Arc<[u8]> = <Arc<[u8]> as ::std::convert::From<&[u8]>>::from(v);
+        SendArea::new(r)
+    }
+
+    /// See `Area::from_static`.
+    pub fn from_static(v: &'static [u8]) -> SendArea {
+        SendArea::new(v)
+    }
+
+    /// See `Area::from_mmap`.
+    ///
+    /// # Safety
+    ///
+    /// As for `Area::from_mmap`, the file must not change while the
+    /// area is alive, on any thread.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn from_mmap(file: &File) -> io::Result<SendArea> {
+        if file.metadata()?.len() == 0 {
+            return Ok(SendArea::from_static(&[]));
+        }
+        let map = Mmap::map(file)?;
+        Ok(SendArea::new(Arc::new(map)))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.ptr.as_bytes()
+    }
+}
+
+impl Clone for SendArea {
+    fn clone(&self) -> SendArea {
+        self.ptr.increment();
+        SendArea { ptr: self.ptr }
+    }
+}
+
+impl Drop for SendArea {
+    fn drop(&mut self) {
+        self.ptr.decrement();
+    }
+}
+
+impl From<SendArea> for Area {
+    fn from(area: SendArea) -> Area {
+        let ptr = area.ptr;
+        mem::forget(area);
+        Area::new(ptr)
+    }
+}
+
 /// Conversion to a Details object.
 ///
 /// This represents the conversion from some T to a type implementing
@@ -74,9 +403,18 @@ pub trait DetailsConstructor {
     fn details(self) -> DetailsStack;
 }
 
+/// Conversion to a thread-safe Details object, for `SendArea`.
+///
+/// This is unsafe to implement: the created details must support
+/// `as_bytes`, `increment` and `decrement` being called concurrently from
+/// any thread, and the buffer must not change while it is referenced.
+pub unsafe trait SendDetailsConstructor: DetailsConstructor {}
+
 /// Area content and reference-counting support.
 pub trait Details {
     /// Get the underlying buffer.
+    ///
+    /// The buffer must not move or change while references remain.
     fn as_bytes(&self) -> &[u8];
 
     /// Increment the strong count.
@@ -132,9 +470,96 @@ impl<T: AsRef<[u8]> + ?Sized + 'static> DetailsConstructor for Rc<T> {
     }
 }
 
+#[derive(Debug)]
+struct ArcPtr<T: ?Sized> {
+    arc: NonNull<T>,
+}
+
+impl<T: ?Sized + AsRef<[u8]>> Clone for ArcPtr<T> {
+    fn clone(&self) -> ArcPtr<T> {
+        self.increment();
+        ArcPtr { arc: self.arc }
+    }
+}
+
+impl<T: ?Sized + AsRef<[u8]>> Copy for ArcPtr<T> {}
+
+impl<T: ?Sized + AsRef<[u8]>> Details for ArcPtr<T> {
+    fn increment(&self) {
+        unsafe {
+            let v = Arc::from_raw(self.arc.as_ptr() as *const T);
+            mem::forget(v.clone());
+            mem::forget(v);
+        }
+    }
+
+    fn decrement(&self) {
+        unsafe {
+            mem::drop(Arc::from_raw(self.arc.as_ptr() as *const T));
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { <T as AsRef<[u8]>>::as_ref(self.arc.as_ref()) }
+    }
+}
+
+impl<T: AsRef<[u8]> + ?Sized + 'static> DetailsConstructor for Arc<T> {
+    fn details(self) -> DetailsStack {
+        let ptr = Arc::into_raw(self);
+        DetailsStack::from(ArcPtr {
+            arc: NonNull::new(ptr as *mut T).unwrap(),
+        })
+    }
+}
+
+unsafe impl<T: AsRef<[u8]> + Send + Sync + ?Sized + 'static> SendDetailsConstructor for Arc<T> {}
+
+/// Static buffers live forever, so there is nothing to count.
+#[derive(Copy, Clone, Debug)]
+struct StaticPtr {
+    bytes: &'static [u8],
+}
+
+impl Details for StaticPtr {
+    fn increment(&self) {}
+
+    fn decrement(&self) {}
+
+    fn as_bytes(&self) -> &[u8] {
+        self.bytes
+    }
+}
+
+impl DetailsConstructor for &'static [u8] {
+    fn details(self) -> DetailsStack {
+        DetailsStack::from(StaticPtr { bytes: self })
+    }
+}
+
+unsafe impl SendDetailsConstructor for &'static [u8] {}
+
+/// `Bytes` does not fit into a `DetailsStack`, so it is boxed in an `Arc`;
+/// the buffer itself is not copied.
+#[cfg(feature = "bytes")]
+impl DetailsConstructor for Bytes {
+    fn details(self) -> DetailsStack {
+        Arc::new(self).details()
+    }
+}
+
+#[cfg(feature = "bytes")]
+unsafe impl SendDetailsConstructor for Bytes {}
+
+/// A `Details` object stored inline, without allocation.
+///
+/// The stack itself is `Copy`: like a raw pointer, it is up to the user to
+/// keep increments and decrements balanced. An `Area` owns one reference
+/// on its stack.
 #[derive(Copy, Clone)]
 pub struct DetailsStack {
-    value: [usize; 2],
+    value: [usize; 4],
+    type_id: TypeId,
     increment: fn(*const ()),
     decrement: fn(*const ()),
     as_bytes: fn(*const ()) -> &'static [u8],
@@ -144,24 +569,41 @@ impl DetailsStack {
     /// Creates a stack-allocated details trait object and stores the
     /// passed T into it.
     ///
-    /// The passed T must fit into `[usize; 2]` and have the same
+    /// The passed T must fit into `[usize; 4]` and have the same
     /// alignment. This restriction may be relaxed in the future.
     pub fn from<T: Details + Copy + 'static>(v: T) -> DetailsStack {
-        let mut data = [0; 2];
-        assert!(mem::size_of::<T>() <= mem::size_of::<[usize; 2]>());
-        assert_eq!(mem::align_of::<T>(), mem::align_of::<[usize; 2]>());
+        let mut data = [0; 4];
+        assert!(mem::size_of::<T>() <= mem::size_of::<[usize; 4]>());
+        assert_eq!(mem::align_of::<T>(), mem::align_of::<[usize; 4]>());
         assert!(!mem::needs_drop::<T>());
         unsafe {
-            ptr::copy_nonoverlapping(&v as *const T, &mut data as *mut [usize; 2] as *mut T, 1);
+            ptr::copy_nonoverlapping(&v as *const T, &mut data as *mut [usize; 4] as *mut T, 1);
         }
         mem::forget(v);
         DetailsStack {
             value: data,
+            type_id: TypeId::of::<T>(),
             increment: |ptr| unsafe { (&*(ptr as *const T)).increment() },
             decrement: |ptr| unsafe { (&*(ptr as *const T)).decrement() },
             as_bytes: |ptr| unsafe { (&*(ptr as *const T)).as_bytes() },
         }
     }
+
+    /// Returns the stored T, if this stack was created from a T.
+    pub fn downcast_ref<T: Details + Copy + 'static>(&self) -> Option<&T> {
+        if self.type_id == TypeId::of::<T>() {
+            Some(unsafe { &*(&self.value as *const [usize; 4] as *const T) })
+        } else {
+            None
+        }
+    }
+}
+
+/// The stack is taken to own one reference, which is passed on.
+impl DetailsConstructor for DetailsStack {
+    fn details(self) -> DetailsStack {
+        self
+    }
 }
 
 impl Details for DetailsStack {
@@ -177,3 +619,290 @@ impl Details for DetailsStack {
         (self.decrement)(&self.value as *const _ as *const ())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+    #[cfg(feature = "mmap")]
+    use std::{env, fs, io::Write, path::PathBuf, process};
+
+    #[test]
+    fn clones_share_the_buffer() {
+        let buf: Rc<[u8]> = Rc::from(&b"hello"[..]);
+        let area = Area::new(buf.clone());
+        assert_eq!(Rc::strong_count(&buf), 2);
+        let clone = area.clone();
+        assert_eq!(Rc::strong_count(&buf), 3);
+        assert_eq!(clone.as_bytes(), b"hello");
+        assert_eq!(clone.as_bytes().as_ptr(), buf.as_ptr());
+        drop(area);
+        drop(clone);
+        assert_eq!(Rc::strong_count(&buf), 1);
+    }
+
+    #[test]
+    fn from_bytes_copies() {
+        let bytes = b"hello".to_vec();
+        let area = Area::from_bytes(&bytes);
+        drop(bytes);
+        assert_eq!(area.as_bytes(), b"hello");
+        assert!(area.details().downcast_ref::<RcPtr<[u8]>>().is_some());
+    }
+
+    #[test]
+    fn details_pass_on_the_reference() {
+        let buf: Arc<[u8]> = Arc::from(&b"hello"[..]);
+        let area = Area::new(buf.clone());
+        assert!(area.details().downcast_ref::<ArcPtr<[u8]>>().is_some());
+        assert!(area.details().downcast_ref::<RcPtr<[u8]>>().is_none());
+
+        let details = area.into_details();
+        assert_eq!(Arc::strong_count(&buf), 2);
+        let area = Area::new(details);
+        assert_eq!(Arc::strong_count(&buf), 2);
+        assert_eq!(area.as_bytes(), b"hello");
+        drop(area);
+        assert_eq!(Arc::strong_count(&buf), 1);
+    }
+
+    #[test]
+    fn slice_within_bounds() {
+        let area = Area::from_bytes(b"hello");
+        assert_eq!(area.slice(..).as_bytes(), b"hello");
+        assert_eq!(area.slice(1..4).as_bytes(), b"ell");
+        assert_eq!(area.slice(..=1).as_bytes(), b"he");
+        assert_eq!(area.slice(3..).as_bytes(), b"lo");
+        // Empty slices at the end are fine.
+        assert_eq!(area.slice(5..).as_bytes(), b"");
+        assert_eq!(area.slice(5..5).as_bytes(), b"");
+    }
+
+    #[test]
+    #[should_panic(expected = "index 6 out of range for area of length 5")]
+    fn slice_past_end() {
+        Area::from_bytes(b"hello").slice(..6);
+    }
+
+    #[test]
+    #[should_panic(expected = "index 6 out of range for area of length 5")]
+    fn slice_inclusive_past_end() {
+        Area::from_bytes(b"hello").slice(..=5);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice index starts at 6 but ends at 5")]
+    fn slice_starting_past_end() {
+        Area::from_bytes(b"hello").slice(6..);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice index starts at 3 but ends at 2")]
+    fn slice_reversed() {
+        Area::from_bytes(b"hello").slice((Bound::Included(3), Bound::Excluded(2)));
+    }
+
+    #[test]
+    fn slice_of_slice() {
+        let buf: Rc<[u8]> = Rc::from(&b"hello, world"[..]);
+        let area = Area::new(buf.clone());
+        let world = area.slice(7..);
+        let orl = world.slice(1..4);
+        drop(area);
+        drop(world);
+        assert_eq!(orl.as_bytes(), b"orl");
+        assert_eq!(orl.as_bytes().as_ptr(), buf[8..].as_ptr());
+        // The details still cover the whole buffer.
+        assert_eq!(orl.details().as_bytes(), b"hello, world");
+        assert_eq!(Rc::strong_count(&buf), 2);
+        drop(orl);
+        assert_eq!(Rc::strong_count(&buf), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "index 4 out of range for area of length 3")]
+    fn slice_of_slice_past_its_end() {
+        Area::from_bytes(b"hello").slice(1..4).slice(..4);
+    }
+
+    fn chain(parts: &[&[u8]]) -> AreaChain {
+        parts.iter().map(|part| Area::from_bytes(part)).collect()
+    }
+
+    #[test]
+    fn chain_to_area() {
+        let empty = chain(&[]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.to_area().as_bytes(), b"");
+
+        // A single area is passed on without copying.
+        let one = chain(&[b"hello"]);
+        let area = one.to_area();
+        assert_eq!(area.as_bytes(), b"hello");
+        assert_eq!(
+            area.as_bytes().as_ptr(),
+            one.areas().next().unwrap().as_bytes().as_ptr()
+        );
+
+        let many = chain(&[b"hello", b", ", b"world"]);
+        assert_eq!(many.len(), 12);
+        assert_eq!(many.areas().count(), 3);
+        assert_eq!(many.to_area().as_bytes(), b"hello, world");
+    }
+
+    #[test]
+    fn chain_skips_empty_areas() {
+        let chain = chain(&[b"", b"hello", b""]);
+        assert_eq!(chain.areas().count(), 1);
+        assert_eq!(chain.len(), 5);
+    }
+
+    #[test]
+    fn chain_content() {
+        let chain = chain(&[b"hello", b", ", b"world"]);
+        // Content ends at the end of the area containing the offset.
+        assert_eq!(chain.content(0, 100).as_bytes(), b"hello");
+        assert_eq!(chain.content(1, 3).as_bytes(), b"ell");
+        assert_eq!(chain.content(5, 100).as_bytes(), b", ");
+        assert_eq!(chain.content(8, 2).as_bytes(), b"or");
+        assert_eq!(chain.content(12, 1).as_bytes(), b"");
+        assert_eq!(chain.content(100, 1).as_bytes(), b"");
+    }
+
+    #[test]
+    fn chain_shift() {
+        let mut chain = chain(&[b"hello", b", ", b"world"]);
+        chain.shift(3);
+        assert_eq!(chain.len(), 9);
+        assert_eq!(chain.content(0, 100).as_bytes(), b"lo");
+        chain.shift(4);
+        assert_eq!(chain.len(), 5);
+        assert_eq!(chain.areas().count(), 1);
+        assert_eq!(chain.to_area().as_bytes(), b"world");
+        chain.shift(5);
+        assert!(chain.is_empty());
+        assert_eq!(chain.areas().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "shifted 6 bytes out of a chain of length 5")]
+    fn chain_shift_past_end() {
+        chain(&[b"hel", b"lo"]).shift(6);
+    }
+
+    #[test]
+    fn send_area_across_threads() {
+        let buf: Arc<[u8]> = Arc::from(&b"hello"[..]);
+        let area = SendArea::new(buf.clone());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let area = area.clone();
+                thread::spawn(move || {
+                    let clone = area.clone();
+                    assert_eq!(clone.as_bytes(), b"hello");
+                    clone.as_bytes().as_ptr() as usize
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), buf.as_ptr() as usize);
+        }
+        // Each thread dropped its clones.
+        assert_eq!(Arc::strong_count(&buf), 2);
+        drop(area);
+        assert_eq!(Arc::strong_count(&buf), 1);
+    }
+
+    #[test]
+    fn send_area_shared_between_threads() {
+        let area = Arc::new(SendArea::from_bytes(b"hello"));
+        let other = area.clone();
+        let len = thread::spawn(move || other.as_bytes().len())
+            .join()
+            .unwrap();
+        assert_eq!(len, 5);
+        assert_eq!(area.as_bytes(), b"hello");
+    }
+
+    #[test]
+    fn send_area_into_area() {
+        let buf: Arc<[u8]> = Arc::from(&b"hello"[..]);
+        let area: Area = thread::spawn({
+            let buf = buf.clone();
+            move || SendArea::new(buf)
+        })
+        .join()
+        .unwrap()
+        .into();
+        // The reference is passed on, and the buffer is not copied.
+        assert_eq!(Arc::strong_count(&buf), 2);
+        assert_eq!(area.as_bytes().as_ptr(), buf.as_ptr());
+        assert!(area.details().downcast_ref::<ArcPtr<[u8]>>().is_some());
+        drop(area);
+        assert_eq!(Arc::strong_count(&buf), 1);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn send_area_from_bytes_buffer() {
+        // Small buffers are stored inline, and so move with the `Bytes`.
+        let bytes = Bytes::from(vec![b'x'; 1024]);
+        let area = SendArea::new(bytes.clone());
+        let ptr = thread::spawn(move || area.as_bytes().as_ptr() as usize)
+            .join()
+            .unwrap();
+        assert_eq!(ptr, bytes.as_ptr() as usize);
+    }
+
+    #[test]
+    fn from_static_borrows() {
+        static PAGE: &[u8] = b"<h1>Forbidden</h1>";
+        let area = Area::from_static(PAGE);
+        assert_eq!(area.as_bytes().as_ptr(), PAGE.as_ptr());
+        assert!(area.details().downcast_ref::<StaticPtr>().is_some());
+        assert_eq!(area.slice(4..13).as_bytes(), b"Forbidden");
+
+        let area = SendArea::from_static(PAGE);
+        let ptr = thread::spawn(move || area.as_bytes().as_ptr() as usize)
+            .join()
+            .unwrap();
+        assert_eq!(ptr, PAGE.as_ptr() as usize);
+    }
+
+    #[cfg(feature = "mmap")]
+    fn temp_file(name: &str, contents: &[u8]) -> (PathBuf, File) {
+        let path = env::temp_dir().join(format!("ecap-area-{}-{}", name, process::id()));
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        let file = File::open(&path).unwrap();
+        (path, file)
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn from_mmap() {
+        let (path, file) = temp_file("mmap", b"hello, world");
+        let area = unsafe { Area::from_mmap(&file).unwrap() };
+        let send_area = unsafe { SendArea::from_mmap(&file).unwrap() };
+        drop(file);
+        assert_eq!(area.as_bytes(), b"hello, world");
+        assert_eq!(area.slice(7..).as_bytes(), b"world");
+        let bytes = thread::spawn(move || send_area.as_bytes().to_vec())
+            .join()
+            .unwrap();
+        assert_eq!(bytes, b"hello, world");
+        drop(area);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn from_mmap_empty_file() {
+        let (path, file) = temp_file("mmap-empty", b"");
+        let area = unsafe { Area::from_mmap(&file).unwrap() };
+        let send_area = unsafe { SendArea::from_mmap(&file).unwrap() };
+        assert_eq!(area.as_bytes(), b"");
+        assert_eq!(send_area.as_bytes(), b"");
+        fs::remove_file(&path).unwrap();
+    }
+}
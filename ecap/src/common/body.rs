@@ -1,3 +1,5 @@
+use common::{Error, ErrorKind};
+
 /// Message body buffer, shared by producer and consumer.
 ///
 /// Usually implemented in hosts.
@@ -9,4 +11,13 @@ pub trait Body {
     ///
     /// Will return `None` if the size is not known.
     fn size(&self) -> Option<u64>;
+
+    /// Change the size of the Body, e.g. after adaptation changed it.
+    ///
+    /// libecap bodies cannot be resized, so by default this fails
+    /// with `ErrorKind::Unsupported`; such hosts work the size out
+    /// themselves.
+    fn set_size(&mut self, _size: Option<u64>) -> Result<(), Error> {
+        Err(ErrorKind::Unsupported.into())
+    }
 }
@@ -0,0 +1,520 @@
+//! Typed service configuration.
+//!
+//! [`from_options`] deserializes the options passed to
+//! `Service::configure` and `Service::reconfigure` into any
+//! `Deserialize` type:
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! #[serde(deny_unknown_fields)]
+//! struct Config {
+//!     victim: String,
+//!     replacement: String,
+//!     #[serde(default)]
+//!     limit: Option<usize>,
+//! }
+//!
+//! fn configure<T: Options + ?Sized>(&mut self, options: &T) -> Result<(), Error> {
+//!     self.config = Some(config::from_options(options)?);
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Each option is a field named by the option's name. Values are parsed
+//! from their text: numbers, `bool`s (`true`/`false`, `on`/`off`,
+//! `yes`/`no` or `1`/`0`), comma-separated sequences and unit enum
+//! variants are supported. Options with a host-specific name (see
+//! `Name::host_id`) are configuration of the host, not of the service,
+//! and are skipped.
+//!
+//! Missing, unknown (with `deny_unknown_fields`) and malformed options
+//! are reported as `ErrorKind::Config` errors naming the option.
+
+use std::fmt;
+use std::str::{self, FromStr};
+use std::vec;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess,
+                Visitor};
+
+use common::{Area, Error, ErrorKind, Name, NamedValueVisitor, Options};
+
+/// Deserializes `T` from the service options in `options`.
+pub fn from_options<T, O>(options: &O) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    O: Options + ?Sized,
+{
+    let mut entries = Vec::new();
+    options.visit_each(Collect(&mut entries));
+    T::deserialize(OptionsDeserializer {
+        entries: entries.into_iter(),
+        value: None,
+    })
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::new(ErrorKind::Config, msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Error {
+        Error::new(ErrorKind::Config, format!("missing option `{}`", field))
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Error {
+        let message = if expected.is_empty() {
+            format!("unknown option `{}`, there are no options", field)
+        } else {
+            let expected = expected
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>();
+            format!(
+                "unknown option `{}`, expected one of {}",
+                field,
+                expected.join(", ")
+            )
+        };
+        Error::new(ErrorKind::Config, message)
+    }
+
+    fn duplicate_field(field: &'static str) -> Error {
+        Error::new(ErrorKind::Config, format!("duplicate option `{}`", field))
+    }
+}
+
+struct Collect<'a>(&'a mut Vec<(String, Vec<u8>)>);
+
+impl<'a> NamedValueVisitor for Collect<'a> {
+    fn visit(&mut self, name: &Name, value: &Area) {
+        if name.host_id().is_some() {
+            return;
+        }
+        if let Some(image) = name.image() {
+            let image = String::from_utf8_lossy(image).into_owned();
+            self.0.push((image, value.as_bytes().to_vec()));
+        }
+    }
+}
+
+struct OptionsDeserializer {
+    entries: vec::IntoIter<(String, Vec<u8>)>,
+    value: Option<(String, Vec<u8>)>,
+}
+
+impl<'de> de::Deserializer<'de> for OptionsDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for OptionsDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.entries.next() {
+            Some((name, value)) => {
+                let key = {
+                    let deserializer: de::value::StrDeserializer<Error> =
+                        name[..].into_deserializer();
+                    seed.deserialize(deserializer)?
+                };
+                self.value = Some((name, value));
+                Ok(Some(key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let (name, value) = self.value
+            .take()
+            .expect("next_value_seed called before next_key_seed");
+        seed.deserialize(ValueDeserializer { value: &value })
+            .map_err(|e| {
+                Error::new(
+                    ErrorKind::Config,
+                    format!("malformed option `{}`: {}", name, e.message()),
+                )
+            })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+/// Deserializes a single option value from its text.
+struct ValueDeserializer<'a> {
+    value: &'a [u8],
+}
+
+impl<'a> ValueDeserializer<'a> {
+    fn text(&self) -> Result<&'a str, Error> {
+        str::from_utf8(self.value)
+            .map_err(|_| Error::new(ErrorKind::Config, "value is not valid UTF-8"))
+    }
+
+    fn parse<T>(&self) -> Result<T, Error>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let text = self.text()?;
+        text.trim().parse().map_err(|e| {
+            Error::new(
+                ErrorKind::Config,
+                format!("cannot parse {:?}: {}", text, e),
+            )
+        })
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'a, 'de> de::Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match str::from_utf8(self.value) {
+            Ok(text) => visitor.visit_str(text),
+            Err(_) => visitor.visit_bytes(self.value),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let text = self.text()?;
+        match text.trim() {
+            "true" | "on" | "yes" | "1" => visitor.visit_bool(true),
+            "false" | "off" | "no" | "0" => visitor.visit_bool(false),
+            _ => Err(Error::new(
+                ErrorKind::Config,
+                format!("cannot parse {:?}: expected a boolean", text),
+            )),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str(self.text()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_bytes(self.value)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // A present option always has a value, even if it is empty.
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let text = self.text()?;
+        let items = if text.trim().is_empty() {
+            None
+        } else {
+            Some(text.split(','))
+        };
+        visitor.visit_seq(Items(items))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let variant: de::value::StrDeserializer<Error> = self.text()?.trim().into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        map struct
+    }
+}
+
+/// The items of a comma-separated option value.
+struct Items<'a>(Option<str::Split<'a, char>>);
+
+impl<'a, 'de> SeqAccess<'de> for Items<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.0.as_mut().and_then(|items| items.next()) {
+            Some(item) => seed.deserialize(ValueDeserializer {
+                value: item.trim().as_bytes(),
+            }).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestOptions(Vec<(Name<'static>, Area)>);
+
+    impl TestOptions {
+        fn new(options: &[(&'static str, &'static str)]) -> TestOptions {
+            TestOptions(
+                options
+                    .iter()
+                    .map(|&(name, value)| {
+                        (
+                            Name::new_known(name.as_bytes()),
+                            Area::from_static(value.as_bytes()),
+                        )
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    impl Options for TestOptions {
+        fn option(&self, name: &Name) -> Option<Area> {
+            self.0
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.clone())
+        }
+        fn visit_each<V: NamedValueVisitor>(&self, mut visitor: V) {
+            for (name, value) in &self.0 {
+                visitor.visit(name, value);
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Mode {
+        Strict,
+        Lax,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(deny_unknown_fields)]
+    struct Config {
+        victim: String,
+        #[serde(default)]
+        limit: Option<usize>,
+        #[serde(default)]
+        ratio: f64,
+        #[serde(default)]
+        enabled: bool,
+        #[serde(default)]
+        mode: Option<Mode>,
+        #[serde(default)]
+        hosts: Vec<String>,
+    }
+
+    fn config(options: &[(&'static str, &'static str)]) -> Result<Config, Error> {
+        from_options(&TestOptions::new(options))
+    }
+
+    /// Asserts that `result` is a `Config` error mentioning `message`.
+    fn assert_config_error<T: fmt::Debug>(result: Result<T, Error>, message: &str) {
+        let error = result.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Config);
+        assert!(
+            error.message().contains(message),
+            "{:?} does not mention {:?}",
+            error.message(),
+            message
+        );
+    }
+
+    #[test]
+    fn all_options() {
+        let parsed = config(&[
+            ("victim", "hello"),
+            ("limit", " 42 "),
+            ("ratio", "0.5"),
+            ("enabled", "yes"),
+            ("mode", "strict"),
+            ("hosts", "a.example, b.example"),
+        ])
+        .unwrap();
+        assert_eq!(
+            parsed,
+            Config {
+                victim: "hello".to_owned(),
+                limit: Some(42),
+                ratio: 0.5,
+                enabled: true,
+                mode: Some(Mode::Strict),
+                hosts: vec!["a.example".to_owned(), "b.example".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn defaults() {
+        let parsed = config(&[("victim", "")]).unwrap();
+        assert_eq!(parsed.victim, "");
+        assert_eq!(parsed.limit, None);
+        assert!(!parsed.enabled);
+        assert_eq!(parsed.mode, None);
+        assert!(parsed.hosts.is_empty());
+    }
+
+    #[test]
+    fn missing_option() {
+        assert_config_error(config(&[("limit", "1")]), "missing option `victim`");
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert_config_error(
+            config(&[("victim", "x"), ("victm", "y")]),
+            "unknown option `victm`, expected one of `victim`",
+        );
+    }
+
+    #[test]
+    fn host_options_are_skipped() {
+        let mut name = Name::new_known(&b"host-only"[..]);
+        name.assign_host_id(1);
+        let mut options = TestOptions::new(&[("victim", "x")]);
+        options.0.push((name, Area::from_static(b"y")));
+        let config: Config = from_options(&options).unwrap();
+        assert_eq!(config.victim, "x");
+    }
+
+    #[test]
+    fn numbers() {
+        assert_config_error(
+            config(&[("victim", "x"), ("limit", "many")]),
+            "malformed option `limit`",
+        );
+        assert_config_error(config(&[("victim", "x"), ("limit", "-1")]), "`limit`");
+        assert_config_error(config(&[("victim", "x"), ("ratio", "")]), "`ratio`");
+    }
+
+    #[test]
+    fn bools() {
+        for &(text, value) in &[
+            ("true", true),
+            ("on", true),
+            ("1", true),
+            ("false", false),
+            ("off", false),
+            ("no", false),
+            ("0", false),
+        ] {
+            let parsed = config(&[("victim", "x"), ("enabled", text)]).unwrap();
+            assert_eq!(parsed.enabled, value, "{}", text);
+        }
+        assert_config_error(
+            config(&[("victim", "x"), ("enabled", "maybe")]),
+            "malformed option `enabled`",
+        );
+    }
+
+    #[test]
+    fn enums() {
+        let parsed = config(&[("victim", "x"), ("mode", " lax")]).unwrap();
+        assert_eq!(parsed.mode, Some(Mode::Lax));
+        assert_config_error(
+            config(&[("victim", "x"), ("mode", "loose")]),
+            "malformed option `mode`",
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut options = TestOptions::new(&[]);
+        options
+            .0
+            .push((Name::new_known(&b"victim"[..]), Area::from_static(b"\xff")));
+        assert_config_error(from_options::<Config, _>(&options), "not valid UTF-8");
+    }
+}
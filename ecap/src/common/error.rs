@@ -0,0 +1,87 @@
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+
+/// The kind of an [`Error`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A buffer, such as a header, could not be parsed.
+    Parse,
+
+    /// A name was not acceptable, e.g. an empty header field name.
+    InvalidName,
+
+    /// The other side of the eCAP interface raised an error, e.g. a
+    /// C++ host or adapter threw an exception.
+    Host,
+
+    /// The operation is not supported by this implementation.
+    ///
+    /// libecap's defaults for optional operations, such as adding a
+    /// trailer, fail this way.
+    Unsupported,
+
+    /// A service rejected its configuration, e.g. because a required
+    /// option was missing or malformed.
+    Config,
+}
+
+impl ErrorKind {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            ErrorKind::Parse => "parse error",
+            ErrorKind::InvalidName => "invalid name",
+            ErrorKind::Host => "host error",
+            ErrorKind::Unsupported => "unsupported operation",
+            ErrorKind::Config => "configuration error",
+        }
+    }
+}
+
+/// Failure of a fallible eCAP operation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    message: Cow<'static, str>,
+}
+
+impl Error {
+    pub fn new<M: Into<Cow<'static, str>>>(kind: ErrorKind, message: M) -> Error {
+        Error {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The description of this error, e.g. the message of the C++
+    /// exception.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error::new(kind, kind.as_str())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.message == self.kind.as_str() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.kind.as_str(), self.message)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
@@ -1,4 +1,4 @@
-use common::{Area, Name, NamedValueVisitor, Version};
+use common::{Area, Error, Name, NamedValueVisitor, Version};
 
 /// This represents a header structure.
 ///
@@ -33,15 +33,17 @@ pub trait Header {
 
     /// Parses a given buffer into this Header.
     ///
+    /// Fails with `ErrorKind::Parse` if `buf` is not a valid header.
+    ///
     /// XXX: Should this be Self::Area?
-    // XXX: error handling
-    fn parse(&mut self, buf: &Area) -> Result<(), ()>;
+    fn parse(&mut self, buf: &Area) -> Result<(), Error>;
 }
 
 /// The first line in a request/response, e.g. `GET / HTTP/1.1` or
 /// `HTTP/1.1 200 OK`.
 ///
-/// See also the [`RequestLine`] and [`StatusLine`] traits.
+/// See also the [`RequestLine`] and [`StatusLine`] traits, which are
+/// reachable through [`FirstLine::kind`].
 ///
 /// XXX: This does not correlate directly in a HTTP 2 scenario?
 pub trait FirstLine {
@@ -50,6 +52,63 @@ pub trait FirstLine {
 
     fn protocol(&self) -> Name;
     fn set_protocol(&mut self, protocol: Name);
+
+    /// The request or status line this first line is.
+    ///
+    /// Returns `None` if it is neither; hosts are expected to always
+    /// provide one of the two.
+    fn kind(&self) -> Option<FirstLineKind> {
+        None
+    }
+
+    /// Mutable version of [`FirstLine::kind`].
+    fn kind_mut(&mut self) -> Option<FirstLineKindMut> {
+        None
+    }
+}
+
+/// A typed view of a [`FirstLine`], determining the message direction.
+pub enum FirstLineKind<'a> {
+    Request(&'a dyn RequestLine),
+    Status(&'a dyn StatusLine),
+}
+
+impl<'a> FirstLineKind<'a> {
+    pub fn request_line(self) -> Option<&'a dyn RequestLine> {
+        match self {
+            FirstLineKind::Request(line) => Some(line),
+            FirstLineKind::Status(_) => None,
+        }
+    }
+
+    pub fn status_line(self) -> Option<&'a dyn StatusLine> {
+        match self {
+            FirstLineKind::Request(_) => None,
+            FirstLineKind::Status(line) => Some(line),
+        }
+    }
+}
+
+/// A mutable typed view of a [`FirstLine`].
+pub enum FirstLineKindMut<'a> {
+    Request(&'a mut dyn RequestLine),
+    Status(&'a mut dyn StatusLine),
+}
+
+impl<'a> FirstLineKindMut<'a> {
+    pub fn request_line(self) -> Option<&'a mut dyn RequestLine> {
+        match self {
+            FirstLineKindMut::Request(line) => Some(line),
+            FirstLineKindMut::Status(_) => None,
+        }
+    }
+
+    pub fn status_line(self) -> Option<&'a mut dyn StatusLine> {
+        match self {
+            FirstLineKindMut::Request(_) => None,
+            FirstLineKindMut::Status(line) => Some(line),
+        }
+    }
 }
 
 /// The URI and method, e.g. "GET /".
@@ -0,0 +1,154 @@
+use std::fmt::{self, Write};
+use std::thread::{self, ThreadId};
+
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use common::log::LogVerbosity;
+use host::Host;
+
+impl From<Level> for LogVerbosity {
+    fn from(level: Level) -> LogVerbosity {
+        LogVerbosity::for_severity(match level {
+            Level::ERROR => 1,
+            Level::WARN => 2,
+            Level::INFO => 3,
+            Level::DEBUG => 4,
+            Level::TRACE => 5,
+        })
+    }
+}
+
+/// A `tracing` layer which writes each event to a debug stream of the
+/// host, prefixed by the spans it occurred in.
+///
+/// With the `tracing` feature of `erased-ecap`, each adapter transaction
+/// runs in an `xaction` span, so events are attributed to their
+/// transaction:
+///
+/// ```text
+/// xaction{service=ecap://rust/sample/icap id=7 direction=response url=http://example.com/}: adapter_icap: ICAP service failed
+/// ```
+///
+/// Like `HostLogger`, this is installed from `Service::start`, on the
+/// host's thread; events on other threads are dropped.
+///
+/// ```rust,ignore
+/// let layer = HostLayer::new(ecap_common_link::erased_host().unwrap());
+/// let _ = tracing::subscriber::set_global_default(Registry::default().with(layer));
+/// ```
+pub struct HostLayer<H: ?Sized + 'static> {
+    host: &'static H,
+    thread: ThreadId,
+}
+
+// The host is only used from `thread`.
+unsafe impl<H: ?Sized> Send for HostLayer<H> {}
+unsafe impl<H: ?Sized> Sync for HostLayer<H> {}
+
+impl<H: ?Sized> HostLayer<H> {
+    /// A layer writing to `host`, from the calling thread.
+    pub fn new(host: &'static H) -> HostLayer<H> {
+        HostLayer {
+            host,
+            thread: thread::current().id(),
+        }
+    }
+}
+
+/// The fields of a span, formatted.
+struct SpanFields(String);
+
+/// Formats fields as `name=value`, and the message of events as-is.
+struct FieldWriter<'a>(&'a mut String);
+
+impl<'a> Visit for FieldWriter<'a> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record_debug(field, &format_args!("{}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        let _ = if field.name() == "message" {
+            write!(self.0, "{:?}", value)
+        } else {
+            write!(self.0, "{}={:?}", field.name(), value)
+        };
+    }
+}
+
+impl<S, H> Layer<S> for HostLayer<H>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    H: Host + ?Sized,
+{
+    fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = String::new();
+            attrs.record(&mut FieldWriter(&mut fields));
+            span.extensions_mut().insert(SpanFields(fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if let Some(fields) = extensions.get_mut::<SpanFields>() {
+                values.record(&mut FieldWriter(&mut fields.0));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event, ctx: Context<S>) {
+        if thread::current().id() != self.thread {
+            return;
+        }
+        let metadata = event.metadata();
+        let mut stream = match self.host.open_debug((*metadata.level()).into()) {
+            Some(stream) => stream,
+            None => return,
+        };
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                let fields = extensions.get::<SpanFields>().map_or("", |f| &f.0[..]);
+                let _ = write!(stream, "{}{{{}}}: ", span.name(), fields);
+            }
+        }
+        let mut message = String::new();
+        event.record(&mut FieldWriter(&mut message));
+        let _ = write!(stream, "{}: {}", metadata.target(), message);
+        self.host.close_debug(stream);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_of_levels() {
+        // The same as for the levels of `log`.
+        let levels = [
+            (Level::ERROR, 1),
+            (Level::WARN, 2),
+            (Level::INFO, 3),
+            (Level::DEBUG, 4),
+            (Level::TRACE, 5),
+        ];
+        for &(level, severity) in &levels {
+            assert_eq!(
+                LogVerbosity::from(level),
+                LogVerbosity::for_severity(severity),
+                "{}",
+                level
+            );
+        }
+        assert!(LogVerbosity::from(Level::ERROR) > LogVerbosity::from(Level::INFO));
+    }
+}
@@ -0,0 +1,127 @@
+use std::fmt::Write;
+use std::thread::{self, ThreadId};
+
+use common::log::LogVerbosity;
+use host::Host;
+use log::{self, Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+impl From<Level> for LogVerbosity {
+    fn from(level: Level) -> LogVerbosity {
+        LogVerbosity::for_severity(level as usize)
+    }
+}
+
+/// A `log::Log` which writes each record to a debug stream of the host.
+///
+/// Hosts are not thread-safe, so records are only written from the
+/// thread which installed the logger; records logged on other threads
+/// are dropped.
+pub struct HostLogger<H: ?Sized + 'static> {
+    host: &'static H,
+    thread: ThreadId,
+}
+
+// The host is only used from `thread`.
+unsafe impl<H: ?Sized> Send for HostLogger<H> {}
+unsafe impl<H: ?Sized> Sync for HostLogger<H> {}
+
+impl<H: Host + ?Sized> Log for HostLogger<H> {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        // The host decides by verbosity when the stream is opened.
+        thread::current().id() == self.thread
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Some(mut stream) = self.host.open_debug(record.level().into()) {
+            let _ = write!(stream, "{}: {}", record.target(), record.args());
+            self.host.close_debug(stream);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Route records of the `log` crate to `host`'s debug streams.
+///
+/// This is meant to be called from `Service::start`, on the host's
+/// thread. Each adapter library has its own logger, so this fails if
+/// the library has already installed one, e.g. for another service.
+pub fn init<H: Host + ?Sized>(host: &'static H) -> Result<(), SetLoggerError> {
+    log::set_boxed_logger(Box::new(HostLogger {
+        host,
+        thread: thread::current().id(),
+    }))?;
+    log::set_max_level(LevelFilter::Trace);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::log::{FrequencyLevel, ImportanceLevel, MessageSizeLevel};
+
+    #[test]
+    fn verbosity_of_levels() {
+        let verbosity = |level: Level| {
+            let verbosity = LogVerbosity::from(level);
+            (verbosity.importance, verbosity.frequency, verbosity.size)
+        };
+        assert_eq!(
+            verbosity(Level::Error),
+            (
+                ImportanceLevel::Critical,
+                FrequencyLevel::Application,
+                MessageSizeLevel::Normal
+            )
+        );
+        assert_eq!(
+            verbosity(Level::Warn),
+            (
+                ImportanceLevel::Critical,
+                FrequencyLevel::Xaction,
+                MessageSizeLevel::Normal
+            )
+        );
+        assert_eq!(
+            verbosity(Level::Info),
+            (
+                ImportanceLevel::Normal,
+                FrequencyLevel::Xaction,
+                MessageSizeLevel::Normal
+            )
+        );
+        assert_eq!(
+            verbosity(Level::Debug),
+            (
+                ImportanceLevel::Debug,
+                FrequencyLevel::Operation,
+                MessageSizeLevel::Normal
+            )
+        );
+        assert_eq!(
+            verbosity(Level::Trace),
+            (
+                ImportanceLevel::Debug,
+                FrequencyLevel::Operation,
+                MessageSizeLevel::Large
+            )
+        );
+    }
+
+    #[test]
+    fn verbosity_survives_the_mask() {
+        for level in &[
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ] {
+            let verbosity = LogVerbosity::from(*level);
+            assert_eq!(LogVerbosity::from_mask(verbosity.mask()), verbosity);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt;
+
+#[cfg(feature = "log")]
+mod logger;
+#[cfg(feature = "log")]
+pub use self::logger::{init, HostLogger};
+#[cfg(feature = "tracing")]
+mod layer;
+#[cfg(feature = "tracing")]
+pub use self::layer::HostLayer;
+
+/// Importance of the logged message to the host application admin
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ImportanceLevel {
+    /// Debugging information. Not normally logged.
+    Debug = 0,
+
+    /// General information. Seen and logged by default.
+    Normal = 1,
+
+    /// Information logged and seen in "quiet" mode.
+    Critical = 2,
+}
+
+/// Quantity of messages expected under normal conditions
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FrequencyLevel {
+    /// Many times in transaction lifetime
+    Operation = 0,
+
+    /// Once/twice in transaction lifetime
+    Xaction = 1 << 4,
+
+    /// Occurs just a few times in application lifetime
+    Application = 2 << 4,
+}
+
+/// Message length in normal conditions
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MessageSizeLevel {
+    /// Regular log line, under ~120 characters
+    Normal = 0,
+
+    /// Data dumps mostly
+    Large = 1 << 8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LogVerbosity {
+    pub importance: ImportanceLevel,
+    pub frequency: FrequencyLevel,
+    pub size: MessageSizeLevel,
+}
+
+impl LogVerbosity {
+    pub fn new() -> LogVerbosity {
+        LogVerbosity {
+            importance: ImportanceLevel::Critical,
+            frequency: FrequencyLevel::Operation,
+            size: MessageSizeLevel::Normal,
+        }
+    }
+
+    /// XXX: This is quite specific to passing through C.
+    pub fn mask(&self) -> usize {
+        self.importance as usize | self.frequency as usize | self.size as usize
+    }
+
+    /// The verbosity of the levels shared by `log` and `tracing`, from
+    /// errors (1) to traces (5): errors are seen even in "quiet" mode,
+    /// traces are treated as data dumps.
+    #[cfg(any(feature = "log", feature = "tracing"))]
+    fn for_severity(severity: usize) -> LogVerbosity {
+        let (importance, frequency, size) = match severity {
+            1 => (
+                ImportanceLevel::Critical,
+                FrequencyLevel::Application,
+                MessageSizeLevel::Normal,
+            ),
+            2 => (
+                ImportanceLevel::Critical,
+                FrequencyLevel::Xaction,
+                MessageSizeLevel::Normal,
+            ),
+            3 => (
+                ImportanceLevel::Normal,
+                FrequencyLevel::Xaction,
+                MessageSizeLevel::Normal,
+            ),
+            4 => (
+                ImportanceLevel::Debug,
+                FrequencyLevel::Operation,
+                MessageSizeLevel::Normal,
+            ),
+            _ => (
+                ImportanceLevel::Debug,
+                FrequencyLevel::Operation,
+                MessageSizeLevel::Large,
+            ),
+        };
+        LogVerbosity {
+            importance,
+            frequency,
+            size,
+        }
+    }
+
+    /// The inverse of `mask`; unknown levels are treated as the highest
+    /// level of their kind.
+    pub fn from_mask(mask: usize) -> LogVerbosity {
+        LogVerbosity {
+            importance: match mask & 0xf {
+                0 => ImportanceLevel::Debug,
+                1 => ImportanceLevel::Normal,
+                _ => ImportanceLevel::Critical,
+            },
+            frequency: match mask & 0xf0 {
+                0 => FrequencyLevel::Operation,
+                0x10 => FrequencyLevel::Xaction,
+                _ => FrequencyLevel::Application,
+            },
+            size: match mask & 0xf00 {
+                0 => MessageSizeLevel::Normal,
+                _ => MessageSizeLevel::Large,
+            },
+        }
+    }
+}
+
+pub trait DebugStream: fmt::Write {}
+
@@ -1,3 +1,6 @@
+use common::header::Header;
+use common::name::{HEADER_CONTENT_LENGTH, HEADER_TRANSFER_ENCODING};
+use common::{Area, Body, Error};
 use host::Host;
 
 pub trait Message<H: ?Sized + Host> {
@@ -8,21 +11,54 @@ pub trait Message<H: ?Sized + Host> {
 
     /// Always present, determines direction
     ///
-    /// XXX: We cannot do FirstLine without additional code to subclass into the other traits
-    /// XXX: Should this return an enum?
+    /// Use `FirstLine::kind` to reach the request or status line.
     fn first_line_mut(&mut self) -> &mut H::FirstLine;
     fn first_line(&self) -> &H::FirstLine;
 
     fn header_mut(&mut self) -> &mut H::Header;
     fn header(&self) -> &H::Header;
 
-    fn add_body(&mut self);
+    /// Fails with `ErrorKind::Unsupported` if the message cannot have
+    /// a body.
+    fn add_body(&mut self) -> Result<(), Error>;
     fn body_mut(&mut self) -> Option<&mut H::Body>;
     fn body(&self) -> Option<&H::Body>;
 
-    fn add_trailer(&mut self); // XXX: throws by default
+    /// Fails with `ErrorKind::Unsupported` if the message cannot have
+    /// a trailer, which is the libecap default.
+    fn add_trailer(&mut self) -> Result<(), Error>;
     fn trailer_mut(&mut self) -> &mut H::Trailer;
     fn trailer(&self) -> &H::Trailer;
+
+    /// Make the header agree with a body of `size` bytes, or of unknown
+    /// size if `None`.
+    ///
+    /// `Content-Length` is set to the size, or removed if the size is
+    /// unknown or the message has a `Transfer-Encoding`, which takes
+    /// precedence. Framing a body of unknown size, e.g. with chunked
+    /// encoding or by closing the connection, is left to the host, as
+    /// it depends on how the message is sent. The size of the body is
+    /// updated where the host allows it. Messages without a body are
+    /// left alone.
+    fn set_body_length(&mut self, size: Option<u64>) {
+        if self.body().is_none() {
+            return;
+        }
+        let encoded = self.header().contains_field(&HEADER_TRANSFER_ENCODING);
+        self.header_mut().remove_any(&HEADER_CONTENT_LENGTH);
+        match size {
+            Some(size) if !encoded => {
+                let value = Area::from_bytes(size.to_string().as_bytes());
+                self.header_mut().insert(HEADER_CONTENT_LENGTH, value);
+            }
+            _ => {}
+        }
+        if let Some(body) = self.body_mut() {
+            // hosts which cannot resize bodies work the size out
+            // from the header
+            let _ = body.set_size(size);
+        }
+    }
 }
 
 impl<H: Host + ?Sized, T: Message<H> + ?Sized> Message<H> for Box<T> {
@@ -46,8 +82,8 @@ impl<H: Host + ?Sized, T: Message<H> + ?Sized> Message<H> for Box<T> {
         (&**self).header()
     }
 
-    fn add_body(&mut self) {
-        (&mut **self).add_body();
+    fn add_body(&mut self) -> Result<(), Error> {
+        (&mut **self).add_body()
     }
     fn body_mut(&mut self) -> Option<&mut H::Body> {
         (&mut **self).body_mut()
@@ -56,8 +92,8 @@ impl<H: Host + ?Sized, T: Message<H> + ?Sized> Message<H> for Box<T> {
         (&**self).body()
     }
 
-    fn add_trailer(&mut self) {
-        (&mut **self).add_trailer();
+    fn add_trailer(&mut self) -> Result<(), Error> {
+        (&mut **self).add_trailer()
     }
     fn trailer_mut(&mut self) -> &mut H::Trailer {
         (&mut **self).trailer_mut()
@@ -2,11 +2,17 @@ pub mod body;
 pub use self::body::Body;
 
 pub mod area;
-pub use self::area::Area;
+pub use self::area::{Area, AreaChain, SendArea};
+
+#[cfg(feature = "serde")]
+pub mod config;
 
 mod delay;
 pub use self::delay::Delay;
 
+mod error;
+pub use self::error::{Error, ErrorKind};
+
 pub mod header;
 
 pub mod log;
@@ -24,4 +30,4 @@ mod options;
 pub use self::options::Options;
 
 mod version;
-pub use self::version::Version;
+pub use self::version::{Version, LIBECAP_VERSION};
@@ -1,8 +1,10 @@
 use std::borrow::Cow;
-use std::cell::Cell;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-static LAST_ID: AtomicUsize = AtomicUsize::new(0);
+/// Ids below this are reserved for the predefined names.
+const FIRST_DYNAMIC_ID: usize = FIRST_PREDEFINED_ID as usize + PREDEFINED_COUNT;
+
+static LAST_ID: AtomicUsize = AtomicUsize::new(FIRST_DYNAMIC_ID);
 
 /// Representation of a protocol token constant or similar name.
 ///
@@ -10,11 +12,9 @@ static LAST_ID: AtomicUsize = AtomicUsize::new(0);
 /// associated strings through associating an integer key with these
 /// IDs.
 ///
-/// FIXME: Rust does not have great support for global, mutable,
-/// constant data that is initialized consecutively. It is unclear as
-/// such how best to implement these IDs (other than trivially doing so
-/// via having a list and manually 0, 1, 2, 3... then setting the
-/// LAST_ID global to the last id from that list.
+/// The names predefined by libecap (e.g. [`HEADER_CONTENT_LENGTH`])
+/// are available as constants with fixed ids; ids for other names
+/// are handed out by [`Name::new_identified`] after those.
 ///
 /// A given name can also be associated by the host with some `u32` ID,
 /// which will persist across adapter boundary.
@@ -24,7 +24,7 @@ static LAST_ID: AtomicUsize = AtomicUsize::new(0);
 pub struct Name<'a> {
     image: Option<Cow<'a, [u8]>>,
     id: Id,
-    host_id: Cell<Option<u32>>,
+    host_id: Option<u32>,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -55,7 +55,7 @@ impl<'a> Name<'a> {
         Name {
             image: if image.is_empty() { None } else { Some(image) },
             id,
-            host_id: Cell::new(host_id),
+            host_id,
         }
     }
 
@@ -63,7 +63,7 @@ impl<'a> Name<'a> {
         Name {
             image: None,
             id: Id::Unknown,
-            host_id: Cell::new(None),
+            host_id: None,
         }
     }
 
@@ -71,7 +71,7 @@ impl<'a> Name<'a> {
         Name {
             image: Some(image.into()),
             id: Id::Unidentified,
-            host_id: Cell::new(None),
+            host_id: None,
         }
     }
 
@@ -79,7 +79,7 @@ impl<'a> Name<'a> {
         Name {
             image: Some(image.into()),
             id: Id::Id(LAST_ID.fetch_add(1, Ordering::Relaxed) as u32),
-            host_id: Cell::new(None),
+            host_id: None,
         }
     }
 
@@ -128,16 +128,18 @@ impl<'a> Name<'a> {
     ///
     /// This will panic if called twice on the same `Name`.
     pub fn host_id(&self) -> Option<u32> {
-        self.host_id.get()
+        self.host_id
     }
 
     /// Assigns a host ID.
     ///
-    /// This must only be called by the host.
+    /// This must only be called by the host. The predefined names are
+    /// constants, so a host keeps its own copies of those it assigns
+    /// ids to.
     ///
     /// This will panic if called twice on the same `Name`.
-    pub fn assign_host_id(&self, id: u32) {
-        assert_eq!(self.host_id.replace(Some(id)), None);
+    pub fn assign_host_id(&mut self, id: u32) {
+        assert_eq!(self.host_id.replace(id), None);
     }
 }
 
@@ -150,3 +152,106 @@ impl<'a> PartialEq for Name<'a> {
         }
     }
 }
+
+macro_rules! predefined_names {
+    ($($(#[$attr:meta])* $name:ident = $id:expr, $image:expr;)*) => {
+        $(
+            $(#[$attr])*
+            pub const $name: Name<'static> = Name {
+                image: Some(Cow::Borrowed($image)),
+                id: Id::Id($id),
+                host_id: None,
+            };
+        )*
+
+        /// Images of the predefined names, in id order.
+        const PREDEFINED_IMAGES: &[&[u8]] = &[$($image),*];
+
+        /// Number of predefined names.
+        pub const PREDEFINED_COUNT: usize = PREDEFINED_IMAGES.len();
+    };
+}
+
+/// The id of the first predefined name; the rest follow consecutively.
+pub const FIRST_PREDEFINED_ID: u32 = 2;
+
+predefined_names! {
+    /// libecap::protocolHttp
+    PROTOCOL_HTTP = 2, b"HTTP";
+    /// libecap::protocolHttps
+    PROTOCOL_HTTPS = 3, b"HTTPS";
+    /// libecap::protocolFtp
+    PROTOCOL_FTP = 4, b"FTP";
+    /// libecap::protocolGopher
+    PROTOCOL_GOPHER = 5, b"GOPHER";
+    /// libecap::protocolWais
+    PROTOCOL_WAIS = 6, b"WAIS";
+    /// libecap::protocolUrn
+    PROTOCOL_URN = 7, b"URN";
+    /// libecap::protocolWhois
+    PROTOCOL_WHOIS = 8, b"WHOIS";
+
+    /// libecap::methodGet
+    METHOD_GET = 9, b"GET";
+    /// libecap::methodPut
+    METHOD_PUT = 10, b"PUT";
+    /// libecap::methodPost
+    METHOD_POST = 11, b"POST";
+    /// libecap::methodHead
+    METHOD_HEAD = 12, b"HEAD";
+    /// libecap::methodConnect
+    METHOD_CONNECT = 13, b"CONNECT";
+    /// libecap::methodOptions
+    METHOD_OPTIONS = 14, b"OPTIONS";
+    /// libecap::methodDelete
+    METHOD_DELETE = 15, b"DELETE";
+    /// libecap::methodTrace
+    METHOD_TRACE = 16, b"TRACE";
+
+    /// libecap::headerContentLength
+    HEADER_CONTENT_LENGTH = 17, b"Content-Length";
+    /// libecap::headerTransferEncoding
+    HEADER_TRANSFER_ENCODING = 18, b"Transfer-Encoding";
+    /// libecap::headerReferer
+    HEADER_REFERER = 19, b"Referer";
+    /// libecap::headerVia
+    HEADER_VIA = 20, b"Via";
+    /// libecap::headerXClientIp
+    HEADER_X_CLIENT_IP = 21, b"X-Client-IP";
+    /// libecap::headerXServerIp
+    HEADER_X_SERVER_IP = 22, b"X-Server-IP";
+
+    /// libecap::metaClientIp, the transaction option holding the
+    /// client address.
+    META_CLIENT_IP = 23, b"X-Client-IP";
+    /// libecap::metaServerIp
+    META_SERVER_IP = 24, b"X-Server-IP";
+    /// libecap::metaUserName
+    META_USER_NAME = 25, b"X-Client-Username";
+    /// libecap::metaAuthenticatedUser
+    META_AUTHENTICATED_USER = 26, b"X-Authenticated-User";
+    /// libecap::metaAuthenticatedGroups
+    META_AUTHENTICATED_GROUPS = 27, b"X-Authenticated-Groups";
+    /// libecap::metaSubscriberId
+    META_SUBSCRIBER_ID = 28, b"X-Subscriber-ID";
+}
+
+/// The predefined name with the given id, if any.
+pub fn predefined(id: u32) -> Option<Name<'static>> {
+    let index = id.checked_sub(FIRST_PREDEFINED_ID)? as usize;
+    PREDEFINED_IMAGES.get(index).map(|image| Name {
+        image: Some(Cow::Borrowed(*image)),
+        id: Id::Id(id),
+        host_id: None,
+    })
+}
+
+impl<'a> Name<'a> {
+    /// Is this one of the names predefined by libecap?
+    pub fn is_predefined(&self) -> bool {
+        match self.id {
+            Id::Id(id) => predefined(id).is_some(),
+            _ => false,
+        }
+    }
+}
@@ -1,3 +1,15 @@
+use std::fmt;
+
+/// The version of libecap whose interface this crate implements.
+///
+/// Services built with this crate target it, unless they declare
+/// otherwise through `adapter::Service::libecap_version`.
+pub const LIBECAP_VERSION: Version = Version {
+    major: Some(1),
+    minor: Some(0),
+    micro: Some(1),
+};
+
 #[derive(Debug, Copy, Clone, Eq)]
 pub struct Version {
     pub major: Option<u32>,
@@ -9,6 +21,38 @@ impl Version {
     pub fn known(&self) -> bool {
         self.major.is_some()
     }
+
+    /// Parse a libecap version string such as `1.0.1`.
+    ///
+    /// Missing trailing components are unknown; a malformed string gives
+    /// an unknown version.
+    pub fn parse(s: &str) -> Version {
+        let parts = s
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<Vec<u32>>>();
+        match parts {
+            Some(ref parts) if parts.len() <= 3 => Version {
+                major: parts.get(0).cloned(),
+                minor: parts.get(1).cloned(),
+                micro: parts.get(2).cloned(),
+            },
+            _ => Version {
+                major: None,
+                minor: None,
+                micro: None,
+            },
+        }
+    }
+
+    /// Whether a service built for libecap version `self` can be used by
+    /// a host built for `host`.
+    ///
+    /// libecap keeps its interface across micro versions, so the major
+    /// and minor versions must match.
+    pub fn is_compatible(&self, host: &Version) -> bool {
+        self.known() && self.minor.is_some() && self.major == host.major && self.minor == host.minor
+    }
 }
 
 impl PartialEq for Version {
@@ -19,3 +63,80 @@ impl PartialEq for Version {
             && self.micro == other.micro
     }
 }
+
+/// Formats the known components, e.g. `1.0.1`, or `unknown`.
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let major = match self.major {
+            Some(major) => major,
+            None => return f.write_str("unknown"),
+        };
+        write!(f, "{}", major)?;
+        for part in [self.minor, self.micro].iter().take_while(|p| p.is_some()) {
+            write!(f, ".{}", part.unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u32, minor: u32, micro: u32) -> Version {
+        Version {
+            major: Some(major),
+            minor: Some(minor),
+            micro: Some(micro),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Version::parse("1.0.1"), version(1, 0, 1));
+        assert_eq!(Version::parse("0.2.10"), version(0, 2, 10));
+
+        let partial = Version::parse("1.2");
+        assert_eq!(partial.major, Some(1));
+        assert_eq!(partial.minor, Some(2));
+        assert_eq!(partial.micro, None);
+        assert_eq!(partial.to_string(), "1.2");
+    }
+
+    #[test]
+    fn parse_malformed() {
+        for s in &["", "x", "1.x.0", "1..0", "1.0.1.2", "-1.0", " 1.0"] {
+            let parsed = Version::parse(s);
+            assert!(!parsed.known(), "{:?}", s);
+            assert_eq!(parsed.minor, None, "{:?}", s);
+            assert_eq!(parsed.micro, None, "{:?}", s);
+            assert_eq!(parsed.to_string(), "unknown");
+        }
+    }
+
+    #[test]
+    fn unknown_versions_are_unequal() {
+        let unknown = Version::parse("");
+        assert_ne!(unknown, Version::parse(""));
+        assert_eq!(LIBECAP_VERSION, version(1, 0, 1));
+        assert_ne!(LIBECAP_VERSION, version(1, 0, 2));
+    }
+
+    #[test]
+    fn compatible_across_micro_versions() {
+        assert!(version(1, 0, 0).is_compatible(&LIBECAP_VERSION));
+        assert!(version(1, 0, 7).is_compatible(&LIBECAP_VERSION));
+        assert!(Version::parse("1.0").is_compatible(&LIBECAP_VERSION));
+    }
+
+    #[test]
+    fn incompatible_versions() {
+        assert!(!version(1, 1, 1).is_compatible(&LIBECAP_VERSION));
+        assert!(!version(0, 0, 1).is_compatible(&LIBECAP_VERSION));
+        assert!(!version(2, 0, 1).is_compatible(&LIBECAP_VERSION));
+        // The minor version must be known to be compatible.
+        assert!(!Version::parse("1").is_compatible(&LIBECAP_VERSION));
+        assert!(!Version::parse("").is_compatible(&LIBECAP_VERSION));
+        assert!(!Version::parse("").is_compatible(&Version::parse("")));
+    }
+}
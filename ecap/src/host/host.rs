@@ -1,6 +1,6 @@
 use common::log::{DebugStream, LogVerbosity};
 use common::{
-    header::{FirstLine, Header}, Body, Message,
+    header::{FirstLine, Header}, Body, Error, ErrorKind, Message, Version, LIBECAP_VERSION,
 };
 
 use host::Transaction;
@@ -26,14 +26,18 @@ where
     /// A description of the Host, free-format.
     fn describe(&self) -> String;
 
-    /// Register a versioned service for a given eCAP version.
+    /// Decide whether to accept a service built for the given version
+    /// of libecap, before it is first used.
     ///
-    /// The version is not encoded in the Service as it may not be safely readable if the Service
-    /// was implemented for a different version of libecap.
-    ///
-    /// XXX: Investigate and document why this takes a weak_ptr.
-    /// XXX: This now takes T: Service, not weak_ptr<Service>. this is different semantics
-    //fn note_versioned_service<T: Service<Self>>(&mut self, ecap_version: &CStr, service: T);
+    /// The service itself is not passed, as a service built for an
+    /// incompatible version may not be safely usable, even to ask for
+    /// its URI; callers record the version when the service registers.
+    /// Services are refused by returning an error, which by default
+    /// happens unless `check_libecap_version` accepts the version.
+    /// Hosts which can adapt to other versions accept them here.
+    fn note_versioned_service(&self, ecap_version: Version) -> Result<(), Error> {
+        check_libecap_version(ecap_version)
+    }
 
     /// Open a logging stream with the given verbosity.
     ///
@@ -65,3 +69,44 @@ where
     /// Utilized when copying an existing Message is not appropriate.
     fn new_response(&self) -> <Self::Message as Message<Self>>::MessageClone;
 }
+
+/// Accept services built for a version of libecap compatible with
+/// `LIBECAP_VERSION`, as `Host::note_versioned_service` does by default.
+pub fn check_libecap_version(ecap_version: Version) -> Result<(), Error> {
+    if ecap_version.is_compatible(&LIBECAP_VERSION) {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "service built for libecap {} is incompatible with libecap {}",
+                ecap_version, LIBECAP_VERSION
+            ),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_compatible_versions() {
+        assert_eq!(check_libecap_version(LIBECAP_VERSION), Ok(()));
+        assert_eq!(check_libecap_version(Version::parse("1.0.0")), Ok(()));
+    }
+
+    #[test]
+    fn refuses_incompatible_versions() {
+        let error = check_libecap_version(Version::parse("0.2.0")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert_eq!(
+            error.message(),
+            "service built for libecap 0.2.0 is incompatible with libecap 1.0.1"
+        );
+
+        let error = check_libecap_version(Version::parse("")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert!(error.message().contains("libecap unknown"));
+    }
+}
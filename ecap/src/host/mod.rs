@@ -3,4 +3,4 @@ mod transaction;
 pub use self::transaction::Transaction;
 
 mod host;
-pub use self::host::Host;
+pub use self::host::{check_libecap_version, Host};
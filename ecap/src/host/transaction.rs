@@ -1,4 +1,4 @@
-use common::{Area, Delay, Message};
+use common::{Area, Delay, Error, Message};
 use host::Host;
 
 /// The host side of the eCAP transaction.
@@ -42,7 +42,25 @@ pub trait Transaction<H: ?Sized + Host> {
     /// By calling this, the adapter indicates that the host should call
     /// the `adapted_body` methods on the `adapter::Transaction` in
     /// order to receive a message body.
-    fn use_adapted<M: Message<H> + 'static>(&mut self, msg: M);
+    ///
+    /// Fails if the host rejects the message, e.g. with
+    /// `ErrorKind::Unsupported` if it was not created by this host.
+    fn use_adapted<M: Message<H> + 'static>(&mut self, msg: M) -> Result<(), Error>;
+
+    /// Use the message passed, with an adapted body of `size` bytes, or
+    /// of unknown size if `None`.
+    ///
+    /// The framing of the message is first updated with
+    /// `Message::set_body_length`, so adapters which change the length
+    /// of the body do not need to fix `Content-Length` by hand.
+    fn use_adapted_sized<M: Message<H> + 'static>(
+        &mut self,
+        mut msg: M,
+        size: Option<u64>,
+    ) -> Result<(), Error> {
+        msg.set_body_length(size);
+        self.use_adapted(msg)
+    }
 
     /// Prevent access to this message.
     ///
@@ -172,7 +190,7 @@ impl<H: Host + ?Sized, T: ?Sized + Transaction<H>> Transaction<H> for Box<T> {
     fn use_virgin(&mut self) {
         (&mut **self).use_virgin()
     }
-    fn use_adapted<M: Message<H> + 'static>(&mut self, msg: M) {
+    fn use_adapted<M: Message<H> + 'static>(&mut self, msg: M) -> Result<(), Error> {
         (&mut **self).use_adapted(msg)
     }
     fn block_virgin(&mut self) {
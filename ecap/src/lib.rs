@@ -1,12 +1,36 @@
+#[cfg(feature = "content-encoding")]
+extern crate brotli;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "content-encoding")]
+extern crate flate2;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "mmap")]
+extern crate memmap;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "tracing")]
+extern crate tracing_core;
+#[cfg(feature = "tracing")]
+extern crate tracing_subscriber;
+
 pub mod adapter;
 pub mod common;
 pub mod host;
 
 use adapter::Service;
+use common::Version;
 use host::Host;
 
 pub trait Translator: Send + Sync {
-    fn register_service<H, T>(&self, service: T)
+    /// Register `service`, built for version `ecap_version` of libecap,
+    /// with the host on the other side.
+    fn register_service<H, T>(&self, ecap_version: Version, service: T)
     where
         H: Host + ?Sized,
         T: Service<H> + 'static;
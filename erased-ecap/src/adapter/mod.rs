@@ -4,3 +4,6 @@ pub use self::service::Service;
 
 mod transaction;
 pub use self::transaction::Transaction;
+
+#[cfg(feature = "tracing")]
+pub mod trace;
@@ -1,10 +1,9 @@
-use std::any::TypeId;
+use std::any::{type_name, TypeId};
 use std::ffi::CStr;
 use std::time::Duration;
 
-use std::intrinsics::type_name;
-
 use ecap;
+use ecap::common::{Error, Version};
 
 use adapter;
 use common;
@@ -14,8 +13,12 @@ use host::Host as ErasedHost;
 use host::Transaction as ErasedTransaction;
 
 pub struct ErasedService {
-    service: *mut dyn Service<Box<dyn host::Host>>, // lies
+    /// A `Box<dyn Service<H>>`, for the host type identified by `host`.
+    service: *mut (),
     host: TypeId,
+    /// The libecap version of the service, recorded when it was erased
+    /// so that hosts can check it without calling into the service.
+    version: Version,
 }
 
 unsafe impl Send for ErasedService {}
@@ -23,27 +26,24 @@ unsafe impl Sync for ErasedService {}
 
 impl ErasedService {
     pub fn new<H: ?Sized + host::Host + 'static, S: Service<H>>(s: S) -> ErasedService {
-        unsafe {
-            println!(
-                "erasing service {:?} with host {:?}",
-                type_name::<S>(),
-                type_name::<H>()
-            );
-        }
+        let version = s.libecap_version();
         ErasedService {
-            service: Box::into_raw(Box::new(s)) as *mut dyn Service<H>
-                as *mut dyn Service<Box<dyn host::Host>>,
+            service: Box::into_raw(Box::new(Box::new(s) as Box<dyn Service<H>>)) as *mut (),
             host: TypeId::of::<H>(),
+            version,
         }
     }
 
+    /// The version of libecap the service was built for.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
     pub fn take<H: ?Sized + host::Host + 'static>(self) -> Box<dyn Service<H>> {
         if TypeId::of::<H>() == self.host {
-            unsafe { Box::from_raw(self.service as *mut dyn Service<H>) }
+            unsafe { *Box::from_raw(self.service as *mut Box<dyn Service<H>>) }
         } else {
-            panic!("taking with a different host: {}", unsafe {
-                type_name::<H>()
-            })
+            panic!("taking with a different host: {}", type_name::<H>())
         }
     }
 }
@@ -52,8 +52,9 @@ pub trait Service<H: 'static + host::Host + ?Sized> {
     fn uri(&self) -> String;
     fn tag(&self) -> String;
     fn describe(&self) -> String;
-    fn configure(&mut self, options: &dyn common::Options);
-    fn reconfigure(&mut self, options: &dyn common::Options);
+    fn libecap_version(&self) -> Version;
+    fn configure(&mut self, options: &dyn common::Options) -> Result<(), Error>;
+    fn reconfigure(&mut self, options: &dyn common::Options) -> Result<(), Error>;
     fn start(&self);
     fn stop(&self);
     fn retire(&self);
@@ -72,6 +73,7 @@ where
     S: ecap::adapter::Service<dyn ErasedHost> + ?Sized,
     <S as ecap::adapter::Service<dyn ErasedHost>>::Transaction: 'static,
 {
+    #[cfg(not(feature = "tracing"))]
     fn make_transaction(
         &mut self,
         host: &mut (dyn ErasedTransaction<dyn ErasedHost> + 'static),
@@ -79,6 +81,18 @@ where
         Box::new(S::make_transaction(self, host))
     }
 
+    /// Creates the transaction, and later runs each of its methods, in
+    /// a span identifying it.
+    #[cfg(feature = "tracing")]
+    fn make_transaction(
+        &mut self,
+        host: &mut (dyn ErasedTransaction<dyn ErasedHost> + 'static),
+    ) -> Box<dyn adapter::Transaction> {
+        let span = adapter::trace::transaction_span(&S::uri(self), host);
+        let transaction = span.in_scope(|| S::make_transaction(self, host));
+        Box::new(adapter::trace::Traced::new(transaction, span))
+    }
+
     fn uri(&self) -> String {
         S::uri(self)
     }
@@ -91,11 +105,15 @@ where
         S::describe(self)
     }
 
-    fn configure(&mut self, options: &dyn common::Options) {
+    fn libecap_version(&self) -> Version {
+        S::libecap_version(self)
+    }
+
+    fn configure(&mut self, options: &dyn common::Options) -> Result<(), Error> {
         S::configure(self, &options)
     }
 
-    fn reconfigure(&mut self, options: &dyn common::Options) {
+    fn reconfigure(&mut self, options: &dyn common::Options) -> Result<(), Error> {
         S::reconfigure(self, &options)
     }
 
@@ -131,45 +149,48 @@ where
 impl ecap::adapter::Service<dyn ErasedHost> for dyn Service<dyn ErasedHost> {
     type Transaction = Box<dyn adapter::Transaction>;
     fn uri(&self) -> String {
-        Self::uri(self)
+        <Self as Service<dyn ErasedHost>>::uri(self)
     }
     fn tag(&self) -> String {
-        Self::tag(self)
+        <Self as Service<dyn ErasedHost>>::tag(self)
     }
     fn describe(&self) -> String {
-        Self::describe(self)
+        <Self as Service<dyn ErasedHost>>::describe(self)
+    }
+    fn libecap_version(&self) -> Version {
+        <Self as Service<dyn ErasedHost>>::libecap_version(self)
     }
-    fn configure<T: ecap::common::Options>(&mut self, options: &T) {
-        Self::configure(self, options)
+    fn configure<T: ecap::common::Options>(&mut self, options: &T) -> Result<(), Error> {
+        <Self as Service<dyn ErasedHost>>::configure(self, options)
     }
-    fn reconfigure<T: ecap::common::Options>(&mut self, options: &T) {
-        Self::reconfigure(self, options)
+    fn reconfigure<T: ecap::common::Options>(&mut self, options: &T) -> Result<(), Error> {
+        <Self as Service<dyn ErasedHost>>::reconfigure(self, options)
     }
     fn start(&self) {
-        Self::start(self)
+        <Self as Service<dyn ErasedHost>>::start(self)
     }
     fn stop(&self) {
-        Self::stop(self)
+        <Self as Service<dyn ErasedHost>>::stop(self)
     }
     fn retire(&self) {
-        Self::retire(self)
+        <Self as Service<dyn ErasedHost>>::retire(self)
     }
     fn wants_url(&self, url: &CStr) -> bool {
-        Self::wants_url(self, url)
+        <Self as Service<dyn ErasedHost>>::wants_url(self, url)
     }
     fn make_transaction<'a>(
         &mut self,
         host: &'a mut (dyn ErasedTransaction<dyn ErasedHost> + 'static),
     ) -> Box<dyn adapter::Transaction> {
-        Self::make_transaction(self, host)
+        <Self as Service<dyn ErasedHost>>::make_transaction(self, host)
     }
     fn is_async(&self) -> bool {
-        Self::is_async(self)
+        <Self as Service<dyn ErasedHost>>::is_async(self)
     }
     fn suspend(&self, timeout: &mut Duration) {
-        Self::suspend(self, timeout)
+        <Self as Service<dyn ErasedHost>>::suspend(self, timeout)
     }
     fn resume(&self) {
-        Self::resume(self)
+        <Self as Service<dyn ErasedHost>>::resume(self)
     }
 }
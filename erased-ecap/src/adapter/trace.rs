@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ecap;
+use ecap::common::header::{FirstLineKind, RequestLine};
+use ecap::common::{Area, Name, NamedValueVisitor};
+use tracing::field;
+use tracing::Span;
+
+use host::Host as ErasedHost;
+use host::Transaction as ErasedTransaction;
+
+type HostTransaction = dyn ErasedTransaction<dyn ErasedHost> + 'static;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// A span identifying the transaction `service` is about to create
+/// for `host`.
+///
+/// The URL is that of the virgin request, or of the request which
+/// caused the virgin response; it is left empty if the host does not
+/// know that request.
+pub fn transaction_span(service: &str, host: &mut HostTransaction) -> Span {
+    let span = info_span!(
+        "xaction",
+        service = service,
+        id = NEXT_ID.fetch_add(1, Ordering::Relaxed) as u64,
+        direction = field::Empty,
+        url = field::Empty
+    );
+    let is_request = match host.virgin().first_line().kind() {
+        Some(FirstLineKind::Request(line)) => {
+            record_url(&span, line);
+            true
+        }
+        Some(FirstLineKind::Status(_)) => false,
+        None => return span,
+    };
+    if is_request {
+        span.record("direction", &"request");
+    } else {
+        span.record("direction", &"response");
+        if let Some(FirstLineKind::Request(line)) = host.cause().first_line().kind() {
+            record_url(&span, line);
+        }
+    }
+    span
+}
+
+fn record_url(span: &Span, line: &dyn RequestLine) {
+    let uri = line.uri();
+    // Hosts report a missing cause as an empty request.
+    if !uri.as_bytes().is_empty() {
+        span.record("url", &*String::from_utf8_lossy(uri.as_bytes()));
+    }
+}
+
+/// An adapter transaction whose methods run in its span.
+pub struct Traced<T> {
+    transaction: T,
+    span: Span,
+}
+
+impl<T> Traced<T> {
+    pub fn new(transaction: T, span: Span) -> Traced<T> {
+        Traced { transaction, span }
+    }
+}
+
+macro_rules! traced_method {
+    ($name:ident) => {
+        fn $name<'a>(&mut self, host: &'a mut HostTransaction)
+        where
+            HostTransaction: 'a,
+        {
+            let _entered = self.span.enter();
+            T::$name(&mut self.transaction, host)
+        }
+    };
+}
+
+impl<T> ecap::adapter::Transaction<dyn ErasedHost> for Traced<T>
+where
+    T: ecap::adapter::Transaction<dyn ErasedHost>,
+{
+    traced_method!(start);
+    traced_method!(stop);
+    traced_method!(resume);
+    traced_method!(adapted_body_discard);
+    traced_method!(adapted_body_make);
+    traced_method!(adapted_body_make_more);
+    traced_method!(adapted_body_stop_making);
+    traced_method!(adapted_body_pause);
+    traced_method!(adapted_body_resume);
+    traced_method!(virgin_body_content_available);
+
+    fn adapted_body_content<'a>(
+        &mut self,
+        host: &'a mut HostTransaction,
+        offset: usize,
+        size: usize,
+    ) -> Area
+    where
+        HostTransaction: 'a,
+    {
+        let _entered = self.span.enter();
+        T::adapted_body_content(&mut self.transaction, host, offset, size)
+    }
+
+    fn adapted_body_content_shift<'a>(&mut self, host: &'a mut HostTransaction, size: usize)
+    where
+        HostTransaction: 'a,
+    {
+        let _entered = self.span.enter();
+        T::adapted_body_content_shift(&mut self.transaction, host, size)
+    }
+
+    fn virgin_body_content_done<'a>(&mut self, host: &'a mut HostTransaction, at_end: bool)
+    where
+        HostTransaction: 'a,
+    {
+        let _entered = self.span.enter();
+        T::virgin_body_content_done(&mut self.transaction, host, at_end)
+    }
+}
+
+impl<T: ecap::common::Options> ecap::common::Options for Traced<T> {
+    fn option(&self, name: &Name) -> Option<Area> {
+        T::option(&self.transaction, name)
+    }
+
+    fn visit_each<V: NamedValueVisitor>(&self, visitor: V) {
+        T::visit_each(&self.transaction, visitor)
+    }
+}
@@ -1,5 +1,5 @@
 use ecap;
-use ecap::common::{Area, Name, NamedValueVisitor};
+use ecap::common::{Area, Error, Name, NamedValueVisitor};
 
 pub trait Header {
     fn contains_field(&self, field: &Name) -> bool;
@@ -8,7 +8,7 @@ pub trait Header {
     fn insert(&mut self, field: Name, value: Area);
     fn visit_each(&self, visitor: &mut dyn NamedValueVisitor);
     fn image(&self) -> Area;
-    fn parse(&mut self, buf: &Area) -> Result<(), ()>;
+    fn parse(&mut self, buf: &Area) -> Result<(), Error>;
 }
 
 impl<T: ?Sized> Header for T
@@ -33,7 +33,7 @@ where
     fn image(&self) -> Area {
         self.image()
     }
-    fn parse(&mut self, buf: &Area) -> Result<(), ()> {
+    fn parse(&mut self, buf: &Area) -> Result<(), Error> {
         self.parse(buf)
     }
 }
@@ -57,9 +57,11 @@ impl ecap::common::header::Header for dyn Header {
     fn image(&self) -> Area {
         Self::image(self)
     }
-    fn parse(&mut self, buf: &Area) -> Result<(), ()> {
+    fn parse(&mut self, buf: &Area) -> Result<(), Error> {
         Self::parse(self, buf)
     }
 }
 
-pub use ecap::common::header::{FirstLine, RequestLine, StatusLine};
+pub use ecap::common::header::{
+    FirstLine, FirstLineKind, FirstLineKindMut, RequestLine, StatusLine,
+};
@@ -1,5 +1,5 @@
 use ecap;
-use ecap::common::Body;
+use ecap::common::{Body, Error};
 use mopa::Any;
 
 use common::header::{FirstLine, Header};
@@ -15,11 +15,11 @@ pub trait Message: Any {
     fn header_mut<'a>(&'a mut self) -> &'a mut (dyn Header + 'static);
     fn header<'a>(&'a self) -> &'a (dyn Header + 'static);
 
-    fn add_body(&mut self);
+    fn add_body(&mut self) -> Result<(), Error>;
     fn body_mut<'a>(&'a mut self) -> Option<&'a mut (dyn Body + 'static)>;
     fn body<'a>(&'a self) -> Option<&'a (dyn Body + 'static)>;
 
-    fn add_trailer(&mut self); // XXX: throws by default
+    fn add_trailer(&mut self) -> Result<(), Error>;
     fn trailer_mut<'a>(&'a mut self) -> &'a mut (dyn Header + 'static);
     fn trailer<'a>(&'a self) -> &'a (dyn Header + 'static);
 }
@@ -51,7 +51,7 @@ where
         self.header()
     }
 
-    fn add_body<'a>(&'a mut self) {
+    fn add_body<'a>(&'a mut self) -> Result<(), Error> {
         self.add_body()
     }
 
@@ -69,7 +69,7 @@ where
         }
     }
 
-    fn add_trailer(&mut self) {
+    fn add_trailer(&mut self) -> Result<(), Error> {
         self.add_trailer()
     }
 
@@ -103,7 +103,7 @@ impl ecap::common::Message<dyn ErasedHost> for dyn Message {
         <Self as Message>::header(self)
     }
 
-    fn add_body(&mut self) {
+    fn add_body(&mut self) -> Result<(), Error> {
         Self::add_body(self)
     }
     fn body_mut(&mut self) -> Option<&mut (dyn Body + 'static)> {
@@ -113,7 +113,7 @@ impl ecap::common::Message<dyn ErasedHost> for dyn Message {
         <Self as Message>::body(self)
     }
 
-    fn add_trailer(&mut self) {
+    fn add_trailer(&mut self) -> Result<(), Error> {
         Self::add_trailer(self)
     }
     fn trailer_mut(&mut self) -> &mut (dyn Header + 'static) {
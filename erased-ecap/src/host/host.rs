@@ -1,6 +1,6 @@
 use ecap;
 use ecap::common::log::LogVerbosity;
-use ecap::common::Body;
+use ecap::common::{Body, Error, Version};
 
 use common::header::{FirstLine, Header};
 use common::{log::DebugStream, Message};
@@ -9,7 +9,7 @@ use host::Transaction;
 pub trait Host {
     fn uri(&self) -> String;
     fn describe(&self) -> String;
-    //fn note_versioned_service(&mut self, ecap_version: &CStr, service: ErasedService);
+    fn note_versioned_service(&self, ecap_version: Version) -> Result<(), Error>;
     fn open_debug(&self, verbosity: LogVerbosity) -> Option<Box<dyn DebugStream>>;
     fn close_debug(&self, stream: Box<dyn DebugStream>);
     fn new_request(&self) -> Box<dyn Message>;
@@ -33,11 +33,9 @@ impl ecap::host::Host for dyn Host {
     fn describe(&self) -> String {
         Self::describe(self)
     }
-    //fn note_versioned_service<T: Service<Self>>(
-    //    &mut self,
-    //    ecap_version: &CStr,
-    //    service: T
-    //);
+    fn note_versioned_service(&self, ecap_version: Version) -> Result<(), Error> {
+        (&*self).note_versioned_service(ecap_version)
+    }
     fn open_debug(&self, verbosity: LogVerbosity) -> Option<Self::DebugStream> {
         (&*self).open_debug(verbosity)
     }
@@ -66,9 +64,9 @@ where
     fn describe(&self) -> String {
         H::describe(self)
     }
-    //fn note_versioned_service(&mut self, ecap_version: &CStr, service: ErasedService) {
-    //    H::note_versioned_service(self, ecap_version, service.take::<H>())
-    //}
+    fn note_versioned_service(&self, ecap_version: Version) -> Result<(), Error> {
+        H::note_versioned_service(self, ecap_version)
+    }
     fn open_debug(&self, verbosity: LogVerbosity) -> Option<Box<dyn DebugStream>> {
         H::open_debug(self, verbosity).map(|d| -> Box<dyn DebugStream> { Box::new(d) })
     }
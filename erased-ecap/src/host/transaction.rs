@@ -1,5 +1,5 @@
 use ecap;
-use ecap::common::{Area, Delay};
+use ecap::common::{Area, Delay, Error};
 
 use common::Message;
 
@@ -10,7 +10,7 @@ pub trait Transaction<H: ecap::host::Host + ?Sized> {
     fn cause(&mut self) -> &dyn Message;
     fn adapted(&mut self) -> &mut dyn Message;
     fn use_virgin(&mut self);
-    fn use_adapted(&mut self, msg: Box<dyn Message>);
+    fn use_adapted(&mut self, msg: Box<dyn Message>) -> Result<(), Error>;
     fn block_virgin(&mut self);
     fn adaptation_delayed(&mut self, delay: &Delay);
     fn adaptation_aborted(&mut self);
@@ -27,7 +27,7 @@ pub trait Transaction<H: ecap::host::Host + ?Sized> {
     fn adapted_body_content_available(&mut self);
 }
 
-impl ecap::host::Transaction<dyn crate::host::Host> for dyn Transaction<dyn crate::host::Host> {
+impl ecap::host::Transaction<dyn ErasedHost> for dyn Transaction<dyn ErasedHost> {
     fn virgin(&mut self) -> &mut dyn Message {
         Self::virgin(self)
     }
@@ -40,7 +40,10 @@ impl ecap::host::Transaction<dyn crate::host::Host> for dyn Transaction<dyn crat
     fn use_virgin(&mut self) {
         Self::use_virgin(self)
     }
-    fn use_adapted<M: ecap::common::Message<dyn ErasedHost> + 'static>(&mut self, msg: M) {
+    fn use_adapted<M: ecap::common::Message<dyn ErasedHost> + 'static>(
+        &mut self,
+        msg: M,
+    ) -> Result<(), Error> {
         Self::use_adapted(self, Box::new(msg))
     }
     fn block_virgin(&mut self) {
@@ -1,10 +1,11 @@
-#![feature(crate_in_paths, core_intrinsics)]
-
 extern crate ecap;
 #[macro_use]
 extern crate mopa;
 #[macro_use]
 extern crate parse_generics_shim;
+#[cfg(feature = "tracing")]
+#[macro_use]
+extern crate tracing;
 
 pub mod adapter;
 pub mod common;
@@ -21,8 +22,9 @@ where
     T: ecap::Translator,
 {
     fn register_service(&self, s: ErasedService) {
+        let version = s.version();
         let service = s.take::<dyn host::Host>();
-        Self::register_service(self, service);
+        Self::register_service(self, version, service);
     }
 }
 
@@ -0,0 +1,477 @@
+//! A blocking ICAP client, making one connection per request.
+
+use std::cmp;
+use std::fmt;
+use std::io::{self, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use ecap_host::MemMessage;
+use ecap_icap::protocol::{self, header_value, set_header_value, Encapsulated, RequestHead,
+                          ResponseHead};
+use ecap_icap::{Error, Method};
+
+/// Where to find an ICAP service, e.g. `icap://127.0.0.1:1344/respmod`.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    /// The `host:port` to connect to.
+    pub authority: String,
+    /// The full service URI, as sent in requests.
+    pub uri: String,
+}
+
+impl Endpoint {
+    pub fn parse(uri: &str) -> Option<Endpoint> {
+        if !uri.starts_with("icap://") {
+            return None;
+        }
+        let rest = &uri["icap://".len()..];
+        let authority = rest.split('/').next().unwrap_or("");
+        if authority.is_empty() {
+            return None;
+        }
+        let authority = if authority.contains(':') {
+            authority.to_owned()
+        } else {
+            format!("{}:1344", authority)
+        };
+        Some(Endpoint {
+            authority,
+            uri: uri.to_owned(),
+        })
+    }
+
+    fn host(&self) -> &str {
+        self.authority.split(':').next().unwrap_or("")
+    }
+}
+
+/// Failure of an ICAP exchange.
+#[derive(Debug)]
+pub enum ClientError {
+    Icap(Error),
+    /// The server responded with an unexpected status.
+    Status(u16, String),
+}
+
+impl From<Error> for ClientError {
+    fn from(e: Error) -> ClientError {
+        ClientError::Icap(e)
+    }
+}
+
+impl From<::std::io::Error> for ClientError {
+    fn from(e: ::std::io::Error) -> ClientError {
+        ClientError::Icap(Error::Io(e))
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClientError::Icap(ref e) => write!(f, "{}", e),
+            ClientError::Status(status, ref reason) => {
+                write!(f, "ICAP server responded {} {}", status, reason)
+            }
+        }
+    }
+}
+
+/// What the server advertised in response to `OPTIONS`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ServiceOptions {
+    pub preview: Option<usize>,
+    /// Whether the server may respond `204 No Content` outside of
+    /// previews, if allowed to by the client.
+    pub allow_204: bool,
+}
+
+/// The outcome of adapting a message.
+pub enum Reply {
+    /// `204 No Content`: the message should be used as-is.
+    Unmodified,
+    Modified {
+        /// The request or response the server returned.
+        message: MemMessage,
+        body: Option<Vec<u8>>,
+    },
+}
+
+/// Connect to the server, giving up on connecting, and on each read or
+/// write, after `timeout`.
+fn connect(endpoint: &Endpoint, timeout: Duration) -> Result<TcpStream, ClientError> {
+    let mut error = None;
+    for addr in endpoint.authority.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(e) => error = Some(e),
+        }
+    }
+    let error = error
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "ICAP server has no address"));
+    Err(error.into())
+}
+
+fn request_head(endpoint: &Endpoint, method: Method) -> RequestHead {
+    let mut head = RequestHead::new(method, &endpoint.uri);
+    set_header_value(&mut head.header, "Host", endpoint.host());
+    set_header_value(&mut head.header, "Connection", "close");
+    head
+}
+
+/// Ask the server what the service supports.
+pub fn options(endpoint: &Endpoint, timeout: Duration) -> Result<ServiceOptions, ClientError> {
+    let mut stream = connect(endpoint, timeout)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request = Vec::new();
+    let mut head = request_head(endpoint, Method::Options);
+    set_header_value(&mut head.header, "Encapsulated", "null-body=0");
+    head.write_to(&mut request);
+    stream.write_all(&request)?;
+
+    let response = ResponseHead::read(&mut reader)?;
+    if response.status != 200 {
+        return Err(ClientError::Status(response.status, response.reason));
+    }
+    Ok(ServiceOptions {
+        preview: header_value(&response.header, "Preview")
+            .and_then(|preview| preview.trim().parse().ok()),
+        allow_204: header_value(&response.header, "Allow")
+            .map_or(false, |allow| allow.split(',').any(|code| code.trim() == "204")),
+    })
+}
+
+/// Send `message` and `body` to the server for adaptation.
+///
+/// If `preview` is set, only that many bytes of the body are sent
+/// until the server asks for the rest. Unless `allow_204` is set, the
+/// server may only leave the message unmodified in response to a
+/// preview, and has to send it back otherwise.
+pub fn adapt(
+    endpoint: &Endpoint,
+    timeout: Duration,
+    method: Method,
+    message: MemMessage,
+    body: Option<&[u8]>,
+    preview: Option<usize>,
+    allow_204: bool,
+) -> Result<Reply, ClientError> {
+    let mut stream = connect(endpoint, timeout)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let (request, response) = match method {
+        Method::Reqmod => (Some(message), None),
+        _ => (None, Some(message)),
+    };
+    let encapsulated = Encapsulated {
+        request,
+        response,
+        has_body: body.is_some(),
+    };
+    let mut sections = Vec::new();
+    let value = encapsulated.write_to(&mut sections);
+
+    let mut head = request_head(endpoint, method);
+    if allow_204 {
+        set_header_value(&mut head.header, "Allow", "204");
+    }
+    set_header_value(&mut head.header, "Encapsulated", &value);
+    let preview = match (body, preview) {
+        (Some(body), Some(preview)) => Some(cmp::min(body.len(), preview)),
+        _ => None,
+    };
+    if let Some(preview) = preview {
+        set_header_value(&mut head.header, "Preview", &preview.to_string());
+    }
+
+    let mut request = Vec::new();
+    head.write_to(&mut request);
+    request.extend_from_slice(&sections);
+
+    // Whether the response is to a preview, rather than to the whole
+    // message.
+    let (response, previewed) = match (body, preview) {
+        (None, _) => {
+            stream.write_all(&request)?;
+            (ResponseHead::read(&mut reader)?, false)
+        }
+        (Some(body), None) => {
+            protocol::write_chunk(&mut request, body);
+            protocol::write_last_chunk(&mut request, false);
+            stream.write_all(&request)?;
+            (ResponseHead::read(&mut reader)?, false)
+        }
+        (Some(body), Some(preview)) => {
+            let complete = preview == body.len();
+            protocol::write_chunk(&mut request, &body[..preview]);
+            protocol::write_last_chunk(&mut request, complete);
+            stream.write_all(&request)?;
+
+            let response = ResponseHead::read(&mut reader)?;
+            if response.status == 100 && !complete {
+                let mut rest = Vec::new();
+                protocol::write_chunk(&mut rest, &body[preview..]);
+                protocol::write_last_chunk(&mut rest, false);
+                stream.write_all(&rest)?;
+                (ResponseHead::read(&mut reader)?, false)
+            } else {
+                (response, true)
+            }
+        }
+    };
+
+    match response.status {
+        204 if allow_204 || previewed => Ok(Reply::Unmodified),
+        200 => {
+            let encapsulated = Encapsulated::read(&mut reader, &response.header)?;
+            let body = if encapsulated.has_body {
+                let mut body = Vec::new();
+                protocol::read_chunks(&mut reader, &mut body)?;
+                Some(body)
+            } else {
+                None
+            };
+            // A response to REQMOD satisfies the request itself.
+            let message = encapsulated
+                .response
+                .or(encapsulated.request)
+                .ok_or(Error::Protocol("no encapsulated message"))?;
+            Ok(Reply::Modified { message, body })
+        }
+        status => Err(ClientError::Status(status, response.reason)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::{self, JoinHandle};
+
+    use ecap_host::MemMessage;
+    use ecap_icap::protocol::{self, header_value, Encapsulated, RequestHead};
+
+    use super::*;
+
+    const NO_CONTENT: &[u8] = b"ICAP/1.0 204 No Content\r\n\r\n";
+
+    /// What the stub server received.
+    #[derive(Debug, Default)]
+    struct Received {
+        allow: Option<String>,
+        preview: Option<String>,
+        /// The body, in the order of the reads: the preview, if any,
+        /// then the rest.
+        reads: Vec<Vec<u8>>,
+    }
+
+    /// How the stub server responds.
+    enum Script {
+        /// `204 No Content` once the whole body has been read.
+        NoContent,
+        /// `204 No Content` after the preview.
+        NoContentAfterPreview,
+        /// `100 Continue` after the preview, then the message back with
+        /// the body upper-cased.
+        ContinueThenUppercase,
+        /// The message back with the body upper-cased.
+        Uppercase,
+    }
+
+    /// Serve a single ICAP request on an ephemeral port.
+    fn stub(script: Script) -> (Endpoint, JoinHandle<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("icap://{}/respmod", listener.local_addr().unwrap());
+        let endpoint = Endpoint::parse(&uri).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve(stream, script)
+        });
+        (endpoint, server)
+    }
+
+    fn serve(mut stream: TcpStream, script: Script) -> Received {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let head = RequestHead::read(&mut reader).unwrap().unwrap();
+        let encapsulated = Encapsulated::read(&mut reader, &head.header).unwrap();
+        let mut received = Received {
+            allow: header_value(&head.header, "Allow"),
+            preview: header_value(&head.header, "Preview"),
+            reads: Vec::new(),
+        };
+        let mut body = Vec::new();
+        let ieof = protocol::read_chunks(&mut reader, &mut body).unwrap();
+        received.reads.push(body.clone());
+
+        match script {
+            Script::NoContentAfterPreview => {
+                stream.write_all(NO_CONTENT).unwrap();
+                return received;
+            }
+            Script::ContinueThenUppercase => {
+                assert!(!ieof);
+                stream.write_all(b"ICAP/1.0 100 Continue\r\n\r\n").unwrap();
+                let mut rest = Vec::new();
+                protocol::read_chunks(&mut reader, &mut rest).unwrap();
+                received.reads.push(rest.clone());
+                body.extend_from_slice(&rest);
+            }
+            Script::NoContent => {
+                stream.write_all(NO_CONTENT).unwrap();
+                return received;
+            }
+            Script::Uppercase => {}
+        }
+
+        let reply = Encapsulated {
+            has_body: true,
+            ..encapsulated
+        };
+        let mut sections = Vec::new();
+        let value = reply.write_to(&mut sections);
+        protocol::write_chunk(&mut sections, &body.to_ascii_uppercase());
+        protocol::write_last_chunk(&mut sections, false);
+        let mut out = format!("ICAP/1.0 200 OK\r\nEncapsulated: {}\r\n\r\n", value).into_bytes();
+        out.extend_from_slice(&sections);
+        stream.write_all(&out).unwrap();
+        received
+    }
+
+    fn adapt_response(
+        endpoint: &Endpoint,
+        body: &[u8],
+        preview: Option<usize>,
+        allow_204: bool,
+    ) -> Result<Reply, ClientError> {
+        let message = MemMessage::response(200, "OK");
+        adapt(
+            endpoint,
+            Duration::from_secs(5),
+            Method::Respmod,
+            message,
+            Some(body),
+            preview,
+            allow_204,
+        )
+    }
+
+    fn modified_body(reply: Reply) -> Vec<u8> {
+        match reply {
+            Reply::Modified { body, .. } => body.unwrap(),
+            Reply::Unmodified => panic!("expected a modified message"),
+        }
+    }
+
+    #[test]
+    fn options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("icap://{}/respmod", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let head = RequestHead::read(&mut reader).unwrap().unwrap();
+            assert_eq!(head.method, Method::Options);
+            stream
+                .write_all(
+                    b"ICAP/1.0 200 OK\r\nMethods: RESPMOD\r\nPreview: 1024\r\n\
+                      Allow: 204\r\nEncapsulated: null-body=0\r\n\r\n",
+                )
+                .unwrap();
+        });
+        let endpoint = Endpoint::parse(&uri).unwrap();
+        let options = super::options(&endpoint, Duration::from_secs(5)).unwrap();
+        server.join().unwrap();
+        assert_eq!(options.preview, Some(1024));
+        assert!(options.allow_204);
+    }
+
+    #[test]
+    fn modified() {
+        let (endpoint, server) = stub(Script::Uppercase);
+        let reply = adapt_response(&endpoint, b"hello", None, false).unwrap();
+        let received = server.join().unwrap();
+        assert_eq!(received.allow, None);
+        assert_eq!(received.preview, None);
+        assert_eq!(received.reads, vec![b"hello".to_vec()]);
+        assert_eq!(modified_body(reply), b"HELLO");
+    }
+
+    #[test]
+    fn no_content_if_allowed() {
+        let (endpoint, server) = stub(Script::NoContent);
+        let reply = adapt_response(&endpoint, b"hello", None, true).unwrap();
+        let received = server.join().unwrap();
+        assert_eq!(received.allow, Some("204".to_owned()));
+        match reply {
+            Reply::Unmodified => {}
+            Reply::Modified { .. } => panic!("expected an unmodified message"),
+        }
+    }
+
+    #[test]
+    fn no_content_if_not_allowed() {
+        let (endpoint, server) = stub(Script::NoContent);
+        let result = adapt_response(&endpoint, b"hello", None, false);
+        server.join().unwrap();
+        match result {
+            Err(ClientError::Status(204, _)) => {}
+            _ => panic!("expected 204 to be refused"),
+        }
+    }
+
+    #[test]
+    fn no_content_after_preview() {
+        let (endpoint, server) = stub(Script::NoContentAfterPreview);
+        let reply = adapt_response(&endpoint, b"hello, world", Some(4), false).unwrap();
+        let received = server.join().unwrap();
+        assert_eq!(received.allow, None);
+        assert_eq!(received.preview, Some("4".to_owned()));
+        assert_eq!(received.reads, vec![b"hell".to_vec()]);
+        match reply {
+            Reply::Unmodified => {}
+            Reply::Modified { .. } => panic!("expected an unmodified message"),
+        }
+    }
+
+    #[test]
+    fn continue_after_preview() {
+        let (endpoint, server) = stub(Script::ContinueThenUppercase);
+        let reply = adapt_response(&endpoint, b"hello, world", Some(4), true).unwrap();
+        let received = server.join().unwrap();
+        assert_eq!(received.preview, Some("4".to_owned()));
+        assert_eq!(received.reads, vec![b"hell".to_vec(), b"o, world".to_vec()]);
+        assert_eq!(modified_body(reply), b"HELLO, WORLD");
+    }
+
+    #[test]
+    fn preview_of_whole_body() {
+        // The preview is limited to the body, which it then contains
+        // entirely, so the server does not ask for more.
+        let (endpoint, server) = stub(Script::Uppercase);
+        let reply = adapt_response(&endpoint, b"hello", Some(1024), false).unwrap();
+        let received = server.join().unwrap();
+        assert_eq!(received.preview, Some("5".to_owned()));
+        assert_eq!(received.reads, vec![b"hello".to_vec()]);
+        assert_eq!(modified_body(reply), b"HELLO");
+    }
+
+    #[test]
+    fn unresponsive_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("icap://{}/respmod", listener.local_addr().unwrap());
+        // Accept the connection, but never respond.
+        let server = thread::spawn(move || listener.accept().unwrap());
+        let endpoint = Endpoint::parse(&uri).unwrap();
+        let result = super::options(&endpoint, Duration::from_millis(50));
+        drop(server.join().unwrap());
+        match result {
+            Err(ClientError::Icap(Error::Io(_))) => {}
+            _ => panic!("expected the request to time out"),
+        }
+    }
+}
@@ -0,0 +1,565 @@
+//! An adapter which forwards messages to an ICAP (RFC 3507) service.
+//!
+//! Configuration:
+//!
+//!  * `uri`: the ICAP service, e.g. `icap://127.0.0.1:1344/respmod`
+//!  * `mode`: `reqmod` or `respmod` (default), depending on whether
+//!    requests or responses are adapted
+//!  * `preview`: preview size in bytes; by default, the size the
+//!    service advertises in response to `OPTIONS` is used
+//!  * `bypass`: if `on`, messages are left unmodified when the ICAP
+//!    service fails, instead of aborting the transaction
+//!  * `timeout`: how long to wait, in seconds, for the ICAP service to
+//!    accept a connection, and for each read or write; 10 by default
+//!
+//! The body is not streamed: it is buffered until the whole virgin body
+//! has been received, and the ICAP exchange then runs on the host's
+//! thread, blocking it. Set `timeout` to bound how long an unresponsive
+//! service may hold up the host. Failed exchanges are logged through
+//! the host, attributed to their transaction.
+//!
+//! The service is sent `OPTIONS` when started; if it advertises
+//! `Allow: 204`, it may answer with `204 No Content` instead of
+//! returning unmodified messages in full.
+//!
+//! Modifications to the method, URI, status code and reason phrase,
+//! header and body made by the ICAP service are applied; an ICAP
+//! service satisfying a request with a response blocks it instead.
+
+extern crate ecap;
+#[macro_use]
+extern crate ecap_common_link;
+extern crate ecap_host;
+extern crate ecap_icap;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate tracing;
+extern crate tracing_subscriber;
+
+mod client;
+
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::rc::Rc;
+use std::time::Duration;
+
+use ecap::adapter::{Service, Transaction};
+use ecap::common::header::{FirstLine, FirstLineKind, FirstLineKindMut, Header};
+use ecap::common::{config, Area, Error, ErrorKind, Message, Name, NamedValueVisitor, Options};
+use ecap::common::log::HostLayer;
+use ecap::host::{self, Transaction as HostTransactionTrait};
+use ecap_host::{MemFirstLine, MemHost, MemMessage, MemRequestLine, MemStatusLine};
+use ecap_icap::Method;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+use client::{Endpoint, Reply, ServiceOptions};
+
+/// How long to wait for the ICAP service if `timeout` is not set.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The options, as configured.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    uri: String,
+    #[serde(default)]
+    mode: Mode,
+    preview: Option<usize>,
+    #[serde(default)]
+    bypass: bool,
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    Reqmod,
+    Respmod,
+}
+
+impl Default for Mode {
+    fn default() -> Mode {
+        Mode::Respmod
+    }
+}
+
+#[derive(Debug)]
+struct Config {
+    endpoint: Endpoint,
+    method: Method,
+    preview: Option<usize>,
+    bypass: bool,
+    timeout: Duration,
+}
+
+impl Config {
+    fn from_options<T: Options + ?Sized>(options: &T) -> Result<Config, Error> {
+        let settings: Settings = config::from_options(options)?;
+        let endpoint = Endpoint::parse(&settings.uri).ok_or_else(|| {
+            Error::new(
+                ErrorKind::Config,
+                format!("invalid ICAP uri: {}", settings.uri),
+            )
+        })?;
+        let timeout = match settings.timeout {
+            Some(0) => {
+                return Err(Error::new(
+                    ErrorKind::Config,
+                    "timeout must be at least a second",
+                ))
+            }
+            Some(timeout) => Duration::from_secs(timeout),
+            None => DEFAULT_TIMEOUT,
+        };
+        Ok(Config {
+            endpoint,
+            method: match settings.mode {
+                Mode::Reqmod => Method::Reqmod,
+                Mode::Respmod => Method::Respmod,
+            },
+            preview: settings.preview,
+            bypass: settings.bypass,
+            timeout,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct IcapService {
+    /// `None` until configured, or if the configuration was rejected,
+    /// in which case messages are left unmodified.
+    config: Option<Rc<Config>>,
+    /// What the service advertised in response to `OPTIONS`, as of
+    /// `start`.
+    advertised: Cell<ServiceOptions>,
+}
+
+impl<H> Service<H> for IcapService
+where
+    H: host::Host + ?Sized,
+    H::Transaction: 'static,
+{
+    type Transaction = IcapTransaction;
+
+    fn uri(&self) -> String {
+        format!("ecap://rust/sample/icap")
+    }
+
+    fn configure<T: Options + ?Sized>(&mut self, options: &T) -> Result<(), Error> {
+        self.config = Some(Rc::new(Config::from_options(options)?));
+        Ok(())
+    }
+
+    fn reconfigure<T: Options + ?Sized>(&mut self, options: &T) -> Result<(), Error> {
+        *self = IcapService::default();
+        self.config = Some(Rc::new(Config::from_options(options)?));
+        Ok(())
+    }
+
+    fn tag(&self) -> String {
+        env!("CARGO_PKG_VERSION").to_owned()
+    }
+
+    fn start(&self) {
+        if let Some(host) = ecap_common_link::erased_host() {
+            // fails if another service of this library installed one first
+            let subscriber = Registry::default().with(HostLayer::new(host));
+            let _ = tracing::subscriber::set_global_default(subscriber);
+        }
+        let config = match self.config {
+            Some(ref config) => config,
+            None => return,
+        };
+        // Without a preview or `Allow: 204`, whole messages are sent and
+        // returned; that is also the fallback if the service is
+        // unreachable for now.
+        let options = client::options(&config.endpoint, config.timeout)
+            .map_err(|e| warn!("OPTIONS request failed: {}", e))
+            .unwrap_or_default();
+        self.advertised.set(options);
+    }
+
+    fn stop(&self) {
+        // custom code goes here, but none for this service
+    }
+
+    fn retire(&self) {
+        // custom code goes here, but none for this service
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "An ICAP client adapter from {} v{}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+        )
+    }
+
+    fn wants_url(&self, _url: &CStr) -> bool {
+        // the ICAP service decides what to adapt
+        true
+    }
+
+    fn make_transaction(&mut self, _transaction: &mut H::TransactionRef) -> Self::Transaction {
+        IcapTransaction {
+            config: self.config.clone(),
+            preview: self.config
+                .as_ref()
+                .and_then(|config| config.preview)
+                .or(self.advertised.get().preview),
+            allow_204: self.advertised.get().allow_204,
+            receiving: State::Undecided,
+            sending: State::Undecided,
+            virgin_body: Vec::new(),
+            adapted_body: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Eq, Clone)]
+enum State {
+    Undecided,
+    On,
+    Complete,
+    Never,
+}
+
+pub struct IcapTransaction {
+    config: Option<Rc<Config>>,
+    preview: Option<usize>,
+    allow_204: bool,
+    receiving: State,
+    sending: State,
+    virgin_body: Vec<u8>,
+    adapted_body: Vec<u8>,
+}
+
+/// Collects header fields, so that they can be removed or copied.
+struct Fields(Vec<(Name<'static>, Area)>);
+
+impl NamedValueVisitor for Fields {
+    fn visit(&mut self, name: &Name, value: &Area) {
+        self.0.push((name.clone().to_owned(), value.clone()));
+    }
+}
+
+/// The virgin message as an HTTP message, or `None` if the host does
+/// not provide its request or status line.
+fn encapsulate<H, M>(message: &M) -> Option<MemMessage>
+where
+    H: host::Host + ?Sized,
+    M: Message<H> + ?Sized,
+{
+    let first_line = match message.first_line().kind()? {
+        FirstLineKind::Request(line) => MemFirstLine::Request(MemRequestLine {
+            version: line.version(),
+            protocol: line.protocol().to_owned(),
+            method: line.method().to_owned(),
+            uri: line.uri(),
+        }),
+        FirstLineKind::Status(line) => MemFirstLine::Status(MemStatusLine {
+            version: line.version(),
+            protocol: line.protocol().to_owned(),
+            status_code: line.status_code(),
+            reason_phrase: line.reason_phrase().to_owned(),
+        }),
+    };
+    let mut fields = Fields(Vec::new());
+    message.header().visit_each(&mut fields);
+
+    let mut encapsulated = MemMessage::new(first_line);
+    for (name, value) in fields.0 {
+        Message::<MemHost>::header_mut(&mut encapsulated).insert(name, value);
+    }
+    Message::<MemHost>::add_body(&mut encapsulated).ok()?;
+    Some(encapsulated)
+}
+
+/// Apply the method and URI, or status code and reason phrase, of
+/// `from` to `to` if both are of the same kind.
+fn copy_first_line<L: FirstLine + ?Sized>(from: &MemFirstLine, to: &mut L) {
+    match (from, to.kind_mut()) {
+        (&MemFirstLine::Request(ref from), Some(FirstLineKindMut::Request(to))) => {
+            to.set_method(from.method.clone());
+            to.set_uri(from.uri.clone());
+        }
+        (&MemFirstLine::Status(ref from), Some(FirstLineKindMut::Status(to))) => {
+            to.set_status_code(from.status_code);
+            to.set_reason_phrase(from.reason_phrase.clone());
+        }
+        _ => {}
+    }
+}
+
+impl IcapTransaction {
+    /// Run the ICAP exchange, and tell the host what to use.
+    fn adapt<'a, H: host::Host + ?Sized>(&mut self, host: &'a mut H::TransactionRef) {
+        let config = self.config.clone().expect("adapting without a configuration");
+        let has_body = self.receiving != State::Never;
+        let reply = match encapsulate::<H, _>(&*host.virgin()) {
+            Some(message) => {
+                let result = client::adapt(
+                    &config.endpoint,
+                    config.timeout,
+                    config.method,
+                    message,
+                    if has_body {
+                        Some(&self.virgin_body[..])
+                    } else {
+                        None
+                    },
+                    self.preview,
+                    self.allow_204,
+                );
+                result.map_err(|e| warn!("ICAP exchange failed: {}", e)).ok()
+            }
+            None => {
+                debug!("the virgin message has no request or status line");
+                None
+            }
+        };
+
+        let (modified, body) = match reply {
+            Some(Reply::Unmodified) => (None, None),
+            Some(Reply::Modified { message, body }) => {
+                if config.method == Method::Reqmod
+                    && !Message::<MemHost>::first_line(&message).is_request()
+                {
+                    host.block_virgin();
+                    return;
+                }
+                let mut fields = Fields(Vec::new());
+                Message::<MemHost>::header(&message).visit_each(&mut fields);
+                let first_line = Clone::clone(Message::<MemHost>::first_line(&message));
+                // Host messages cannot drop their body, so a body the
+                // ICAP server removed is sent empty.
+                (Some((first_line, fields.0)), Some(body.unwrap_or_default()))
+            }
+            None if config.bypass => (None, None),
+            None => {
+                host.adaptation_aborted();
+                return;
+            }
+        };
+
+        let mut adapted = host.virgin().clone();
+        if let Some((first_line, fields)) = modified {
+            copy_first_line(&first_line, adapted.first_line_mut());
+            let mut old = Fields(Vec::new());
+            adapted.header().visit_each(&mut old);
+            for (name, _) in old.0 {
+                adapted.header_mut().remove_any(&name);
+            }
+            for (name, value) in fields {
+                adapted.header_mut().insert(name, value);
+            }
+        }
+        self.adapted_body = match body {
+            Some(body) => body,
+            // Unmodified; the virgin body is passed on as-is.
+            None => ::std::mem::replace(&mut self.virgin_body, Vec::new()),
+        };
+
+        if adapted.body().is_none() {
+            self.sending = State::Never;
+        }
+        // The whole body is known, whatever the ICAP server said.
+        let size = self.adapted_body.len() as u64;
+        if host.use_adapted_sized(adapted, Some(size)).is_err() {
+            host.adaptation_aborted();
+        }
+    }
+}
+
+impl<H: host::Host + ?Sized> Transaction<H> for IcapTransaction {
+    fn start<'a>(&mut self, host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        if self.config.is_none() {
+            host.use_virgin();
+            return;
+        }
+        if host.virgin().body().is_some() {
+            self.receiving = State::On;
+            host.virgin_body_make();
+        } else {
+            self.receiving = State::Never;
+            self.adapt::<H>(host);
+        }
+    }
+
+    fn stop<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn resume<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_discard<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        assert_eq!(self.sending, State::Undecided);
+        self.sending = State::Never;
+    }
+    fn adapted_body_make<'a>(&mut self, host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        assert_eq!(self.sending, State::Undecided);
+        // The whole adapted body is known by the time it is used.
+        self.sending = State::Complete;
+        if !self.adapted_body.is_empty() {
+            host.adapted_body_content_available();
+        }
+        host.adapted_body_content_done(true);
+    }
+    fn adapted_body_make_more<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_stop_making<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        self.sending = State::Complete;
+    }
+    fn adapted_body_pause<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_resume<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+    }
+    fn adapted_body_content<'a>(
+        &mut self,
+        _host: &'a mut H::TransactionRef,
+        offset: usize,
+        size: usize,
+    ) -> Area
+    where
+        H::TransactionRef: 'a,
+    {
+        let start = ::std::cmp::min(offset, self.adapted_body.len());
+        let end = ::std::cmp::min(start.saturating_add(size), self.adapted_body.len());
+        Area::from_bytes(&self.adapted_body[start..end])
+    }
+    fn adapted_body_content_shift<'a>(&mut self, _host: &'a mut H::TransactionRef, size: usize)
+    where
+        H::TransactionRef: 'a,
+    {
+        let size = ::std::cmp::min(size, self.adapted_body.len());
+        self.adapted_body.drain(0..size);
+    }
+    fn virgin_body_content_done<'a>(&mut self, host: &'a mut H::TransactionRef, at_end: bool)
+    where
+        H::TransactionRef: 'a,
+    {
+        assert_eq!(self.receiving, State::On);
+        self.receiving = State::Complete;
+        if at_end {
+            self.adapt::<H>(host);
+        } else {
+            // a truncated body cannot be adapted
+            host.adaptation_aborted();
+        }
+    }
+    fn virgin_body_content_available<'a>(&mut self, host: &'a mut H::TransactionRef)
+    where
+        H::TransactionRef: 'a,
+    {
+        assert_eq!(self.receiving, State::On);
+        let content = host.virgin_body_content(0, usize::max_value());
+        self.virgin_body.extend_from_slice(content.as_bytes());
+        host.virgin_body_content_shift(content.as_bytes().len());
+    }
+}
+
+impl Options for IcapTransaction {
+    fn option(&self, _name: &Name) -> Option<Area> {
+        // no meta-information to provide
+        None
+    }
+
+    fn visit_each<V: NamedValueVisitor>(&self, _visitor: V) {
+        // no meta-information to provide
+    }
+}
+
+ecap_adapter!(IcapService::default());
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use ecap::common::name::HEADER_CONTENT_LENGTH;
+    use ecap_host::{Decision, MemOptions, MemTransaction};
+    use ecap_icap::protocol::{self, Encapsulated, RequestHead};
+
+    use super::*;
+
+    fn field(message: &MemMessage, name: &Name) -> Option<Vec<u8>> {
+        Message::<MemHost>::header(message)
+            .get(name)
+            .map(|value| value.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn body_removed_by_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("icap://{}/respmod", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let head = RequestHead::read(&mut reader).unwrap().unwrap();
+            Encapsulated::read(&mut reader, &head.header).unwrap();
+            let mut body = Vec::new();
+            protocol::read_chunks(&mut reader, &mut body).unwrap();
+            // The response comes back without its body.
+            let reply: &[u8] = b"HTTP/1.1 403 Forbidden\r\nX-Removed: yes\r\n\r\n";
+            let mut out = format!(
+                "ICAP/1.0 200 OK\r\nEncapsulated: res-hdr=0, null-body={}\r\n\r\n",
+                reply.len()
+            )
+            .into_bytes();
+            out.extend_from_slice(reply);
+            stream.write_all(&out).unwrap();
+            body
+        });
+
+        let mut options = MemOptions::new();
+        options.insert_pair(&format!("uri={}", uri)).unwrap();
+        let mut service = IcapService::default();
+        Service::<MemHost>::configure(&mut service, &options).unwrap();
+        let mut transaction = MemTransaction::new(MemMessage::response(200, "OK"));
+        transaction.set_virgin_body(b"hello".to_vec());
+        let outcome = transaction.run::<MemHost, _>(&mut service);
+        assert_eq!(server.join().unwrap(), b"hello");
+
+        assert_eq!(outcome.decision(), Decision::Adapted);
+        assert!(outcome.is_complete());
+        assert_eq!(outcome.body(), b"");
+        let message = outcome.message().unwrap();
+        match *Message::<MemHost>::first_line(message) {
+            MemFirstLine::Status(ref line) => assert_eq!(line.status_code, 403),
+            MemFirstLine::Request(_) => panic!("not a response"),
+        }
+        assert_eq!(
+            field(message, &Name::new_known(&b"X-Removed"[..])),
+            Some(b"yes".to_vec())
+        );
+        assert_eq!(field(message, &HEADER_CONTENT_LENGTH), Some(b"0".to_vec()));
+    }
+}
@@ -1,12 +1,11 @@
-#![feature(used)]
-
 extern crate ecap;
+#[macro_use]
 extern crate ecap_common_link;
 
 use std::ffi::CStr;
 
 use ecap::adapter::{Service, Transaction};
-use ecap::common::{Area, Name, NamedValueVisitor, Options};
+use ecap::common::{Area, Error, Name, NamedValueVisitor, Options};
 use ecap::host::{self, Transaction as HostTransactionTrait};
 
 #[derive(Debug)]
@@ -23,12 +22,14 @@ where
         format!("ecap://rust/sample/minimal")
     }
 
-    fn configure<T: Options + ?Sized>(&mut self, _options: &T) {
+    fn configure<T: Options + ?Sized>(&mut self, _options: &T) -> Result<(), Error> {
         // no configuration
+        Ok(())
     }
 
-    fn reconfigure<T: Options + ?Sized>(&mut self, _options: &T) {
+    fn reconfigure<T: Options + ?Sized>(&mut self, _options: &T) -> Result<(), Error> {
         // no configuration
+        Ok(())
     }
 
     fn tag(&self) -> String {
@@ -155,10 +156,4 @@ impl Options for MinimalTransaction {
     }
 }
 
-pub extern "C" fn on_load() {
-    ecap_common_link::register_erased_service(MinimalService);
-}
-
-#[link_section = ".ctors"]
-#[used]
-pub static ON_LOAD_PTR: extern "C" fn() = on_load;
+ecap_adapter!(MinimalService);
@@ -1,19 +1,41 @@
-#![feature(used)]
 extern crate ecap;
+#[macro_use]
 extern crate ecap_common_link;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
 
 use std::ffi::CStr;
-use std::mem;
 use std::rc::Rc;
 
-use ecap::adapter::{Service, Transaction};
-use ecap::common::{header::Header, Area, Message, Name, NamedValueVisitor, Options};
+use ecap::adapter::{AdaptedBody, Decoded, Replace, Service, Transaction};
+use ecap::common::{config, header::Header, Area, Error, ErrorKind, Message, Name,
+                   NamedValueVisitor, Options};
 use ecap::host::{self, Transaction as HostTransactionTrait};
 
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    victim: String,
+    replacement: String,
+}
+
+impl Config {
+    fn from_options<T: Options + ?Sized>(options: &T) -> Result<Config, Error> {
+        let config: Config = config::from_options(options)?;
+        if config.victim.is_empty() {
+            return Err(Error::new(ErrorKind::Config, "unsupported empty victim"));
+        }
+        Ok(config)
+    }
+}
+
 #[derive(Debug)]
 pub struct ModifyService {
-    victim: Option<Rc<Vec<u8>>>,
-    replacement: Option<Rc<Vec<u8>>>,
+    /// `None` until configured, or if the configuration was rejected,
+    /// in which case messages are left unmodified.
+    config: Option<Rc<Config>>,
 }
 
 impl<H> Service<H> for ModifyService
@@ -27,41 +49,16 @@ where
         format!("ecap://rust/sample/modifying")
     }
 
-    fn configure<T: Options + ?Sized>(&mut self, options: &T) {
-        options.visit_each(CfgVisitor(self));
-        assert!(self.victim.is_some(), "Must have configured a victim");
-        assert!(
-            self.replacement.is_some(),
-            "Must have configured a replacement"
-        );
-        if self.replacement
-            .as_ref()
-            .unwrap()
-            .windows(self.victim.as_ref().unwrap().len())
-            .any(|w| *w == self.victim.as_ref().unwrap()[..])
-        {
-            panic!("will replace indefinitely");
-        }
+    fn configure<T: Options + ?Sized>(&mut self, options: &T) -> Result<(), Error> {
+        self.config = Some(Rc::new(Config::from_options(options)?));
+        Ok(())
     }
 
-    fn reconfigure<T: Options + ?Sized>(&mut self, options: &T) {
+    fn reconfigure<T: Options + ?Sized>(&mut self, options: &T) -> Result<(), Error> {
         // clear the victim and replacement
-        let _ = self.victim.take();
-        let _ = self.replacement.take();
-        options.visit_each(CfgVisitor(self));
-        assert!(self.victim.is_some(), "Must have configured a victim");
-        assert!(
-            self.replacement.is_some(),
-            "Must have configured a replacement"
-        );
-        if self.replacement
-            .as_ref()
-            .unwrap()
-            .windows(self.victim.as_ref().unwrap().len())
-            .any(|w| *w == self.victim.as_ref().unwrap()[..])
-        {
-            panic!("will replace indefinitely");
-        }
+        self.config = None;
+        self.config = Some(Rc::new(Config::from_options(options)?));
+        Ok(())
     }
 
     fn tag(&self) -> String {
@@ -69,7 +66,17 @@ where
     }
 
     fn start(&self) {
-        // custom code goes here, but none for this service
+        if let Some(host) = ecap_common_link::erased_host() {
+            // fails if another service of this library installed it first
+            let _ = ecap::common::log::init(host);
+        }
+        match self.config {
+            Some(ref config) => info!(
+                "replacing {:?} with {:?}",
+                config.victim, config.replacement
+            ),
+            None => warn!("not configured; messages are left unmodified"),
+        }
     }
 
     fn stop(&self) {
@@ -94,64 +101,19 @@ where
     }
 
     fn make_transaction(&mut self, _transaction: &mut H::TransactionRef) -> Self::Transaction {
+        let pairs = self.config
+            .as_ref()
+            .map(|config| (config.victim.clone(), config.replacement.clone()));
         ModifyTransaction {
-            victim: self.victim.as_ref().unwrap().clone(),
-            replacement: self.replacement.as_ref().unwrap().clone(),
-            sending: State::Undecided,
-            receiving: State::Undecided,
-            buffer: Vec::new(),
+            config: self.config.clone(),
+            body: AdaptedBody::new(Decoded::new(Replace::new(pairs), true)),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Copy, Eq, Clone)]
-enum State {
-    Undecided,
-    On,
-    Complete,
-    Never,
-}
-
 pub struct ModifyTransaction {
-    receiving: State,
-    sending: State,
-    victim: Rc<Vec<u8>>,
-    replacement: Rc<Vec<u8>>,
-    buffer: Vec<u8>,
-}
-
-impl ModifyTransaction {
-    fn stop_virgin_body<'a, H: host::Host + ?Sized>(&mut self, host: &'a mut H::TransactionRef) {
-        if self.receiving == State::On {
-            host.virgin_body_stop_making();
-            self.receiving = State::Complete;
-        } else {
-            // we already got the entire body or refused it earlier
-            assert_ne!(self.receiving, State::Undecided);
-        }
-    }
-
-    fn adapt_content(&self, content: &mut Vec<u8>) {
-        // this is oversimplified; production code should worry about arbitrary
-        // chunk boundaries, content encodings, service reconfigurations, etc.
-
-        let mut pos = 0;
-        loop {
-            let r = content[pos..]
-                .windows(self.victim.len())
-                .enumerate()
-                .find(|(_, window)| **window == self.victim[..])
-                .map(|(idx, _)| idx);
-            if let Some(idx) = r {
-                let range = (pos + idx)..(pos + idx + self.victim.len());
-                mem::drop(content.splice(range, self.replacement.iter().cloned()));
-                pos = pos + idx;
-            } else {
-                // did not find victim in content
-                break;
-            }
-        }
-    }
+    config: Option<Rc<Config>>,
+    body: AdaptedBody<Decoded<Replace>>,
 }
 
 impl<H: host::Host + ?Sized> Transaction<H> for ModifyTransaction {
@@ -159,28 +121,27 @@ impl<H: host::Host + ?Sized> Transaction<H> for ModifyTransaction {
     where
         H::TransactionRef: 'a,
     {
-        if host.virgin().body().is_some() {
-            self.receiving = State::On;
-            host.virgin_body_make();
-        } else {
-            self.receiving = State::Never;
+        if self.config.is_none() {
+            host.use_virgin();
+            return;
         }
-
         let mut adapted = host.virgin().clone();
         // FIXME: assert!(adapted.is_some()); -- can host return None from clone?
-        // XXX: This content-length should be gotten from the host.
-        let content_length = Name::new_known("Content-Length".as_bytes());
-        adapted.header_mut().remove_any(&content_length);
+        if let Err(e) = self.body.transformer_mut().prepare(adapted.header_mut()) {
+            // we cannot look inside the body, so leave it alone
+            debug!("not modifying: {}", e);
+            host.use_virgin();
+            return;
+        }
+        self.body.start::<H>(host);
+        let size = self.body.adapted_size::<H>(host);
 
         let name = Name::new_known("X-Ecap".as_bytes());
         // XXX: use host global and get uri
-        let value = Area::from_bytes(b"foo");
+        let value = Area::from_static(b"foo");
         adapted.header_mut().insert(name, value);
-        if adapted.body().is_none() {
-            self.sending = State::Never;
-            host.use_adapted(adapted);
-        } else {
-            host.use_adapted(adapted);
+        if host.use_adapted_sized(adapted, size).is_err() {
+            host.adaptation_aborted();
         }
     }
 
@@ -198,48 +159,38 @@ impl<H: host::Host + ?Sized> Transaction<H> for ModifyTransaction {
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.sending, State::Undecided);
-        self.sending = State::Never;
         // we do not need more vb if the host is not interested in ab
-        self.stop_virgin_body::<H>(host);
+        self.body.adapted_body_discard::<H>(host);
     }
     fn adapted_body_make<'a>(&mut self, host: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.sending, State::Undecided);
-        assert!(host.virgin().body().is_some());
-
-        assert!(self.receiving == State::On || self.receiving == State::Complete);
-
-        self.sending = State::On;
-        if !self.buffer.is_empty() {
-            host.adapted_body_content_available();
-        }
+        self.body.adapted_body_make::<H>(host);
     }
     fn adapted_body_make_more<'a>(&mut self, host: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.receiving, State::On);
-        host.virgin_body_make_more();
+        self.body.adapted_body_make_more::<H>(host);
     }
     fn adapted_body_stop_making<'a>(&mut self, host: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
-        self.sending = State::Complete;
-        self.stop_virgin_body::<H>(host);
+        self.body.adapted_body_stop_making::<H>(host);
     }
-    fn adapted_body_pause<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    fn adapted_body_pause<'a>(&mut self, host: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
+        self.body.adapted_body_pause::<H>(host);
     }
-    fn adapted_body_resume<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    fn adapted_body_resume<'a>(&mut self, host: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
+        self.body.adapted_body_resume::<H>(host);
     }
     fn adapted_body_content<'a>(
         &mut self,
@@ -250,45 +201,35 @@ impl<H: host::Host + ?Sized> Transaction<H> for ModifyTransaction {
     where
         H::TransactionRef: 'a,
     {
-        assert!(self.sending == State::On || self.sending == State::Complete);
-        if offset + size > self.buffer.len() {
-            Area::from_bytes(&self.buffer[offset..])
-        } else {
-            Area::from_bytes(&self.buffer[offset..offset + size])
-        }
+        self.body.adapted_body_content(offset, size)
     }
     fn adapted_body_content_shift<'a>(&mut self, _host: &'a mut H::TransactionRef, size: usize)
     where
         H::TransactionRef: 'a,
     {
-        self.buffer.drain(0..size);
+        self.body.adapted_body_content_shift(size);
     }
     fn virgin_body_content_done<'a>(&mut self, host: &'a mut H::TransactionRef, at_end: bool)
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.receiving, State::On);
-        self.stop_virgin_body::<H>(host);
-        if self.sending == State::On {
-            host.adapted_body_content_done(at_end);
-            self.sending = State::Complete;
+        self.body.virgin_body_content_done::<H>(host, at_end);
+        if let Some(ref config) = self.config {
+            debug!(
+                "replaced {:?} {} times",
+                config.victim,
+                self.body.transformer().inner().replaced()
+            );
+        }
+        if let Some(e) = self.body.transformer().error() {
+            warn!("adapted body cut short: {}", e);
         }
     }
     fn virgin_body_content_available<'a>(&mut self, host: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.receiving, State::On);
-        let mut bytes: Vec<u8> = host.virgin_body_content(0, usize::max_value())
-            .as_bytes()
-            .into();
-        host.virgin_body_content_shift(bytes.len());
-        self.adapt_content(&mut bytes);
-        self.buffer.extend(bytes);
-
-        if self.sending == State::On {
-            host.adapted_body_content_available();
-        }
+        self.body.virgin_body_content_available::<H>(host);
     }
 }
 
@@ -303,39 +244,4 @@ impl Options for ModifyTransaction {
     }
 }
 
-struct CfgVisitor<'a>(&'a mut ModifyService);
-
-impl<'a> NamedValueVisitor for CfgVisitor<'a> {
-    fn visit(&mut self, name: &Name, value: &Area) {
-        let value = value.as_bytes();
-        match name.image() {
-            Some(b"victim") => {
-                if value.is_empty() {
-                    panic!("unsupported empty victim");
-                }
-                self.0.victim = Some(Rc::new(value.into()));
-            }
-            Some(b"replacement") => {
-                self.0.replacement = Some(Rc::new(value.into()));
-            }
-            _ if name.host_id().is_some() => {
-                // skip host options
-                return;
-            }
-            key => {
-                panic!("unsupported configuration parameter: {:?}", key);
-            }
-        }
-    }
-}
-
-pub extern "C" fn on_load() {
-    ecap_common_link::register_erased_service(ModifyService {
-        victim: None,
-        replacement: None,
-    });
-}
-
-#[link_section = ".ctors"]
-#[used]
-pub static _ON_LOAD_PTR: extern "C" fn() = on_load;
+ecap_adapter!(ModifyService { config: None });
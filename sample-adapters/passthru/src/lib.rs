@@ -1,12 +1,11 @@
-#![feature(used)]
-
 extern crate ecap;
+#[macro_use]
 extern crate ecap_common_link;
 
 use std::ffi::CStr;
 
-use ecap::adapter::{Service, Transaction};
-use ecap::common::{Area, Message, Name, NamedValueVisitor, Options};
+use ecap::adapter::{AdaptedBody, Service, Transaction};
+use ecap::common::{Area, Error, Message, Name, NamedValueVisitor, Options};
 use ecap::host::{self, Transaction as HostTransactionTrait};
 
 #[derive(Debug)]
@@ -23,12 +22,14 @@ where
         format!("ecap://rust/sample/passthru")
     }
 
-    fn configure<T: Options + ?Sized>(&mut self, _options: &T) {
+    fn configure<T: Options + ?Sized>(&mut self, _options: &T) -> Result<(), Error> {
         // no configuration
+        Ok(())
     }
 
-    fn reconfigure<T: Options + ?Sized>(&mut self, _options: &T) {
+    fn reconfigure<T: Options + ?Sized>(&mut self, _options: &T) -> Result<(), Error> {
         // no configuration
+        Ok(())
     }
 
     fn tag(&self) -> String {
@@ -62,23 +63,18 @@ where
 
     fn make_transaction(&mut self, _transaction: &mut H::TransactionRef) -> Self::Transaction {
         PassthruTransaction {
-            receiving: State::Undecided,
-            sending: State::Undecided,
+            body: AdaptedBody::new(pass),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum State {
-    Undecided,
-    On,
-    Complete,
-    Never,
+/// Virgin body chunks are passed on as-is.
+fn pass(chunk: Area) -> Area {
+    chunk
 }
 
 pub struct PassthruTransaction {
-    receiving: State,
-    sending: State,
+    body: AdaptedBody<fn(Area) -> Area>,
 }
 
 impl<H: host::Host + ?Sized> Transaction<H> for PassthruTransaction {
@@ -86,19 +82,10 @@ impl<H: host::Host + ?Sized> Transaction<H> for PassthruTransaction {
     where
         H::TransactionRef: 'a,
     {
-        if hostx.virgin().body().is_some() {
-            self.receiving = State::On;
-            hostx.virgin_body_make();
-        } else {
-            self.receiving = State::Never;
-        }
-
+        self.body.start::<H>(hostx);
         let adapted = hostx.virgin().clone();
-        if adapted.body().is_none() {
-            self.sending = State::Never;
-            hostx.use_adapted(adapted);
-        } else {
-            hostx.use_adapted(adapted);
+        if hostx.use_adapted(adapted).is_err() {
+            hostx.adaptation_aborted();
         }
     }
 
@@ -112,80 +99,70 @@ impl<H: host::Host + ?Sized> Transaction<H> for PassthruTransaction {
         H::TransactionRef: 'a,
     {
     }
-    fn adapted_body_discard<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    fn adapted_body_discard<'a>(&mut self, hostx: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.sending, State::Undecided);
-        self.sending = State::Never;
+        self.body.adapted_body_discard::<H>(hostx);
     }
     fn adapted_body_make<'a>(&mut self, hostx: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.sending, State::Undecided);
-        assert!(hostx.virgin().body().is_some());
-        assert!(self.receiving == State::On || self.receiving == State::Complete);
-
-        self.sending = State::On;
-        hostx.adapted_body_content_available();
+        self.body.adapted_body_make::<H>(hostx);
     }
     fn adapted_body_make_more<'a>(&mut self, hostx: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.receiving, State::On);
-        hostx.virgin_body_make_more();
+        self.body.adapted_body_make_more::<H>(hostx);
     }
     fn adapted_body_stop_making<'a>(&mut self, hostx: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
-        self.sending = State::Complete;
-        hostx.virgin_body_stop_making();
+        self.body.adapted_body_stop_making::<H>(hostx);
     }
-    fn adapted_body_pause<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    fn adapted_body_pause<'a>(&mut self, hostx: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
+        self.body.adapted_body_pause::<H>(hostx);
     }
-    fn adapted_body_resume<'a>(&mut self, _host: &'a mut H::TransactionRef)
+    fn adapted_body_resume<'a>(&mut self, hostx: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
+        self.body.adapted_body_resume::<H>(hostx);
     }
     fn adapted_body_content<'a>(
         &mut self,
-        host: &'a mut H::TransactionRef,
+        _host: &'a mut H::TransactionRef,
         offset: usize,
         size: usize,
     ) -> Area
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.sending, State::On);
-        host.virgin_body_content(offset, size)
+        self.body.adapted_body_content(offset, size)
     }
-    fn adapted_body_content_shift<'a>(&mut self, host: &'a mut H::TransactionRef, size: usize)
+    fn adapted_body_content_shift<'a>(&mut self, _host: &'a mut H::TransactionRef, size: usize)
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.sending, State::On);
-        host.virgin_body_content_shift(size);
+        self.body.adapted_body_content_shift(size);
     }
-    fn virgin_body_content_done<'a>(&mut self, host: &'a mut H::TransactionRef, at_end: bool)
+    fn virgin_body_content_done<'a>(&mut self, hostx: &'a mut H::TransactionRef, at_end: bool)
     where
         H::TransactionRef: 'a,
     {
-        assert_eq!(self.receiving, State::On);
-        self.receiving = State::Complete;
-        host.adapted_body_content_done(at_end);
+        self.body.virgin_body_content_done::<H>(hostx, at_end);
     }
-    fn virgin_body_content_available<'a>(&mut self, host: &'a mut H::TransactionRef)
+    fn virgin_body_content_available<'a>(&mut self, hostx: &'a mut H::TransactionRef)
     where
         H::TransactionRef: 'a,
     {
-        host.adapted_body_content_available()
+        self.body.virgin_body_content_available::<H>(hostx);
     }
 }
 
@@ -200,10 +177,4 @@ impl Options for PassthruTransaction {
     }
 }
 
-extern "C" fn on_load() {
-    ecap_common_link::register_erased_service(PassthruService);
-}
-
-#[link_section = ".ctors"]
-#[used]
-pub static ON_LOAD_PTR: extern "C" fn() = on_load;
+ecap_adapter!(PassthruService);